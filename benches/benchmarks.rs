@@ -1,31 +1,46 @@
 use criterion::BatchSize;
+use criterion::BenchmarkGroup;
 use criterion::BenchmarkId;
 use criterion::Throughput;
+use criterion::measurement::WallTime;
 use criterion::{criterion_group, criterion_main, Criterion};
-use lambo::ast::AST;
-
-fn benchmark_ast(benchmark_name: &str, input: usize) -> AST {
-    let lib = include_str!("./benchmarks.lambo");
-    let source = format!("{lib} {benchmark_name} {input}");
-    let mut ast = AST::from_str(&source);
-    ast.garbage_collect();
-    ast
+use lambo::{EvalConfig, Prelude, Program};
+use std::sync::OnceLock;
+
+/// Library is parsed once, every program starts from a copy of it
+fn benchmark_program(benchmark_name: &str, input: usize) -> Program {
+    static PRELUDE: OnceLock<Prelude> = OnceLock::new();
+    PRELUDE
+        .get_or_init(|| Prelude::parse(include_str!("./benchmarks.lambo")).unwrap())
+        .program(&format!("{benchmark_name} {input}"))
+        .unwrap()
+}
+
+/// Time evaluation of `benchmark_name` and report how many nodes it needed at peak.
+/// Criterion only measures time, so memory is printed next to its output.
+fn bench_lambo(group: &mut BenchmarkGroup<WallTime>, benchmark_name: &str, size: usize) {
+    let program = benchmark_program(benchmark_name, size);
+
+    let value = program.clone().evaluate(EvalConfig::default()).unwrap();
+    println!(
+        "{benchmark_name}/{size}: {} peak nodes",
+        value.ast().peak_node_count()
+    );
+
+    group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, _| {
+        b.iter_batched(
+            || program.clone(),
+            |program| program.evaluate(EvalConfig::default()).unwrap(),
+            BatchSize::SmallInput,
+        );
+    });
 }
 
 fn numbers_stream(c: &mut Criterion) {
     let mut group = c.benchmark_group("numbers_from");
-    for size in (8..12).map(|exp| (2 as usize).pow(exp)) {
+    for size in (8..12).map(|exp| 2_usize.pow(exp)) {
         group.throughput(Throughput::Elements(size as u64));
-        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, _| {
-            let ast = benchmark_ast("bench_numbers", size);
-            b.iter_batched(
-                || ast.clone(),
-                |mut ast| {
-                    ast.evaluate(ast.root).unwrap();
-                },
-                BatchSize::SmallInput,
-            );
-        });
+        bench_lambo(&mut group, "bench_numbers", size);
         group.bench_with_input(BenchmarkId::new("Native", size), &size, |b, &size| {
             b.iter(|| native::bench_numbers(size, 0));
         });
@@ -35,29 +50,108 @@ fn numbers_stream(c: &mut Criterion) {
 
 fn primes_stream(c: &mut Criterion) {
     let mut group = c.benchmark_group("primes");
-    for size in (5..8).map(|exp| (2 as usize).pow(exp)) {
+    for size in (5..8).map(|exp| 2_usize.pow(exp)) {
+        group.throughput(Throughput::Elements(size as u64));
+        bench_lambo(&mut group, "bench_primes", size);
+    }
+    group.finish();
+}
+
+fn ackermann(c: &mut Criterion) {
+    let mut group = c.benchmark_group("ackermann");
+    for size in [4, 8, 16] {
+        bench_lambo(&mut group, "bench_ackermann", size);
+        group.bench_with_input(BenchmarkId::new("Native", size), &size, |b, &size| {
+            b.iter(|| native::ackermann(2, size));
+        });
+    }
+    group.finish();
+}
+
+fn church_factorial(c: &mut Criterion) {
+    let mut group = c.benchmark_group("church_factorial");
+    for size in [3, 4, 5, 6] {
+        bench_lambo(&mut group, "bench_church_factorial", size);
+        group.bench_with_input(BenchmarkId::new("Native", size), &size, |b, &size| {
+            b.iter(|| native::bench_church_factorial(size));
+        });
+    }
+    group.finish();
+}
+
+fn list_sorting(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sort");
+    for size in (4..7).map(|exp| 2_usize.pow(exp)) {
         group.throughput(Throughput::Elements(size as u64));
-        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, _| {
-            let ast = benchmark_ast("bench_primes", size);
-            b.iter_batched(
-                || ast.clone(),
-                |mut ast| {
-                    ast.evaluate(ast.root).unwrap();
-                },
-                BatchSize::SmallInput,
-            );
+        bench_lambo(&mut group, "bench_sort", size);
+        group.bench_with_input(BenchmarkId::new("Native", size), &size, |b, &size| {
+            b.iter(|| native::bench_sort(size));
+        });
+    }
+    group.finish();
+}
+
+fn strings(c: &mut Criterion) {
+    let mut group = c.benchmark_group("strings");
+    for size in (6..9).map(|exp| 2_usize.pow(exp)) {
+        group.throughput(Throughput::Bytes(size as u64));
+        bench_lambo(&mut group, "bench_strings", size);
+        group.bench_with_input(BenchmarkId::new("Native", size), &size, |b, &size| {
+            b.iter(|| native::bench_strings(size));
         });
     }
     group.finish();
 }
 
-criterion_group!(benches, numbers_stream, primes_stream);
+type Backend = fn(Program) -> lambo::Value;
+
+/// Evaluation strategies to compare on identical programs.
+/// The petgraph AST evaluator is currently the only backend,
+/// others belong here as they appear so their slowdown shows up side by side.
+const BACKENDS: &[(&str, Backend)] = &[("AST", |program| {
+    program.evaluate(EvalConfig::default()).unwrap()
+})];
+
+fn backends(c: &mut Criterion) {
+    let mut group = c.benchmark_group("backends");
+    let programs = [
+        ("bench_numbers", 256),
+        ("bench_primes", 16),
+        ("bench_ackermann", 4),
+        ("bench_church_factorial", 4),
+        ("bench_sort", 16),
+        ("bench_strings", 64),
+    ];
+    for (benchmark_name, size) in programs {
+        let program = benchmark_program(benchmark_name, size);
+        for &(backend, evaluate) in BACKENDS {
+            let id = BenchmarkId::new(backend, format!("{benchmark_name}/{size}"));
+            group.bench_function(id, |b| {
+                b.iter_batched(|| program.clone(), evaluate, BatchSize::SmallInput);
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    numbers_stream,
+    primes_stream,
+    ackermann,
+    church_factorial,
+    list_sorting,
+    strings,
+    backends
+);
 criterion_main!(benches);
 
 /// Mirrors native implementations from benchmarks.lambo
 /// The goal is to answer the question:
 /// "If we compiled lambo to Rust (or C), how much faster can we get?"
 mod native {
+    use std::rc::Rc;
+
     type Thunk<T> = Box<dyn Fn() -> T>;
 
     #[allow(dead_code)]
@@ -76,12 +170,71 @@ mod native {
                 if index == 0 {
                     return head;
                 }
-                return nth(tail(), index - 1);
+                nth(tail(), index - 1)
             }
         }
     }
 
     pub fn bench_numbers(n: usize, start: usize) -> usize {
-        return nth(numbers_from(start), n);
+        nth(numbers_from(start), n)
+    }
+
+    pub fn ackermann(m: usize, n: usize) -> usize {
+        match (m, n) {
+            (0, n) => n + 1,
+            (m, 0) => ackermann(m - 1, 1),
+            (m, n) => ackermann(m - 1, ackermann(m, n - 1)),
+        }
+    }
+
+    /// Church numerals specialized to functions on numbers
+    type Function = Rc<dyn Fn(usize) -> usize>;
+    type Church = Rc<dyn Fn(Function) -> Function>;
+
+    fn church_zero() -> Church {
+        Rc::new(|_| Rc::new(|x| x))
+    }
+    fn church_succ(n: Church) -> Church {
+        Rc::new(move |f: Function| {
+            let n_f = n(f.clone());
+            Rc::new(move |x| f(n_f(x)))
+        })
+    }
+    fn church_mul(m: Church, n: Church) -> Church {
+        Rc::new(move |f| m(n(f)))
+    }
+
+    pub fn bench_church_factorial(n: usize) -> usize {
+        let mut k = church_zero();
+        let mut acc = church_succ(church_zero());
+        for _ in 0..n {
+            k = church_succ(k);
+            acc = church_mul(k.clone(), acc);
+        }
+        acc(Rc::new(|x| x + 1))(0)
+    }
+
+    fn random_numbers(n: usize) -> Vec<usize> {
+        std::iter::successors(Some(42), |x| Some((x * 75 + 74) % 65537))
+            .take(n)
+            .collect()
+    }
+
+    pub fn bench_sort(n: usize) -> usize {
+        let mut sorted: Vec<usize> = vec![];
+        for x in random_numbers(n).into_iter().rev() {
+            let position = sorted.iter().position(|&y| y > x).unwrap_or(sorted.len());
+            sorted.insert(position, x);
+        }
+        sorted[n / 2]
+    }
+
+    pub fn bench_strings(n: usize) -> u8 {
+        let bytes = (1..=n)
+            .rev()
+            .map(|i| (i % 26 + 97) as u8)
+            .collect::<Vec<_>>();
+        let reversed = bytes.iter().rev().copied().collect::<Vec<_>>();
+        reversed[0]
     }
 }
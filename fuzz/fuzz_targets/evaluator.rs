@@ -0,0 +1,14 @@
+#![no_main]
+
+use lambo::{ast::AST, fuzz::ArbitraryTerm};
+use libfuzzer_sys::fuzz_target;
+
+// Terms may diverge, run with `-timeout` until evaluation has a step limit
+fuzz_target!(|term: ArbitraryTerm| {
+    let mut ast = AST::new();
+    let Ok(root) = ast.add_term(&term.0) else {
+        return;
+    };
+    ast.root = root;
+    let _ = ast.evaluate(root);
+});
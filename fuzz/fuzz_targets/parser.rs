@@ -0,0 +1,8 @@
+#![no_main]
+
+use lambo::fuzz::ArbitrarySource;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|source: ArbitrarySource| {
+    let _ = lambo::parse(&source.0);
+});
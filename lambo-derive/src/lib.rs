@@ -0,0 +1,207 @@
+//! Derive macros for `lambo::ast::marshal::{ToLambo, FromLambo}`.
+//! Structs become a single constructor, enums one constructor per variant.
+//! Constructors are named in snake_case after the struct or variant,
+//! `#[lambo(name = "...")]` picks a different name.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{Attribute, Data, DeriveInput, Fields, Ident, LitStr, parse_macro_input, parse_quote};
+
+struct Constructor {
+    name: String,
+    /// Path used both to pattern match and to construct, e.g `Self::Some`
+    path: TokenStream2,
+    fields: Fields,
+}
+
+impl Constructor {
+    fn bindings(&self) -> Vec<Ident> {
+        match &self.fields {
+            Fields::Named(fields) => fields
+                .named
+                .iter()
+                .map(|f| f.ident.clone().unwrap())
+                .collect(),
+            Fields::Unnamed(fields) => (0..fields.unnamed.len())
+                .map(|i| format_ident!("field_{i}"))
+                .collect(),
+            Fields::Unit => vec![],
+        }
+    }
+
+    /// Pattern binding every field to a variable named like `bindings`
+    fn pattern(&self) -> TokenStream2 {
+        let path = &self.path;
+        let bindings = self.bindings();
+        match &self.fields {
+            Fields::Named(_) => quote! { #path { #(#bindings),* } },
+            Fields::Unnamed(_) => quote! { #path ( #(#bindings),* ) },
+            Fields::Unit => quote! { #path },
+        }
+    }
+
+    /// Expression with every field set to `value(binding)`
+    fn shape(&self, value: impl Fn(&Ident) -> TokenStream2) -> TokenStream2 {
+        let path = &self.path;
+        let bindings = self.bindings();
+        let values = bindings.iter().map(value);
+        match &self.fields {
+            Fields::Named(_) => quote! { #path { #(#bindings: #values),* } },
+            Fields::Unnamed(_) => quote! { #path ( #(#values),* ) },
+            Fields::Unit => quote! { #path },
+        }
+    }
+}
+
+fn snake_case(name: &str) -> String {
+    let mut result = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if i > 0 {
+                result.push('_');
+            }
+            result.extend(c.to_lowercase());
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+fn constructor_name(attrs: &[Attribute], ident: &Ident) -> syn::Result<String> {
+    let mut name = snake_case(&ident.to_string());
+    for attr in attrs.iter().filter(|a| a.path().is_ident("lambo")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                name = meta.value()?.parse::<LitStr>()?.value();
+                Ok(())
+            } else {
+                Err(meta.error("expected `name`"))
+            }
+        })?;
+    }
+    Ok(name)
+}
+
+fn constructors(input: &DeriveInput) -> syn::Result<Vec<Constructor>> {
+    match &input.data {
+        Data::Struct(data) => Ok(vec![Constructor {
+            name: constructor_name(&input.attrs, &input.ident)?,
+            path: quote! { Self },
+            fields: data.fields.clone(),
+        }]),
+        Data::Enum(data) => data
+            .variants
+            .iter()
+            .map(|variant| {
+                let ident = &variant.ident;
+                Ok(Constructor {
+                    name: constructor_name(&variant.attrs, ident)?,
+                    path: quote! { Self::#ident },
+                    fields: variant.fields.clone(),
+                })
+            })
+            .collect(),
+        Data::Union(_) => Err(syn::Error::new(
+            Span::call_site(),
+            "unions can not be passed to lambo",
+        )),
+    }
+}
+
+fn expand(
+    input: TokenStream,
+    bound: syn::Path,
+    body: impl Fn(&DeriveInput, &[Constructor]) -> TokenStream2,
+) -> TokenStream {
+    let mut input = parse_macro_input!(input as DeriveInput);
+    let constructors = match constructors(&input) {
+        Ok(constructors) => constructors,
+        Err(err) => return err.into_compile_error().into(),
+    };
+
+    for param in input.generics.type_params_mut() {
+        param.bounds.push(parse_quote!(#bound));
+    }
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    let ident = &input.ident;
+    let body = body(&input, &constructors);
+
+    quote! {
+        impl #impl_generics #bound for #ident #type_generics #where_clause {
+            #body
+        }
+    }
+    .into()
+}
+
+#[proc_macro_derive(ToLambo, attributes(lambo))]
+pub fn derive_to_lambo(input: TokenStream) -> TokenStream {
+    expand(
+        input,
+        parse_quote!(::lambo::ast::marshal::ToLambo),
+        |_, constructors| {
+            let arms = constructors.iter().map(|constructor| {
+                let name = &constructor.name;
+                let arity = constructor.bindings().len();
+                let pattern = constructor.pattern();
+                let bindings = constructor.bindings();
+                quote! {
+                    #pattern => {
+                        let tag = ast.named_constructor(#name, #arity);
+                        let fields = [#(::lambo::ast::marshal::ToLambo::to_lambo(#bindings, ast)?),*];
+                        Ok(ast.encode_data(tag, &fields))
+                    }
+                }
+            });
+            quote! {
+                fn to_lambo(
+                    &self,
+                    ast: &mut ::lambo::ast::AST,
+                ) -> ::lambo::ast::ASTResult<::lambo::ast::NodeIndex> {
+                    match self {
+                        #(#arms)*
+                    }
+                }
+            }
+        },
+    )
+}
+
+#[proc_macro_derive(FromLambo, attributes(lambo))]
+pub fn derive_from_lambo(input: TokenStream) -> TokenStream {
+    expand(
+        input,
+        parse_quote!(::lambo::ast::marshal::FromLambo),
+        |input, constructors| {
+            let error = format!("Unexpected constructor for {}", input.ident);
+            let branches = constructors.iter().map(|constructor| {
+                let name = &constructor.name;
+                let arity = constructor.bindings().len();
+                let value = constructor.shape(|_| {
+                    quote! {
+                        ::lambo::ast::marshal::FromLambo::from_lambo(ast, fields.next().unwrap())?
+                    }
+                });
+                quote! {
+                    if tag == ast.named_constructor(#name, #arity) {
+                        return Ok(#value);
+                    }
+                }
+            });
+            quote! {
+                fn from_lambo(
+                    ast: &mut ::lambo::ast::AST,
+                    id: ::lambo::ast::NodeIndex,
+                ) -> ::lambo::ast::ASTResult<Self> {
+                    let (tag, fields) = ast.decode_data(id)?;
+                    #[allow(unused_mut)]
+                    let mut fields = fields.into_iter();
+                    #(#branches)*
+                    Err(::lambo::ast::ASTError::TypeError(id, #error))
+                }
+            }
+        },
+    )
+}
@@ -0,0 +1,148 @@
+use petgraph::{Direction, graph::NodeIndex};
+
+use crate::ast::{
+    AST, Edge, Node, Primitive, VariableKind,
+    builtins::{ConstructorTag, helpers::HelperFunctionTag},
+    types::TypeCheckError,
+};
+
+/// Constructors applied to the wrong number of arguments would otherwise only fail
+/// deep into evaluation, far from the mistake
+impl AST {
+    /// Check constructors whose arity is known from the source, i.e `data` declarations
+    /// and `let` bindings of `#constructor` with a literal arity. Wherever they are applied,
+    /// it must be to exactly as many arguments as they take, and values tested by `#match`
+    /// must be fully applied. A constructor can still be passed around unapplied,
+    /// e.g `fold cons nil`.
+    pub fn check_arity(&self) -> Result<(), TypeCheckError> {
+        for id in self.graph.node_indices() {
+            // Only whole application chains, from their outermost application
+            if !matches!(self.graph[id], Node::Application)
+                || self
+                    .graph
+                    .edges_directed(id, Direction::Incoming)
+                    .any(|e| *e.weight() == Edge::Function)
+            {
+                continue;
+            }
+            let (head, arguments) = self.application_head(id);
+            if let Some((name, arity)) = self.constructor_arity(head)
+                && arguments != arity
+            {
+                return Err(self.labeled_error(
+                    id,
+                    format!(
+                        "{name} takes {arity} argument{}, but is applied to {arguments}",
+                        plural(arity)
+                    ),
+                    format!("applied to {arguments} argument{}", plural(arguments)),
+                    head,
+                ));
+            }
+
+            if let Some(&Node::Data {
+                tag: ConstructorTag::HelperFunction(HelperFunctionTag::Match),
+            }) = self.graph.node_weight(head)
+                && arguments == 4
+                && let Ok(value) = self.follow_edge(id, Edge::Parameter)
+            {
+                let (value_head, applied) = self.application_head(value);
+                if let Some((name, arity)) = self.constructor_arity(value_head)
+                    && applied < arity
+                {
+                    return Err(self.labeled_error(
+                        value,
+                        format!(
+                            "{name} takes {arity} argument{}, but `#match` tests it applied to {applied}",
+                            plural(arity)
+                        ),
+                        format!("applied to {applied} argument{}", plural(applied)),
+                        value_head,
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Name and arity of the constructor `id` refers to, if known without evaluating
+    fn constructor_arity(&self, id: NodeIndex) -> Option<(String, usize)> {
+        let (name, constructor) = match self.graph.node_weight(id)? {
+            Node::Variable(VariableKind::Bound) => {
+                let binder = self.follow_edge(id, Edge::Binder(0)).ok()?;
+                let Node::Closure { argument_name } = self.graph.node_weight(binder)? else {
+                    return None;
+                };
+                (
+                    format!("`{argument_name}`"),
+                    self.follow_edge(binder, Edge::Parameter).ok()?,
+                )
+            }
+            _ => ("constructor".to_string(), id),
+        };
+        match self.graph.node_weight(constructor)? {
+            Node::Data {
+                tag: ConstructorTag::CustomTag { arity, .. },
+            } => Some((name, *arity)),
+            // `#constructor 2`
+            Node::Application => {
+                let function = self.follow_edge(constructor, Edge::Function).ok()?;
+                let parameter = self.follow_edge(constructor, Edge::Parameter).ok()?;
+                match (
+                    self.graph.node_weight(function)?,
+                    self.graph.node_weight(parameter)?,
+                ) {
+                    (
+                        Node::Data {
+                            tag:
+                                ConstructorTag::HelperFunction(HelperFunctionTag::CreateConstructor),
+                        },
+                        Node::Primitive(Primitive::Number(arity)),
+                    ) => Some((name, *arity)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+fn plural(count: usize) -> &'static str {
+    if count == 1 { "" } else { "s" }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse;
+
+    fn check(source: &str) -> Result<(), String> {
+        let program = parse(source).unwrap();
+        program.ast.check_arity().map_err(|error| error.to_string())
+    }
+
+    #[test]
+    fn constructors_take_exactly_their_arity() {
+        let prelude = "let cons #constructor 2 in let nil #constructor 0 in ";
+        assert!(check(&format!("{prelude}cons 1 nil")).is_ok());
+        // Unapplied is fine, it's a function value
+        assert!(check(&format!("{prelude}\\f.f cons")).is_ok());
+
+        let under = check(&format!("{prelude}cons 1")).unwrap_err();
+        assert!(
+            under.contains("`cons` takes 2 arguments, but is applied to 1"),
+            "{under}"
+        );
+        let over = check(&format!("{prelude}cons 1 nil 2")).unwrap_err();
+        assert!(
+            over.contains("`cons` takes 2 arguments, but is applied to 3"),
+            "{over}"
+        );
+
+        let data = "data Pair = Pair left right in Pair 1";
+        assert!(
+            check(data)
+                .unwrap_err()
+                .contains("takes 2 arguments, but is applied to 1")
+        );
+    }
+}
@@ -0,0 +1,69 @@
+use petgraph::graph::NodeIndex;
+
+use crate::ast::{AST, ASTResult};
+
+/// Strategy a program is reduced with. All of them give the same results where they apply,
+/// so the same source can be run through each to compare them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Backend {
+    /// Call-by-need, evaluating every argument at most once and sharing its value
+    #[default]
+    Lazy,
+    /// Evaluate arguments again at every use, much slower but simple
+    CallByName,
+    /// Experimental interaction net reducer, always to the full normal form.
+    /// Pure lambda terms only
+    #[cfg(feature = "optimal")]
+    Optimal,
+}
+
+impl AST {
+    /// Reduce `id` with `backend`, to its full normal form if `normalize`, see `AST::normalize`.
+    /// Returns NodeIndex under the closure chain, like `evaluate`
+    pub fn reduce(
+        &mut self,
+        id: NodeIndex,
+        backend: Backend,
+        normalize: bool,
+    ) -> ASTResult<NodeIndex> {
+        self.call_by_name = backend == Backend::CallByName;
+        match backend {
+            Backend::Lazy | Backend::CallByName if normalize => self.normalize(id),
+            Backend::Lazy | Backend::CallByName => self.evaluate(id),
+            #[cfg(feature = "optimal")]
+            Backend::Optimal => self.reduce_optimal(id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::ValueEnum;
+
+    use super::Backend;
+    use crate::{EvalConfig, parse};
+
+    /// Pure terms, so that every backend supports them
+    #[test]
+    fn backends_agree() {
+        let programs = [
+            "let two \\f x.f (f x) in let mul \\m n f.m (n f) in mul two (mul two two)",
+            "let true \\x y.x in let false \\x y.y in let not \\b.b false true in not (not true)",
+            "(\\x y.x) (\\z.z) (\\w.w w)",
+        ];
+        for source in programs {
+            let reduce = |backend| {
+                let config = EvalConfig {
+                    backend,
+                    normalize: true,
+                    ..EvalConfig::default()
+                };
+                parse(source).unwrap().evaluate(config).unwrap().to_string()
+            };
+            let expected = reduce(Backend::Lazy);
+            for &backend in Backend::value_variants() {
+                assert_eq!(reduce(backend), expected, "{backend:?}: {source}");
+            }
+        }
+    }
+}
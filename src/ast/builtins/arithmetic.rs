@@ -1,64 +1,304 @@
 use petgraph::graph::NodeIndex;
 
 use crate::ast::{
-    builtins::ConstructorTag, ASTError, ASTResult, Node, Number, Primitive, AST,
+    builtins::ConstructorTag, record::PAIR, types::Type, ASTError, ASTResult, Number, Primitive, AST,
 };
 
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ArithmeticTag {
     Add,
     Mul,
     Pow,
     Sub,
     Div,
+    Mod,
+    Min,
+    Max,
+    /// Quotient and remainder as a pair
+    DivMod,
     Eq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    AddFloat,
+    SubFloat,
+    MulFloat,
+    DivFloat,
+    /// Absolute value of a float, numbers are never negative
+    Abs,
 }
 
 impl Primitive {
     pub fn extract_number(&self) -> ASTResult<Number> {
         match self {
             Primitive::Number(number) => ASTResult::Ok(*number),
-            _ => Err(ASTError::Custom(NodeIndex::default(), "NaN")),
+            _ => Err(ASTError::TypeError(NodeIndex::default(), "NaN")),
+        }
+    }
+
+    pub fn extract_float(&self) -> ASTResult<f64> {
+        match self {
+            Primitive::Float(float) => ASTResult::Ok(*float),
+            _ => Err(ASTError::TypeError(NodeIndex::default(), "Not a float")),
         }
     }
 }
 
+/// Float literal, digits on both sides of the point: `3.14`, `2.0`
+pub fn parse_float(symbol: &str) -> Option<f64> {
+    let (whole, fraction) = symbol.split_once('.')?;
+    let is_digits = |part: &str| !part.is_empty() && part.bytes().all(|c| c.is_ascii_digit());
+    (is_digits(whole) && is_digits(fraction))
+        .then(|| symbol.parse().ok())
+        .flatten()
+}
+
+/// Float as a literal, whole floats keep their `.0` to not read back as numbers
+pub fn format_float(float: f64) -> String {
+    if float.is_finite() && float.fract() == 0.0 {
+        format!("{float:.1}")
+    } else {
+        float.to_string()
+    }
+}
+
 impl ArithmeticTag {
     pub fn argument_names(&self) -> Vec<&'static str> {
-        vec!["what", "to"]
+        match self {
+            Self::Abs => vec!["value"],
+            _ => vec!["what", "to"],
+        }
+    }
+
+    /// Fields of the records it returns, `#divmod` gives the quotient and the remainder
+    pub fn record_shape(&self) -> Option<&'static [&'static str]> {
+        match self {
+            Self::DivMod => Some(PAIR),
+            _ => None,
+        }
+    }
+
+    /// Whether the operation is on floats rather than numbers
+    pub fn is_float(&self) -> bool {
+        matches!(
+            self,
+            Self::AddFloat | Self::SubFloat | Self::MulFloat | Self::DivFloat
+        )
+    }
+
+    /// Whether the operation returns a Church boolean
+    pub fn is_comparison(&self) -> bool {
+        matches!(self, Self::Eq | Self::Lt | Self::Le | Self::Gt | Self::Ge)
+    }
+
+    /// Whether `to` compares to `what` as the operation asks, `< 5 n` is `n < 5`
+    pub fn compare(&self, what: Number, to: Number) -> Option<bool> {
+        match self {
+            Self::Eq => Some(to == what),
+            Self::Lt => Some(to < what),
+            Self::Le => Some(to <= what),
+            Self::Gt => Some(to > what),
+            Self::Ge => Some(to >= what),
+            _ => None,
+        }
+    }
+
+    pub fn signature(&self) -> Type {
+        if let Self::Abs = self {
+            return Type::function([Type::Float], Type::Float);
+        }
+        if self.is_float() {
+            return Type::function([Type::Float, Type::Float], Type::Float);
+        }
+        let result = match self {
+            // Church boolean
+            _ if self.is_comparison() => Type::function([Type::Dyn, Type::Dyn], Type::Dyn),
+            Self::DivMod => Type::Record(vec![
+                ("0".to_string(), Type::Number),
+                ("1".to_string(), Type::Number),
+            ]),
+            _ => Type::Number,
+        };
+        Type::function([Type::Number, Type::Number], result)
+    }
+
+    /// Result of `self what to` computed ahead of time, if it's a number and can't fail
+    pub fn fold(&self, what: Number, to: Number) -> Option<Number> {
+        match self {
+            Self::Eq
+            | Self::Lt
+            | Self::Le
+            | Self::Gt
+            | Self::Ge
+            | Self::DivMod
+            | Self::AddFloat
+            | Self::SubFloat
+            | Self::MulFloat
+            | Self::DivFloat
+            | Self::Abs => None,
+            Self::Add => what.checked_add(to),
+            Self::Mul => what.checked_mul(to),
+            Self::Pow => u32::try_from(what)
+                .ok()
+                .and_then(|what| to.checked_pow(what)),
+            Self::Sub => Some(to.saturating_sub(what)),
+            Self::Div => to.checked_div(what),
+            Self::Mod => to.checked_rem(what),
+            Self::Min => Some(what.min(to)),
+            Self::Max => Some(what.max(to)),
+        }
     }
 
     pub fn evaluate(&self, ast: &mut AST, id: NodeIndex) -> ASTResult<NodeIndex> {
         // All arithmetic is strict in all parameters
-        let [what, to] = ConstructorTag::get_binders(ast, id)
+        let arguments = ConstructorTag::get_binders(ast, id)?
             .iter()
-            .map(|&binder| {
-                ast.extract_primitive_from_environment(binder)
-                    .and_then(|p| p.extract_number())
-            })
-            .collect::<ASTResult<Vec<_>>>()?
-            .try_into()
-            .expect("Incorrect argument count for arithmetic operation");
+            .map(|&binder| ast.extract_primitive_from_environment(binder))
+            .collect::<ASTResult<Vec<_>>>()?;
+        if let (Self::Abs, [value]) = (self, arguments.as_slice()) {
+            let result = ast.encode_f64(value.extract_float()?.abs());
+            return replace_call(ast, id, result);
+        }
+        let [what, to] = arguments.try_into().map_err(|_| {
+            ASTError::Custom(id, "Incorrect argument count for arithmetic operation")
+        })?;
 
-        let result = match self {
-            Self::Eq => {
-                let result =
-                    ast.add_expr_from_str(if what == to { "λx.λy.x" } else { "λx.λy.y" });
-                ast.migrate_node(id, result);
-                ast.remove_subtree(id);
-                return Ok(result);
+        if self.is_float() {
+            let (what, to) = (what.extract_float()?, to.extract_float()?);
+            let result = match self {
+                Self::AddFloat => what + to,
+                Self::SubFloat => to - what,
+                Self::MulFloat => what * to,
+                _ => to / what,
+            };
+            let result = ast.encode_f64(result);
+            return replace_call(ast, id, result);
+        }
+        let (what, to) = (what.extract_number()?, to.extract_number()?);
+        if let Some(holds) = self.compare(what, to) {
+            let result = ast.add_expr_from_str(if holds { "λx.λy.x" } else { "λx.λy.y" })?;
+            return replace_call(ast, id, result);
+        }
+        if let Self::DivMod = self {
+            let (Some(quotient), Some(remainder)) = (to.checked_div(what), to.checked_rem(what))
+            else {
+                return Err(ASTError::Arithmetic(id, "Division by zero"));
+            };
+            let tag = ast.record_constructor(PAIR);
+            let [quotient, remainder] =
+                [quotient, remainder].map(|number| ast.encode_usize(number));
+            let result = ast.encode_data(tag, &[quotient, remainder]);
+            replace_call(ast, id, result)?;
+            return Ok(result);
+        }
+
+        let result = if ast.checked_arithmetic {
+            self.fold(what, to).ok_or_else(|| {
+                let error = match self {
+                    Self::Div | Self::Mod if what == 0 => "Division by zero",
+                    _ => "Arithmetic overflow",
+                };
+                ASTError::Arithmetic(id, error)
+            })?
+        } else {
+            // Only overflow wraps around, there is no number to wrap division by zero to
+            let division_by_zero = ASTError::Arithmetic(id, "Division by zero");
+            match self {
+                Self::Add => what.wrapping_add(to),
+                Self::Mul => what.wrapping_mul(to),
+                Self::Pow => wrapping_pow(to, what),
+                Self::Sub => to.saturating_sub(what),
+                Self::Div => to.checked_div(what).ok_or(division_by_zero)?,
+                Self::Mod => to.checked_rem(what).ok_or(division_by_zero)?,
+                Self::Min => what.min(to),
+                Self::Max => what.max(to),
+                Self::Eq
+                | Self::Lt
+                | Self::Le
+                | Self::Gt
+                | Self::Ge
+                | Self::DivMod
+                | Self::AddFloat
+                | Self::SubFloat
+                | Self::MulFloat
+                | Self::DivFloat
+                | Self::Abs => unreachable!("handled above"),
             }
-            Self::Add => what + to,
-            Self::Mul => what * to,
-            Self::Pow => to.pow(what as u32),
-            Self::Sub => to.checked_sub(what).unwrap_or_default(),
-            Self::Div => to / what,
         };
-        let result = ast
-            .graph
-            .add_node(Node::Primitive(Primitive::Number(result)));
-        ast.migrate_node(id, result);
-        ast.remove_subtree(id);
-        Ok(result)
+        let result = ast.encode_usize(result);
+        replace_call(ast, id, result)
+    }
+}
+
+/// `base` to the power of `exponent`, wrapping around like `Number::wrapping_pow`
+/// but without cutting the exponent down to `u32`
+fn wrapping_pow(mut base: Number, mut exponent: Number) -> Number {
+    let mut result: Number = 1;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = result.wrapping_mul(base);
+        }
+        base = base.wrapping_mul(base);
+        exponent >>= 1;
+    }
+    result
+}
+
+/// Put `result` in place of the builtin call `id`
+fn replace_call(ast: &mut AST, id: NodeIndex, result: NodeIndex) -> ASTResult<NodeIndex> {
+    ast.migrate_node(id, result)?;
+    ast.remove_subtree(id);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{EvalConfig, LamboError, ast::ASTError, parse};
+
+    fn evaluate(source: &str, checked_arithmetic: bool) -> Result<Option<usize>, LamboError> {
+        let value = parse(source)?.evaluate(EvalConfig {
+            checked_arithmetic,
+            ..EvalConfig::default()
+        })?;
+        Ok(value.to_usize())
+    }
+
+    fn is_arithmetic_error(result: Result<Option<usize>, LamboError>) -> bool {
+        matches!(result, Err(LamboError::Builtin(ASTError::Arithmetic(..))))
+    }
+
+    /// Dividing by zero has no wrapping result to fall back to, so it fails in both modes
+    #[test]
+    fn division_by_zero_is_an_error() {
+        // `/ 0 1` is `1 / 0`, the lambda keeps it from being folded before evaluation
+        let programs = ["/ 0 1", "% 0 1", "(#divmod 0 1).0", "(λx./ x 1) 0"];
+        for source in programs {
+            for checked_arithmetic in [false, true] {
+                assert!(
+                    is_arithmetic_error(evaluate(source, checked_arithmetic)),
+                    "{source}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn overflow_wraps_by_default() {
+        let value = parse("^ 64 2").unwrap().evaluate(EvalConfig::default());
+        assert_eq!(value.unwrap().to_usize(), Some(0));
+    }
+
+    #[test]
+    fn overflow_wraps_unless_checked() {
+        assert_eq!(evaluate("^ 64 2", false).unwrap(), Some(0));
+        // Exponent doesn't fit in `u32`
+        assert_eq!(evaluate("^ 4294967296 2", false).unwrap(), Some(0));
+        assert!(is_arithmetic_error(evaluate("^ 64 2", true)));
+        assert!(is_arithmetic_error(evaluate(
+            "+ 1 18446744073709551615",
+            true
+        )));
     }
 }
@@ -0,0 +1,149 @@
+use petgraph::{graph::NodeIndex, visit::EdgeRef};
+
+use crate::ast::{
+    AST, ASTError, ASTResult, Edge, Node, VariableKind, builtins::ConstructorTag, types::Type,
+};
+
+/// Builtins on arrays of arbitrary terms, stored as `ConstructorTag::Array`
+/// with one binder per element
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ArrayOpTag {
+    /// `size` elements sharing the same `init` value
+    New,
+    Get,
+    Set,
+    Length,
+}
+
+impl ArrayOpTag {
+    pub fn argument_names(&self) -> Vec<&'static str> {
+        match self {
+            Self::New => vec!["size", "init"],
+            Self::Get => vec!["index", "array"],
+            Self::Set => vec!["index", "value", "array"],
+            Self::Length => vec!["array"],
+        }
+    }
+
+    pub fn signature(&self) -> Type {
+        match self {
+            Self::New => Type::function([Type::Number, Type::Dyn], Type::Data),
+            Self::Get => Type::function([Type::Number, Type::Data], Type::Dyn),
+            Self::Set => Type::function([Type::Number, Type::Dyn, Type::Data], Type::Data),
+            Self::Length => Type::function([Type::Data], Type::Number),
+        }
+    }
+
+    pub fn evaluate(&self, ast: &mut AST, id: NodeIndex) -> ASTResult<NodeIndex> {
+        let binders = ConstructorTag::get_binders(ast, id)?;
+        if let (Self::New, [size_binder, init_binder]) = (self, binders.as_slice()) {
+            let size = ast
+                .extract_primitive_from_environment(*size_binder)
+                .and_then(|p| p.extract_number())?;
+
+            // The `init` closure is above the call, so every element can refer to it directly
+            let array = ast.graph.add_node(Node::Data {
+                tag: ConstructorTag::Array { length: size },
+            });
+            for index in 0..size {
+                ast.graph.add_edge(array, *init_binder, Edge::Binder(index));
+            }
+
+            ast.migrate_node(id, array)?;
+            ast.remove_node(id);
+            return Ok(array);
+        }
+
+        let array_binder = *binders
+            .last()
+            .ok_or(ASTError::Custom(id, "Incorrect argument count"))?;
+        let index = match self {
+            Self::Get | Self::Set => Some(
+                ast.extract_primitive_from_environment(binders[0])
+                    .and_then(|p| p.extract_number())?,
+            ),
+            _ => None,
+        };
+
+        let (array, is_dangling) = ast.evaluate_closure_parameter(array_binder)?;
+        let length = match *ast.node(array)? {
+            Node::Data {
+                tag: ConstructorTag::Array { length },
+            } => length,
+            _ => return Err(ASTError::TypeError(array, "Expected an array")),
+        };
+        if let Some(index) = index
+            && index >= length
+        {
+            return Err(ASTError::Custom(id, "Index out of bounds"));
+        }
+
+        match (self, index) {
+            (Self::Length, _) => {
+                if is_dangling {
+                    ast.remove_node(array);
+                }
+                let node = ast.encode_usize(length);
+                ast.migrate_node(id, node)?;
+                ast.remove_node(id);
+                Ok(node)
+            }
+            (Self::Get, Some(index)) => {
+                let element = ast.array_element(array, index)?;
+                if is_dangling {
+                    ast.remove_node(array);
+                }
+                let variable = ast.graph.add_node(Node::Variable(VariableKind::Bound));
+                ast.graph.add_edge(variable, element, Edge::Binder(0));
+
+                ast.migrate_node(id, variable)?;
+                ast.remove_node(id);
+                Ok(variable)
+            }
+            (Self::Set, Some(index)) => {
+                let value_binder = binders[1];
+                // Nobody else sees the array, so it is updated in place
+                let array = if is_dangling {
+                    array
+                } else {
+                    ast.stats.array_copies += 1;
+                    let copy = ast.graph.add_node(Node::Data {
+                        tag: ConstructorTag::Array { length },
+                    });
+                    for (index, element) in ConstructorTag::get_binders(ast, array)?
+                        .into_iter()
+                        .enumerate()
+                    {
+                        ast.graph.add_edge(copy, element, Edge::Binder(index));
+                    }
+                    copy
+                };
+                let edge = ast
+                    .graph
+                    .edges_directed(array, petgraph::Direction::Outgoing)
+                    .find(|e| *e.weight() == Edge::Binder(index))
+                    .map(|e| e.id())
+                    .ok_or(ASTError::Custom(array, "Incomplete array"))?;
+                ast.graph.remove_edge(edge);
+                ast.graph.add_edge(array, value_binder, Edge::Binder(index));
+
+                ast.migrate_node(id, array)?;
+                ast.remove_node(id);
+                Ok(array)
+            }
+            _ => Err(ASTError::Custom(id, "Incorrect argument count")),
+        }
+    }
+}
+
+impl AST {
+    /// Closure holding the `index`th element of evaluated `array`
+    fn array_element(&self, array: NodeIndex, index: usize) -> ASTResult<NodeIndex> {
+        self.graph
+            .edges_directed(array, petgraph::Direction::Outgoing)
+            .find(|e| *e.weight() == Edge::Binder(index))
+            .map(|e| e.target())
+            .ok_or(ASTError::Custom(array, "Incomplete array"))
+    }
+}
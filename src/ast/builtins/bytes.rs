@@ -1,41 +1,76 @@
-use crate::ast::{builtins::ConstructorTag, ASTError, ASTResult, Node, Number, Primitive, AST};
+use crate::ast::{builtins::ConstructorTag, types::Type, ASTError, ASTResult, Node, Number, Primitive, AST};
 use petgraph::graph::NodeIndex;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BytesOpTag {
     New,
     Get,
+    /// `some byte`, or `none` when the index is out of bounds
+    TryGet,
     Set,
     Length,
     Push,
     Pop,
+    /// Bytes from `start` up to, but not including `end`
+    Slice,
+    /// `bytes` followed by `suffix`
+    Concat,
+    /// Decimal digits of a number
+    FromNumber,
+    /// Number written in decimal digits
+    ToNumber,
 }
 
 impl BytesOpTag {
     pub fn argument_names(&self) -> Vec<&'static str> {
         match self {
             Self::New => vec!["size"],
-            Self::Get => vec!["index", "bytes"],
+            Self::Get | Self::TryGet => vec!["index", "bytes"],
             Self::Set => vec!["index", "value", "bytes"],
             Self::Length => vec!["bytes"],
             Self::Push => vec!["value", "bytes"],
             Self::Pop => vec!["bytes"],
+            Self::Slice => vec!["start", "end", "bytes"],
+            Self::Concat => vec!["suffix", "bytes"],
+            Self::FromNumber => vec!["number"],
+            Self::ToNumber => vec!["bytes"],
+        }
+    }
+
+    pub fn signature(&self) -> Type {
+        match self {
+            Self::New => Type::function([Type::Number], Type::Bytes),
+            Self::Get => Type::function([Type::Number, Type::Bytes], Type::Number),
+            Self::TryGet => Type::function([Type::Number, Type::Bytes], Type::Data),
+            Self::Set => Type::function([Type::Number, Type::Number, Type::Bytes], Type::Bytes),
+            Self::Length => Type::function([Type::Bytes], Type::Number),
+            Self::Push => Type::function([Type::Number, Type::Bytes], Type::Bytes),
+            Self::Pop => Type::function([Type::Bytes], Type::Bytes),
+            Self::Slice => Type::function([Type::Number, Type::Number, Type::Bytes], Type::Bytes),
+            Self::Concat => Type::function([Type::Bytes, Type::Bytes], Type::Bytes),
+            Self::FromNumber => Type::function([Type::Number], Type::Bytes),
+            Self::ToNumber => Type::function([Type::Bytes], Type::Number),
         }
     }
 
     pub fn evaluate(&self, ast: &mut AST, id: NodeIndex) -> ASTResult<NodeIndex> {
-        let binders = ConstructorTag::get_binders(ast, id);
+        let binders = ConstructorTag::get_binders(ast, id)?;
         match self {
             Self::New => {
+                let [size_binder] = binders
+                    .try_into()
+                    .map_err(|_| ASTError::Custom(id, "Incorrect argument count"))?;
+
                 let size = ast
-                    .extract_primitive_from_environment(binders[0])
+                    .extract_primitive_from_environment(size_binder)
                     .and_then(|p| p.extract_number())?;
 
                 let bytes = vec![0; size];
                 let node = ast.graph.add_node(Node::Primitive(Primitive::Bytes(bytes)));
 
-                ast.migrate_node(id, node);
-                ast.graph.remove_node(id);
+                ast.migrate_node(id, node)?;
+                ast.remove_node(id);
 
                 Ok(node)
             }
@@ -51,42 +86,114 @@ impl BytesOpTag {
                 let (byte_array_id, is_dangling) =
                     ast.evaluate_closure_parameter(byte_array_binder)?;
 
-                let value = match ast.graph.node_weight(byte_array_id).unwrap() {
-                    Node::Primitive(Primitive::Bytes(byte_array)) => byte_array[index],
-                    _ => return Err(ASTError::Custom(byte_array_id, "Expected Bytes")),
+                let value = match ast.node(byte_array_id)? {
+                    Node::Primitive(Primitive::Bytes(byte_array)) => *byte_array
+                        .get(index)
+                        .ok_or(ASTError::Custom(id, "Index out of bounds"))?,
+                    _ => return Err(ASTError::TypeError(byte_array_id, "Expected Bytes")),
                 };
 
                 if is_dangling {
-                    ast.graph.remove_node(byte_array_id);
+                    ast.remove_node(byte_array_id);
                 }
 
                 let node = ast
                     .graph
                     .add_node(Node::Primitive(Primitive::Number(value as Number)));
 
-                ast.migrate_node(id, node);
-                ast.graph.remove_node(id);
+                ast.migrate_node(id, node)?;
+                ast.remove_node(id);
+
+                Ok(node)
+            }
+            Self::TryGet => {
+                let [index_binder, byte_array_binder] = binders
+                    .try_into()
+                    .map_err(|_| ASTError::Custom(id, "Incorrect argument count"))?;
+
+                let index = ast
+                    .extract_primitive_from_environment(index_binder)
+                    .and_then(|p| p.extract_number())?;
+
+                let (byte_array_id, is_dangling) =
+                    ast.evaluate_closure_parameter(byte_array_binder)?;
+
+                let value = match ast.node(byte_array_id)? {
+                    Node::Primitive(Primitive::Bytes(byte_array)) => byte_array.get(index).copied(),
+                    _ => return Err(ASTError::TypeError(byte_array_id, "Expected Bytes")),
+                };
+
+                if is_dangling {
+                    ast.remove_node(byte_array_id);
+                }
+
+                let node = match value {
+                    Some(value) => {
+                        let tag = ast.named_constructor("some", 1);
+                        let value = ast.encode_usize(value as Number);
+                        ast.encode_data(tag, &[value])
+                    }
+                    None => {
+                        let tag = ast.named_constructor("none", 0);
+                        ast.encode_data(tag, &[])
+                    }
+                };
+
+                ast.migrate_node(id, node)?;
+                ast.remove_node(id);
 
                 Ok(node)
             }
             Self::Length => {
-                let (byte_array_id, is_dangling) = ast.evaluate_closure_parameter(binders[0])?;
+                let [byte_array_binder] = binders
+                    .try_into()
+                    .map_err(|_| ASTError::Custom(id, "Incorrect argument count"))?;
+
+                let (byte_array_id, is_dangling) =
+                    ast.evaluate_closure_parameter(byte_array_binder)?;
 
-                let value = match ast.graph.node_weight(byte_array_id).unwrap() {
+                let value = match ast.node(byte_array_id)? {
                     Node::Primitive(Primitive::Bytes(byte_array)) => byte_array.len(),
-                    _ => return Err(ASTError::Custom(byte_array_id, "Expected Bytes")),
+                    _ => return Err(ASTError::TypeError(byte_array_id, "Expected Bytes")),
                 };
 
                 if is_dangling {
-                    ast.graph.remove_node(byte_array_id);
+                    ast.remove_node(byte_array_id);
                 }
 
                 let node = ast
                     .graph
                     .add_node(Node::Primitive(Primitive::Number(value as Number)));
 
-                ast.migrate_node(id, node);
-                ast.graph.remove_node(id);
+                ast.migrate_node(id, node)?;
+                ast.remove_node(id);
+
+                Ok(node)
+            }
+            Self::Set => {
+                let [index_binder, value_binder, byte_array_binder] = binders
+                    .try_into()
+                    .map_err(|_| ASTError::Custom(id, "Incorrect argument count"))?;
+
+                let index = ast
+                    .extract_primitive_from_environment(index_binder)
+                    .and_then(|p| p.extract_number())?;
+                let value = ast
+                    .extract_primitive_from_environment(value_binder)
+                    .and_then(|p| p.extract_number())?;
+
+                let mut bytes = ast.take_bytes(byte_array_binder)?;
+
+                *bytes
+                    .get_mut(index)
+                    .ok_or(ASTError::Custom(id, "Index out of bounds"))? = value
+                    .try_into()
+                    .map_err(|_| ASTError::Custom(id, "Value larger than byte"))?;
+
+                let node = ast.graph.add_node(Node::Primitive(Primitive::Bytes(bytes)));
+
+                ast.migrate_node(id, node)?;
+                ast.remove_node(id);
 
                 Ok(node)
             }
@@ -99,10 +206,7 @@ impl BytesOpTag {
                     .extract_primitive_from_environment(value_binder)
                     .and_then(|p| p.extract_number())?;
 
-                let mut bytes = match ast.extract_primitive_from_environment(byte_array_binder)? {
-                    Primitive::Bytes(bytes) => bytes,
-                    _ => return Err(ASTError::Custom(id, "Expected Bytes")),
-                };
+                let mut bytes = ast.take_bytes(byte_array_binder)?;
 
                 bytes.push(
                     value
@@ -112,12 +216,174 @@ impl BytesOpTag {
 
                 let node = ast.graph.add_node(Node::Primitive(Primitive::Bytes(bytes)));
 
-                ast.migrate_node(id, node);
-                ast.graph.remove_node(id);
+                ast.migrate_node(id, node)?;
+                ast.remove_node(id);
 
                 Ok(node)
             }
-            _ => todo!(),
+            Self::Pop => {
+                let [byte_array_binder] = binders
+                    .try_into()
+                    .map_err(|_| ASTError::Custom(id, "Incorrect argument count"))?;
+
+                let mut bytes = ast.take_bytes(byte_array_binder)?;
+
+                bytes
+                    .pop()
+                    .ok_or(ASTError::Custom(id, "Pop from empty Bytes"))?;
+
+                let node = ast.graph.add_node(Node::Primitive(Primitive::Bytes(bytes)));
+
+                ast.migrate_node(id, node)?;
+                ast.remove_node(id);
+
+                Ok(node)
+            }
+            Self::Slice => {
+                let [start_binder, end_binder, byte_array_binder] = binders
+                    .try_into()
+                    .map_err(|_| ASTError::Custom(id, "Incorrect argument count"))?;
+
+                let start = ast
+                    .extract_primitive_from_environment(start_binder)
+                    .and_then(|p| p.extract_number())?;
+                let end = ast
+                    .extract_primitive_from_environment(end_binder)
+                    .and_then(|p| p.extract_number())?;
+
+                let bytes = match ast.extract_primitive_from_environment(byte_array_binder)? {
+                    Primitive::Bytes(bytes) => bytes,
+                    _ => return Err(ASTError::TypeError(id, "Expected Bytes")),
+                };
+
+                let slice = bytes
+                    .get(start..end)
+                    .ok_or(ASTError::Custom(id, "Slice out of bounds"))?
+                    .to_vec();
+
+                let node = ast.graph.add_node(Node::Primitive(Primitive::Bytes(slice)));
+
+                ast.migrate_node(id, node)?;
+                ast.remove_node(id);
+
+                Ok(node)
+            }
+            Self::Concat => {
+                let [suffix_binder, byte_array_binder] = binders
+                    .try_into()
+                    .map_err(|_| ASTError::Custom(id, "Incorrect argument count"))?;
+
+                let suffix = match ast.extract_primitive_from_environment(suffix_binder)? {
+                    Primitive::Bytes(bytes) => bytes,
+                    _ => return Err(ASTError::TypeError(id, "Expected Bytes")),
+                };
+                let mut bytes = ast.take_bytes(byte_array_binder)?;
+
+                bytes.extend(suffix);
+
+                let node = ast.graph.add_node(Node::Primitive(Primitive::Bytes(bytes)));
+
+                ast.migrate_node(id, node)?;
+                ast.remove_node(id);
+
+                Ok(node)
+            }
+            Self::FromNumber => {
+                let [number_binder] = binders
+                    .try_into()
+                    .map_err(|_| ASTError::Custom(id, "Incorrect argument count"))?;
+
+                let number = ast
+                    .extract_primitive_from_environment(number_binder)
+                    .and_then(|p| p.extract_number())?;
+
+                let node = ast.graph.add_node(Node::Primitive(Primitive::Bytes(
+                    number.to_string().into_bytes(),
+                )));
+
+                ast.migrate_node(id, node)?;
+                ast.remove_node(id);
+
+                Ok(node)
+            }
+            Self::ToNumber => {
+                let [byte_array_binder] = binders
+                    .try_into()
+                    .map_err(|_| ASTError::Custom(id, "Incorrect argument count"))?;
+
+                let bytes = match ast.extract_primitive_from_environment(byte_array_binder)? {
+                    Primitive::Bytes(bytes) => bytes,
+                    _ => return Err(ASTError::TypeError(id, "Expected Bytes")),
+                };
+
+                let number = str::from_utf8(&bytes)
+                    .ok()
+                    // `parse` would also accept a sign
+                    .filter(|text| !text.starts_with('+'))
+                    .and_then(|text| text.parse::<Number>().ok())
+                    .ok_or(ASTError::Custom(id, "Bytes is not a decimal number"))?;
+
+                let node = ast
+                    .graph
+                    .add_node(Node::Primitive(Primitive::Number(number)));
+
+                ast.migrate_node(id, node)?;
+                ast.remove_node(id);
+
+                Ok(node)
+            }
+        }
+    }
+}
+
+impl AST {
+    /// Bytes bound by `binder`, to be modified by the caller.
+    /// Moved out of the graph on their last use, otherwise copied and counted in `stats`
+    fn take_bytes(&mut self, binder: NodeIndex) -> ASTResult<Vec<u8>> {
+        let (parameter, is_dangling) = self.evaluate_closure_parameter(binder)?;
+        if is_dangling {
+            return match self.remove_node(parameter) {
+                Some(Node::Primitive(Primitive::Bytes(bytes))) => Ok(bytes),
+                _ => Err(ASTError::TypeError(parameter, "Expected Bytes")),
+            };
         }
+        match self.node(parameter)? {
+            Node::Primitive(Primitive::Bytes(bytes)) => {
+                let bytes = bytes.clone();
+                self.stats.bytes_copies += 1;
+                Ok(bytes)
+            }
+            _ => Err(ASTError::TypeError(parameter, "Expected Bytes")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{EvalConfig, parse};
+
+    fn bytes_copies(source: &str) -> usize {
+        let value = parse(source)
+            .unwrap()
+            .evaluate(EvalConfig::default())
+            .unwrap();
+        value.ast().stats().bytes_copies
+    }
+
+    #[test]
+    fn only_shared_bytes_are_copied() {
+        assert_eq!(
+            bytes_copies("#bytes_len (#bytes_push 1 (#bytes_push 2 (#bytes_new 3)))"),
+            0
+        );
+        // Reading shared Bytes leaves them where they are
+        assert_eq!(
+            bytes_copies("let b #bytes_new 3 in + (#bytes_len b) (#bytes_get 0 b)"),
+            0
+        );
+        // Whichever of them runs first copies `b`, the other one takes it over
+        let source = "let b #bytes_new 3 in
+            + (#bytes_len (#bytes_push 1 b)) (#bytes_len (#bytes_pop b))";
+        assert_eq!(bytes_copies(source), 1);
     }
 }
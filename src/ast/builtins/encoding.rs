@@ -0,0 +1,153 @@
+use petgraph::graph::NodeIndex;
+
+use crate::ast::{AST, ASTError, ASTResult, Edge, Node, builtins::ConstructorTag, types::Type};
+
+/// Conversions between native values and their encodings as plain lambdas
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EncodingTag {
+    /// `λf x.f (f x)` for 2
+    NumberToChurch,
+    NumberFromChurch,
+    /// `λs z.s predecessor`, with `λs z.z` for 0
+    NumberToScott,
+    NumberFromScott,
+    /// `λc1 c2.c2 field`, for the second of `constructors` with one field
+    DataToScott,
+    DataFromScott,
+}
+
+impl EncodingTag {
+    pub fn argument_names(&self) -> Vec<&'static str> {
+        match self {
+            Self::NumberToChurch | Self::NumberToScott => vec!["number"],
+            Self::NumberFromChurch => vec!["church"],
+            Self::NumberFromScott => vec!["scott"],
+            Self::DataToScott => vec!["constructors", "value"],
+            Self::DataFromScott => vec!["constructors", "scott"],
+        }
+    }
+
+    pub fn signature(&self) -> Type {
+        match self {
+            Self::NumberToChurch | Self::NumberToScott => Type::function([Type::Number], Type::Dyn),
+            Self::NumberFromChurch | Self::NumberFromScott => {
+                Type::function([Type::Dyn], Type::Number)
+            }
+            Self::DataToScott => Type::function([Type::Data, Type::Data], Type::Dyn),
+            Self::DataFromScott => Type::function([Type::Data, Type::Dyn], Type::Data),
+        }
+    }
+
+    /// Conversions are plain lambo functions, given the arguments in order
+    fn conversion(&self) -> Option<&'static str> {
+        match self {
+            Self::NumberToChurch => Some("λn f x.=num 0 n x (f (#num_to_church (- 1 n) f x))"),
+            Self::NumberFromChurch => Some("λchurch.church (+ 1) 0"),
+            Self::NumberToScott => Some("λn s z.=num 0 n z (s (#num_to_scott (- 1 n)))"),
+            Self::NumberFromScott => {
+                Some("λscott.scott (λpredecessor.+ 1 (#num_from_scott predecessor)) 0")
+            }
+            Self::DataFromScott => Some(
+                "λconstructors scott.#match #cons (λconstructor rest.#data_from_scott rest (scott constructor)) (λ_.scott) constructors",
+            ),
+            Self::DataToScott => None,
+        }
+    }
+
+    pub fn evaluate(&self, ast: &mut AST, id: NodeIndex) -> ASTResult<NodeIndex> {
+        let binders = ConstructorTag::get_binders(ast, id)?;
+
+        if let Some(source) = self.conversion() {
+            let mut application = ast.add_expr_from_str(source)?;
+            for binder in binders {
+                let argument = ast.add_bound_variable(binder);
+                application = ast.apply(application, argument);
+            }
+            ast.migrate_node(id, application)?;
+            ast.remove_node(id);
+            return Ok(application);
+        }
+
+        let [constructors_binder, value_binder] = binders
+            .try_into()
+            .map_err(|_| ASTError::Custom(id, "Incorrect argument count"))?;
+        let (value, is_dangling) = ast.evaluate_closure_parameter(value_binder)?;
+        if is_dangling {
+            ast.in_flight.push(value);
+        }
+        let constructors = ast.constructor_tags(constructors_binder);
+        if is_dangling {
+            ast.in_flight.pop();
+        }
+
+        let tag = match *ast.node(value)? {
+            Node::Data {
+                tag: tag @ ConstructorTag::CustomTag { .. },
+            } => tag,
+            _ => return Err(ASTError::TypeError(value, "Expected a constructor")),
+        };
+        let fields = ConstructorTag::get_binders(ast, value)?;
+        if fields.len() != tag.arity() {
+            return Err(ASTError::TypeError(
+                value,
+                "Constructor is not fully applied",
+            ));
+        }
+        if is_dangling {
+            ast.remove_node(value);
+        }
+
+        // Fields stay shared with their closures, like #match does
+        let fields = fields
+            .into_iter()
+            .map(|binder| ast.add_bound_variable(binder))
+            .collect::<Vec<_>>();
+        let result =
+            ast.encode_scott_data(tag, &fields, &constructors?)
+                .ok_or(ASTError::Custom(
+                    id,
+                    "Constructor is not one of the constructors",
+                ))?;
+
+        ast.migrate_node(id, result)?;
+        ast.remove_node(id);
+        Ok(result)
+    }
+}
+
+impl AST {
+    /// Tags of the constructors in the list bound by `binder`, in order
+    fn constructor_tags(&mut self, binder: NodeIndex) -> ASTResult<Vec<ConstructorTag>> {
+        let (list, is_dangling) = self.evaluate_closure_parameter(binder)?;
+        if is_dangling {
+            self.in_flight.push(list);
+        }
+        let mut tags = vec![];
+        let result = loop {
+            let head = match self.list_cell(list, tags.len()) {
+                Ok((Some([head, _]), _)) => head,
+                Ok((None, _)) => break Ok(()),
+                Err(error) => break Err(error),
+            };
+            let constructor = match self.evaluate(self.follow_edge(head, Edge::Parameter)?) {
+                Ok(under_closures) => {
+                    self.lift_closure_chain(head, under_closures, Edge::Parameter)?;
+                    self.follow_edge(head, Edge::Parameter)?
+                }
+                Err(error) => break Err(error),
+            };
+            match *self.node(constructor)? {
+                Node::Data {
+                    tag: tag @ ConstructorTag::CustomTag { .. },
+                } => tags.push(tag),
+                _ => break Err(ASTError::TypeError(constructor, "Expected a constructor")),
+            }
+        };
+        if is_dangling {
+            self.in_flight.pop();
+            self.remove_node(list);
+        }
+        result.map(|_| tags)
+    }
+}
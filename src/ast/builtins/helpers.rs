@@ -1,11 +1,54 @@
-use crate::ast::{AST, ASTError, ASTResult, Edge, Node, VariableKind, builtins::ConstructorTag};
+use crate::ast::{
+    AST, ASTError, ASTResult, Edge, Node, Number, Primitive, VariableKind,
+    builtins::ConstructorTag, types::Type,
+};
 use petgraph::graph::NodeIndex;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum HelperFunctionTag {
     /// Meta-constructor to create constructors at runtime
     CreateConstructor,
     Match,
+    /// Name of the runtime type of a value, e.g `"Number"` or `"Function"`
+    TypeOf,
+    /// Value itself if `#typeof` it is the expected name, inserted by `AST::insert_casts`
+    ExpectType,
+    /// Value itself, after a full garbage collection
+    CollectGarbage,
+    /// 64-bit FNV-1a hash of a Bytes or Number, the same on every run and platform
+    Hash,
+}
+
+/// FNV-1a, simple and stable rather than resistant to collisions crafted on purpose
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+impl AST {
+    /// Name of the type of evaluated value `id`, see `Type::runtime_name`
+    fn runtime_type(&self, id: NodeIndex) -> ASTResult<&'static str> {
+        let mut current = id;
+        // Evaluated lambdas may sit under the closures of their environment
+        while let Node::Closure { .. } = self.node(current)? {
+            current = self.follow_edge(current, Edge::Body)?;
+        }
+        let result = match self.node(current)? {
+            Node::Primitive(Primitive::Number(_)) => Type::Number,
+            Node::Primitive(Primitive::Float(_)) => Type::Float,
+            Node::Primitive(Primitive::Bytes(_)) => Type::Bytes,
+            Node::Primitive(Primitive::String(_)) => Type::String,
+            Node::Lambda { .. } => Type::function([Type::Dyn], Type::Dyn),
+            Node::Data { tag } => {
+                let applied = ConstructorTag::get_binders(self, current)?.len();
+                tag.signature().skip_arguments(applied)
+            }
+            _ => Type::Dyn,
+        };
+        Ok(result.runtime_name())
+    }
 }
 
 impl HelperFunctionTag {
@@ -13,11 +56,26 @@ impl HelperFunctionTag {
         match self {
             Self::CreateConstructor => vec!["arity"],
             Self::Match => vec!["constructor", "transform", "fallback", "value"],
+            Self::TypeOf => vec!["value"],
+            Self::ExpectType => vec!["type", "value"],
+            Self::CollectGarbage => vec!["value"],
+            Self::Hash => vec!["value"],
+        }
+    }
+
+    pub fn signature(&self) -> Type {
+        match self {
+            Self::CreateConstructor => Type::function([Type::Number], Type::Dyn),
+            Self::Match => Type::function(vec![Type::Dyn; 4], Type::Dyn),
+            Self::TypeOf => Type::function([Type::Dyn], Type::Bytes),
+            Self::ExpectType => Type::function([Type::Bytes, Type::Dyn], Type::Dyn),
+            Self::CollectGarbage => Type::function([Type::Dyn], Type::Dyn),
+            Self::Hash => Type::function([Type::Dyn], Type::Number),
         }
     }
 
     pub fn evaluate(&self, ast: &mut AST, id: NodeIndex) -> ASTResult<NodeIndex> {
-        let binders = ConstructorTag::get_binders(ast, id);
+        let binders = ConstructorTag::get_binders(ast, id)?;
         match self {
             Self::CreateConstructor => {
                 let [arity_binder] = binders.try_into().map_err(|_| {
@@ -33,10 +91,79 @@ impl HelperFunctionTag {
                 };
 
                 let constructor = ast.graph.add_node(Node::Data { tag });
-                ast.migrate_node(id, constructor);
-                ast.graph.remove_node(id);
+                ast.migrate_node(id, constructor)?;
+                ast.remove_node(id);
                 Ok(constructor)
             }
+            Self::TypeOf => {
+                let [value_binder] = binders
+                    .try_into()
+                    .map_err(|_| ASTError::Custom(id, "Incorrect argument count for TypeOf"))?;
+                let (value, is_value_dangling) = ast.evaluate_closure_parameter(value_binder)?;
+                let name = ast.runtime_type(value)?;
+                if is_value_dangling {
+                    ast.remove_subtree(value);
+                }
+
+                let result = ast
+                    .graph
+                    .add_node(Node::Primitive(Primitive::Bytes(name.into())));
+                ast.migrate_node(id, result)?;
+                ast.remove_node(id);
+                Ok(result)
+            }
+            Self::ExpectType => {
+                let [type_binder, value_binder] = binders
+                    .try_into()
+                    .map_err(|_| ASTError::Custom(id, "Incorrect argument count for ExpectType"))?;
+                let expected = match ast.extract_primitive_from_environment(type_binder)? {
+                    Primitive::Bytes(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+                    _ => return Err(ASTError::TypeError(id, "Expected Bytes")),
+                };
+                let (value, is_value_dangling) = ast.evaluate_closure_parameter(value_binder)?;
+                let actual = ast.runtime_type(value)?;
+                if actual != expected {
+                    return Err(ASTError::CastFailed(value, expected, actual));
+                }
+
+                let value = if is_value_dangling {
+                    value
+                } else {
+                    let value_var = ast.graph.add_node(Node::Variable(VariableKind::Bound));
+                    ast.graph.add_edge(value_var, value_binder, Edge::Binder(0));
+                    value_var
+                };
+                ast.migrate_node(id, value)?;
+                ast.remove_node(id);
+                Ok(value)
+            }
+            Self::CollectGarbage => {
+                let [value_binder] = binders.try_into().map_err(|_| {
+                    ASTError::Custom(id, "Incorrect argument count for CollectGarbage")
+                })?;
+                ast.collect_all()?;
+
+                let value = ast.graph.add_node(Node::Variable(VariableKind::Bound));
+                ast.graph.add_edge(value, value_binder, Edge::Binder(0));
+                ast.migrate_node(id, value)?;
+                ast.remove_node(id);
+                Ok(value)
+            }
+            Self::Hash => {
+                let [value_binder] = binders
+                    .try_into()
+                    .map_err(|_| ASTError::Custom(id, "Incorrect argument count for Hash"))?;
+                let hash = match ast.extract_primitive_from_environment(value_binder)? {
+                    Primitive::Number(number) => fnv1a(&(number as u64).to_le_bytes()),
+                    Primitive::Bytes(bytes) => fnv1a(&bytes),
+                    _ => return Err(ASTError::TypeError(id, "Expected Bytes or Number")),
+                };
+
+                let result = ast.encode_usize(hash as Number);
+                ast.migrate_node(id, result)?;
+                ast.remove_node(id);
+                Ok(result)
+            }
             Self::Match => {
                 let [constructor, transform, fallback, value_binder] = binders
                     .as_slice()
@@ -46,37 +173,51 @@ impl HelperFunctionTag {
                 // We are strict only in constructor and value
                 let (constructor, _is_constructor_dangling) =
                     ast.evaluate_closure_parameter(constructor)?;
-                let (value, is_value_dangling) = ast.evaluate_closure_parameter(value_binder)?;
+                // Dangling constructor is detached, it must survive evaluation of the value
+                ast.in_flight.push(constructor);
+                let value = ast.evaluate_closure_parameter(value_binder);
+                ast.in_flight.pop();
+                let (value, is_value_dangling) = value?;
 
-                let value_tag_uid = match ast.graph.node_weight(value).unwrap() {
+                let value_tag_uid = match ast.node(value)? {
                     Node::Data {
                         tag: ConstructorTag::CustomTag { uid, .. },
                     } => uid,
-                    _ => return Err(ASTError::Custom(value, "Not a data constructor")),
+                    _ => return Err(ASTError::TypeError(value, "Not a data constructor")),
                 };
 
-                let (constructor_tag_uid, constructor_id) = {
+                let (constructor_tag_uid, _constructor_id) = {
                     let mut current = constructor;
                     loop {
-                        let edge = match ast.graph.node_weight(current).unwrap() {
+                        let edge = match ast.node(current)? {
                             Node::Closure { .. } | Node::Lambda { .. } => Edge::Body,
                             Node::Application => Edge::Function,
                             Node::Data { .. } => break,
-                            _ => unreachable!(),
+                            _ => {
+                                return Err(ASTError::TypeError(
+                                    constructor,
+                                    "Expected a data constructor",
+                                ));
+                            }
                         };
                         current = ast.follow_edge(current, edge)?;
                     }
-                    match ast.graph.node_weight(current).unwrap() {
+                    match ast.node(current)? {
                         Node::Data {
                             tag: ConstructorTag::CustomTag { uid, .. },
                             ..
                         } => (uid, current),
-                        _ => unreachable!(), // Not really
+                        _ => {
+                            return Err(ASTError::TypeError(
+                                current,
+                                "Only custom constructors can be matched",
+                            ));
+                        }
                     }
                 };
 
                 if constructor_tag_uid == value_tag_uid {
-                    let mut chain = ConstructorTag::get_binders(ast, value)
+                    let mut chain = ConstructorTag::get_binders(ast, value)?
                         .iter()
                         .map(|&constructor_binder| {
                             let var = ast.graph.add_node(Node::Variable(VariableKind::Bound));
@@ -89,7 +230,7 @@ impl HelperFunctionTag {
                         .collect::<Vec<_>>();
 
                     if is_value_dangling {
-                        ast.graph.remove_node(value);
+                        ast.remove_node(value);
                     }
 
                     let transform_var = ast.graph.add_node(Node::Variable(VariableKind::Bound));
@@ -102,9 +243,9 @@ impl HelperFunctionTag {
                     }
 
                     let head = *chain.first().unwrap();
-                    ast.migrate_node(id, head);
-                    ast.graph.remove_node(id);
-                    ast.evaluate(head)
+                    ast.migrate_node(id, head)?;
+                    ast.remove_node(id);
+                    Ok(head)
                 } else {
                     // Call fallback function with value again
                     // Such API allows easier chaining of #match expressions
@@ -124,11 +265,49 @@ impl HelperFunctionTag {
                         .add_edge(application, fallback_var, Edge::Function);
                     ast.graph.add_edge(application, value, Edge::Parameter);
 
-                    ast.migrate_node(id, application);
-                    ast.graph.remove_node(id);
-                    ast.evaluate(application)
+                    ast.migrate_node(id, application)?;
+                    ast.remove_node(id);
+                    Ok(application)
                 }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{EvalConfig, parse};
+
+    fn evaluate(source: &str, full_laziness: bool) -> Option<usize> {
+        let config = EvalConfig {
+            full_laziness,
+            ..EvalConfig::default()
+        };
+        parse(source).unwrap().evaluate(config).unwrap().to_usize()
+    }
+
+    /// Constructors don't get their identity from the name they are bound to
+    #[test]
+    fn constructors_are_distinct() {
+        let source = "let mk \\u.
+              let a #constructor 1 in let b #constructor 1 in #match a (\\x.x) (\\v.999) (b 5)
+            in mk 0";
+        for full_laziness in [false, true] {
+            assert_eq!(
+                evaluate(source, full_laziness),
+                Some(999),
+                "{full_laziness}"
+            );
+        }
+        let source = "let f \\u.let a #constructor 1 in a in
+            let g \\u.let a #constructor 1 in a in #match (f 0) (\\x.x) (\\v.999) (g 0 5)";
+        assert_eq!(evaluate(source, false), Some(999));
+    }
+
+    #[test]
+    fn data_constructors_are_shared_with_builtins() {
+        let source = "data Option = some value | none in
+            match (#bytes_try_get 1 \"abc\") { some byte -> byte, none -> 0 }";
+        assert_eq!(evaluate(source, false), Some(98));
+    }
+}
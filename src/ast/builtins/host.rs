@@ -0,0 +1,75 @@
+use std::{fmt::Debug, sync::Arc};
+
+use petgraph::graph::NodeIndex;
+
+use crate::ast::{AST, ASTError, ASTResult, Node, Primitive, builtins::ConstructorTag};
+
+type Function = dyn Fn(&[Primitive]) -> Result<Primitive, String> + Send + Sync;
+
+/// Rust function callable from lambo programs by name
+#[derive(Clone)]
+pub struct HostFunction {
+    name: Arc<String>,
+    arity: usize,
+    function: Arc<Function>,
+}
+
+impl Debug for HostFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "HostFunction({}, arity {})", self.name, self.arity)
+    }
+}
+
+impl AST {
+    /// Make `function` available to programs parsed into this AST afterwards, see `add_expr_from_str`.
+    /// It is strict in all of its `arity` (at least 1) arguments, which are evaluated to primitives.
+    /// Returned error message aborts the evaluation.
+    pub fn register_function(
+        &mut self,
+        name: &str,
+        arity: usize,
+        function: impl Fn(&[Primitive]) -> Result<Primitive, String> + Send + Sync + 'static,
+    ) {
+        self.host_functions.push(HostFunction {
+            name: Arc::new(name.to_string()),
+            arity,
+            function: Arc::new(function),
+        });
+    }
+
+    /// Latest function registered under `name`
+    pub(crate) fn host_function_tag(&self, name: &str) -> Option<ConstructorTag> {
+        let index = self
+            .host_functions
+            .iter()
+            .rposition(|function| *function.name == name)?;
+        Some(ConstructorTag::HostFunction {
+            index,
+            arity: self.host_functions[index].arity,
+        })
+    }
+
+    pub(super) fn call_host_function(
+        &mut self,
+        index: usize,
+        id: NodeIndex,
+    ) -> ASTResult<NodeIndex> {
+        let function = self
+            .host_functions
+            .get(index)
+            .ok_or(ASTError::Custom(id, "Unknown host function"))?
+            .function
+            .clone();
+
+        let arguments = ConstructorTag::get_binders(self, id)?
+            .into_iter()
+            .map(|binder| self.extract_primitive_from_environment(binder))
+            .collect::<ASTResult<Vec<_>>>()?;
+
+        let result = function(&arguments).map_err(|message| ASTError::Host(id, message))?;
+        let result = self.graph.add_node(Node::Primitive(result));
+        self.migrate_node(id, result)?;
+        self.remove_subtree(id);
+        Ok(result)
+    }
+}
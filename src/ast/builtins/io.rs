@@ -1,96 +1,395 @@
-use std::{io::stdin, rc::Rc};
+use std::{
+    fmt::Debug,
+    io::{self, Read, Write, stdin, stdout},
+    process::{Command, Stdio},
+    sync::Arc,
+};
 
 use petgraph::graph::NodeIndex;
 
 use crate::ast::{
-    builtins::ConstructorTag, ASTError, ASTResult, Edge, Node, Primitive, VariableKind, AST,
+    builtins::ConstructorTag, marshal::FromLambo, record::PAIR, types::Type, ASTError, ASTResult, Edge, Node,
+    Number, Primitive, VariableKind, AST,
 };
 
+/// Performs the actual side effects requested by IO builtins.
+/// Every effect a program performs goes through here, so a handler can intercept it,
+/// or veto it by returning an error which aborts the evaluation.
+/// Handlers travel with the program, so they must be safe to send to another thread.
+pub trait EffectHandler: Debug + Send + Sync {
+    fn handle_read(&mut self) -> io::Result<String>;
+    /// Everything left to read as raw bytes. By default line by line until `handle_read`
+    /// gives nothing, for handlers that only deal in text
+    fn handle_read_all(&mut self) -> io::Result<Vec<u8>> {
+        let mut bytes = vec![];
+        loop {
+            let line = self.handle_read()?;
+            if line.is_empty() {
+                return Ok(bytes);
+            }
+            bytes.extend(line.into_bytes());
+        }
+    }
+    fn handle_print(&mut self, text: &str) -> io::Result<()>;
+    /// Run `command` with `arguments` to completion, returning its stdout and exit code
+    fn handle_exec(&mut self, _command: &str, _arguments: &[String]) -> io::Result<(Vec<u8>, i32)> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "this IO handler can't run commands",
+        ))
+    }
+    /// Program threw `message` with `#io_throw`, evaluation fails with it right after
+    /// unless an enclosing `#io_catch` handles it
+    fn handle_throw(&mut self, _message: &str) {}
+    fn clone_box(&self) -> Box<dyn EffectHandler>;
+}
+
+impl Clone for Box<dyn EffectHandler> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+/// Talks to the real STDIN/STDOUT
+#[derive(Debug, Clone, Default)]
+pub struct StdIO;
+
+impl EffectHandler for StdIO {
+    fn handle_read(&mut self) -> io::Result<String> {
+        let mut line = String::new();
+        stdin().read_line(&mut line)?;
+        Ok(line)
+    }
+    /// Not necessarily UTF-8, e.g a binary file piped in
+    fn handle_read_all(&mut self) -> io::Result<Vec<u8>> {
+        let mut bytes = vec![];
+        stdin().read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+    fn handle_print(&mut self, text: &str) -> io::Result<()> {
+        print!("{text}");
+        stdout().flush()
+    }
+    fn handle_exec(&mut self, command: &str, arguments: &[String]) -> io::Result<(Vec<u8>, i32)> {
+        let output = Command::new(command)
+            .args(arguments)
+            .stderr(Stdio::inherit())
+            .output()?;
+        let code = output
+            .status
+            .code()
+            .ok_or_else(|| io::Error::other(format!("{command} was terminated by a signal")))?;
+        Ok((output.stdout, code))
+    }
+    fn clone_box(&self) -> Box<dyn EffectHandler> {
+        Box::new(self.clone())
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum IOTag {
+    /// `value` as is, without any side effects
+    Pure,
     ReadLine,
+    /// Line of lambo source, parsed into a new term
+    ReadLambda,
+    /// Rest of the input up to its end
+    ReadAll,
     Print,
+    /// Prints `value` formatted as an expression and returns it
+    Debug,
+    /// `(stdout, exit code)` of a command
+    Exec,
+    Throw,
+    /// Runs `io`, or `handler` applied to the message if it throws
+    Catch,
     Flatmap,
 }
 
 impl IOTag {
     pub fn argument_names(&self) -> Vec<&'static str> {
         match self {
-            IOTag::ReadLine => vec![],
+            IOTag::ReadLine | IOTag::ReadLambda | IOTag::ReadAll => vec![],
+            IOTag::Pure | IOTag::Debug => vec!["value"],
             IOTag::Print => vec!["bytes"],
+            IOTag::Exec => vec!["command", "arguments"],
+            IOTag::Throw => vec!["message"],
+            IOTag::Catch => vec!["handler", "io"],
             IOTag::Flatmap => vec!["transform", "io"],
         }
     }
 
+    /// Fields of the records it returns, `#io_exec` gives stdout and the exit code
+    pub fn record_shape(&self) -> Option<&'static [&'static str]> {
+        match self {
+            IOTag::Exec => Some(PAIR),
+            _ => None,
+        }
+    }
+
+    pub fn signature(&self) -> Type {
+        match self {
+            IOTag::ReadLine | IOTag::ReadLambda | IOTag::ReadAll => Type::IO,
+            IOTag::Pure | IOTag::Debug => Type::function([Type::Dyn], Type::IO),
+            IOTag::Print | IOTag::Throw => Type::function([Type::Bytes], Type::IO),
+            IOTag::Exec => Type::function([Type::Bytes, Type::Data], Type::IO),
+            IOTag::Flatmap => {
+                Type::function([Type::function([Type::Dyn], Type::IO), Type::IO], Type::IO)
+            }
+            IOTag::Catch => Type::function(
+                [Type::function([Type::Bytes], Type::IO), Type::IO],
+                Type::IO,
+            ),
+        }
+    }
+
     pub fn run(&self, ast: &mut AST, id: NodeIndex) -> ASTResult<NodeIndex> {
+        self.perform(ast, id)
+            .map_err(|error| ConstructorTag::IO(*self).explain(error))
+    }
+
+    fn perform(&self, ast: &mut AST, id: NodeIndex) -> ASTResult<NodeIndex> {
         match self {
+            IOTag::Pure => {
+                let [value_binder] = ConstructorTag::get_binders(ast, id)?
+                    .try_into()
+                    .map_err(|_| ASTError::Custom(id, "Incorrect argument count"))?;
+
+                Ok(ast.add_bound_variable(value_binder))
+            }
             IOTag::ReadLine => {
-                let mut line = String::new();
-                stdin().read_line(&mut line).unwrap();
+                let line = ast.io.handle_read().map_err(ASTError::IO)?;
 
                 Ok(ast
                     .graph
                     .add_node(Node::Primitive(Primitive::Bytes(line.into()))))
             }
+            IOTag::ReadLambda => {
+                let line = ast.io.handle_read().map_err(ASTError::IO)?;
+
+                Ok(ast.add_expr_from_str(&line)?)
+            }
+            IOTag::ReadAll => {
+                let bytes = ast.io.handle_read_all().map_err(ASTError::IO)?;
+
+                Ok(ast.graph.add_node(Node::Primitive(Primitive::Bytes(bytes))))
+            }
             IOTag::Print => {
-                let binders = ConstructorTag::get_binders(ast, id);
-                let (bytes, is_bytes_dangling) = ast.evaluate_closure_parameter(binders[0])?;
+                let [bytes_binder] = ConstructorTag::get_binders(ast, id)?
+                    .try_into()
+                    .map_err(|_| ASTError::Custom(id, "Incorrect argument count"))?;
+                let (bytes, is_bytes_dangling) = ast.evaluate_closure_parameter(bytes_binder)?;
 
-                let value = match ast.graph.node_weight(bytes).unwrap() {
-                    Node::Primitive(Primitive::Bytes(bytes)) => bytes,
-                    _ => return Err(ASTError::Custom(bytes, "Expected Bytes")),
+                // Borrow graph directly to keep ast.io available
+                let value = match ast.graph.node_weight(bytes) {
+                    Some(Node::Primitive(Primitive::Bytes(bytes))) => bytes,
+                    _ => return Err(ASTError::TypeError(bytes, "Expected Bytes")),
                 };
 
-                print!(
-                    "{}",
-                    str::from_utf8(value)
-                        .map_err(|_| ASTError::Custom(bytes, "Bytes is not a valid utf8 string"))?
-                );
+                ast.io
+                    .handle_print(
+                        str::from_utf8(value).map_err(|_| {
+                            ASTError::Custom(bytes, "Bytes is not a valid utf8 string")
+                        })?,
+                    )
+                    .map_err(ASTError::IO)?;
                 if is_bytes_dangling {
-                    ast.graph.remove_node(bytes);
+                    ast.remove_node(bytes);
                 }
 
                 Ok(ast
                     .graph
-                    .add_node(Node::Variable(VariableKind::Free(Rc::new(
+                    .add_node(Node::Variable(VariableKind::Free(Arc::new(
                         "#io_print finished".to_string(),
                     )))))
             }
+            IOTag::Debug => {
+                let [value_binder] = ConstructorTag::get_binders(ast, id)?
+                    .try_into()
+                    .map_err(|_| ASTError::Custom(id, "Incorrect argument count"))?;
+                let value = ast.add_bound_variable(value_binder);
+                let value = ast.evaluate(value)?;
+
+                let expr = ast.fmt_expr(value)?;
+                ast.io
+                    .handle_print(&format!("{expr}\n"))
+                    .map_err(ASTError::IO)?;
+                Ok(value)
+            }
+            IOTag::Exec => {
+                let [command_binder, arguments_binder] = ConstructorTag::get_binders(ast, id)?
+                    .try_into()
+                    .map_err(|_| ASTError::Custom(id, "Incorrect argument count"))?;
+                let command = match ast.extract_primitive_from_environment(command_binder)? {
+                    Primitive::Bytes(bytes) => String::from_utf8(bytes)
+                        .map_err(|_| ASTError::Custom(id, "Bytes is not a valid utf8 string"))?,
+                    _ => return Err(ASTError::TypeError(id, "Expected Bytes")),
+                };
+                let arguments = ast.graph.add_node(Node::Variable(VariableKind::Bound));
+                ast.graph
+                    .add_edge(arguments, arguments_binder, Edge::Binder(0));
+                let arguments = Vec::<String>::from_lambo(ast, arguments)?;
+
+                let (stdout, code) = ast
+                    .io
+                    .handle_exec(&command, &arguments)
+                    .map_err(ASTError::IO)?;
+
+                let tag = ast.record_constructor(PAIR);
+                let stdout = ast
+                    .graph
+                    .add_node(Node::Primitive(Primitive::Bytes(stdout)));
+                let code = ast.encode_usize(code as Number);
+                Ok(ast.encode_data(tag, &[stdout, code]))
+            }
+            IOTag::Throw => {
+                let [message_binder] = ConstructorTag::get_binders(ast, id)?
+                    .try_into()
+                    .map_err(|_| ASTError::Custom(id, "Incorrect argument count"))?;
+                let message = match ast.extract_primitive_from_environment(message_binder)? {
+                    Primitive::Bytes(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+                    _ => return Err(ASTError::TypeError(id, "Expected Bytes")),
+                };
+
+                ast.io.handle_throw(&message);
+                Err(ASTError::Thrown(message))
+            }
+            IOTag::Catch => {
+                let [handler_binder, io_binder] = ConstructorTag::get_binders(ast, id)?
+                    .try_into()
+                    .map_err(|_| ASTError::Custom(id, "Incorrect argument count"))?;
+
+                // Keep the handler reachable while the IO runs
+                ast.in_flight.push(id);
+                let result = match Self::run_parameter(ast, id, io_binder) {
+                    Err(ASTError::Thrown(message)) => {
+                        let handler = ast.add_bound_variable(handler_binder);
+                        let message = ast
+                            .graph
+                            .add_node(Node::Primitive(Primitive::Bytes(message.into())));
+                        let application = ast.apply(handler, message);
+                        ast.evaluate(application)
+                            .and_then(|io| match ast.node(io)? {
+                                &Node::Data {
+                                    tag: ConstructorTag::IO(io_tag),
+                                } => io_tag.run(ast, io),
+                                _ => Err(ASTError::TypeError(id, "Expected IO")),
+                            })
+                    }
+                    result => result,
+                };
+                ast.in_flight.pop();
+                result
+            }
             IOTag::Flatmap => {
                 Err(ASTError::Custom(id, "#io_flatmap is not an effectful IO"))
             }
         }
     }
 
-    pub fn flatmap(ast: &mut AST, id: NodeIndex) -> ASTResult<NodeIndex> {
-        let binders = ConstructorTag::get_binders(ast, id);
-
-        let [trasform_binder, io_binder] = binders
-            .try_into()
-            .map_err(|_| ASTError::Custom(id, "Incorrect argument count"))?;
-
+    /// Evaluate the IO bound by `io_binder` and perform it
+    fn run_parameter(ast: &mut AST, id: NodeIndex, io_binder: NodeIndex) -> ASTResult<NodeIndex> {
         let (io, is_io_dangling) = ast.evaluate_closure_parameter(io_binder)?;
 
-        let io_result = match ast.graph.node_weight(io).unwrap() {
+        let io_result = match ast.node(io)? {
             &Node::Data {
                 tag: ConstructorTag::IO(io_tag),
-            } => io_tag.run(ast, io)?,
-            _ => return Err(ASTError::Custom(id, "Expected IO")),
+            } => io_tag.run(ast, io),
+            _ => Err(ASTError::TypeError(id, "Expected IO")),
         };
 
+        // Even if it failed, e.g threw for `#io_catch` to carry on with the handler
         if is_io_dangling {
-            ast.graph.remove_node(io);
+            ast.remove_node(io);
         }
+        io_result
+    }
+
+    pub fn flatmap(ast: &mut AST, id: NodeIndex) -> ASTResult<NodeIndex> {
+        let binders = ConstructorTag::get_binders(ast, id)?;
+
+        let [trasform_binder, io_binder] = binders
+            .try_into()
+            .map_err(|_| ASTError::Custom(id, "Incorrect argument count"))?;
+
+        let io_result = Self::run_parameter(ast, id, io_binder)?;
 
-        let (transform, _) = ast.evaluate_closure_parameter(trasform_binder)?;
+        // Result of the IO is detached until it's passed to the transform
+        ast.in_flight.push(io_result);
+        let transform = ast.evaluate_closure_parameter(trasform_binder);
+        ast.in_flight.pop();
+        let (transform, _) = transform?;
 
         let result = ast.graph.add_node(Node::Application);
         ast.graph.add_edge(result, transform, Edge::Function);
         ast.graph.add_edge(result, io_result, Edge::Parameter);
 
-        ast.migrate_node(id, result);
-        ast.graph.remove_node(id);
+        ast.migrate_node(id, result)?;
+        ast.remove_node(id);
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use crate::{
+        EvalConfig,
+        ast::{Node, builtins::ConstructorTag},
+        parse,
+    };
+
+    use super::{EffectHandler, IOTag};
+
+    /// Input that is not UTF-8, like a binary file
+    #[derive(Debug, Clone)]
+    struct Binary(Vec<u8>);
 
-        ast.evaluate(result)
+    impl EffectHandler for Binary {
+        fn handle_read(&mut self) -> io::Result<String> {
+            Err(io::ErrorKind::InvalidData.into())
+        }
+        fn handle_read_all(&mut self) -> io::Result<Vec<u8>> {
+            Ok(std::mem::take(&mut self.0))
+        }
+        fn handle_print(&mut self, _text: &str) -> io::Result<()> {
+            Ok(())
+        }
+        fn clone_box(&self) -> Box<dyn EffectHandler> {
+            Box::new(self.clone())
+        }
+    }
+
+    #[test]
+    fn read_all_keeps_bytes_as_is() {
+        let input = b"a\r\n\xff\0".to_vec();
+        let mut program = parse("#io_readall").unwrap();
+        program.ast.io = Box::new(Binary(input.clone()));
+        let value = program.evaluate(EvalConfig::default()).unwrap();
+        assert_eq!(value.to_bytes(), Some(&input[..]));
+    }
+
+    /// Thrown action is dropped once the handler takes over
+    #[test]
+    fn caught_action_is_removed() {
+        let source = "#io_catch (\\message.#io_pure 7) (#io_throw \"boom\")";
+        let value = parse(source)
+            .unwrap()
+            .evaluate(EvalConfig::default())
+            .unwrap();
+        assert_eq!(value.to_usize(), Some(7));
+
+        let thrown = value.ast().graph.node_weights().filter(|node| {
+            matches!(
+                node,
+                Node::Data {
+                    tag: ConstructorTag::IO(IOTag::Throw)
+                }
+            )
+        });
+        assert_eq!(thrown.count(), 0);
     }
 }
@@ -0,0 +1,473 @@
+use std::fmt::Write;
+
+use petgraph::graph::NodeIndex;
+
+use crate::ast::{
+    AST, ASTError, ASTResult, Node, Number, Primitive,
+    builtins::{ConstructorTag, arithmetic::format_float, list::continues_list},
+    marshal::FromLambo,
+    record::PAIR,
+    types::Type,
+};
+
+/// Builtins reading and writing JSON as `json_null`, `json_bool`, `json_number`, `json_string`,
+/// `json_array` and `json_object` constructors, declared the same way as `some` and `none`
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum JsonOpTag {
+    Parse,
+    Stringify,
+}
+
+impl JsonOpTag {
+    pub fn argument_names(&self) -> Vec<&'static str> {
+        match self {
+            Self::Parse => vec!["bytes"],
+            Self::Stringify => vec!["json"],
+        }
+    }
+
+    /// Fields of the records it builds, `#json_parse` gives object fields as name and value pairs
+    pub fn record_shape(&self) -> Option<&'static [&'static str]> {
+        match self {
+            Self::Parse => Some(PAIR),
+            Self::Stringify => None,
+        }
+    }
+
+    pub fn signature(&self) -> Type {
+        match self {
+            Self::Parse => Type::function([Type::Bytes], Type::Data),
+            Self::Stringify => Type::function([Type::Data], Type::Bytes),
+        }
+    }
+
+    pub fn evaluate(&self, ast: &mut AST, id: NodeIndex) -> ASTResult<NodeIndex> {
+        let [binder] = ConstructorTag::get_binders(ast, id)?
+            .try_into()
+            .map_err(|_| ASTError::Custom(id, "Incorrect argument count"))?;
+        match self {
+            Self::Parse => {
+                let bytes = match ast.extract_primitive_from_environment(binder)? {
+                    Primitive::Bytes(bytes) => bytes,
+                    _ => return Err(ASTError::TypeError(id, "Expected Bytes")),
+                };
+                let mut parser = Parser {
+                    bytes: &bytes,
+                    position: 0,
+                    depth: 0,
+                };
+                let result = parser
+                    .document(ast)
+                    .ok_or(ASTError::Custom(id, "Bytes is not valid JSON"))?;
+
+                ast.migrate_node(id, result)?;
+                ast.remove_node(id);
+                Ok(result)
+            }
+            Self::Stringify => {
+                let json = ast.add_bound_variable(binder);
+                let mut out = String::new();
+                ast.write_json(json, &mut out)?;
+
+                let result = ast.encode_str(&out);
+                ast.migrate_node(id, result)?;
+                ast.remove_node(id);
+                Ok(result)
+            }
+        }
+    }
+}
+
+/// Arrays and objects nested deeper than this are rejected, rather than overflowing the stack
+const MAX_DEPTH: usize = 512;
+
+/// Recursive descent over the JSON text, building values right in the graph.
+/// `None` on any syntax error, with whatever was built for the value removed again
+struct Parser<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    /// Arrays and objects the parser is inside of
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn document(&mut self, ast: &mut AST) -> Option<NodeIndex> {
+        let value = self.value(ast)?;
+        self.skip_whitespace();
+        if self.position != self.bytes.len() {
+            ast.remove_subtree(value);
+            return None;
+        }
+        Some(value)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.position).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, literal: &[u8]) -> Option<()> {
+        self.bytes[self.position..].starts_with(literal).then(|| {
+            self.position += literal.len();
+        })
+    }
+
+    fn value(&mut self, ast: &mut AST) -> Option<NodeIndex> {
+        self.skip_whitespace();
+        match self.peek()? {
+            b'n' => {
+                self.expect(b"null")?;
+                let tag = ast.named_constructor("json_null", 0);
+                Some(ast.encode_data(tag, &[]))
+            }
+            b't' | b'f' => {
+                let value = self.expect(b"true").is_some() || {
+                    self.expect(b"false")?;
+                    false
+                };
+                let tag = ast.named_constructor("json_bool", 1);
+                let value = ast.encode_bool(value);
+                Some(ast.encode_data(tag, &[value]))
+            }
+            b'"' => {
+                let tag = ast.named_constructor("json_string", 1);
+                let value = self.string()?;
+                let value = ast.encode_str(&value);
+                Some(ast.encode_data(tag, &[value]))
+            }
+            b'[' => {
+                self.position += 1;
+                let mut items = vec![];
+                self.nested(ast, &mut items, |parser, ast, items| {
+                    if !parser.close(b']') {
+                        loop {
+                            items.push(parser.value(ast)?);
+                            if parser.close(b']') {
+                                break;
+                            }
+                            parser.separator(b',')?;
+                        }
+                    }
+                    Some(())
+                })?;
+                let tag = ast.named_constructor("json_array", 1);
+                let items = ast.encode_native_list(&items);
+                Some(ast.encode_data(tag, &[items]))
+            }
+            b'{' => {
+                self.position += 1;
+                let pair = ast.record_constructor(PAIR);
+                let mut fields = vec![];
+                self.nested(ast, &mut fields, |parser, ast, fields| {
+                    if !parser.close(b'}') {
+                        loop {
+                            parser.skip_whitespace();
+                            let key = parser.string()?;
+                            parser.separator(b':')?;
+                            let value = parser.value(ast)?;
+                            let key = ast.encode_str(&key);
+                            fields.push(ast.encode_data(pair, &[key, value]));
+                            if parser.close(b'}') {
+                                break;
+                            }
+                            parser.separator(b',')?;
+                        }
+                    }
+                    Some(())
+                })?;
+                let tag = ast.named_constructor("json_object", 1);
+                let fields = ast.encode_native_list(&fields);
+                Some(ast.encode_data(tag, &[fields]))
+            }
+            _ => {
+                let tag = ast.named_constructor("json_number", 1);
+                let value = self.number(ast)?;
+                Some(ast.encode_data(tag, &[value]))
+            }
+        }
+    }
+
+    /// Parse the elements of an array or object into `elements` with `parse`, one level deeper.
+    /// If that fails, elements parsed so far are removed
+    fn nested(
+        &mut self,
+        ast: &mut AST,
+        elements: &mut Vec<NodeIndex>,
+        parse: impl FnOnce(&mut Self, &mut AST, &mut Vec<NodeIndex>) -> Option<()>,
+    ) -> Option<()> {
+        let result = if self.depth < MAX_DEPTH {
+            self.depth += 1;
+            let result = parse(self, ast, elements);
+            self.depth -= 1;
+            result
+        } else {
+            None
+        };
+        if result.is_none() {
+            for &element in elements.iter() {
+                ast.remove_subtree(element);
+            }
+        }
+        result
+    }
+
+    /// Whether the next token is `bracket`, consuming it if so
+    fn close(&mut self, bracket: u8) -> bool {
+        self.skip_whitespace();
+        let is_closed = self.peek() == Some(bracket);
+        if is_closed {
+            self.position += 1;
+        }
+        is_closed
+    }
+
+    fn separator(&mut self, separator: u8) -> Option<()> {
+        self.skip_whitespace();
+        self.expect(&[separator])
+    }
+
+    /// Non-negative integers become Numbers, anything else (or too big) Floats
+    fn number(&mut self, ast: &mut AST) -> Option<NodeIndex> {
+        let start = self.position;
+        while let Some(b'0'..=b'9' | b'-' | b'+' | b'.' | b'e' | b'E') = self.peek() {
+            self.position += 1;
+        }
+        let text = str::from_utf8(&self.bytes[start..self.position]).ok()?;
+        let is_integer = !text.is_empty() && text.bytes().all(|byte| byte.is_ascii_digit());
+        if is_integer
+            && (text == "0" || !text.starts_with('0'))
+            && let Ok(number) = text.parse::<Number>()
+        {
+            return Some(ast.encode_usize(number));
+        }
+        // Rust accepts a few forms JSON doesn't, like `1.` or `+1`
+        let digits = text.strip_prefix('-').unwrap_or(text);
+        let (mantissa, _) = digits.split_once(['e', 'E']).unwrap_or((digits, ""));
+        let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, "0"));
+        let is_valid = !integer.is_empty()
+            && (integer == "0" || !integer.starts_with('0'))
+            && !fraction.is_empty()
+            && integer
+                .bytes()
+                .chain(fraction.bytes())
+                .all(|b| b.is_ascii_digit());
+        if !is_valid {
+            return None;
+        }
+        text.parse::<f64>().ok().map(|float| ast.encode_f64(float))
+    }
+
+    fn string(&mut self) -> Option<String> {
+        self.expect(b"\"")?;
+        let mut bytes = vec![];
+        loop {
+            match *self.bytes.get(self.position)? {
+                b'"' => {
+                    self.position += 1;
+                    return String::from_utf8(bytes).ok();
+                }
+                b'\\' => {
+                    let escaped = *self.bytes.get(self.position + 1)?;
+                    self.position += 2;
+                    let character = match escaped {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.unicode_escape()?,
+                        _ => return None,
+                    };
+                    bytes.extend_from_slice(character.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                0x00..0x20 => return None,
+                byte => {
+                    bytes.push(byte);
+                    self.position += 1;
+                }
+            }
+        }
+    }
+
+    /// Character of `\uXXXX`, with the `u` already consumed, joining surrogate pairs
+    fn unicode_escape(&mut self) -> Option<char> {
+        let high = self.code_unit()?;
+        if !(0xd800..0xdc00).contains(&high) {
+            return char::from_u32(high);
+        }
+        self.expect(b"\\u")?;
+        let low = self
+            .code_unit()
+            .filter(|low| (0xdc00..0xe000).contains(low))?;
+        char::from_u32(0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00))
+    }
+
+    fn code_unit(&mut self) -> Option<u32> {
+        let hex = str::from_utf8(self.bytes.get(self.position..self.position + 4)?).ok()?;
+        self.position += 4;
+        u32::from_str_radix(hex, 16).ok()
+    }
+}
+
+impl AST {
+    /// `#cons` list of already encoded items
+    fn encode_native_list(&mut self, items: &[NodeIndex]) -> NodeIndex {
+        let cons = self.named_constructor("cons", 2);
+        let nil = self.named_constructor("nil", 0);
+        let mut list = self.encode_data(nil, &[]);
+        for &item in items.iter().rev() {
+            list = self.encode_data(cons, &[item, list]);
+        }
+        list
+    }
+
+    /// Evaluate JSON constructor `id` all the way down and write it out
+    fn write_json(&mut self, id: NodeIndex, out: &mut String) -> ASTResult<()> {
+        let (tag, fields) = self.decode_data(id)?;
+        let name = self
+            .constructors
+            .iter()
+            .find(|&(_, &constructor)| constructor == tag)
+            .map(|(name, _)| name.to_string());
+        match (name.as_deref(), fields.as_slice()) {
+            (Some("json_null"), []) => out.push_str("null"),
+            (Some("json_bool"), [value]) => out.push_str(if bool::from_lambo(self, *value)? {
+                "true"
+            } else {
+                "false"
+            }),
+            (Some("json_number"), [value]) => {
+                let value = self.evaluate(*value)?;
+                match self.node(value)? {
+                    Node::Primitive(Primitive::Number(number)) => write!(out, "{number}").unwrap(),
+                    Node::Primitive(Primitive::Float(float)) if float.is_finite() => {
+                        out.push_str(&format_float(*float))
+                    }
+                    _ => return Err(ASTError::TypeError(value, "Expected a finite number")),
+                }
+            }
+            (Some("json_string"), [value]) => {
+                let value = String::from_lambo(self, *value)?;
+                write_json_string(&value, out);
+            }
+            (Some("json_array"), [items]) => {
+                out.push('[');
+                for (index, item) in self.json_list(*items)?.into_iter().enumerate() {
+                    if index > 0 {
+                        out.push(',');
+                    }
+                    self.write_json(item, out)?;
+                }
+                out.push(']');
+            }
+            (Some("json_object"), [fields]) => {
+                out.push('{');
+                for (index, field) in self.json_list(*fields)?.into_iter().enumerate() {
+                    if index > 0 {
+                        out.push(',');
+                    }
+                    let (_, pair) = self.decode_data(field)?;
+                    let [key, value] = pair
+                        .try_into()
+                        .map_err(|_| ASTError::TypeError(field, "Expected a pair"))?;
+                    write_json_string(&String::from_lambo(self, key)?, out);
+                    out.push(':');
+                    self.write_json(value, out)?;
+                }
+                out.push('}');
+            }
+            _ => return Err(ASTError::TypeError(id, "Expected a JSON constructor")),
+        }
+        Ok(())
+    }
+
+    /// Items of list `id`, not evaluated yet
+    fn json_list(&mut self, id: NodeIndex) -> ASTResult<Vec<NodeIndex>> {
+        let mut cons = None;
+        let mut items = vec![];
+        let mut current = id;
+        loop {
+            let (tag, fields) = self.decode_data(current)?;
+            match continues_list(tag, &mut cons) {
+                Some(true) => {
+                    items.push(fields[0]);
+                    current = fields[1];
+                }
+                Some(false) => return Ok(items),
+                None => return Err(ASTError::TypeError(current, "Expected a list")),
+            }
+        }
+    }
+}
+
+fn write_json_string(value: &str, out: &mut String) {
+    out.push('"');
+    for character in value.chars() {
+        match character {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\u{0}'..'\u{20}' => write!(out, "\\u{:04x}", character as u32).unwrap(),
+            character => out.push(character),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{EvalConfig, ast::AST, parse};
+
+    use super::{MAX_DEPTH, Parser};
+
+    fn parse_json(ast: &mut AST, json: &str) -> Option<()> {
+        let mut parser = Parser {
+            bytes: json.as_bytes(),
+            position: 0,
+            depth: 0,
+        };
+        parser.document(ast).map(|_| ())
+    }
+
+    #[test]
+    fn deep_nesting_is_invalid() {
+        let mut ast = AST::new();
+        let json = |depth| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(parse_json(&mut ast, &json(MAX_DEPTH)).is_some());
+        assert!(parse_json(&mut ast, &json(MAX_DEPTH + 1)).is_none());
+
+        let source = format!("#json_parse \"{}\"", "[".repeat(100_000));
+        let Err(error) = parse(&source).unwrap().evaluate(EvalConfig::default()) else {
+            panic!("deeply nested JSON parsed");
+        };
+        assert!(error.to_string().contains("not valid JSON"), "{error}");
+    }
+
+    /// Values parsed before the error are removed
+    #[test]
+    fn invalid_json_leaves_no_nodes() {
+        let mut ast = AST::new();
+        let deep = "[".repeat(MAX_DEPTH + 1);
+        for json in [
+            r#"[1, {"a": [true, null]}, x]"#,
+            r#"{"a": "b", "c": [1, 2] 3}"#,
+            &format!("[1, [2], {deep}"),
+            "[1] 2",
+        ] {
+            let before = ast.graph.node_count();
+            assert!(parse_json(&mut ast, json).is_none(), "{json}");
+            assert_eq!(ast.graph.node_count(), before, "{json}");
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use petgraph::graph::NodeIndex;
+
+use crate::ast::{
+    AST, ASTError, ASTResult, Edge, Node, VariableKind, builtins::ConstructorTag, types::Type,
+};
+
+/// Builtins walking lists natively, without a reduction for every element on the way.
+/// Lists are built with any constructor taking a head and a tail, the same one for every cell,
+/// and end with any constructor without arguments, like `#cons` and `#nil`
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ListTag {
+    Head,
+    Tail,
+    Length,
+    Get,
+    /// `λc n.c x1 (c x2 n)`
+    ToChurch,
+    FromChurch,
+    /// `λc n.c x1 tail`, with the tail encoded the same way
+    ToScott,
+    FromScott,
+}
+
+impl ListTag {
+    pub fn argument_names(&self) -> Vec<&'static str> {
+        match self {
+            Self::Get => vec!["index", "list"],
+            _ => vec!["list"],
+        }
+    }
+
+    pub fn signature(&self) -> Type {
+        match self {
+            Self::Head | Self::ToChurch | Self::ToScott => Type::function([Type::Data], Type::Dyn),
+            Self::Tail => Type::function([Type::Data], Type::Data),
+            Self::Length => Type::function([Type::Data], Type::Number),
+            Self::Get => Type::function([Type::Number, Type::Data], Type::Dyn),
+            Self::FromChurch | Self::FromScott => Type::function([Type::Dyn], Type::Data),
+        }
+    }
+
+    /// Conversions from encoded lists are plain lambo functions, given the encoded list
+    fn conversion(&self) -> Option<&'static str> {
+        match self {
+            Self::FromChurch => Some("λlist.list #cons #nil"),
+            Self::FromScott => {
+                Some("λlist.list (λhead tail.#cons head (#list_from_scott tail)) #nil")
+            }
+            _ => None,
+        }
+    }
+
+    /// Encoding of a list, given the head and tail of its first cell if it has one
+    fn encoding(&self, cell: Option<[NodeIndex; 2]>) -> &'static str {
+        match (self, cell) {
+            (Self::ToScott, Some(_)) => "λhead tail c n.c head (#list_to_scott tail)",
+            (_, Some(_)) => "λhead tail c n.c head (#list_to_church tail c n)",
+            (_, None) => "λc n.n",
+        }
+    }
+
+    pub fn evaluate(&self, ast: &mut AST, id: NodeIndex) -> ASTResult<NodeIndex> {
+        let binders = ConstructorTag::get_binders(ast, id)?;
+        let list_binder = *binders
+            .last()
+            .ok_or(ASTError::Custom(id, "Incorrect argument count"))?;
+
+        if let Some(source) = self.conversion() {
+            let function = ast.add_expr_from_str(source)?;
+            let list = ast.graph.add_node(Node::Variable(VariableKind::Bound));
+            ast.graph.add_edge(list, list_binder, Edge::Binder(0));
+            let application = ast.graph.add_node(Node::Application);
+            ast.graph.add_edge(application, function, Edge::Function);
+            ast.graph.add_edge(application, list, Edge::Parameter);
+
+            ast.migrate_node(id, application)?;
+            ast.remove_node(id);
+            return Ok(application);
+        }
+
+        let index = match self {
+            Self::Get => ast
+                .extract_primitive_from_environment(binders[0])
+                .and_then(|p| p.extract_number())?,
+            Self::Length => usize::MAX,
+            _ => 0,
+        };
+
+        let (list, is_dangling) = ast.evaluate_closure_parameter(list_binder)?;
+        // Detached list must survive evaluation of its tails
+        if is_dangling {
+            ast.in_flight.push(list);
+        }
+        let cell = ast.list_cell(list, index);
+        if is_dangling {
+            ast.in_flight.pop();
+        }
+        let (cell, walked) = cell?;
+
+        // Fields of the cell are closures, which the result refers to
+        let reference = |ast: &mut AST, field: NodeIndex| -> ASTResult<NodeIndex> {
+            Ok(match ast.node(field)? {
+                Node::Closure { .. } => {
+                    let variable = ast.graph.add_node(Node::Variable(VariableKind::Bound));
+                    ast.graph.add_edge(variable, field, Edge::Binder(0));
+                    variable
+                }
+                _ => field,
+            })
+        };
+        let result = match (self, cell) {
+            (Self::Length, _) => Some(ast.encode_usize(walked)),
+            (Self::Head | Self::Get, Some([head, _])) => Some(reference(ast, head)?),
+            (Self::Tail, Some([_, tail])) => Some(reference(ast, tail)?),
+            (Self::ToChurch | Self::ToScott, _) => {
+                let mut encoded = ast.add_expr_from_str(self.encoding(cell))?;
+                for field in cell.into_iter().flatten() {
+                    let field = reference(ast, field)?;
+                    encoded = ast.apply(encoded, field);
+                }
+                Some(encoded)
+            }
+            _ => None,
+        };
+        if is_dangling {
+            ast.remove_node(list);
+        }
+        let value = match result {
+            Some(result) => result,
+            None if walked < index => return Err(ASTError::Custom(id, "Index out of bounds")),
+            None => return Err(ASTError::Custom(id, "Empty list")),
+        };
+
+        ast.migrate_node(id, value)?;
+        ast.remove_node(id);
+        Ok(value)
+    }
+}
+
+impl AST {
+    /// `#cons` and `#nil`, the same constructors as `cons` and `nil` declared with `data`
+    pub(crate) fn list_constructor(&mut self, name: &str) -> Option<ConstructorTag> {
+        match name {
+            "#cons" => Some(self.named_constructor("cons", 2)),
+            "#nil" => Some(self.named_constructor("nil", 0)),
+            _ => None,
+        }
+    }
+
+    /// Closures holding head and tail of the `index`th cell of evaluated `list`,
+    /// or `None` if the list ends before it, along with how many cells were walked
+    pub(crate) fn list_cell(
+        &mut self,
+        list: NodeIndex,
+        index: usize,
+    ) -> ASTResult<(Option<[NodeIndex; 2]>, usize)> {
+        let mut cons = None;
+        let mut cell = list;
+        let mut walked = 0;
+        loop {
+            let tag = match *self.node(cell)? {
+                Node::Data { tag } => continues_list(tag, &mut cons),
+                _ => None,
+            };
+            match tag {
+                Some(true) => {
+                    let [head, tail] = ConstructorTag::get_binders(self, cell)?
+                        .try_into()
+                        .map_err(|_| ASTError::Custom(cell, "Incomplete list cell"))?;
+                    if walked == index {
+                        return Ok((Some([head, tail]), walked));
+                    }
+                    let under_closures = self.evaluate(self.follow_edge(tail, Edge::Parameter)?)?;
+                    self.lift_closure_chain(tail, under_closures, Edge::Parameter)?;
+                    cell = self.follow_edge(tail, Edge::Parameter)?;
+                    walked += 1;
+                }
+                Some(false) => return Ok((None, walked)),
+                None => return Err(ASTError::TypeError(cell, "Expected a list")),
+            }
+        }
+    }
+}
+
+/// Whether `tag` builds another cell of a list whose cells were built by `cons` so far,
+/// rather than ending it. `None` if it does neither, see `ListTag`
+pub(crate) fn continues_list(
+    tag: ConstructorTag,
+    cons: &mut Option<ConstructorTag>,
+) -> Option<bool> {
+    match tag {
+        ConstructorTag::CustomTag { arity: 0, .. } => Some(false),
+        ConstructorTag::CustomTag { arity: 2, .. } if *cons.get_or_insert(tag) == tag => Some(true),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{EvalConfig, parse};
+
+    /// Same result with and without full laziness, which moves constructor applications around
+    fn evaluate(source: &str) -> Option<usize> {
+        let [lazy, fully_lazy] = [false, true].map(|full_laziness| {
+            let config = EvalConfig {
+                full_laziness,
+                ..EvalConfig::default()
+            };
+            parse(source).ok()?.evaluate(config).ok()?.to_usize()
+        });
+        assert_eq!(lazy, fully_lazy, "{source}");
+        lazy
+    }
+
+    #[test]
+    fn lists_of_any_constructors() {
+        let source = "let mk \\u.
+              let cons #constructor 2 in let nil #constructor 0 in
+              #list_length (cons 1 (cons 2 nil))
+            in mk 0";
+        assert_eq!(evaluate(source), Some(2));
+        let source = "data List = cons head tail | nil in #list_get 1 (cons 1 (#cons 2 nil))";
+        assert_eq!(evaluate(source), Some(2));
+        // Cells built by different constructors
+        let source = "let other #constructor 2 in #list_length (#cons 1 (other 2 #nil))";
+        assert_eq!(evaluate(source), None);
+        assert_eq!(
+            evaluate("let some #constructor 1 in #list_length (some 1)"),
+            None
+        );
+    }
+
+    #[test]
+    fn encodings_round_trip() {
+        let list = "(let cons #constructor 2 in cons 1 (cons 2 (cons 3 #nil)))";
+        let church = format!("#list_to_church {list} + 0");
+        assert_eq!(evaluate(&church), Some(6));
+        let scott = format!("#list_from_scott (#list_to_scott {list}) | #list_get 2");
+        assert_eq!(evaluate(&scott), Some(3));
+        assert_eq!(evaluate("#list_to_church #nil + 7"), Some(7));
+    }
+}
@@ -5,22 +5,48 @@ use petgraph::{graph::NodeIndex, visit::EdgeRef};
 use crate::ast::{
     AST, ASTError, ASTResult, Edge, Node, Primitive,
     builtins::{
-        arithmetic::ArithmeticTag, bytes::BytesOpTag, helpers::HelperFunctionTag, io::IOTag,
+        arithmetic::ArithmeticTag, array::ArrayOpTag, bytes::BytesOpTag, encoding::EncodingTag,
+        helpers::HelperFunctionTag, io::IOTag, json::JsonOpTag, list::ListTag, string::StringOpTag,
     },
+    types::Type,
 };
 
 pub mod arithmetic;
+pub mod array;
 pub mod bytes;
+pub mod encoding;
 pub mod helpers;
+pub mod host;
 pub mod io;
+pub mod json;
+pub mod list;
+pub mod string;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ConstructorTag {
     IO(IOTag),
     Arithmetic(ArithmeticTag),
     HelperFunction(HelperFunctionTag),
     BytesOp(BytesOpTag),
-    CustomTag { uid: usize, arity: usize },
+    StringOp(StringOpTag),
+    List(ListTag),
+    ArrayOp(ArrayOpTag),
+    Encoding(EncodingTag),
+    JsonOp(JsonOpTag),
+    /// Array built by `#array_new`, holding one binder per element
+    Array {
+        length: usize,
+    },
+    CustomTag {
+        uid: usize,
+        arity: usize,
+    },
+    /// Index into functions registered with `AST::register_function`
+    HostFunction {
+        index: usize,
+        arity: usize,
+    },
 }
 
 const TAGS: &[(&str, ConstructorTag)] = &[
@@ -32,18 +58,127 @@ const TAGS: &[(&str, ConstructorTag)] = &[
         "#match",
         ConstructorTag::HelperFunction(HelperFunctionTag::Match),
     ),
+    (
+        "#typeof",
+        ConstructorTag::HelperFunction(HelperFunctionTag::TypeOf),
+    ),
+    (
+        "#expect_type",
+        ConstructorTag::HelperFunction(HelperFunctionTag::ExpectType),
+    ),
+    (
+        "#gc",
+        ConstructorTag::HelperFunction(HelperFunctionTag::CollectGarbage),
+    ),
+    (
+        "#hash",
+        ConstructorTag::HelperFunction(HelperFunctionTag::Hash),
+    ),
     ("=num", ConstructorTag::Arithmetic(ArithmeticTag::Eq)),
+    ("<", ConstructorTag::Arithmetic(ArithmeticTag::Lt)),
+    ("<=", ConstructorTag::Arithmetic(ArithmeticTag::Le)),
+    (">", ConstructorTag::Arithmetic(ArithmeticTag::Gt)),
+    (">=", ConstructorTag::Arithmetic(ArithmeticTag::Ge)),
     ("+", ConstructorTag::Arithmetic(ArithmeticTag::Add)),
     ("-", ConstructorTag::Arithmetic(ArithmeticTag::Sub)),
     ("*", ConstructorTag::Arithmetic(ArithmeticTag::Mul)),
     ("/", ConstructorTag::Arithmetic(ArithmeticTag::Div)),
+    ("%", ConstructorTag::Arithmetic(ArithmeticTag::Mod)),
+    ("#divmod", ConstructorTag::Arithmetic(ArithmeticTag::DivMod)),
+    ("#min", ConstructorTag::Arithmetic(ArithmeticTag::Min)),
+    ("#max", ConstructorTag::Arithmetic(ArithmeticTag::Max)),
+    ("#abs", ConstructorTag::Arithmetic(ArithmeticTag::Abs)),
     ("^", ConstructorTag::Arithmetic(ArithmeticTag::Pow)),
+    ("+.", ConstructorTag::Arithmetic(ArithmeticTag::AddFloat)),
+    ("-.", ConstructorTag::Arithmetic(ArithmeticTag::SubFloat)),
+    ("*.", ConstructorTag::Arithmetic(ArithmeticTag::MulFloat)),
+    ("/.", ConstructorTag::Arithmetic(ArithmeticTag::DivFloat)),
     ("#bytes_new", ConstructorTag::BytesOp(BytesOpTag::New)),
     ("#bytes_get", ConstructorTag::BytesOp(BytesOpTag::Get)),
+    (
+        "#bytes_try_get",
+        ConstructorTag::BytesOp(BytesOpTag::TryGet),
+    ),
+    ("#bytes_set", ConstructorTag::BytesOp(BytesOpTag::Set)),
     ("#bytes_push", ConstructorTag::BytesOp(BytesOpTag::Push)),
+    ("#bytes_pop", ConstructorTag::BytesOp(BytesOpTag::Pop)),
     ("#bytes_len", ConstructorTag::BytesOp(BytesOpTag::Length)),
+    ("#bytes_slice", ConstructorTag::BytesOp(BytesOpTag::Slice)),
+    ("#bytes_concat", ConstructorTag::BytesOp(BytesOpTag::Concat)),
+    (
+        "#num_to_bytes",
+        ConstructorTag::BytesOp(BytesOpTag::FromNumber),
+    ),
+    (
+        "#bytes_to_num",
+        ConstructorTag::BytesOp(BytesOpTag::ToNumber),
+    ),
+    (
+        "#str_from_bytes",
+        ConstructorTag::StringOp(StringOpTag::FromBytes),
+    ),
+    (
+        "#str_to_bytes",
+        ConstructorTag::StringOp(StringOpTag::ToBytes),
+    ),
+    ("#str_len", ConstructorTag::StringOp(StringOpTag::Length)),
+    ("#str_get", ConstructorTag::StringOp(StringOpTag::Get)),
+    ("#str_concat", ConstructorTag::StringOp(StringOpTag::Concat)),
+    ("#str_slice", ConstructorTag::StringOp(StringOpTag::Slice)),
+    ("#str_eq", ConstructorTag::StringOp(StringOpTag::Eq)),
+    ("#list_head", ConstructorTag::List(ListTag::Head)),
+    ("#list_tail", ConstructorTag::List(ListTag::Tail)),
+    ("#list_length", ConstructorTag::List(ListTag::Length)),
+    ("#list_get", ConstructorTag::List(ListTag::Get)),
+    ("#list_to_church", ConstructorTag::List(ListTag::ToChurch)),
+    (
+        "#list_from_church",
+        ConstructorTag::List(ListTag::FromChurch),
+    ),
+    ("#list_to_scott", ConstructorTag::List(ListTag::ToScott)),
+    ("#list_from_scott", ConstructorTag::List(ListTag::FromScott)),
+    ("#array_new", ConstructorTag::ArrayOp(ArrayOpTag::New)),
+    ("#array_get", ConstructorTag::ArrayOp(ArrayOpTag::Get)),
+    ("#array_set", ConstructorTag::ArrayOp(ArrayOpTag::Set)),
+    ("#array_len", ConstructorTag::ArrayOp(ArrayOpTag::Length)),
+    (
+        "#num_to_church",
+        ConstructorTag::Encoding(EncodingTag::NumberToChurch),
+    ),
+    (
+        "#num_from_church",
+        ConstructorTag::Encoding(EncodingTag::NumberFromChurch),
+    ),
+    (
+        "#num_to_scott",
+        ConstructorTag::Encoding(EncodingTag::NumberToScott),
+    ),
+    (
+        "#num_from_scott",
+        ConstructorTag::Encoding(EncodingTag::NumberFromScott),
+    ),
+    (
+        "#data_to_scott",
+        ConstructorTag::Encoding(EncodingTag::DataToScott),
+    ),
+    (
+        "#data_from_scott",
+        ConstructorTag::Encoding(EncodingTag::DataFromScott),
+    ),
+    ("#json_parse", ConstructorTag::JsonOp(JsonOpTag::Parse)),
+    (
+        "#json_stringify",
+        ConstructorTag::JsonOp(JsonOpTag::Stringify),
+    ),
+    ("#io_pure", ConstructorTag::IO(IOTag::Pure)),
     ("#io_print", ConstructorTag::IO(IOTag::Print)),
+    ("#io_debug", ConstructorTag::IO(IOTag::Debug)),
+    ("#io_throw", ConstructorTag::IO(IOTag::Throw)),
+    ("#io_catch", ConstructorTag::IO(IOTag::Catch)),
+    ("#io_exec", ConstructorTag::IO(IOTag::Exec)),
     ("#io_readline", ConstructorTag::IO(IOTag::ReadLine)),
+    ("#io_read", ConstructorTag::IO(IOTag::ReadLambda)),
+    ("#io_readall", ConstructorTag::IO(IOTag::ReadAll)),
     ("#io_flatmap", ConstructorTag::IO(IOTag::Flatmap)),
 ];
 
@@ -61,7 +196,9 @@ impl TryFrom<&str> for ConstructorTag {
 impl From<ConstructorTag> for String {
     fn from(tag: ConstructorTag) -> Self {
         match tag {
+            ConstructorTag::Array { length } => format!("Array{length}"),
             ConstructorTag::CustomTag { uid, .. } => format!("CustomTag{uid}"),
+            ConstructorTag::HostFunction { index, .. } => format!("HostFunction{index}"),
             _ => TAGS
                 .iter()
                 .find(|(_, t)| *t == tag)
@@ -72,44 +209,138 @@ impl From<ConstructorTag> for String {
 }
 
 impl ConstructorTag {
+    /// Fields of records the builtins build, so that field accesses can read them
+    pub fn builtin_record_shapes() -> impl Iterator<Item = &'static [&'static str]> {
+        TAGS.iter().filter_map(|(_, tag)| tag.record_shape())
+    }
+
+    /// Fields of the records the builtin returns, e.g pairs of `#divmod`
+    pub fn record_shape(&self) -> Option<&'static [&'static str]> {
+        match self {
+            Self::Arithmetic(tag) => tag.record_shape(),
+            Self::IO(tag) => tag.record_shape(),
+            Self::JsonOp(tag) => tag.record_shape(),
+            _ => None,
+        }
+    }
+
     pub fn argument_names(&self) -> Vec<&str> {
         match self {
             Self::IO(tag) => tag.argument_names(),
             Self::Arithmetic(tag) => tag.argument_names(),
             Self::HelperFunction(tag) => tag.argument_names(),
             Self::BytesOp(tag) => tag.argument_names(),
+            Self::StringOp(tag) => tag.argument_names(),
+            Self::List(tag) => tag.argument_names(),
+            Self::ArrayOp(tag) => tag.argument_names(),
+            Self::Encoding(tag) => tag.argument_names(),
+            Self::JsonOp(tag) => tag.argument_names(),
+            Self::Array { length } => vec!["element"; *length],
             Self::CustomTag { arity, .. } => {
                 vec!["param"; *arity]
             }
+            Self::HostFunction { arity, .. } => {
+                vec!["argument"; *arity]
+            }
+        }
+    }
+
+    /// Which arguments the builtin always evaluates once it has all of them,
+    /// see `analyze_strictness`
+    pub fn strict_arguments(&self) -> Vec<bool> {
+        let mut strict = vec![false; self.arity()];
+        match self {
+            Self::Arithmetic(_)
+            | Self::BytesOp(_)
+            | Self::StringOp(_)
+            | Self::HelperFunction(HelperFunctionTag::Hash)
+            | Self::List(
+                ListTag::Head
+                | ListTag::Tail
+                | ListTag::Length
+                | ListTag::Get
+                | ListTag::FromChurch
+                | ListTag::FromScott,
+            ) => strict.fill(true),
+            Self::ArrayOp(array) => {
+                strict.fill(true);
+                // Elements stay lazy
+                if let ArrayOpTag::New | ArrayOpTag::Set = array {
+                    strict[1] = false;
+                }
+            }
+            // Constructor and value, one of the functions is called depending on them
+            Self::HelperFunction(HelperFunctionTag::Match) => {
+                strict[0] = true;
+                strict[3] = true;
+            }
+            _ => {}
+        }
+        strict
+    }
+
+    /// Type the checker assumes for the builtin, with one argument per `argument_names`
+    pub fn signature(&self) -> Type {
+        match self {
+            Self::IO(tag) => tag.signature(),
+            Self::Arithmetic(tag) => tag.signature(),
+            Self::HelperFunction(tag) => tag.signature(),
+            Self::BytesOp(tag) => tag.signature(),
+            Self::StringOp(tag) => tag.signature(),
+            Self::List(tag) => tag.signature(),
+            Self::ArrayOp(tag) => tag.signature(),
+            Self::Encoding(tag) => tag.signature(),
+            Self::JsonOp(tag) => tag.signature(),
+            Self::Array { length } => Type::function(vec![Type::Dyn; *length], Type::Data),
+            Self::CustomTag { arity, .. } => Type::function(vec![Type::Dyn; *arity], Type::Data),
+            Self::HostFunction { arity, .. } => Type::function(vec![Type::Dyn; *arity], Type::Dyn),
+        }
+    }
+
+    /// Point out the signature when an argument turns out to be of the wrong shape
+    fn explain(&self, error: ASTError) -> ASTError {
+        match error {
+            ASTError::TypeError(node, message) => ASTError::BuiltinTypeError(node, *self, message),
+            error => error,
         }
     }
 
-    pub fn get_binders(ast: &mut AST, id: NodeIndex) -> Vec<NodeIndex> {
-        let mut edges = ast
+    pub fn get_binders(ast: &AST, id: NodeIndex) -> ASTResult<Vec<NodeIndex>> {
+        let mut binders = ast
             .graph
             .edges_directed(id, petgraph::Direction::Outgoing)
-            .collect::<Vec<_>>();
+            .map(|e| match *e.weight() {
+                Edge::Binder(argument_index) => Ok((argument_index, e.target())),
+                _ => Err(ASTError::Custom(id, "Data node can only have binder edges")),
+            })
+            .collect::<ASTResult<Vec<_>>>()?;
 
-        edges.sort_by_key(|e| match *e.weight() {
-            Edge::Binder(argument_index) => argument_index,
-            _ => panic!(),
-        });
+        binders.sort_by_key(|(argument_index, _)| *argument_index);
 
-        edges.into_iter().map(|e| e.target()).collect()
+        Ok(binders.into_iter().map(|(_, binder)| binder).collect())
     }
 
     pub fn arity(&self) -> usize {
         self.argument_names().len()
     }
 
+    /// Call saturated builtin `id`, returning the node that replaced the call.
+    /// The evaluator carries on evaluating it, so builtins don't have to
     pub fn evaluate(&self, ast: &mut AST, id: NodeIndex) -> ASTResult<NodeIndex> {
         match self {
             Self::Arithmetic(tag) => tag.evaluate(ast, id),
             Self::HelperFunction(tag) => tag.evaluate(ast, id),
             Self::BytesOp(tag) => tag.evaluate(ast, id),
+            Self::StringOp(tag) => tag.evaluate(ast, id),
+            Self::List(tag) => tag.evaluate(ast, id),
+            Self::ArrayOp(tag) => tag.evaluate(ast, id),
+            Self::Encoding(tag) => tag.evaluate(ast, id),
+            Self::JsonOp(tag) => tag.evaluate(ast, id),
             Self::IO(IOTag::Flatmap) => IOTag::flatmap(ast, id),
+            Self::HostFunction { index, .. } => ast.call_host_function(*index, id),
             _ => Ok(id),
         }
+        .map_err(|error| self.explain(error))
     }
 }
 
@@ -120,14 +351,14 @@ impl AST {
     ) -> ASTResult<Primitive> {
         let (parameter, is_dangling) = self.evaluate_closure_parameter(closure_id)?;
         let primitive = if is_dangling {
-            self.graph.remove_node(parameter)
+            self.remove_node(parameter)
         } else {
             self.graph.node_weight(parameter).cloned()
         };
 
         match primitive {
             Some(Node::Primitive(primitive)) => Ok(primitive),
-            _ => Err(ASTError::Custom(closure_id, "Not a primitive")),
+            _ => Err(ASTError::TypeError(closure_id, "Not a primitive")),
         }
     }
 }
@@ -0,0 +1,126 @@
+use petgraph::graph::NodeIndex;
+
+use crate::ast::{
+    AST, ASTError, ASTResult, Node, Primitive, builtins::ConstructorTag, types::Type,
+};
+
+/// Builtins on Strings, which count and index characters rather than bytes
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StringOpTag {
+    /// Fails unless the Bytes are valid UTF-8
+    FromBytes,
+    ToBytes,
+    Length,
+    /// Character at `index`, as a String
+    Get,
+    /// `string` followed by `suffix`
+    Concat,
+    /// Characters from `start` up to, but not including `end`
+    Slice,
+    /// Church boolean
+    Eq,
+}
+
+impl Primitive {
+    pub fn extract_string(&self) -> ASTResult<&str> {
+        match self {
+            Primitive::String(string) => ASTResult::Ok(string),
+            _ => Err(ASTError::TypeError(NodeIndex::default(), "Expected String")),
+        }
+    }
+}
+
+impl StringOpTag {
+    pub fn argument_names(&self) -> Vec<&'static str> {
+        match self {
+            Self::FromBytes => vec!["bytes"],
+            Self::ToBytes | Self::Length => vec!["string"],
+            Self::Get => vec!["index", "string"],
+            Self::Concat => vec!["suffix", "string"],
+            Self::Slice => vec!["start", "end", "string"],
+            Self::Eq => vec!["other", "string"],
+        }
+    }
+
+    pub fn signature(&self) -> Type {
+        match self {
+            Self::FromBytes => Type::function([Type::Bytes], Type::String),
+            Self::ToBytes => Type::function([Type::String], Type::Bytes),
+            Self::Length => Type::function([Type::String], Type::Number),
+            Self::Get => Type::function([Type::Number, Type::String], Type::String),
+            Self::Concat => Type::function([Type::String, Type::String], Type::String),
+            Self::Slice => Type::function([Type::Number, Type::Number, Type::String], Type::String),
+            Self::Eq => Type::function(
+                [Type::String, Type::String],
+                Type::function([Type::Dyn, Type::Dyn], Type::Dyn),
+            ),
+        }
+    }
+
+    pub fn evaluate(&self, ast: &mut AST, id: NodeIndex) -> ASTResult<NodeIndex> {
+        let arguments = ConstructorTag::get_binders(ast, id)?
+            .into_iter()
+            .map(|binder| ast.extract_primitive_from_environment(binder))
+            .collect::<ASTResult<Vec<_>>>()?;
+
+        let result = match (self, arguments.as_slice()) {
+            (Self::FromBytes, [bytes]) => match bytes {
+                Primitive::Bytes(bytes) => Primitive::String(
+                    String::from_utf8(bytes.clone())
+                        .map_err(|_| ASTError::Custom(id, "Bytes is not a valid utf8 string"))?,
+                ),
+                _ => return Err(ASTError::TypeError(id, "Expected Bytes")),
+            },
+            (Self::ToBytes, [string]) => {
+                Primitive::Bytes(string.extract_string()?.as_bytes().to_vec())
+            }
+            (Self::Length, [string]) => Primitive::Number(string.extract_string()?.chars().count()),
+            (Self::Get, [index, string]) => {
+                let character = string
+                    .extract_string()?
+                    .chars()
+                    .nth(index.extract_number()?)
+                    .ok_or(ASTError::Custom(id, "Index out of bounds"))?;
+                Primitive::String(character.to_string())
+            }
+            (Self::Concat, [suffix, string]) => {
+                Primitive::String(string.extract_string()?.to_owned() + suffix.extract_string()?)
+            }
+            (Self::Slice, [start, end, string]) => {
+                let string = string.extract_string()?;
+                let (start, end) = (start.extract_number()?, end.extract_number()?);
+                // Byte offsets of every character boundary, including the end of the string
+                let mut boundaries = string
+                    .char_indices()
+                    .map(|(offset, _)| offset)
+                    .chain([string.len()]);
+                let slice = match (boundaries.nth(start), end.checked_sub(start)) {
+                    (Some(from), Some(0)) => Some(&string[from..from]),
+                    (Some(from), Some(length)) => {
+                        boundaries.nth(length - 1).map(|to| &string[from..to])
+                    }
+                    _ => None,
+                };
+                Primitive::String(
+                    slice
+                        .ok_or(ASTError::Custom(id, "Slice out of bounds"))?
+                        .to_owned(),
+                )
+            }
+            (Self::Eq, [other, string]) => {
+                let equal = other.extract_string()? == string.extract_string()?;
+                let result = ast.encode_bool(equal);
+                ast.migrate_node(id, result)?;
+                ast.remove_node(id);
+                return Ok(result);
+            }
+            _ => return Err(ASTError::Custom(id, "Incorrect argument count")),
+        };
+
+        let node = ast.graph.add_node(Node::Primitive(result));
+        ast.migrate_node(id, node)?;
+        ast.remove_node(id);
+        Ok(node)
+    }
+}
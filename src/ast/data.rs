@@ -0,0 +1,30 @@
+use crate::ast::{AST, builtins::ConstructorTag};
+
+/// Type declared with `data List = Cons head tail | Nil`
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DataType {
+    pub name: String,
+    /// Constructor names and tags, in declaration order
+    pub constructors: Vec<(String, ConstructorTag)>,
+}
+
+impl AST {
+    /// Remember that `constructors` are all the ways to build a value of type `name`
+    pub(crate) fn declare_data(
+        &mut self,
+        name: String,
+        constructors: Vec<(String, ConstructorTag)>,
+    ) {
+        self.data_types.push(DataType { name, constructors });
+    }
+
+    /// Declared type built by constructor `tag`
+    pub fn data_type(&self, tag: ConstructorTag) -> Option<&DataType> {
+        self.data_types.iter().rev().find(|data| {
+            data.constructors
+                .iter()
+                .any(|&(_, constructor)| constructor == tag)
+        })
+    }
+}
@@ -1,6 +1,67 @@
+use std::path::PathBuf;
+
+use petgraph::{Direction, graph::NodeIndex, visit::EdgeRef};
+
 use crate::ast::{DebugNode, Edge, Node, Primitive, VariableKind, AST};
 
+/// How bound variables are labeled in DOT output.
+/// Names become misleading after shadowing, while depths are hard to map to source.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum DotLabels {
+    #[default]
+    Names,
+    DeBruijn,
+    Both,
+}
+
+/// DOT frames kept for `AST::dump_debug`
+#[derive(Debug, Clone)]
+pub struct DebugDump {
+    pub dir: PathBuf,
+    /// Keep one frame out of this many
+    pub every: usize,
+    /// Frames taken so far, kept or not
+    pub seen: usize,
+}
+
 impl AST {
+    /// Number of binders between a bound variable and its own binder
+    pub fn de_bruijn_index(&self, variable: NodeIndex) -> Option<usize> {
+        let binder = self.follow_edge(variable, Edge::Binder(0)).ok()?;
+
+        let mut depth = 0;
+        let mut current = variable;
+        loop {
+            let parent_edge = self
+                .graph
+                .edges_directed(current, Direction::Incoming)
+                .find(|e| !matches!(e.weight(), Edge::Binder(_) | Edge::Debug))?;
+            let parent = parent_edge.source();
+
+            if parent == binder {
+                return Some(depth);
+            }
+            if *parent_edge.weight() == Edge::Body {
+                depth += 1;
+            }
+            current = parent;
+        }
+    }
+
+    fn dot_variable_label(&self, id: NodeIndex) -> String {
+        let name = self.get_variable_name(id).unwrap();
+        let index = || {
+            self.de_bruijn_index(id)
+                .map(|index| index.to_string())
+                .unwrap_or_else(|| "?".to_string())
+        };
+        match (self.graph.node_weight(id).unwrap(), self.dot_labels) {
+            (Node::Variable(VariableKind::Free(_)), _) | (_, DotLabels::Names) => name.to_string(),
+            (_, DotLabels::DeBruijn) => index(),
+            (_, DotLabels::Both) => format!("{name} ({})", index()),
+        }
+    }
+
     fn dot_node_with_attributes(
         id: usize,
         label: &str,
@@ -55,7 +116,7 @@ impl AST {
                     writeln!(
                         result,
                         "{}",
-                        Self::dot_node_with_attributes(id, &"call".to_string(), "blue", "white")
+                        Self::dot_node_with_attributes(id, "call", "blue", "white")
                     )
                     .unwrap();
                     let parameter = self.follow_edge(node_id, Edge::Parameter).unwrap().index();
@@ -70,7 +131,7 @@ impl AST {
                     "{}",
                     Self::dot_node_with_attributes(
                         id,
-                        self.get_variable_name(node_id).unwrap(),
+                        &self.dot_variable_label(node_id),
                         match kind {
                             VariableKind::Bound => "gray",
                             VariableKind::Free(_) => "orange",
@@ -80,12 +141,7 @@ impl AST {
                 )
                 .unwrap(),
                 Node::Data { tag } => {
-                    writeln!(
-                        result,
-                        "{id} [label=\"{id}: Data {}\"]",
-                        String::try_from(*tag).unwrap()
-                    )
-                    .unwrap();
+                    writeln!(result, "{id} [label=\"{id}: Data {}\"]", String::from(*tag)).unwrap();
                 }
                 Node::Primitive(Primitive::Bytes(bytes)) => writeln!(
                     result,
@@ -0,0 +1,221 @@
+use std::sync::Arc;
+
+use petgraph::graph::NodeIndex;
+
+use crate::ast::{
+    AST, ASTError, ASTResult, Edge, Node, Number, Primitive, VariableKind, builtins::ConstructorTag,
+};
+
+/// Building values for a program directly in the graph, without going through source code.
+/// Returned nodes are detached: pass them to a function with `apply`, or make one the root.
+impl AST {
+    pub fn encode_usize(&mut self, number: Number) -> NodeIndex {
+        self.graph
+            .add_node(Node::Primitive(Primitive::Number(number)))
+    }
+
+    pub fn encode_f64(&mut self, float: f64) -> NodeIndex {
+        self.graph
+            .add_node(Node::Primitive(Primitive::Float(float)))
+    }
+
+    pub fn encode_str(&mut self, text: &str) -> NodeIndex {
+        self.graph
+            .add_node(Node::Primitive(Primitive::Bytes(text.as_bytes().to_vec())))
+    }
+
+    /// Church boolean, `λx y.x` for true and `λx y.y` for false
+    pub fn encode_bool(&mut self, value: bool) -> NodeIndex {
+        let x = self.add_lambda("x");
+        let y = self.add_lambda("y");
+        let result = self.add_bound_variable(if value { x } else { y });
+        self.graph.add_edge(x, y, Edge::Body);
+        self.graph.add_edge(y, result, Edge::Body);
+        x
+    }
+
+    /// Church list `λcons nil.cons a (cons b nil)` of already encoded items.
+    /// Programs turn it into their own list type with `list cons nil`.
+    pub fn encode_list(&mut self, items: &[NodeIndex]) -> NodeIndex {
+        let cons = self.add_lambda("cons");
+        let nil = self.add_lambda("nil");
+        self.graph.add_edge(cons, nil, Edge::Body);
+
+        let mut tail = self.add_bound_variable(nil);
+        for &item in items.iter().rev() {
+            let head = self.add_bound_variable(cons);
+            let partial = self.apply(head, item);
+            tail = self.apply(partial, tail);
+        }
+        self.graph.add_edge(nil, tail, Edge::Body);
+        cons
+    }
+
+    /// Fully applied constructor, each field is bound by its own closure above the data node
+    pub fn encode_data(&mut self, tag: ConstructorTag, fields: &[NodeIndex]) -> NodeIndex {
+        let data = self.graph.add_node(Node::Data { tag });
+        let mut head = data;
+        for (argument_index, &field) in fields.iter().enumerate().rev() {
+            let closure = self.graph.add_node(Node::Closure {
+                argument_name: Arc::new(tag.argument_names()[argument_index].to_string()),
+            });
+            self.graph.add_edge(closure, head, Edge::Body);
+            self.graph.add_edge(closure, field, Edge::Parameter);
+            self.graph
+                .add_edge(data, closure, Edge::Binder(argument_index));
+            head = closure;
+        }
+        head
+    }
+
+    /// Evaluate `id` into a fully applied constructor and return its tag with detached fields.
+    /// Fields are variables bound to the constructor arguments, so they are evaluated lazily.
+    pub fn decode_data(&mut self, id: NodeIndex) -> ASTResult<(ConstructorTag, Vec<NodeIndex>)> {
+        let data = self.evaluate(id)?;
+        let tag = match self.node(data)? {
+            &Node::Data { tag } => tag,
+            _ => return Err(ASTError::TypeError(data, "Expected data")),
+        };
+        let binders = ConstructorTag::get_binders(self, data)?;
+        if binders.len() != tag.arity() {
+            return Err(ASTError::TypeError(
+                data,
+                "Constructor is not fully applied",
+            ));
+        }
+
+        // Fields are shared with the data node, so refer to them like #match does
+        let fields = binders
+            .into_iter()
+            .map(|binder| self.add_bound_variable(binder))
+            .collect();
+        Ok((tag, fields))
+    }
+
+    /// Church numeral `λf x.f (f x)` for 2
+    pub fn encode_church_usize(&mut self, number: Number) -> NodeIndex {
+        let f = self.add_lambda("f");
+        let x = self.add_lambda("x");
+        self.graph.add_edge(f, x, Edge::Body);
+        let mut body = self.add_bound_variable(x);
+        for _ in 0..number {
+            let function = self.add_bound_variable(f);
+            body = self.apply(function, body);
+        }
+        self.graph.add_edge(x, body, Edge::Body);
+        f
+    }
+
+    /// Scott numeral `λs z.s predecessor`, with `λs z.z` for 0
+    pub fn encode_scott_usize(&mut self, number: Number) -> NodeIndex {
+        let mut predecessor = None;
+        for _ in 0..=number {
+            let s = self.add_lambda("s");
+            let z = self.add_lambda("z");
+            self.graph.add_edge(s, z, Edge::Body);
+            let body = match predecessor {
+                Some(predecessor) => {
+                    let function = self.add_bound_variable(s);
+                    self.apply(function, predecessor)
+                }
+                None => self.add_bound_variable(z),
+            };
+            self.graph.add_edge(z, body, Edge::Body);
+            predecessor = Some(s);
+        }
+        predecessor.unwrap()
+    }
+
+    /// Scott encoding `λc0 c1.c1 field` of constructor `tag` applied to `fields`,
+    /// or `None` if `tag` is not one of `constructors`
+    pub fn encode_scott_data(
+        &mut self,
+        tag: ConstructorTag,
+        fields: &[NodeIndex],
+        constructors: &[ConstructorTag],
+    ) -> Option<NodeIndex> {
+        let index = constructors
+            .iter()
+            .position(|&constructor| constructor == tag)?;
+        let cases = (0..constructors.len())
+            .map(|case| self.add_lambda(&format!("c{case}")))
+            .collect::<Vec<_>>();
+        let mut body = self.add_bound_variable(cases[index]);
+        for &field in fields {
+            body = self.apply(body, field);
+        }
+        for (&case, &next) in cases.iter().zip(cases.iter().skip(1)) {
+            self.graph.add_edge(case, next, Edge::Body);
+        }
+        self.graph.add_edge(*cases.last()?, body, Edge::Body);
+        Some(cases[0])
+    }
+
+    /// Evaluate Church numeral `id` into a Number
+    pub fn decode_church_usize(&mut self, id: NodeIndex) -> ASTResult<Number> {
+        let decode = self.add_expr_from_str("#num_from_church")?;
+        let number = self.apply(decode, id);
+        self.decode_usize(number)
+    }
+
+    /// Evaluate Scott numeral `id` into a Number
+    pub fn decode_scott_usize(&mut self, id: NodeIndex) -> ASTResult<Number> {
+        let decode = self.add_expr_from_str("#num_from_scott")?;
+        let number = self.apply(decode, id);
+        self.decode_usize(number)
+    }
+
+    /// Apply Scott encoded `id` to `constructors` and decode the resulting constructor,
+    /// see `decode_data`
+    pub fn decode_scott_data(
+        &mut self,
+        id: NodeIndex,
+        constructors: &[ConstructorTag],
+    ) -> ASTResult<(ConstructorTag, Vec<NodeIndex>)> {
+        let mut data = id;
+        for &tag in constructors {
+            let constructor = self.graph.add_node(Node::Data { tag });
+            data = self.apply(data, constructor);
+        }
+        self.decode_data(data)
+    }
+
+    fn decode_usize(&mut self, id: NodeIndex) -> ASTResult<Number> {
+        let number = self.evaluate(id)?;
+        match self.node(number)? {
+            Node::Primitive(Primitive::Number(number)) => Ok(*number),
+            _ => Err(ASTError::TypeError(number, "Expected Number")),
+        }
+    }
+
+    /// Application node `(function parameter)`
+    pub fn apply(&mut self, function: NodeIndex, parameter: NodeIndex) -> NodeIndex {
+        let node = self.graph.add_node(Node::Application);
+        self.graph.add_edge(node, function, Edge::Function);
+        self.graph.add_edge(node, parameter, Edge::Parameter);
+        node
+    }
+
+    /// Evaluate `expr` with host-supplied values bound to free variables,
+    /// e.g a stored rule `>= limit amount` with `limit` and `amount` from runtime data
+    pub fn evaluate_with_env(
+        &mut self,
+        expr: &str,
+        env: &[(&str, NodeIndex)],
+    ) -> ASTResult<NodeIndex> {
+        let expr = self.add_expr_with_env(expr, env)?;
+        self.evaluate(expr)
+    }
+
+    fn add_lambda(&mut self, argument_name: &str) -> NodeIndex {
+        self.graph.add_node(Node::Lambda {
+            argument_name: Arc::new(argument_name.to_string()),
+        })
+    }
+
+    pub(crate) fn add_bound_variable(&mut self, binder: NodeIndex) -> NodeIndex {
+        let node = self.graph.add_node(Node::Variable(VariableKind::Bound));
+        self.graph.add_edge(node, binder, Edge::Binder(0));
+        node
+    }
+}
@@ -0,0 +1,155 @@
+use petgraph::{Direction, graph::NodeIndex, visit::EdgeRef};
+
+use crate::ast::{
+    AST, Edge, Node, VariableKind,
+    builtins::{ConstructorTag, helpers::HelperFunctionTag},
+    data::DataType,
+    warning::Warning,
+};
+
+/// Chains of `#match` over constructors of a `data` type, either written with `match`
+/// or piped by hand, e.g `EXHAUSTED | #match nil 0 | #match cons (\head tail.head)`
+impl AST {
+    /// Warn about chains over a declared type that miss some of its constructors
+    /// while falling back to an error like `NO_MATCH`, and about cases that can never match.
+    /// Chains testing constructors of other or several types are left alone.
+    pub fn check_matches(&self) -> Vec<Warning> {
+        let mut warnings = vec![];
+        for id in self.graph.node_indices() {
+            if self.matcher(id).is_none() || self.is_fallback(id) {
+                continue;
+            }
+
+            let mut cases = vec![];
+            let mut current = id;
+            while let Some((constructor, fallback)) = self.matcher(current) {
+                cases.push(constructor);
+                current = fallback;
+            }
+            let Some(tags) = cases
+                .iter()
+                .map(|&case| self.case_tag(case))
+                .collect::<Option<Vec<_>>>()
+            else {
+                continue;
+            };
+            let Some(data) = self.data_type(tags[0]) else {
+                continue;
+            };
+            if tags.iter().any(|&tag| {
+                !data
+                    .constructors
+                    .iter()
+                    .any(|&(_, constructor)| constructor == tag)
+            }) {
+                continue;
+            }
+
+            for (index, &tag) in tags.iter().enumerate() {
+                if tags[..index].contains(&tag) {
+                    warnings.push(self.warning(
+                        cases[index],
+                        format!(
+                            "unreachable case, `{}` is already matched above",
+                            name(data, tag)
+                        ),
+                    ));
+                }
+            }
+            let missing = data
+                .constructors
+                .iter()
+                .filter(|(_, constructor)| !tags.contains(constructor))
+                .map(|(name, _)| format!("`{name}`"))
+                .collect::<Vec<_>>();
+            match (missing.is_empty(), self.is_catch_all(current)) {
+                (true, true) => warnings.push(self.warning(
+                    current,
+                    format!(
+                        "unreachable fallback, every constructor of `{}` is matched",
+                        data.name
+                    ),
+                )),
+                (false, false) => warnings.push(self.warning(
+                    id,
+                    format!(
+                        "non-exhaustive match on `{}`, missing {}",
+                        data.name,
+                        missing.join(", ")
+                    ),
+                )),
+                _ => {}
+            }
+        }
+        warnings
+    }
+
+    /// Constructor and fallback of `#match constructor transform fallback`
+    fn matcher(&self, id: NodeIndex) -> Option<(NodeIndex, NodeIndex)> {
+        let (head, 3) = self.application_head(id) else {
+            return None;
+        };
+        let Some(Node::Data {
+            tag: ConstructorTag::HelperFunction(HelperFunctionTag::Match),
+        }) = self.graph.node_weight(head)
+        else {
+            return None;
+        };
+        let fallback = self.follow_edge(id, Edge::Parameter).ok()?;
+        let transform = self.follow_edge(id, Edge::Function).ok()?;
+        let constructor = self
+            .follow_edge(
+                self.follow_edge(transform, Edge::Function).ok()?,
+                Edge::Parameter,
+            )
+            .ok()?;
+        Some((constructor, fallback))
+    }
+
+    /// Whether matcher `id` is the fallback of another one, so it's checked as part of that chain
+    fn is_fallback(&self, id: NodeIndex) -> bool {
+        self.graph
+            .edges_directed(id, Direction::Incoming)
+            .any(|e| *e.weight() == Edge::Parameter && self.matcher(e.source()).is_some())
+    }
+
+    /// Tag of the constructor tested by a case, if it's known without evaluating
+    fn case_tag(&self, id: NodeIndex) -> Option<ConstructorTag> {
+        let constructor = match self.graph.node_weight(id)? {
+            Node::Variable(VariableKind::Bound) => {
+                let binder = self.follow_edge(id, Edge::Binder(0)).ok()?;
+                let Node::Closure { .. } = self.graph.node_weight(binder)? else {
+                    return None;
+                };
+                self.follow_edge(binder, Edge::Parameter).ok()?
+            }
+            _ => id,
+        };
+        match self.graph.node_weight(constructor)? {
+            Node::Data {
+                tag: tag @ ConstructorTag::CustomTag { .. },
+            } => Some(*tag),
+            _ => None,
+        }
+    }
+
+    /// Whether fallback `id` handles any value, rather than being an error like
+    /// `EXHAUSTED` or the `λvalue.NO_MATCH` of `match` without `_`
+    fn is_catch_all(&self, id: NodeIndex) -> bool {
+        let body = match self.graph.node_weight(id) {
+            Some(Node::Lambda { .. }) => self.follow_edge(id, Edge::Body).unwrap_or(id),
+            _ => id,
+        };
+        !matches!(
+            self.graph.node_weight(body),
+            Some(Node::Variable(VariableKind::Free(_)))
+        )
+    }
+}
+
+fn name(data: &DataType, tag: ConstructorTag) -> &str {
+    data.constructors
+        .iter()
+        .find(|&&(_, constructor)| constructor == tag)
+        .map_or("constructor", |(name, _)| name)
+}
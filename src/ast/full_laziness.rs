@@ -0,0 +1,168 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use petgraph::{Direction, graph::NodeIndex, visit::EdgeRef};
+
+use crate::ast::{
+    AST, ASTResult, Edge, Node, VariableKind,
+    builtins::{ConstructorTag, helpers::HelperFunctionTag},
+};
+
+/// Full laziness: computations inside a lambda that don't depend on its argument
+/// are moved out of it, so that they are evaluated once and shared by every call,
+/// e.g `λx.+ x (fib 20)` becomes `let mfe (fib 20) in λx.+ x mfe`.
+/// Calls of `#constructor` stay where they are, as every call creates another constructor
+impl AST {
+    /// Move maximal free expressions out of every lambda, into a closure right above it.
+    /// Returns how many expressions were moved, see `Stats::floated_expressions`
+    pub fn float_free_expressions(&mut self) -> ASTResult<usize> {
+        let mut floated = 0;
+        loop {
+            let lambdas = self
+                .graph
+                .node_indices()
+                .filter(|&id| matches!(self.graph[id], Node::Lambda { .. }))
+                .collect::<Vec<_>>();
+            let mut changed = false;
+            for lambda in lambdas {
+                let closures = self.float_out_of(lambda)?;
+                changed |= !closures.is_empty();
+                floated += closures.len();
+            }
+            if !changed {
+                return Ok(floated);
+            }
+        }
+    }
+
+    /// Move maximal free expressions out of `lambda` only, returning the closures now holding them
+    pub(super) fn float_out_of(&mut self, lambda: NodeIndex) -> ASTResult<Vec<NodeIndex>> {
+        self.free_expressions(lambda)?
+            .into_iter()
+            .map(|expression| self.float_above(expression, lambda))
+            .collect()
+    }
+
+    /// Outermost applications in the body of `lambda` not using its argument,
+    /// nor any binder between it and them, and not calling `#constructor`
+    fn free_expressions(&self, lambda: NodeIndex) -> ASTResult<Vec<NodeIndex>> {
+        let body = self.follow_edge(lambda, Edge::Body)?;
+
+        // Depth of every node below `lambda`, parents before children
+        let mut depth = HashMap::from([(lambda, 0)]);
+        let mut order = vec![];
+        let mut stack = vec![(body, 1)];
+        while let Some((id, level)) = stack.pop() {
+            depth.insert(id, level);
+            order.push(id);
+            stack.extend(self.children(id).map(|(_, child)| (child, level + 1)));
+        }
+
+        // Depth of the outermost binder below `lambda` used inside each subtree,
+        // binders are always ancestors of the nodes they bind
+        let mut outermost = HashMap::<NodeIndex, usize>::new();
+        // Subtrees creating constructors
+        let mut generative = HashSet::new();
+        for &id in order.iter().rev() {
+            if matches!(
+                self.graph[id],
+                Node::Data {
+                    tag: ConstructorTag::HelperFunction(HelperFunctionTag::CreateConstructor)
+                }
+            ) || self
+                .children(id)
+                .any(|(_, child)| generative.contains(&child))
+            {
+                generative.insert(id);
+            }
+            let used = self
+                .graph
+                .edges_directed(id, Direction::Outgoing)
+                .filter(|e| matches!(e.weight(), Edge::Binder(_)))
+                .filter_map(|e| depth.get(&e.target()).copied());
+            let nested = self
+                .children(id)
+                .filter_map(|(_, child)| outermost.get(&child).copied());
+            if let Some(level) = used.chain(nested).min() {
+                outermost.insert(id, level);
+            }
+        }
+
+        let mut free = vec![];
+        let mut stack = vec![body];
+        while let Some(id) = stack.pop() {
+            let independent = outermost.get(&id).is_none_or(|&level| level >= depth[&id])
+                && !generative.contains(&id);
+            if independent && let Node::Application = self.graph[id] {
+                free.push(id);
+            } else {
+                stack.extend(self.children(id).map(|(_, child)| child));
+            }
+        }
+        Ok(free)
+    }
+
+    /// Replace `expression` with a variable bound by a new closure holding it right above `lambda`,
+    /// named like the `let` binding `expression` is the value of, if any
+    fn float_above(&mut self, expression: NodeIndex, lambda: NodeIndex) -> ASTResult<NodeIndex> {
+        let argument_name = self
+            .graph
+            .edges_directed(expression, Direction::Incoming)
+            .find(|e| matches!(e.weight(), Edge::Parameter))
+            .and_then(|e| match &self.graph[e.source()] {
+                Node::Closure { argument_name } => Some(argument_name.clone()),
+                _ => None,
+            })
+            .unwrap_or_else(|| Arc::new("mfe".to_string()));
+        let closure = self.graph.add_node(Node::Closure { argument_name });
+        self.migrate_node(lambda, closure)?;
+        self.graph.add_edge(closure, lambda, Edge::Body);
+
+        let variable = self.graph.add_node(Node::Variable(VariableKind::Bound));
+        self.migrate_node(expression, variable)?;
+        self.graph.add_edge(variable, closure, Edge::Binder(0));
+        self.graph.add_edge(closure, expression, Edge::Parameter);
+
+        self.stats.floated_expressions += 1;
+        self.stats.floated_nodes += self.subtree_size(expression);
+        Ok(closure)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{EvalConfig, ast::AST, parse};
+
+    fn evaluate(source: &str) -> (Option<usize>, usize) {
+        let config = EvalConfig {
+            full_laziness: true,
+            ..EvalConfig::default()
+        };
+        let value = parse(source).unwrap().evaluate(config).unwrap();
+        (value.to_usize(), value.ast().stats().floated_expressions)
+    }
+
+    #[test]
+    fn free_expressions_are_shared() {
+        let source = "let f \\x.+ x ((\\y.* y 7) 6) in + (f 1) (f 2)";
+        assert_eq!(evaluate(source), (Some(87), 1));
+    }
+
+    #[test]
+    fn constructors_are_not_shared() {
+        let source = "let mk \\u.#constructor 1 in #match (mk 0) (\\x.x) (\\v.999) (mk 1 5)";
+        assert_eq!(evaluate(source), (Some(999), 0));
+    }
+
+    #[test]
+    fn floated_bindings_keep_their_name() {
+        let mut ast = AST::new();
+        ast.root = ast
+            .add_expr_from_str("\\x.let total (+ 1 2) in + x total")
+            .unwrap();
+        assert_eq!(ast.float_free_expressions().unwrap(), 1);
+        assert!(ast.to_string().starts_with("let total"), "{ast}");
+    }
+}
@@ -0,0 +1,157 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use petgraph::{Direction, graph::NodeIndex, visit::EdgeRef};
+
+use crate::ast::{AST, ASTError, ASTResult, Edge, Node};
+
+/// Top-level `let` binding as it was written, before evaluation rewrote it
+#[derive(Debug, Clone)]
+struct Definition {
+    closure: NodeIndex,
+    name: Arc<String>,
+    /// Detached copy of the parameter
+    source: NodeIndex,
+    /// Closures of earlier definitions that `source` refers to
+    dependencies: HashSet<NodeIndex>,
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct Incremental {
+    definitions: Vec<Definition>,
+    /// Detached copy of the expression under all definitions
+    body: NodeIndex,
+}
+
+impl Incremental {
+    /// Detached copies that must survive `collect_unreachable`
+    pub(super) fn sources(&self) -> impl Iterator<Item = NodeIndex> + '_ {
+        self.definitions
+            .iter()
+            .map(|definition| definition.source)
+            .chain([self.body])
+    }
+}
+
+impl AST {
+    /// Remember every top-level definition so that `redefine` can later
+    /// reset just the ones affected by an edit, keeping the rest evaluated.
+    /// Must be called before the first evaluation.
+    ///
+    /// Definitions are kept alive for the lifetime of the graph,
+    /// even after their last use, since a later edit may refer to them again.
+    pub fn enable_incremental(&mut self) -> ASTResult<()> {
+        let mut definitions = vec![];
+        let mut current = self.root;
+        while let Node::Closure { argument_name } = self.node(current)? {
+            let name = argument_name.clone();
+            self.keep_alive(current);
+
+            let parameter = self.follow_edge(current, Edge::Parameter)?;
+            let source = self.clone_subtree(parameter, HashMap::new())?;
+            definitions.push(Definition {
+                closure: current,
+                name,
+                source,
+                dependencies: self.dependencies(source),
+            });
+            current = self.follow_edge(current, Edge::Body)?;
+        }
+        let body = self.clone_subtree(current, HashMap::new())?;
+
+        self.incremental = Some(Incremental { definitions, body });
+        Ok(())
+    }
+
+    /// Replace the value of top-level definition `name` with `expr`,
+    /// which may refer to any definition above it.
+    /// Every definition depending on it (directly or not) goes back to its unevaluated form,
+    /// as does the body, so the next evaluation of root recomputes only those.
+    ///
+    /// Returns names of the definitions that were reset.
+    pub fn redefine(&mut self, name: &str, expr: &str) -> ASTResult<Vec<Arc<String>>> {
+        let Some(mut incremental) = self.incremental.take() else {
+            return Err(ASTError::Custom(
+                self.root,
+                "Incremental evaluation is not enabled",
+            ));
+        };
+        let result = self.redefine_in(&mut incremental, name, expr);
+        self.incremental = Some(incremental);
+        result
+    }
+
+    fn redefine_in(
+        &mut self,
+        incremental: &mut Incremental,
+        name: &str,
+        expr: &str,
+    ) -> ASTResult<Vec<Arc<String>>> {
+        let definitions = &mut incremental.definitions;
+        let position = definitions
+            .iter()
+            .rposition(|definition| *definition.name == name)
+            .ok_or(ASTError::Custom(self.root, "No such top-level definition"))?;
+
+        let scope = definitions[..position]
+            .iter()
+            .map(|definition| definition.closure)
+            .collect();
+        let source = self.add_expr_in_scope(expr, scope)?;
+        self.remove_subtree(definitions[position].source);
+        definitions[position].source = source;
+        definitions[position].dependencies = self.dependencies(source);
+
+        // Definitions only see the ones above them, so a single pass finds every dependant
+        let mut dirty = HashSet::from([definitions[position].closure]);
+        let mut reset = vec![];
+        for definition in &definitions[position..] {
+            if !dirty.contains(&definition.closure) && definition.dependencies.is_disjoint(&dirty) {
+                continue;
+            }
+            dirty.insert(definition.closure);
+
+            let parameter = self.follow_edge(definition.closure, Edge::Parameter)?;
+            self.remove_subtree(parameter);
+            let parameter = self.clone_subtree(definition.source, HashMap::new())?;
+            self.graph
+                .add_edge(definition.closure, parameter, Edge::Parameter);
+            reset.push(definition.name.clone());
+        }
+
+        let body = self.clone_subtree(incremental.body, HashMap::new())?;
+        match definitions.last() {
+            Some(definition) => {
+                let old_body = self.follow_edge(definition.closure, Edge::Body)?;
+                self.remove_subtree(old_body);
+                self.graph.add_edge(definition.closure, body, Edge::Body);
+            }
+            None => {
+                self.remove_subtree(self.root);
+                self.root = body;
+            }
+        }
+
+        Ok(reset)
+    }
+
+    /// Closures bound by variables and data nodes anywhere in subtree `id`
+    fn dependencies(&self, id: NodeIndex) -> HashSet<NodeIndex> {
+        let mut dependencies = HashSet::new();
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            for edge in self.graph.edges_directed(id, Direction::Outgoing) {
+                match edge.weight() {
+                    Edge::Binder(_) => {
+                        dependencies.insert(edge.target());
+                    }
+                    Edge::Debug => {}
+                    _ => stack.push(edge.target()),
+                }
+            }
+        }
+        dependencies
+    }
+}
@@ -0,0 +1,392 @@
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::ast::builtins::io::{EffectHandler, StdIO};
+
+/// Single IO interaction performed by a program
+#[derive(Debug, Clone, PartialEq)]
+pub enum IOEvent {
+    Read(String),
+    /// Everything left on STDIN, not necessarily UTF-8
+    ReadAll(Vec<u8>),
+    Print(String),
+    /// Command run to completion, with its stdout and exit code
+    Exec {
+        command: String,
+        arguments: Vec<String>,
+        stdout: Vec<u8>,
+        code: i32,
+    },
+}
+
+/// Write `bytes` in quotes on a single line, escaping control characters
+/// and bytes that are not UTF-8 as `\xff`
+fn write_quoted(f: &mut std::fmt::Formatter<'_>, bytes: &[u8]) -> std::fmt::Result {
+    write!(f, "\"")?;
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '\n' => write!(f, "\\n")?,
+                '\t' => write!(f, "\\t")?,
+                '\r' => write!(f, "\\r")?,
+                '\\' => write!(f, "\\\\")?,
+                '"' => write!(f, "\\\"")?,
+                c if c.is_ascii_control() => write!(f, "\\x{:02x}", c as u8)?,
+                c => write!(f, "{c}")?,
+            }
+        }
+        for byte in chunk.invalid() {
+            write!(f, "\\x{byte:02x}")?;
+        }
+    }
+    write!(f, "\"")
+}
+
+impl Display for IOEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Read(text) => {
+                write!(f, "read ")?;
+                write_quoted(f, text.as_bytes())
+            }
+            Self::ReadAll(bytes) => {
+                write!(f, "readall ")?;
+                write_quoted(f, bytes)
+            }
+            Self::Print(text) => {
+                write!(f, "print ")?;
+                write_quoted(f, text.as_bytes())
+            }
+            Self::Exec {
+                command,
+                arguments,
+                stdout,
+                code,
+            } => {
+                write!(f, "exec")?;
+                for word in std::iter::once(command).chain(arguments) {
+                    write!(f, " ")?;
+                    write_quoted(f, word.as_bytes())?;
+                }
+                write!(f, " -> {code} ")?;
+                write_quoted(f, stdout)
+            }
+        }
+    }
+}
+
+/// Part of a log line after the kind of event
+enum Field {
+    Quoted(Vec<u8>),
+    /// Exit code or `->`
+    Word(String),
+}
+
+/// Space separated fields of `line`, `None` if they are malformed
+fn fields(line: &str) -> Option<Vec<Field>> {
+    let mut fields = vec![];
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' => {}
+            '"' => {
+                let mut bytes = vec![];
+                loop {
+                    match chars.next()? {
+                        '"' => break,
+                        '\\' => match chars.next()? {
+                            'n' => bytes.push(b'\n'),
+                            't' => bytes.push(b'\t'),
+                            'r' => bytes.push(b'\r'),
+                            '\\' => bytes.push(b'\\'),
+                            '"' => bytes.push(b'"'),
+                            'x' => {
+                                let hex = [chars.next()?, chars.next()?].iter().collect::<String>();
+                                bytes.push(u8::from_str_radix(&hex, 16).ok()?);
+                            }
+                            _ => return None,
+                        },
+                        c => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+                    }
+                }
+                fields.push(Field::Quoted(bytes));
+            }
+            c => {
+                let mut word = c.to_string();
+                while let Some(&c) = chars.peek()
+                    && c != ' '
+                {
+                    word.push(c);
+                    chars.next();
+                }
+                fields.push(Field::Word(word));
+            }
+        }
+    }
+    Some(fields)
+}
+
+impl IOEvent {
+    fn parse(kind: &str, fields: &[Field]) -> Option<Self> {
+        let text = |field: &Field| match field {
+            Field::Quoted(bytes) => String::from_utf8(bytes.clone()).ok(),
+            Field::Word(_) => None,
+        };
+        match (kind, fields) {
+            ("read", [field]) => text(field).map(Self::Read),
+            ("readall", [Field::Quoted(bytes)]) => Some(Self::ReadAll(bytes.clone())),
+            ("print", [field]) => text(field).map(Self::Print),
+            (
+                "exec",
+                [
+                    words @ ..,
+                    Field::Word(arrow),
+                    Field::Word(code),
+                    Field::Quoted(stdout),
+                ],
+            ) if arrow == "->" => {
+                let mut words = words.iter().map(text).collect::<Option<Vec<_>>>()?;
+                if words.is_empty() {
+                    return None;
+                }
+                Some(Self::Exec {
+                    command: words.remove(0),
+                    arguments: words,
+                    stdout: stdout.clone(),
+                    code: code.parse().ok()?,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+impl TryFrom<&str> for IOEvent {
+    type Error = io::Error;
+
+    fn try_from(line: &str) -> Result<Self, Self::Error> {
+        let (kind, rest) = line.split_once(' ').unwrap_or((line, ""));
+        fields(rest)
+            .and_then(|fields| Self::parse(kind, &fields))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Bad IO log line: {line}"),
+                )
+            })
+    }
+}
+
+/// Performs IO through the inner handler and appends every interaction to a log file.
+/// Events are written as they happen, so the log survives a crashing program.
+#[derive(Debug, Clone)]
+pub struct RecordingIO {
+    inner: Box<dyn EffectHandler>,
+    path: PathBuf,
+}
+
+impl RecordingIO {
+    /// Record real STDIN/STDOUT interactions into `path`, truncating it
+    pub fn new(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::with_handler(path, Box::new(StdIO))
+    }
+
+    /// Record interactions performed by `inner` into `path`, truncating it
+    pub fn with_handler(path: impl AsRef<Path>, inner: Box<dyn EffectHandler>) -> io::Result<Self> {
+        File::create(&path)?;
+        Ok(Self {
+            inner,
+            path: path.as_ref().to_path_buf(),
+        })
+    }
+
+    fn record(&self, event: IOEvent) -> io::Result<()> {
+        let mut file = OpenOptions::new().append(true).open(&self.path)?;
+        writeln!(file, "{event}")
+    }
+}
+
+impl EffectHandler for RecordingIO {
+    fn handle_read(&mut self) -> io::Result<String> {
+        let line = self.inner.handle_read()?;
+        self.record(IOEvent::Read(line.clone()))?;
+        Ok(line)
+    }
+    fn handle_read_all(&mut self) -> io::Result<Vec<u8>> {
+        let bytes = self.inner.handle_read_all()?;
+        self.record(IOEvent::ReadAll(bytes.clone()))?;
+        Ok(bytes)
+    }
+    fn handle_print(&mut self, text: &str) -> io::Result<()> {
+        self.inner.handle_print(text)?;
+        self.record(IOEvent::Print(text.to_string()))
+    }
+    fn handle_exec(&mut self, command: &str, arguments: &[String]) -> io::Result<(Vec<u8>, i32)> {
+        let (stdout, code) = self.inner.handle_exec(command, arguments)?;
+        self.record(IOEvent::Exec {
+            command: command.to_string(),
+            arguments: arguments.to_vec(),
+            stdout: stdout.clone(),
+            code,
+        })?;
+        Ok((stdout, code))
+    }
+    fn handle_throw(&mut self, message: &str) {
+        self.inner.handle_throw(message)
+    }
+    fn clone_box(&self) -> Box<dyn EffectHandler> {
+        Box::new(self.clone())
+    }
+}
+
+/// Feeds a recorded log back to the program without touching real STDIN/STDOUT.
+/// Printed text is checked against the log, so diverging executions are reported.
+#[derive(Debug, Clone)]
+pub struct ReplayIO {
+    events: VecDeque<IOEvent>,
+}
+
+impl ReplayIO {
+    pub fn new(path: impl AsRef<Path>) -> io::Result<Self> {
+        let events = std::fs::read_to_string(path)?
+            .lines()
+            .map(IOEvent::try_from)
+            .collect::<io::Result<_>>()?;
+        Ok(Self { events })
+    }
+
+    fn next_event(&mut self, expected: &str) -> io::Result<IOEvent> {
+        self.events.pop_front().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("IO log exhausted, program wants to {expected}"),
+            )
+        })
+    }
+}
+
+impl EffectHandler for ReplayIO {
+    fn handle_read(&mut self) -> io::Result<String> {
+        match self.next_event("read")? {
+            IOEvent::Read(line) => Ok(line),
+            event => Err(io::Error::other(format!(
+                "Replay diverged: program reads, but log has {event}"
+            ))),
+        }
+    }
+    fn handle_read_all(&mut self) -> io::Result<Vec<u8>> {
+        match self.next_event("read all")? {
+            IOEvent::ReadAll(bytes) => Ok(bytes),
+            event => Err(io::Error::other(format!(
+                "Replay diverged: program reads all, but log has {event}"
+            ))),
+        }
+    }
+    fn handle_print(&mut self, text: &str) -> io::Result<()> {
+        match self.next_event("print")? {
+            IOEvent::Print(recorded) if recorded == text => Ok(()),
+            event => Err(io::Error::other(format!(
+                "Replay diverged: program prints {}, but log has {event}",
+                IOEvent::Print(text.to_string())
+            ))),
+        }
+    }
+    /// The command doesn't run again, its recorded output is returned instead
+    fn handle_exec(&mut self, command: &str, arguments: &[String]) -> io::Result<(Vec<u8>, i32)> {
+        match self.next_event("exec")? {
+            IOEvent::Exec {
+                command: recorded,
+                arguments: recorded_arguments,
+                stdout,
+                code,
+            } if recorded == command && recorded_arguments == arguments => Ok((stdout, code)),
+            event => Err(io::Error::other(format!(
+                "Replay diverged: program runs {command} {arguments:?}, but log has {event}"
+            ))),
+        }
+    }
+    fn clone_box(&self) -> Box<dyn EffectHandler> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use crate::{EvalConfig, ast::builtins::io::EffectHandler, parse};
+
+    use super::{IOEvent, RecordingIO, ReplayIO};
+
+    /// Binary STDIN and a command printing its arguments
+    #[derive(Debug, Clone)]
+    struct Scripted;
+
+    impl EffectHandler for Scripted {
+        fn handle_read(&mut self) -> io::Result<String> {
+            Err(io::ErrorKind::InvalidData.into())
+        }
+        fn handle_read_all(&mut self) -> io::Result<Vec<u8>> {
+            Ok(b"\xff\n".to_vec())
+        }
+        fn handle_print(&mut self, _text: &str) -> io::Result<()> {
+            Ok(())
+        }
+        fn handle_exec(
+            &mut self,
+            command: &str,
+            arguments: &[String],
+        ) -> io::Result<(Vec<u8>, i32)> {
+            Ok((format!("{command} {}", arguments.join(" ")).into_bytes(), 3))
+        }
+        fn clone_box(&self) -> Box<dyn EffectHandler> {
+            Box::new(self.clone())
+        }
+    }
+
+    #[test]
+    fn events_round_trip() {
+        let events = [
+            IOEvent::Read("\"quoted\"\tline\n".to_string()),
+            IOEvent::ReadAll(b"\\x00 \0\x7f\xff\xfe".to_vec()),
+            IOEvent::Print("Zoë".to_string()),
+            IOEvent::Exec {
+                command: "ls".to_string(),
+                arguments: vec!["-a".to_string(), "two words".to_string()],
+                stdout: b"\x80".to_vec(),
+                code: -1,
+            },
+        ];
+        for event in events {
+            let line = event.to_string();
+            assert!(!line.contains('\n'), "{line}");
+            assert_eq!(IOEvent::try_from(line.as_str()).unwrap(), event, "{line}");
+        }
+    }
+
+    #[test]
+    fn read_all_and_exec_replay() {
+        let source = "#io_flatmap
+            (\\input.#io_flatmap
+                (\\result.#io_pure (#bytes_concat input result.0))
+                (#io_exec \"echo\" (#cons \"-n\" #nil)))
+            #io_readall";
+        let log = std::env::temp_dir().join(format!("lambo-io-log-{}", std::process::id()));
+
+        let mut program = parse(source).unwrap();
+        program.ast.io = Box::new(RecordingIO::with_handler(&log, Box::new(Scripted)).unwrap());
+        let recorded = program.evaluate(EvalConfig::default()).unwrap();
+        assert_eq!(recorded.to_bytes(), Some(&b"echo -n\xff\n"[..]));
+
+        let mut program = parse(source).unwrap();
+        program.ast.io = Box::new(ReplayIO::new(&log).unwrap());
+        let replayed = program.evaluate(EvalConfig::default()).unwrap();
+        std::fs::remove_file(&log).unwrap();
+        assert_eq!(replayed.to_bytes(), recorded.to_bytes());
+    }
+}
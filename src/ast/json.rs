@@ -0,0 +1,267 @@
+//! Interchange format for terms, so external generators and test corpora
+//! can produce programs without going through the text parser.
+//!
+//! Every term is an object with a `type` field:
+//!
+//! | `type` | fields                                       | meaning                                |
+//! |--------|----------------------------------------------|----------------------------------------|
+//! | `var`  | `name`: string or `index`: number            | variable by name or De Bruijn index    |
+//! | `lam`  | `name`: string or null, `body`               | `λname.body`                           |
+//! | `app`  | `function`, `argument`                       | `function argument`                    |
+//! | `let`  | `name`: string or null, `value`, `body`      | `let name value in body`               |
+//! | `prim` | `number`, `float`, `string`, `text`, `bytes` | primitive, `bytes` for non-utf8 data   |
+//! | `data` | `tag`, `arguments`: [term]                   | builtin or constructor applied to args |
+//!
+//! `string` and `bytes` are Bytes, `bytes` is an array of numbers. `text` is a String.
+//! `tag` is either a builtin name like `"+"` or `"#bytes_get"`,
+//! `{"custom": uid, "arity": n}`, `{"host": index, "arity": n}` or `{"array": length}`.
+//!
+//! ```json
+//! {"type": "lam", "name": "x", "body":
+//!   {"type": "app",
+//!    "function": {"type": "var", "name": "f"},
+//!    "argument": {"type": "var", "index": 0}}}
+//! ```
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ast::{Primitive, builtins::ConstructorTag, term::Term};
+
+#[derive(Debug, thiserror::Error)]
+pub enum JsonTermError {
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error("invalid term: {0}")]
+    Invalid(&'static str),
+    #[error("unknown builtin {0}")]
+    UnknownBuiltin(String),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum JsonTerm {
+    Var {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        index: Option<usize>,
+    },
+    Lam {
+        #[serde(default)]
+        name: Option<String>,
+        body: Box<JsonTerm>,
+    },
+    App {
+        function: Box<JsonTerm>,
+        argument: Box<JsonTerm>,
+    },
+    Let {
+        #[serde(default)]
+        name: Option<String>,
+        value: Box<JsonTerm>,
+        body: Box<JsonTerm>,
+    },
+    Prim {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        number: Option<usize>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        float: Option<f64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        string: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        text: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        bytes: Option<Vec<u8>>,
+    },
+    Data {
+        tag: JsonTag,
+        #[serde(default)]
+        arguments: Vec<JsonTerm>,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum JsonTag {
+    Builtin(String),
+    Custom { custom: usize, arity: usize },
+    Host { host: usize, arity: usize },
+    Array { array: usize },
+}
+
+impl From<&Term> for JsonTerm {
+    fn from(term: &Term) -> Self {
+        let name = |name: &Option<Arc<String>>| name.as_ref().map(|name| name.to_string());
+        let boxed = |term: &Term| Box::new(JsonTerm::from(term));
+        match term {
+            Term::Var(name) => JsonTerm::Var {
+                name: Some(name.to_string()),
+                index: None,
+            },
+            Term::Index(index) => JsonTerm::Var {
+                name: None,
+                index: Some(*index),
+            },
+            Term::Lambda {
+                argument_name,
+                body,
+            } => JsonTerm::Lam {
+                name: name(argument_name),
+                body: boxed(body),
+            },
+            Term::Application {
+                function,
+                parameter,
+            } => JsonTerm::App {
+                function: boxed(function),
+                argument: boxed(parameter),
+            },
+            Term::Closure {
+                argument_name,
+                parameter,
+                body,
+            } => JsonTerm::Let {
+                name: name(argument_name),
+                value: boxed(parameter),
+                body: boxed(body),
+            },
+            Term::Primitive(Primitive::Number(number)) => JsonTerm::Prim {
+                number: Some(*number),
+                float: None,
+                string: None,
+                text: None,
+                bytes: None,
+            },
+            Term::Primitive(Primitive::Float(float)) => JsonTerm::Prim {
+                number: None,
+                float: Some(*float),
+                string: None,
+                text: None,
+                bytes: None,
+            },
+            Term::Primitive(Primitive::String(text)) => JsonTerm::Prim {
+                number: None,
+                float: None,
+                string: None,
+                text: Some(text.clone()),
+                bytes: None,
+            },
+            Term::Primitive(Primitive::Bytes(bytes)) => match String::from_utf8(bytes.clone()) {
+                Ok(string) => JsonTerm::Prim {
+                    number: None,
+                    float: None,
+                    string: Some(string),
+                    text: None,
+                    bytes: None,
+                },
+                Err(_) => JsonTerm::Prim {
+                    number: None,
+                    float: None,
+                    string: None,
+                    text: None,
+                    bytes: Some(bytes.clone()),
+                },
+            },
+            Term::Data { tag, arguments } => JsonTerm::Data {
+                tag: match *tag {
+                    ConstructorTag::CustomTag { uid, arity } => {
+                        JsonTag::Custom { custom: uid, arity }
+                    }
+                    ConstructorTag::HostFunction { index, arity } => {
+                        JsonTag::Host { host: index, arity }
+                    }
+                    ConstructorTag::Array { length } => JsonTag::Array { array: length },
+                    tag => JsonTag::Builtin(String::from(tag)),
+                },
+                arguments: arguments.iter().map(JsonTerm::from).collect(),
+            },
+        }
+    }
+}
+
+impl TryFrom<JsonTerm> for Term {
+    type Error = JsonTermError;
+
+    fn try_from(term: JsonTerm) -> Result<Self, Self::Error> {
+        let name = |name: Option<String>| name.map(Arc::new);
+        let boxed = |term: Box<JsonTerm>| Term::try_from(*term).map(Box::new);
+        Ok(match term {
+            JsonTerm::Var {
+                name: Some(name),
+                index: None,
+            } => Term::Var(Arc::new(name)),
+            JsonTerm::Var {
+                name: None,
+                index: Some(index),
+            } => Term::Index(index),
+            JsonTerm::Var { .. } => {
+                return Err(JsonTermError::Invalid("var needs either name or index"));
+            }
+            JsonTerm::Lam { name: n, body } => Term::Lambda {
+                argument_name: name(n),
+                body: boxed(body)?,
+            },
+            JsonTerm::App { function, argument } => Term::Application {
+                function: boxed(function)?,
+                parameter: boxed(argument)?,
+            },
+            JsonTerm::Let {
+                name: n,
+                value,
+                body,
+            } => Term::Closure {
+                argument_name: name(n),
+                parameter: boxed(value)?,
+                body: boxed(body)?,
+            },
+            JsonTerm::Prim {
+                number,
+                float,
+                string,
+                text,
+                bytes,
+            } => Term::Primitive(match (number, float, string, text, bytes) {
+                (Some(number), None, None, None, None) => Primitive::Number(number),
+                (None, Some(float), None, None, None) => Primitive::Float(float),
+                (None, None, Some(string), None, None) => Primitive::Bytes(string.into_bytes()),
+                (None, None, None, Some(text), None) => Primitive::String(text),
+                (None, None, None, None, Some(bytes)) => Primitive::Bytes(bytes),
+                _ => {
+                    return Err(JsonTermError::Invalid(
+                        "prim needs exactly one of number, float, string, text or bytes",
+                    ));
+                }
+            }),
+            JsonTerm::Data { tag, arguments } => Term::Data {
+                tag: match tag {
+                    JsonTag::Builtin(name) => ConstructorTag::try_from(name.as_str())
+                        .map_err(|_| JsonTermError::UnknownBuiltin(name))?,
+                    JsonTag::Custom { custom, arity } => {
+                        ConstructorTag::CustomTag { uid: custom, arity }
+                    }
+                    JsonTag::Host { host, arity } => {
+                        ConstructorTag::HostFunction { index: host, arity }
+                    }
+                    JsonTag::Array { array } => ConstructorTag::Array { length: array },
+                },
+                arguments: arguments
+                    .into_iter()
+                    .map(Term::try_from)
+                    .collect::<Result<_, _>>()?,
+            },
+        })
+    }
+}
+
+impl Term {
+    /// Serialize into the JSON interchange format, see module docs for the schema
+    pub fn to_json_term(&self) -> String {
+        serde_json::to_string(&JsonTerm::from(self)).expect("terms are always valid JSON")
+    }
+
+    /// Parse a term in the JSON interchange format
+    pub fn from_json_term(input: &str) -> Result<Term, JsonTermError> {
+        Term::try_from(serde_json::from_str::<JsonTerm>(input)?)
+    }
+}
@@ -0,0 +1,153 @@
+use petgraph::graph::NodeIndex;
+
+use crate::ast::{
+    AST, ASTError, ASTResult, Node, Number, Primitive, builtins::list::continues_list,
+};
+
+#[cfg(feature = "derive")]
+pub use lambo_derive::{FromLambo, ToLambo};
+
+/// Rust values that can be passed into a lambo program.
+/// Derive it to encode structs and enums as named constructors, see `AST::named_constructor`
+pub trait ToLambo {
+    fn to_lambo(&self, ast: &mut AST) -> ASTResult<NodeIndex>;
+}
+
+/// Rust values that can be read back from a lambo expression.
+/// Derive it to decode named constructors into structs and enums
+pub trait FromLambo: Sized {
+    /// Evaluates `id` as much as needed to read the value
+    fn from_lambo(ast: &mut AST, id: NodeIndex) -> ASTResult<Self>;
+}
+
+impl ToLambo for Number {
+    fn to_lambo(&self, ast: &mut AST) -> ASTResult<NodeIndex> {
+        Ok(ast.encode_usize(*self))
+    }
+}
+
+impl FromLambo for Number {
+    fn from_lambo(ast: &mut AST, id: NodeIndex) -> ASTResult<Self> {
+        let id = ast.evaluate(id)?;
+        match ast.node(id)? {
+            Node::Primitive(Primitive::Number(number)) => Ok(*number),
+            _ => Err(ASTError::TypeError(id, "Expected Number")),
+        }
+    }
+}
+
+impl ToLambo for f64 {
+    fn to_lambo(&self, ast: &mut AST) -> ASTResult<NodeIndex> {
+        Ok(ast.encode_f64(*self))
+    }
+}
+
+impl FromLambo for f64 {
+    fn from_lambo(ast: &mut AST, id: NodeIndex) -> ASTResult<Self> {
+        let id = ast.evaluate(id)?;
+        match ast.node(id)? {
+            Node::Primitive(Primitive::Float(float)) => Ok(*float),
+            _ => Err(ASTError::TypeError(id, "Expected Float")),
+        }
+    }
+}
+
+impl ToLambo for String {
+    fn to_lambo(&self, ast: &mut AST) -> ASTResult<NodeIndex> {
+        Ok(ast.encode_str(self))
+    }
+}
+
+impl FromLambo for String {
+    fn from_lambo(ast: &mut AST, id: NodeIndex) -> ASTResult<Self> {
+        let id = ast.evaluate(id)?;
+        match ast.node(id)? {
+            Node::Primitive(Primitive::Bytes(bytes)) => String::from_utf8(bytes.clone())
+                .map_err(|_| ASTError::Custom(id, "Bytes is not a valid utf8 string")),
+            Node::Primitive(Primitive::String(string)) => Ok(string.clone()),
+            _ => Err(ASTError::TypeError(id, "Expected Bytes")),
+        }
+    }
+}
+
+impl ToLambo for bool {
+    fn to_lambo(&self, ast: &mut AST) -> ASTResult<NodeIndex> {
+        Ok(ast.encode_bool(*self))
+    }
+}
+
+impl FromLambo for bool {
+    /// Church booleans are told apart by what they select
+    fn from_lambo(ast: &mut AST, id: NodeIndex) -> ASTResult<Self> {
+        let if_true = ast.encode_usize(1);
+        let if_false = ast.encode_usize(0);
+        let partial = ast.apply(id, if_true);
+        let selected = ast.apply(partial, if_false);
+        Ok(Number::from_lambo(ast, selected)? == 1)
+    }
+}
+
+/// `some` and `none` constructors
+impl<T: ToLambo> ToLambo for Option<T> {
+    fn to_lambo(&self, ast: &mut AST) -> ASTResult<NodeIndex> {
+        Ok(match self {
+            Some(value) => {
+                let tag = ast.named_constructor("some", 1);
+                let value = value.to_lambo(ast)?;
+                ast.encode_data(tag, &[value])
+            }
+            None => {
+                let tag = ast.named_constructor("none", 0);
+                ast.encode_data(tag, &[])
+            }
+        })
+    }
+}
+
+impl<T: FromLambo> FromLambo for Option<T> {
+    fn from_lambo(ast: &mut AST, id: NodeIndex) -> ASTResult<Self> {
+        let (tag, fields) = ast.decode_data(id)?;
+        if tag == ast.named_constructor("none", 0) {
+            Ok(None)
+        } else if tag == ast.named_constructor("some", 1) {
+            Ok(Some(T::from_lambo(ast, fields[0])?))
+        } else {
+            Err(ASTError::TypeError(id, "Expected some or none"))
+        }
+    }
+}
+
+/// `#cons` and `#nil` constructors
+impl<T: ToLambo> ToLambo for Vec<T> {
+    fn to_lambo(&self, ast: &mut AST) -> ASTResult<NodeIndex> {
+        let cons = ast.named_constructor("cons", 2);
+        let nil = ast.named_constructor("nil", 0);
+
+        let mut list = ast.encode_data(nil, &[]);
+        for item in self.iter().rev() {
+            let head = item.to_lambo(ast)?;
+            list = ast.encode_data(cons, &[head, list]);
+        }
+        Ok(list)
+    }
+}
+
+/// Lists built with any constructors, see `continues_list`
+impl<T: FromLambo> FromLambo for Vec<T> {
+    fn from_lambo(ast: &mut AST, id: NodeIndex) -> ASTResult<Self> {
+        let mut cons = None;
+        let mut items = vec![];
+        let mut current = id;
+        loop {
+            let (tag, fields) = ast.decode_data(current)?;
+            match continues_list(tag, &mut cons) {
+                Some(false) => return Ok(items),
+                Some(true) => {
+                    items.push(T::from_lambo(ast, fields[0])?);
+                    current = fields[1];
+                }
+                None => return Err(ASTError::TypeError(current, "Expected a list")),
+            }
+        }
+    }
+}
@@ -1,43 +1,104 @@
 use std::{
     collections::{HashMap, HashSet},
     fmt::Display,
-    rc::Rc,
+    path::PathBuf,
+    sync::Arc,
 };
 
+pub mod arity;
+pub mod backend;
 pub mod builtins;
-mod debug;
+pub mod data;
+pub mod debug;
+pub mod encode;
+pub mod exhaustive;
+pub mod full_laziness;
+pub mod incremental;
+pub mod io_log;
+#[cfg(feature = "serde")]
+pub mod json;
+pub mod marshal;
+pub mod names;
+pub mod normalize;
+#[cfg(feature = "optimal")]
+pub mod optimal;
 pub mod preprocess;
+pub mod profile;
+pub mod query;
+pub mod record;
+pub mod render;
+#[cfg(feature = "serde")]
+pub mod serialize;
+pub mod session;
+pub mod sexpr;
+pub mod span;
+pub mod specialize;
+pub mod stats;
+pub mod strictness;
+#[cfg(feature = "supercompile")]
+pub mod supercompile;
+pub mod term;
+pub mod types;
+pub mod warning;
 
+pub use petgraph::graph::NodeIndex;
 use petgraph::{
-    graph::{EdgeIndex, NodeIndex},
+    graph::EdgeIndex,
     prelude::StableGraph,
     stable_graph::EdgeReference,
     visit::EdgeRef,
     Direction,
 };
 
-use crate::ast::builtins::ConstructorTag;
+use crate::ast::{
+    builtins::{
+        ConstructorTag,
+        arithmetic::format_float,
+        helpers::HelperFunctionTag,
+        host::HostFunction,
+        io::{EffectHandler, StdIO},
+    },
+    data::DataType,
+    debug::{DebugDump, DotLabels},
+    incremental::Incremental,
+    preprocess::GcPolicy,
+    profile::{Profile, ProfileFrame},
+    span::SourceMap,
+    stats::Stats,
+    types::{Type, TypeCheckError},
+};
+use crate::{
+    debug_server::{DebugFrame, FrameLog},
+    parser::ParseErrors,
+};
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum VariableKind {
-    Free(Rc<String>),
+    Free(Arc<String>),
     Bound,
 }
 
 pub type Number = usize;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Primitive {
     Number(Number),
+    Float(f64),
     Bytes(Vec<u8>),
+    /// Valid UTF-8 text, indexed by characters rather than bytes
+    String(String),
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum DebugNode {
     Annotation { text: String },
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Edge {
     Body,
     Parameter,
@@ -49,15 +110,16 @@ pub enum Edge {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Node {
     Lambda {
-        argument_name: Rc<String>,
+        argument_name: Arc<String>,
     },
     Application,
     Variable(VariableKind),
     Primitive(Primitive),
     Closure {
-        argument_name: Rc<String>,
+        argument_name: Arc<String>,
     },
     /// Data is basically multi-dimensional variable -
     /// it just holds multiple (tagged) references to other expressions
@@ -72,23 +134,156 @@ pub struct AST {
     pub graph: StableGraph<Node, Edge>,
     pub root: NodeIndex,
     next_uid: usize,
-    until_gc: usize,
+    /// Live nodes left by the last automatic collection, see `GcPolicy`
+    nodes_after_gc: usize,
+    /// Largest node count seen during evaluation, see `peak_node_count`
+    peak_nodes: usize,
+    /// Constructors shared with builtins and the host by name, see `named_constructor`
+    constructors: HashMap<Arc<String>, ConstructorTag>,
+    host_functions: Vec<HostFunction>,
 
     debug_frames: Vec<String>,
+    /// Where and how often frames are dumped, see `enable_debug`
+    debug_dump: Option<DebugDump>,
+    /// Live frames consumer, see debug_server
+    pub debug_stream: Option<Arc<FrameLog>>,
+    pub dot_labels: DotLabels,
+    profile: Option<Profile>,
+    incremental: Option<Incremental>,
+    /// Closures added by `define`, outermost first
+    session: Vec<NodeIndex>,
+    /// Argument types of annotated lambdas, see `check_types`
+    annotations: HashMap<NodeIndex, Type>,
+    /// Applications lowered from `record.field`, see `mark_field_access`
+    field_accesses: HashMap<NodeIndex, Arc<String>>,
+    /// Types declared with `data`, see `declare_data`
+    data_types: Vec<DataType>,
+    source_map: SourceMap,
+
+    /// Where IO builtins perform their effects
+    pub io: Box<dyn EffectHandler>,
+    /// Evaluation fails with `HeapLimitExceeded` once the graph grows past this many nodes
+    pub max_nodes: Option<usize>,
+    /// Directories searched by `#import "file.lambo"`, after the current directory
+    pub import_paths: Vec<PathBuf>,
+    /// Evaluation fails with `FuelExhausted` after this many reductions
+    pub max_steps: Option<usize>,
+    /// When evaluation collects garbage on its own
+    pub gc_policy: GcPolicy,
+    /// Substitute a copy of the unevaluated parameter for every variable, sharing nothing.
+    /// Much slower, but simple enough to test the lazy evaluator against
+    pub call_by_name: bool,
+    /// Fail with `ASTError::Arithmetic` on overflow instead of wrapping around.
+    /// Division by zero is an error either way
+    pub checked_arithmetic: bool,
+    /// Evaluation counters, see `stats`
+    stats: Stats,
+    /// Strict lambdas with how many arguments they need to be given, see `analyze_strictness`
+    strict_lambdas: HashMap<NodeIndex, usize>,
+    /// Nodes being evaluated and detached values a builtin still needs,
+    /// kept along with their whole tree by `collect_unreachable`
+    in_flight: Vec<NodeIndex>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, thiserror::Error)]
 pub enum ASTError {
+    #[error("node {0:?} has no {1:?} edge")]
     EdgeNotFound(NodeIndex, Edge),
+    #[error("node {0:?} does not exist")]
+    NodeNotFound(NodeIndex),
+    #[error("edge {0:?} does not exist")]
+    DanglingEdge(EdgeIndex),
+    #[error("node {0:?} has an invalid parent")]
     ParentError(NodeIndex),
+    #[error("invalid closure chain")]
     InvalidClosureChain,
+    #[error("De Bruijn index {0} does not refer to any binder")]
+    UnboundIndex(usize),
+    #[error("{1} (at node {0:?})")]
     Custom(NodeIndex, &'static str),
+    #[error("{1} (at node {0:?})")]
     TypeError(NodeIndex, &'static str),
+    /// `TypeError` raised by a builtin, shown along with its signature
+    #[error("{2}, {name} : {signature} (at node {0:?})", name = String::from(*.1), signature = .1.signature())]
+    BuiltinTypeError(NodeIndex, ConstructorTag, &'static str),
+    /// Division by zero, or overflow with `checked_arithmetic`
+    #[error("{1} (at node {0:?})")]
+    Arithmetic(NodeIndex, &'static str),
+    /// Error returned by a host function
+    #[error("{1} (at node {0:?})")]
+    Host(NodeIndex, String),
+    /// Check inserted by `insert_casts` failed, the value is of a different type
+    #[error("expected a value of type {1}, got {2} (at node {0:?})")]
+    CastFailed(NodeIndex, String, &'static str),
+    #[error("heap limit of {0} nodes exceeded")]
+    HeapLimitExceeded(usize),
+    #[error("fuel of {0} reduction steps exhausted")]
+    FuelExhausted(usize),
+    /// Program aborted itself with `#io_throw`
+    #[error("uncaught exception: {0}")]
+    Thrown(String),
+    /// Source parsed at runtime, e.g by `define`, is malformed
+    #[error(transparent)]
+    Parse(#[from] ParseErrors),
+    /// Source checked at runtime, e.g by `type_of`, is ill-typed
+    #[error(transparent)]
+    TypeCheck(#[from] TypeCheckError),
+    #[error(transparent)]
+    IO(std::io::Error),
+}
+
+pub type ASTResult<T> = Result<T, ASTError>;
+
+/// Pending work of `AST::evaluate`
+enum Task {
+    Evaluate(NodeIndex),
+    /// Evaluation of a node is over, see `profile_enter`
+    Exit(ProfileFrame),
+    /// Waits for the value of the task above it
+    Continue(Continuation),
+}
+
+/// What evaluating a node amounts to
+enum Step {
+    /// Node is already a value
+    Done(NodeIndex),
+    /// Value of the node is the value of another one
+    Evaluate(NodeIndex),
+    /// Evaluate a node first, then resume with its value
+    Then(NodeIndex, Continuation),
 }
 
-type ASTResult<T> = Result<T, ASTError>;
+/// Node waiting for the value of another one
+enum Continuation {
+    /// Application waiting for its function
+    Apply(NodeIndex),
+    /// Variable waiting for the parameter of its binding closure
+    Substitute {
+        variable: NodeIndex,
+        closure: NodeIndex,
+    },
+    /// Closure of a strict lambda waiting for its parameter
+    StrictBody(NodeIndex),
+    /// Builtin `function` waiting for the parameter of `binder`, then the rest of `arguments`
+    CallBuiltin {
+        function: NodeIndex,
+        tag: ConstructorTag,
+        binder: NodeIndex,
+        arguments: Vec<NodeIndex>,
+        frame: ProfileFrame,
+    },
+}
 
-const GC_INTERVAL: usize = 10_000;
+impl Continuation {
+    /// Detached node to keep alive while waiting, see `in_flight`
+    fn held(&self) -> Option<NodeIndex> {
+        match *self {
+            Self::StrictBody(closure) => Some(closure),
+            Self::CallBuiltin { function, .. } => Some(function),
+            Self::Apply(_) | Self::Substitute { .. } => None,
+        }
+    }
+}
 
 impl Default for AST {
     fn default() -> Self {
@@ -102,8 +297,31 @@ impl AST {
             root: NodeIndex::default(),
             graph: StableGraph::new(),
             debug_frames: Vec::new(),
-            until_gc: GC_INTERVAL,
+            debug_dump: None,
+            debug_stream: None,
+            nodes_after_gc: 0,
+            peak_nodes: 0,
             next_uid: 0,
+            constructors: HashMap::new(),
+            host_functions: Vec::new(),
+            dot_labels: DotLabels::default(),
+            profile: None,
+            incremental: None,
+            session: Vec::new(),
+            annotations: HashMap::new(),
+            field_accesses: HashMap::new(),
+            data_types: Vec::new(),
+            source_map: SourceMap::default(),
+            io: Box::new(StdIO),
+            max_nodes: None,
+            max_steps: None,
+            gc_policy: GcPolicy::default(),
+            call_by_name: false,
+            checked_arithmetic: false,
+            in_flight: vec![],
+            import_paths: Vec::new(),
+            stats: Stats::default(),
+            strict_lambdas: HashMap::new(),
         }
     }
     fn next_uid(&mut self) -> usize {
@@ -111,22 +329,67 @@ impl AST {
         self.next_uid += 1;
         uid
     }
-    fn maybe_gc(&mut self) {
-        if self.until_gc == 0 {
-            let (node_capacity, edge_capacity) = self.graph.capacity();
-            let nodes = self.graph.node_indices().count();
-            let edges = self.graph.edge_indices().count();
-
-            let node_ratio = nodes as f32 / node_capacity as f32;
-            let edge_ratio = edges as f32 / edge_capacity as f32;
-
-            if f32::max(node_ratio, edge_ratio) > 0.75 {
-                self.garbage_collect();
+    /// Tag of the constructor called `name`, created on first use.
+    /// Builtins and the host share constructors with programs through it, and so do `data`
+    /// declarations. Every evaluation of `#constructor` creates a new tag instead
+    pub fn named_constructor(&mut self, name: &str, arity: usize) -> ConstructorTag {
+        let name = Arc::new(name.to_string());
+        match self.constructors.get(&name) {
+            Some(&tag) if tag.arity() == arity => tag,
+            _ => {
+                let tag = ConstructorTag::CustomTag {
+                    uid: self.next_uid(),
+                    arity,
+                };
+                self.constructors.insert(name, tag);
+                tag
             }
-
-            self.until_gc = GC_INTERVAL;
         }
-        self.until_gc -= 1;
+    }
+    /// Detached variable bound to `binder`, so it counts as used forever:
+    /// it is neither collected nor consumed by its last reference
+    fn keep_alive(&mut self, binder: NodeIndex) {
+        let anchor = self.graph.add_node(Node::Variable(VariableKind::Bound));
+        self.graph.add_edge(anchor, binder, Edge::Binder(0));
+    }
+    fn node(&self, id: NodeIndex) -> ASTResult<&Node> {
+        self.graph.node_weight(id).ok_or(ASTError::NodeNotFound(id))
+    }
+    fn node_mut(&mut self, id: NodeIndex) -> ASTResult<&mut Node> {
+        self.graph
+            .node_weight_mut(id)
+            .ok_or(ASTError::NodeNotFound(id))
+    }
+    /// Largest number of live nodes observed at any evaluation step so far
+    pub fn peak_node_count(&self) -> usize {
+        self.peak_nodes.max(self.graph.node_count())
+    }
+    /// Number of lambda applications and builtin calls reduced so far
+    pub fn step_count(&self) -> usize {
+        self.stats.beta_reductions + self.stats.builtin_calls
+    }
+    fn consume_fuel(&mut self) -> ASTResult<()> {
+        match self.max_steps {
+            Some(max_steps) if self.step_count() > max_steps => {
+                Err(ASTError::FuelExhausted(max_steps))
+            }
+            _ => Ok(()),
+        }
+    }
+    fn maybe_gc(&mut self) -> ASTResult<()> {
+        self.peak_nodes = self.peak_nodes.max(self.graph.node_count());
+        if let Some(max_nodes) = self.max_nodes
+            && self.graph.node_count() > max_nodes
+        {
+            self.collect_all()?;
+            if self.graph.node_count() > max_nodes {
+                return Err(ASTError::HeapLimitExceeded(max_nodes));
+            }
+        }
+        if self.graph.node_count() > self.gc_policy.threshold(self.nodes_after_gc) {
+            self.collect_all()?;
+        }
+        Ok(())
     }
     fn get_edge_ref<'a>(
         &'a self,
@@ -143,13 +406,20 @@ impl AST {
         self.get_edge_ref(expr, edge).map(|e| e.target())
     }
     #[tracing::instrument(skip(self))]
-    fn redirect_edge(&mut self, edge_id: EdgeIndex, node: NodeIndex) {
-        let (source, _) = self.graph.edge_endpoints(edge_id).unwrap();
-        let edge = self.graph.remove_edge(edge_id).unwrap();
+    fn redirect_edge(&mut self, edge_id: EdgeIndex, node: NodeIndex) -> ASTResult<()> {
+        let (source, _) = self
+            .graph
+            .edge_endpoints(edge_id)
+            .ok_or(ASTError::DanglingEdge(edge_id))?;
+        let edge = self
+            .graph
+            .remove_edge(edge_id)
+            .ok_or(ASTError::DanglingEdge(edge_id))?;
         self.graph.add_edge(source, node, edge);
+        Ok(())
     }
     #[tracing::instrument(skip(self))]
-    fn migrate_node(&mut self, from: NodeIndex, to: NodeIndex) {
+    fn migrate_node(&mut self, from: NodeIndex, to: NodeIndex) -> ASTResult<()> {
         for edge in self
             .graph
             .edges_directed(from, Direction::Incoming)
@@ -157,15 +427,17 @@ impl AST {
             .map(|e| e.id())
             .collect::<Vec<_>>()
         {
-            self.redirect_edge(edge, to)
+            self.redirect_edge(edge, to)?
         }
 
         if self.root == from {
             self.root = to;
         }
+        self.profile_migrate(from, to);
+        Ok(())
     }
     pub fn get_variable_name(&self, id: NodeIndex) -> ASTResult<&String> {
-        match self.graph.node_weight(id).unwrap() {
+        match self.node(id)? {
             Node::Variable(VariableKind::Free(name)) => Ok(name),
             Node::Variable(VariableKind::Bound) => {
                 let binder_id = self.follow_edge(id, Edge::Binder(0))?;
@@ -181,7 +453,7 @@ impl AST {
         }
     }
     pub fn fmt_expr(&self, expr: NodeIndex) -> ASTResult<String> {
-        match &self.graph[expr] {
+        match self.node(expr)? {
             Node::Variable(_) => Ok(self.get_variable_name(expr)?.to_string()),
             Node::Lambda { argument_name } => Ok(format!(
                 "λ{}.{}",
@@ -194,11 +466,14 @@ impl AST {
                 self.fmt_expr(self.follow_edge(expr, Edge::Parameter)?)?
             )),
             Node::Primitive(Primitive::Number(number)) => Ok(format!("{}", number)),
-            Node::Primitive(Primitive::Bytes(bytes)) => Ok(format!(
-                "{:?}",
-                str::from_utf8(bytes)
-                    .map_err(|_| ASTError::Custom(expr, "Bytes is not a valid ut8 string"))?
-            )),
+            Node::Primitive(Primitive::Float(float)) => Ok(format_float(*float)),
+            // Bytes read as is, e.g by `#io_readall`, need not be UTF-8
+            Node::Primitive(Primitive::Bytes(bytes)) => {
+                Ok(format!("{:?}", String::from_utf8_lossy(bytes)))
+            }
+            Node::Primitive(Primitive::String(string)) => {
+                Ok(format!("(#str_from_bytes {:?})", string))
+            }
             Node::Closure { argument_name, .. } => Ok(format!(
                 "let {} \n{} in\n{}",
                 argument_name,
@@ -207,24 +482,16 @@ impl AST {
             )),
             Node::Debug(_) => Ok(String::new()),
             Node::Data { tag } => {
-                let tag_string = String::try_from(*tag).unwrap().replace("*", " *");
-                let mut edges = self
-                    .graph
-                    .edges_directed(expr, Direction::Outgoing)
-                    .collect::<Vec<_>>();
-                edges.sort_by_key(|e| match *e.weight() {
-                    Edge::Binder(argument_index) => argument_index,
-                    _ => panic!(),
-                });
-                let assigned_params = edges
+                let tag_string = String::from(*tag).replace("*", " *");
+                let assigned_params = ConstructorTag::get_binders(self, expr)?
                     .into_iter()
-                    .map(|e| match self.graph.node_weight(e.target()).unwrap() {
+                    .map(|binder| match self.node(binder)? {
                         Node::Closure { argument_name } | Node::Lambda { argument_name } => {
-                            argument_name.to_string()
+                            Ok(argument_name.to_string())
                         }
-                        other => panic!("Incorrect binder {:?}", other),
+                        _ => Err(ASTError::Custom(binder, "Incorrect binder")),
                     })
-                    .collect::<Vec<_>>()
+                    .collect::<ASTResult<Vec<_>>>()?
                     .join(" ");
 
                 Ok(if !assigned_params.is_empty() {
@@ -241,29 +508,50 @@ impl AST {
         &mut self,
         node_id: NodeIndex,
         mut binder_remaps: HashMap<NodeIndex, NodeIndex>,
-    ) -> NodeIndex {
-        let node_weight = self.graph.node_weight(node_id).unwrap().clone();
-        let is_binder = matches!(node_weight, Node::Closure { .. } | Node::Lambda { .. });
-        let cloned_id = self.graph.add_node(node_weight);
+    ) -> ASTResult<NodeIndex> {
+        // Explicit stack, values like long lists are too deep to recurse over.
+        // Binders are above their variables, so they are always cloned first.
+        let mut root = None;
+        let mut stack = vec![(node_id, None)];
+        while let Some((node_id, parent)) = stack.pop() {
+            let node_weight = self.node(node_id)?.clone();
+            let is_binder = matches!(node_weight, Node::Closure { .. } | Node::Lambda { .. });
+            let cloned_id = self.graph.add_node(node_weight);
+            self.profile_clone(node_id, cloned_id);
 
-        if is_binder {
-            binder_remaps.insert(node_id, cloned_id);
-        }
+            if is_binder {
+                binder_remaps.insert(node_id, cloned_id);
+                if let Some(&needed) = self.strict_lambdas.get(&node_id) {
+                    self.strict_lambdas.insert(cloned_id, needed);
+                }
+            }
+            match parent {
+                Some((parent, weight)) => {
+                    self.graph.add_edge(parent, cloned_id, weight);
+                }
+                None => root = Some(cloned_id),
+            }
 
-        let edges = self
-            .graph
-            .edges_directed(node_id, Direction::Outgoing)
-            .map(|e| (e.target(), *e.weight()))
-            .collect::<Vec<_>>();
-
-        for (target, weight) in edges {
-            let to = match weight {
-                Edge::Binder(_) => *binder_remaps.get(&target).unwrap_or(&target),
-                _ => self.clone_subtree(target, binder_remaps.clone()),
-            };
-            self.graph.add_edge(cloned_id, to, weight);
+            let edges = self
+                .graph
+                .edges_directed(node_id, Direction::Outgoing)
+                .map(|e| (e.target(), *e.weight()))
+                .collect::<Vec<_>>();
+
+            let mut children = vec![];
+            for (target, weight) in edges {
+                match weight {
+                    Edge::Binder(_) => {
+                        let to = *binder_remaps.get(&target).unwrap_or(&target);
+                        self.graph.add_edge(cloned_id, to, weight);
+                    }
+                    _ => children.push((target, Some((cloned_id, weight)))),
+                }
+            }
+            // Reversed so that children are cloned, and their edges added, in order
+            stack.extend(children.into_iter().rev());
         }
-        cloned_id
+        Ok(root.expect("subtree root is cloned first"))
     }
 
     /// Lifts environment above the current node and returns the length of lifted closure chain
@@ -274,28 +562,28 @@ impl AST {
         node_under_closures: NodeIndex,
         edge: Edge,
     ) -> ASTResult<()> {
-        assert!(
-            !matches!(
-                self.graph.node_weight(node_under_closures).unwrap(),
-                Node::Closure { .. }
-            ),
-            "Node under closures can't itself be a closure"
-        );
+        if let Node::Closure { .. } = self.node(node_under_closures)? {
+            return Err(ASTError::Custom(
+                node_under_closures,
+                "Node under closures can't itself be a closure",
+            ));
+        }
         let (edge_id, edge_target) = self
             .get_edge_ref(node_id, edge)
             .map(|edge_ref| (edge_ref.id(), edge_ref.target()))?;
 
-        if let Node::Closure { .. } = self.graph.node_weight(edge_target).unwrap() {
+        if let Node::Closure { .. } = self.node(edge_target)? {
             let first_closure = edge_target;
             // Parent now points to a closure chain
-            self.migrate_node(node_id, first_closure);
+            self.migrate_node(node_id, first_closure)?;
 
             // Closure chain now points to current node
-            self.migrate_node(node_under_closures, node_id);
+            self.migrate_node(node_under_closures, node_id)?;
 
             // Current edge now points to whatever was under closure chain
-            self.redirect_edge(edge_id, node_under_closures);
+            self.redirect_edge(edge_id, node_under_closures)?;
 
+            self.stats.lifts += 1;
             self.add_debug_frame_with_annotation(node_under_closures, "Lift");
         }
 
@@ -323,13 +611,27 @@ impl AST {
         }
     }
 
-    pub fn debug_ast_error(&self, error: ASTError) {
-        println!("\n\n{:?}", error);
-        let id = match error {
+    pub fn debug_ast_error(&self, error: &ASTError) {
+        println!("\n\n{}", error);
+        let id = match *error {
             ASTError::EdgeNotFound(id, _edge) => id,
+            ASTError::NodeNotFound(id) => id,
             ASTError::ParentError(id) => id,
             ASTError::Custom(id, _) => id,
-            _ => todo!(),
+            ASTError::TypeError(id, _) => id,
+            ASTError::BuiltinTypeError(id, ..) => id,
+            ASTError::CastFailed(id, ..) => id,
+            ASTError::Host(id, _) => id,
+            ASTError::Arithmetic(id, _) => id,
+            ASTError::DanglingEdge(_)
+            | ASTError::InvalidClosureChain
+            | ASTError::UnboundIndex(_)
+            | ASTError::HeapLimitExceeded(_)
+            | ASTError::FuelExhausted(_)
+            | ASTError::Thrown(_)
+            | ASTError::Parse(_)
+            | ASTError::TypeCheck(_)
+            | ASTError::IO(_) => return,
         };
         self.debug_node(id);
     }
@@ -341,162 +643,396 @@ impl AST {
             .map(|e| e.source())
     }
 
-    /// Returns NodeIndex under the closure chain
+    /// Returns NodeIndex under the closure chain.
+    /// Nodes waiting for the value of another one are kept on an explicit stack of tasks
+    /// rather than the native one, so evaluation depth is only limited by memory.
+    /// Builtins still evaluate lazy arguments with nested calls, see `call_builtin`
     pub fn evaluate(&mut self, node_id: NodeIndex) -> Result<NodeIndex, ASTError> {
-        self.maybe_gc();
-        self.add_debug_frame_with_annotation(node_id, "evaluate");
-        match *self.graph.node_weight(node_id).unwrap() {
-            Node::Closure { .. } => {
-                let body = self.follow_edge(node_id, Edge::Body)?;
-                return self.evaluate(body);
-            }
-            Node::Application => {
-                let under_closures = self.evaluate(self.follow_edge(node_id, Edge::Function)?)?;
-                self.lift_closure_chain(node_id, under_closures, Edge::Function)?;
-
-                let function = self.follow_edge(node_id, Edge::Function)?;
-                let parameter = self.follow_edge(node_id, Edge::Parameter)?;
-
-                match self.graph.node_weight(function).unwrap() {
-                    // Partial application for data tags
-                    &Node::Data { tag } => {
-                        let provided_count = self.graph.neighbors(function).count();
-                        if provided_count < tag.arity() {
-                            let binding_closure = if let Node::Variable(VariableKind::Bound) =
-                                self.graph.node_weight(parameter).unwrap()
-                            {
-                                self.add_debug_frame_with_annotation(
-                                    node_id,
-                                    "GC: Redirecting application",
-                                );
-                                let true_binder = self.follow_edge(parameter, Edge::Binder(0))?;
-                                self.migrate_node(node_id, function);
-                                self.graph.remove_node(node_id);
-                                self.graph.remove_node(parameter);
-                                true_binder
-                            } else {
-                                // Current node becomes a closure
-                                *self.graph.node_weight_mut(node_id).unwrap() = Node::Closure {
-                                    argument_name: Rc::new(
-                                        tag.argument_names()[provided_count].to_string(),
-                                    ),
-                                };
-                                let edge_id = self.get_edge_ref(node_id, Edge::Function)?.id();
-                                *self.graph.edge_weight_mut(edge_id).unwrap() = Edge::Body;
-                                node_id
-                            };
-
-                            // Add new binder!
-                            self.graph.add_edge(
-                                function,
-                                binding_closure,
-                                Edge::Binder(provided_count),
-                            );
-
-                            return if provided_count + 1 == tag.arity() {
-                                tag.evaluate(self, function)
-                            } else {
-                                Ok(function)
-                            };
-                        }
+        let mut tasks = vec![Task::Evaluate(node_id)];
+        let mut result = Ok(node_id);
+        while let Some(task) = tasks.pop() {
+            let step = match task {
+                Task::Evaluate(id) => {
+                    let frame = self.profile_enter(id);
+                    self.in_flight.push(id);
+                    tasks.push(Task::Exit(frame));
+                    self.evaluate_node(id)
+                }
+                Task::Exit(frame) => {
+                    self.in_flight.pop();
+                    self.profile_exit(frame);
+                    continue;
+                }
+                Task::Continue(continuation) => {
+                    if continuation.held().is_some() {
+                        self.in_flight.pop();
                     }
-                    Node::Lambda { argument_name } => {
-                        let skip_through = |ast: &mut Self| {
-                            let body = ast.follow_edge(function, Edge::Body)?;
-                            ast.migrate_node(node_id, body);
-                            ast.graph.remove_node(node_id);
-                            ast.graph.remove_node(function);
-                            ast.remove_subtree(parameter);
-                            ast.evaluate(body)
-                        };
-
-                        if self.binder_references(function).next().is_none() {
-                            // Function has no binders, parameter will be ignored!
-                            self.add_debug_frame_with_annotation(
-                                function,
-                                "GC: Parameter is never used",
-                            );
-                            return skip_through(self);
+                    match result {
+                        Ok(value) => self.resume(continuation, value),
+                        Err(_) => {
+                            self.abandon(continuation);
+                            continue;
                         }
-                        if let Node::Variable(VariableKind::Bound) =
-                            self.graph.node_weight(parameter).unwrap()
-                        {
-                            // Paramater is not interesting - simply pointing to the other place.
-                            // No need to create closure here
-                            self.add_debug_frame_with_annotation(
-                                node_id,
-                                "GC: Redirecting application",
-                            );
-                            let true_binder = self.follow_edge(parameter, Edge::Binder(0))?;
-
-                            // Redirect all variables to a new binder
-                            for variable in self.binder_references(function).collect::<Vec<_>>() {
-                                let (edge_id, edge_weight) = self
-                                    .graph
-                                    .edges_connecting(variable, function)
-                                    .next()
-                                    .map(|e| (e.id(), *e.weight()))
-                                    .unwrap();
-                                self.graph.remove_edge(edge_id);
-                                self.graph.add_edge(variable, true_binder, edge_weight);
-                            }
-
-                            return skip_through(self);
-                        }
-
-                        let argument_name = argument_name.clone();
-
-                        // Lambda node becomes a closure
-                        self.migrate_node(node_id, function);
-                        *self.graph.node_weight_mut(function).unwrap() =
-                            Node::Closure { argument_name };
-                        let closure_id = function;
-
-                        // Add parameter edge to the closure
-                        let parameter_target = self.follow_edge(node_id, Edge::Parameter)?;
-                        self.graph
-                            .add_edge(closure_id, parameter_target, Edge::Parameter);
-
-                        // Cleanup application node
-                        self.graph.remove_node(node_id);
-
-                        return self.evaluate(closure_id);
                     }
-                    _ => {}
                 }
+            };
+            match step {
+                Ok(Step::Done(value)) => result = Ok(value),
+                Ok(Step::Evaluate(id)) => tasks.push(Task::Evaluate(id)),
+                Ok(Step::Then(id, continuation)) => {
+                    if let Some(held) = continuation.held() {
+                        self.in_flight.push(held);
+                    }
+                    tasks.push(Task::Continue(continuation));
+                    tasks.push(Task::Evaluate(id));
+                }
+                Err(error) => result = Err(error),
             }
+        }
+        result
+    }
+
+    fn evaluate_node(&mut self, node_id: NodeIndex) -> ASTResult<Step> {
+        self.maybe_gc()?;
+        self.add_debug_frame_with_annotation(node_id, "evaluate");
+        match *self.node(node_id)? {
+            Node::Closure { .. } => Ok(Step::Evaluate(self.follow_edge(node_id, Edge::Body)?)),
+            Node::Application => Ok(Step::Then(
+                self.follow_edge(node_id, Edge::Function)?,
+                Continuation::Apply(node_id),
+            )),
             Node::Variable(VariableKind::Bound) => {
                 let binding_closure_id = self.follow_edge(node_id, Edge::Binder(0))?;
 
-                let (parameter, is_dangling) =
-                    self.evaluate_closure_parameter(binding_closure_id)?;
+                // Argument of an enclosing lambda, only reachable by `normalize`
+                if let Node::Lambda { .. } = self.node(binding_closure_id)? {
+                    return Ok(Step::Done(node_id));
+                }
+                // Constructors are still created once per binding, a copy would be another one
+                if self.call_by_name && !self.creates_constructor(binding_closure_id)? {
+                    // Last use takes the parameter itself, so no closure is left behind
+                    let copy = if self.binder_references(binding_closure_id).nth(1).is_none() {
+                        self.remove_closure(binding_closure_id)?
+                    } else {
+                        let parameter = self.follow_edge(binding_closure_id, Edge::Parameter)?;
+                        self.stats.subtree_clones += 1;
+                        self.clone_subtree(parameter, HashMap::new())?
+                    };
+                    self.migrate_node(node_id, copy)?;
+                    self.remove_node(node_id);
+                    return Ok(Step::Evaluate(copy));
+                }
+
+                Ok(Step::Then(
+                    self.follow_edge(binding_closure_id, Edge::Parameter)?,
+                    Continuation::Substitute {
+                        variable: node_id,
+                        closure: binding_closure_id,
+                    },
+                ))
+            }
+            _ => Ok(Step::Done(node_id)),
+        }
+    }
+
+    /// Carry on with `continuation` now that the node it waited for evaluated to `value`
+    fn resume(&mut self, continuation: Continuation, value: NodeIndex) -> ASTResult<Step> {
+        match continuation {
+            Continuation::Apply(node_id) => self.apply_evaluated(node_id, value),
+            Continuation::Substitute { variable, closure } => {
+                let (parameter, is_dangling) = self.take_closure_parameter(closure, value)?;
 
                 let cloned_node_id = if is_dangling {
                     parameter
                 } else {
-                    self.clone_subtree(parameter, HashMap::new())
+                    self.stats.subtree_clones += 1;
+                    self.clone_subtree(parameter, HashMap::new())?
                 };
-                self.migrate_node(node_id, cloned_node_id);
-                self.graph.remove_node(node_id);
-                return Ok(cloned_node_id);
+                self.migrate_node(variable, cloned_node_id)?;
+                self.remove_node(variable);
+                Ok(Step::Done(cloned_node_id))
+            }
+            Continuation::StrictBody(closure_id) => self.enter_strict_closure(closure_id, value),
+            Continuation::CallBuiltin {
+                function,
+                tag,
+                binder,
+                arguments,
+                frame,
+            } => {
+                if let Err(error) = self.lift_closure_chain(binder, value, Edge::Parameter) {
+                    self.profile_exit(frame);
+                    return Err(error);
+                }
+                self.call_builtin(function, tag, arguments, frame)
+            }
+        }
+    }
+
+    /// Undo what `continuation` holds on to when evaluation failed before it could resume
+    fn abandon(&mut self, continuation: Continuation) {
+        if let Continuation::CallBuiltin { frame, .. } = continuation {
+            self.profile_exit(frame);
+        }
+    }
+
+    /// Application `node_id` whose function evaluated to `under_closures`
+    fn apply_evaluated(
+        &mut self,
+        node_id: NodeIndex,
+        under_closures: NodeIndex,
+    ) -> ASTResult<Step> {
+        self.lift_closure_chain(node_id, under_closures, Edge::Function)?;
+
+        let function = self.follow_edge(node_id, Edge::Function)?;
+        let parameter = self.follow_edge(node_id, Edge::Parameter)?;
+
+        if let Node::Lambda { .. } = self.node(function)? {
+            self.stats.beta_reductions += 1;
+            self.consume_fuel()?;
+        }
+        match self.node(function)? {
+            // Partial application for data tags
+            &Node::Data { tag } => {
+                let provided_count = self.graph.neighbors(function).count();
+                if provided_count < tag.arity() {
+                    // Arguments of builtins get their own closure when sharing is off,
+                    // so that evaluating them copies the variable
+                    let binding_closure = if let Node::Variable(VariableKind::Bound) =
+                        self.node(parameter)?
+                        && !self.call_by_name
+                    {
+                        self.add_debug_frame_with_annotation(
+                            node_id,
+                            "GC: Redirecting application",
+                        );
+                        let true_binder = self.follow_edge(parameter, Edge::Binder(0))?;
+                        self.migrate_node(node_id, function)?;
+                        self.remove_node(node_id);
+                        self.remove_node(parameter);
+                        true_binder
+                    } else {
+                        // Current node becomes a closure
+                        *self.node_mut(node_id)? = Node::Closure {
+                            argument_name: Arc::new(
+                                tag.argument_names()[provided_count].to_string(),
+                            ),
+                        };
+                        let edge_id = self.get_edge_ref(node_id, Edge::Function)?.id();
+                        *self
+                            .graph
+                            .edge_weight_mut(edge_id)
+                            .ok_or(ASTError::DanglingEdge(edge_id))? = Edge::Body;
+                        node_id
+                    };
+
+                    // Add new binder!
+                    self.graph
+                        .add_edge(function, binding_closure, Edge::Binder(provided_count));
+
+                    if provided_count + 1 < tag.arity() {
+                        return Ok(Step::Done(function));
+                    }
+                    self.stats.builtin_calls += 1;
+                    self.consume_fuel()?;
+                    let frame = self.profile_enter_builtin(tag);
+                    let strict = tag.strict_arguments();
+                    let arguments = ConstructorTag::get_binders(self, function)?
+                        .into_iter()
+                        .zip(strict)
+                        .filter(|&(_, strict)| strict)
+                        .map(|(binder, _)| binder)
+                        .rev()
+                        .collect();
+                    return self.call_builtin(function, tag, arguments, frame);
+                }
+            }
+            Node::Lambda { argument_name } => {
+                let skip_through = |ast: &mut Self| {
+                    let body = ast.follow_edge(function, Edge::Body)?;
+                    ast.migrate_node(node_id, body)?;
+                    ast.remove_node(node_id);
+                    ast.remove_node(function);
+                    ast.remove_subtree(parameter);
+                    Ok(Step::Evaluate(body))
+                };
+
+                if self.binder_references(function).next().is_none() {
+                    // Function has no binders, parameter will be ignored!
+                    self.add_debug_frame_with_annotation(function, "GC: Parameter is never used");
+                    return skip_through(self);
+                }
+                if let Node::Variable(VariableKind::Bound) = self.node(parameter)? {
+                    // Paramater is not interesting - simply pointing to the other place.
+                    // No need to create closure here
+                    self.add_debug_frame_with_annotation(node_id, "GC: Redirecting application");
+                    let true_binder = self.follow_edge(parameter, Edge::Binder(0))?;
+
+                    // Redirect all variables to a new binder
+                    for variable in self.binder_references(function).collect::<Vec<_>>() {
+                        let (edge_id, edge_weight) = self
+                            .graph
+                            .edges_connecting(variable, function)
+                            .next()
+                            .map(|e| (e.id(), *e.weight()))
+                            .ok_or(ASTError::Custom(variable, "Variable lost its binder"))?;
+                        self.graph.remove_edge(edge_id);
+                        self.graph.add_edge(variable, true_binder, edge_weight);
+                    }
+
+                    return skip_through(self);
+                }
+
+                let argument_name = argument_name.clone();
+                let is_strict = self
+                    .strict_lambdas
+                    .remove(&function)
+                    .is_some_and(|needed| self.is_applied_to(node_id, needed))
+                    && !self.call_by_name;
+
+                // Lambda node becomes a closure
+                self.migrate_node(node_id, function)?;
+                *self.node_mut(function)? = Node::Closure { argument_name };
+                let closure_id = function;
+
+                // Add parameter edge to the closure
+                let parameter_target = self.follow_edge(node_id, Edge::Parameter)?;
+                self.graph
+                    .add_edge(closure_id, parameter_target, Edge::Parameter);
+
+                // Cleanup application node
+                self.remove_node(node_id);
+
+                if is_strict {
+                    // Parameter of a strict lambda is evaluated before its body
+                    self.stats.strict_arguments += 1;
+                    return Ok(Step::Then(
+                        self.follow_edge(closure_id, Edge::Parameter)?,
+                        Continuation::StrictBody(closure_id),
+                    ));
+                }
+                return Ok(Step::Evaluate(closure_id));
             }
             _ => {}
         }
+        Ok(Step::Done(node_id))
+    }
 
-        Ok(node_id)
+    /// Evaluate the next of strict `arguments` of builtin `function`, last one first,
+    /// and call it once there are none left. Evaluating them here rather than in the builtin
+    /// keeps e.g `+ n (sum rest)` from nesting native calls for every element of `rest`
+    fn call_builtin(
+        &mut self,
+        function: NodeIndex,
+        tag: ConstructorTag,
+        mut arguments: Vec<NodeIndex>,
+        frame: ProfileFrame,
+    ) -> ASTResult<Step> {
+        while let Some(binder) = arguments.pop() {
+            // Arguments of an enclosing lambda are left to the builtin, see `normalize`,
+            // and so are broken closures, for it to report
+            if let Ok(Node::Closure { .. }) = self.node(binder)
+                && let Ok(parameter) = self.follow_edge(binder, Edge::Parameter)
+                && !self.is_evaluated(parameter)?
+            {
+                return Ok(Step::Then(
+                    parameter,
+                    Continuation::CallBuiltin {
+                        function,
+                        tag,
+                        binder,
+                        arguments,
+                        frame,
+                    },
+                ));
+            }
+        }
+        let result = tag.evaluate(self, function);
+        self.profile_exit(frame);
+        let result = result?;
+        // Builtins may return an expression to continue with, e.g a branch of `#match`
+        if self.is_evaluated(result)? {
+            Ok(Step::Done(result))
+        } else {
+            Ok(Step::Evaluate(result))
+        }
+    }
+
+    /// Closure of a strict lambda whose parameter evaluated to `under_closures`.
+    /// Numbers are copied into the variables right away, without keeping the closure
+    fn enter_strict_closure(
+        &mut self,
+        closure_id: NodeIndex,
+        under_closures: NodeIndex,
+    ) -> ASTResult<Step> {
+        self.lift_closure_chain(closure_id, under_closures, Edge::Parameter)?;
+
+        let value = self.follow_edge(closure_id, Edge::Parameter)?;
+        let references = self.binder_references(closure_id).collect::<Vec<_>>();
+        if let Node::Primitive(number @ Primitive::Number(_)) = self.node(value)?
+            && references
+                .iter()
+                .all(|&id| matches!(self.graph[id], Node::Variable(_)))
+        {
+            let number = number.clone();
+            for variable in references {
+                *self.node_mut(variable)? = Node::Primitive(number.clone());
+                let edge_id = self.get_edge_ref(variable, Edge::Binder(0))?.id();
+                self.graph.remove_edge(edge_id);
+            }
+            let body = self.follow_edge(closure_id, Edge::Body)?;
+            let parameter = self.remove_closure(closure_id)?;
+            self.remove_node(parameter);
+            return Ok(Step::Evaluate(body));
+        }
+        Ok(Step::Evaluate(closure_id))
     }
 
     /// Properly evaluates closure's parameter, handling:
     ///  - lifting
     ///  - garbage collecting if necessary
+    ///
     /// Returns (reference to a parameter, is_dangling)
     fn evaluate_closure_parameter(
         &mut self,
         binding_closure_id: NodeIndex,
     ) -> ASTResult<(NodeIndex, bool)> {
-        let under_closures =
-            self.evaluate(self.follow_edge(binding_closure_id, Edge::Parameter)?)?;
+        let parameter = self.follow_edge(binding_closure_id, Edge::Parameter)?;
+        let under_closures = if self.is_evaluated(parameter)? {
+            parameter
+        } else {
+            self.evaluate(parameter)?
+        };
+        self.take_closure_parameter(binding_closure_id, under_closures)
+    }
+
+    /// Whether evaluating `node_id` would return it as is
+    fn is_evaluated(&self, node_id: NodeIndex) -> ASTResult<bool> {
+        Ok(!matches!(
+            self.node(node_id)?,
+            Node::Application | Node::Closure { .. } | Node::Variable(VariableKind::Bound)
+        ))
+    }
+
+    /// Whether the parameter of `binding_closure_id` is a `#constructor` call
+    fn creates_constructor(&self, binding_closure_id: NodeIndex) -> ASTResult<bool> {
+        let parameter = self.follow_edge(binding_closure_id, Edge::Parameter)?;
+        Ok(matches!(self.node(parameter)?, Node::Application)
+            && matches!(
+                self.node(self.follow_edge(parameter, Edge::Function)?)?,
+                Node::Data {
+                    tag: ConstructorTag::HelperFunction(HelperFunctionTag::CreateConstructor)
+                }
+            ))
+    }
 
+    /// Parameter of `binding_closure_id` which evaluated to `under_closures`,
+    /// moved out of the closure on its last use, see `evaluate_closure_parameter`
+    fn take_closure_parameter(
+        &mut self,
+        binding_closure_id: NodeIndex,
+        under_closures: NodeIndex,
+    ) -> ASTResult<(NodeIndex, bool)> {
         let has_other_referrers = self.binder_references(binding_closure_id).take(2).count() == 2;
 
         self.lift_closure_chain(binding_closure_id, under_closures, Edge::Parameter)?;
@@ -507,6 +1043,7 @@ impl AST {
                 false,
             )
         } else {
+            self.stats.last_use_moves += 1;
             self.add_debug_frame_with_annotation(binding_closure_id, "GC: Last usage");
             (self.remove_closure(binding_closure_id)?, true)
         })
@@ -520,15 +1057,38 @@ impl AST {
         }));
         let edge = self.graph.add_edge(node, id, Edge::Debug);
         self.add_debug_frame();
-        self.graph.remove_node(node);
+        self.remove_node(node);
         self.graph.remove_edge(edge);
     }
     pub fn add_debug_frame(&mut self) {
-        if false {
-            self.debug_frames.push(self.to_dot());
+        if let Some(stream) = &self.debug_stream {
+            stream.push(DebugFrame {
+                dot: self.to_dot(),
+                nodes: self.graph.node_count(),
+                edges: self.graph.edge_count(),
+            });
         }
+        if let Some(dump) = &mut self.debug_dump {
+            let keep = dump.seen % dump.every == 0;
+            dump.seen += 1;
+            if keep {
+                self.debug_frames.push(self.to_dot());
+            }
+        }
+    }
+    /// Keep every `every`th frame, to be written into `dir` by `dump_debug`
+    pub fn enable_debug(&mut self, dir: impl Into<PathBuf>, every: usize) {
+        self.debug_dump = Some(DebugDump {
+            dir: dir.into(),
+            every: every.max(1),
+            seen: 0,
+        });
     }
     pub fn dump_debug(&self) {
+        let Some(dump) = &self.debug_dump else {
+            return;
+        };
+        std::fs::create_dir_all(&dump.dir).unwrap();
         let mut seen = HashSet::new();
 
         for (id, frame) in self
@@ -537,31 +1097,39 @@ impl AST {
             .filter(|frame| seen.insert(*frame))
             .enumerate()
         {
-            std::fs::write(format!("./ast-{:04}.dot", id), frame).unwrap();
+            std::fs::write(dump.dir.join(format!("ast-{:04}.dot", id)), frame).unwrap();
         }
     }
 
+    pub(crate) fn remove_node(&mut self, node_id: NodeIndex) -> Option<Node> {
+        self.profile_forget(node_id);
+        self.annotations.remove(&node_id);
+        self.field_accesses.remove(&node_id);
+        self.strict_lambdas.remove(&node_id);
+        self.source_map.forget(node_id);
+        self.graph.remove_node(node_id)
+    }
+
     #[tracing::instrument(skip(self))]
     fn remove_subtree(&mut self, node_id: NodeIndex) {
-        let children = self
-            .graph
-            .edges_directed(node_id, Direction::Outgoing)
-            .filter(|e| !matches!(e.weight(), Edge::Binder(_)))
-            .map(|e| e.target())
-            .collect::<Vec<_>>();
-
-        for child in children {
-            self.remove_subtree(child);
+        let mut stack = vec![node_id];
+        while let Some(node_id) = stack.pop() {
+            stack.extend(
+                self.graph
+                    .edges_directed(node_id, Direction::Outgoing)
+                    .filter(|e| !matches!(e.weight(), Edge::Binder(_)))
+                    .map(|e| e.target()),
+            );
+            self.remove_node(node_id);
         }
-        self.graph.remove_node(node_id);
     }
 
     /// Returns dangling parameter
     fn remove_closure(&mut self, closure_id: NodeIndex) -> ASTResult<NodeIndex> {
         let body = self.follow_edge(closure_id, Edge::Body)?;
         let parameter = self.follow_edge(closure_id, Edge::Parameter)?;
-        self.migrate_node(closure_id, body);
-        self.graph.remove_node(closure_id);
+        self.migrate_node(closure_id, body)?;
+        self.remove_node(closure_id);
         Ok(parameter)
     }
 }
@@ -575,3 +1143,23 @@ impl Display for AST {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{EvalConfig, parse, run_with_stack};
+
+    /// Every pending `+` waits on the task stack, not on the native one
+    #[test]
+    fn deep_recursion_fits_small_stack() {
+        let source = "let Y \\f.(\\x.f (x x)) (\\x.f (x x)) in
+            let sum Y \\sum n.(=num n 0) 0 (+ n (sum (- 1 n))) in
+            sum 10000";
+        let value = run_with_stack(256 * 1024, move || {
+            parse(source)
+                .and_then(|program| program.evaluate(EvalConfig::default()))
+                .map(|value| value.to_usize())
+        })
+        .unwrap();
+        assert_eq!(value.unwrap(), Some(50005000));
+    }
+}
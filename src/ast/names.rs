@@ -0,0 +1,93 @@
+use std::sync::Arc;
+
+use petgraph::graph::NodeIndex;
+
+use crate::ast::{AST, Edge, Node, VariableKind, warning::Warning};
+
+/// Names that are probably typos or mistakes, since a misspelled variable
+/// silently becomes free and only fails deep into evaluation
+impl AST {
+    /// Warn about free variables written in the source and about bindings that shadow
+    /// an outer one with the same name. `ALL_CAPS` free variables like `EXHAUSTED`
+    /// are deliberate markers and `_` can be bound any number of times.
+    pub fn check_names(&self) -> Vec<Warning> {
+        let mut warnings = vec![];
+        self.visit_names(self.root, &mut vec![], &mut warnings);
+        warnings
+    }
+
+    fn visit_names(
+        &self,
+        id: NodeIndex,
+        scope: &mut Vec<(Arc<String>, NodeIndex)>,
+        warnings: &mut Vec<Warning>,
+    ) {
+        match self.graph.node_weight(id) {
+            Some(Node::Lambda { argument_name }) => {
+                self.check_shadowing(id, argument_name, scope, warnings);
+                scope.push((argument_name.clone(), id));
+                if let Ok(body) = self.follow_edge(id, Edge::Body) {
+                    self.visit_names(body, scope, warnings);
+                }
+                scope.pop();
+            }
+            Some(Node::Closure { argument_name }) => {
+                // `let` is not recursive, the value doesn't see its own name
+                if let Ok(parameter) = self.follow_edge(id, Edge::Parameter) {
+                    self.visit_names(parameter, scope, warnings);
+                }
+                self.check_shadowing(id, argument_name, scope, warnings);
+                scope.push((argument_name.clone(), id));
+                if let Ok(body) = self.follow_edge(id, Edge::Body) {
+                    self.visit_names(body, scope, warnings);
+                }
+                scope.pop();
+            }
+            Some(Node::Application) => {
+                for edge in [Edge::Function, Edge::Parameter] {
+                    if let Ok(child) = self.follow_edge(id, edge) {
+                        self.visit_names(child, scope, warnings);
+                    }
+                }
+            }
+            // Free variables made up by the parser, e.g `NO_MATCH`, have no span
+            Some(Node::Variable(VariableKind::Free(name)))
+                if self.span(id).is_some() && !is_marker(name) =>
+            {
+                warnings.push(
+                    self.warning(id, format!("`{name}` is not defined, nor is it a builtin")),
+                );
+            }
+            _ => {}
+        }
+    }
+
+    /// Binders made up by the parser, e.g for `record.field`, have no span
+    fn check_shadowing(
+        &self,
+        id: NodeIndex,
+        name: &Arc<String>,
+        scope: &[(Arc<String>, NodeIndex)],
+        warnings: &mut Vec<Warning>,
+    ) {
+        if **name == "_" || self.binder_span(id).is_none() {
+            return;
+        }
+        let Some(&(_, outer)) = scope.iter().rfind(|(outer, _)| outer == name) else {
+            return;
+        };
+        let message = match self.binder_span(outer) {
+            Some(span) => format!("`{name}` shadows the one bound on line {}", span.start.line),
+            None => format!("`{name}` shadows an outer binding"),
+        };
+        warnings.push(self.binder_warning(id, message));
+    }
+}
+
+/// `EXHAUSTED`, `ERROR_EMPTY_OPTION` and the like
+fn is_marker(name: &str) -> bool {
+    name.chars().any(|c| c.is_ascii_uppercase())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+}
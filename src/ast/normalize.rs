@@ -0,0 +1,100 @@
+use std::collections::HashSet;
+
+use petgraph::{graph::NodeIndex, visit::EdgeRef};
+
+use crate::ast::{AST, ASTResult, Edge, Node, VariableKind};
+
+/// Strong reduction: unlike `evaluate`, which stops at the first lambda or constructor,
+/// reduce everywhere, including lambda bodies and constructor arguments
+impl AST {
+    /// Reduce `id` to its full normal form, e.g `λx.(λy.y) x` becomes `λx.x`.
+    /// Variables of enclosing lambdas are unknown, so builtins needing their value fail.
+    /// Returns NodeIndex under the closure chain, like `evaluate`
+    pub fn normalize(&mut self, id: NodeIndex) -> ASTResult<NodeIndex> {
+        let value = self.evaluate(id)?;
+        match self.node(value)? {
+            Node::Lambda { .. } => {
+                self.normalize(self.follow_edge(value, Edge::Body)?)?;
+            }
+            // Stuck on a variable, arguments can still be reduced
+            Node::Application => {
+                self.normalize(self.follow_edge(value, Edge::Function)?)?;
+                self.normalize(self.follow_edge(value, Edge::Parameter)?)?;
+            }
+            Node::Data { .. } => {
+                for binder in self.binders(value).collect::<Vec<_>>() {
+                    if let Node::Closure { .. } = self.node(binder)? {
+                        self.normalize(self.follow_edge(binder, Edge::Parameter)?)?;
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(value)
+    }
+
+    /// Rewrite every `λx.f x` under `id` where `x` is not used by `f` to just `f`,
+    /// including lambdas in arguments of a constructor `id`. Returns what `id` became.
+    /// Evaluating `f` may now diverge or fail where the lambda was already a value
+    pub fn eta_reduce(&mut self, id: NodeIndex) -> ASTResult<NodeIndex> {
+        let mut result = id;
+        let mut visited = HashSet::new();
+        // Children first, so that `λx y.f x y` reduces all the way to `f`
+        let mut stack = vec![(id, false)];
+        while let Some((node, expanded)) = stack.pop() {
+            if expanded {
+                if let Some((body, variable, function)) = self.eta_redex(node) {
+                    let edge = self.get_edge_ref(body, Edge::Function)?.id();
+                    self.graph.remove_edge(edge);
+                    self.migrate_node(node, function)?;
+                    self.remove_node(variable);
+                    self.remove_node(body);
+                    self.remove_node(node);
+                    self.stats.eta_reductions += 1;
+                    if node == result {
+                        result = function;
+                    }
+                }
+                continue;
+            }
+            if !visited.insert(node) {
+                continue;
+            }
+            stack.push((node, true));
+            stack.extend(self.children(node).map(|(_, child)| (child, false)));
+            if let Node::Data { .. } = self.node(node)? {
+                for binder in self.binders(node).collect::<Vec<_>>() {
+                    if let Node::Closure { .. } = self.node(binder)? {
+                        stack.push((self.follow_edge(binder, Edge::Parameter)?, false));
+                    }
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Body, variable and function of `id = λx.function x`, unless `x` is used elsewhere.
+    /// Annotated lambdas and field accesses are kept, they are still needed by type checking
+    fn eta_redex(&self, id: NodeIndex) -> Option<(NodeIndex, NodeIndex, NodeIndex)> {
+        let Node::Lambda { .. } = self.graph.node_weight(id)? else {
+            return None;
+        };
+        let body = self.follow_edge(id, Edge::Body).ok()?;
+        if self.annotations.contains_key(&id) || self.field_accesses.contains_key(&body) {
+            return None;
+        }
+        let Node::Application = self.graph.node_weight(body)? else {
+            return None;
+        };
+        let variable = self.follow_edge(body, Edge::Parameter).ok()?;
+        let Node::Variable(VariableKind::Bound) = self.graph.node_weight(variable)? else {
+            return None;
+        };
+        if self.follow_edge(variable, Edge::Binder(0)).ok()? != id
+            || self.binder_references(id).nth(1).is_some()
+        {
+            return None;
+        }
+        Some((body, variable, self.follow_edge(body, Edge::Function).ok()?))
+    }
+}
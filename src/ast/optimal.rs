@@ -0,0 +1,408 @@
+use std::{collections::HashMap, sync::Arc};
+
+use petgraph::graph::NodeIndex;
+
+use crate::ast::{AST, ASTError, ASTResult, term::Term};
+
+/// Experimental optimal reduction: the term is translated into an interaction net,
+/// where sharing is explicit and even partially applied functions are never copied twice.
+/// This is Lamping's abstract algorithm without brackets: duplicators are matched by labels,
+/// which is exact for most terms (e.g Church numerals), but not all of them.
+/// Only pure lambda terms are supported, primitives and builtins are not.
+impl AST {
+    /// Reduce `id` to its full normal form with an interaction net, see `normalize`
+    pub fn reduce_optimal(&mut self, id: NodeIndex) -> ASTResult<NodeIndex> {
+        let mut net = Net {
+            origin: id,
+            agents: vec![],
+            ports: vec![],
+            labels: 0,
+        };
+        let root = net.add(Agent::Root);
+        let term = net.compile(&self.to_term(id)?, &mut vec![])?;
+        net.link((root, 0), term);
+
+        loop {
+            let reachable = net.reachable(root);
+            let pairs = reachable
+                .iter()
+                .filter_map(|&agent| {
+                    let (other, slot) = net.ports[agent][0];
+                    (slot == 0 && agent < other).then_some((agent, other))
+                })
+                .collect::<Vec<_>>();
+
+            // Whatever is disconnected from the root was erased
+            let mut is_reachable = vec![false; net.agents.len()];
+            for &agent in &reachable {
+                is_reachable[agent] = true;
+            }
+            for (agent, reachable) in is_reachable.into_iter().enumerate() {
+                if !reachable {
+                    net.agents[agent] = None;
+                }
+            }
+
+            let mut progress = false;
+            for (a, b) in pairs {
+                match net.interact(a, b) {
+                    Interaction::Beta => {
+                        self.stats.beta_reductions += 1;
+                        self.consume_fuel()?;
+                    }
+                    Interaction::Other => {}
+                    Interaction::Stuck => continue,
+                }
+                progress = true;
+            }
+            if !progress {
+                break;
+            }
+        }
+
+        let normal_form = net
+            .read_back((root, 0), &mut vec![], &mut HashMap::new(), 0)?
+            .to_named();
+        let result = self.add_term(&normal_form)?;
+        self.migrate_node(id, result)?;
+        self.remove_subtree(id);
+        Ok(result)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Agent {
+    Root,
+    /// Ports: principal, body, variable
+    Lambda(Arc<String>),
+    /// Ports: function, argument, result
+    Application,
+    /// Ports: principal and two copies, agents with the same label annihilate
+    Duplicator(usize),
+    Eraser,
+    /// Free variable, copied and erased like a nullary constructor
+    Free(Arc<String>),
+}
+
+/// Read back gives up past this depth. Labels are not enough to keep apart copies
+/// of a duplicator copying itself, e.g in `two two`, which then reads back as an infinite term
+const MAX_READ_BACK_DEPTH: usize = 100_000;
+
+/// Agent and slot, slot 0 is the principal port
+type Port = (usize, usize);
+
+enum Interaction {
+    Beta,
+    Other,
+    /// E.g a free variable applied to something
+    Stuck,
+}
+
+struct Net {
+    /// Node the net was built from, errors point at it
+    origin: NodeIndex,
+    agents: Vec<Option<Agent>>,
+    /// Where every port of an agent is connected to
+    ports: Vec<[Port; 3]>,
+    labels: usize,
+}
+
+impl Net {
+    fn add(&mut self, agent: Agent) -> usize {
+        self.agents.push(Some(agent));
+        self.ports.push([(usize::MAX, 0); 3]);
+        self.agents.len() - 1
+    }
+
+    fn link(&mut self, a: Port, b: Port) {
+        self.ports[a.0][a.1] = b;
+        self.ports[b.0][b.1] = a;
+    }
+
+    fn target(&self, port: Port) -> Port {
+        self.ports[port.0][port.1]
+    }
+
+    fn agent(&self, index: usize) -> ASTResult<&Agent> {
+        self.agents[index].as_ref().ok_or(ASTError::Custom(
+            self.origin,
+            "Interaction net lost an agent",
+        ))
+    }
+
+    /// Port producing the value of `term`. `scope` holds the ports left for occurrences
+    /// of every enclosing binder, innermost last
+    fn compile(
+        &mut self,
+        term: &Term,
+        scope: &mut Vec<(Arc<String>, Vec<Port>)>,
+    ) -> ASTResult<Port> {
+        Ok(match term {
+            Term::Var(name) => match scope.iter_mut().rev().find(|(binder, _)| binder == name) {
+                Some((_, occurrences)) => occurrences.pop().ok_or(ASTError::Custom(
+                    self.origin,
+                    "Variable used too many times",
+                ))?,
+                None => (self.add(Agent::Free(name.clone())), 0),
+            },
+            Term::Lambda {
+                argument_name,
+                body,
+            } => {
+                let name = argument_name
+                    .clone()
+                    .unwrap_or_else(|| Arc::new("x".to_string()));
+                let lambda = self.add(Agent::Lambda(name.clone()));
+                let occurrences = self.share((lambda, 2), uses(&name, body));
+                scope.push((name, occurrences));
+                let body = self.compile(body, scope);
+                scope.pop();
+                self.link((lambda, 1), body?);
+                (lambda, 0)
+            }
+            Term::Application {
+                function,
+                parameter,
+            } => self.apply(function, parameter, scope)?,
+            // `let` is an application of a lambda
+            Term::Closure {
+                argument_name,
+                parameter,
+                body,
+            } => self.apply(
+                &Term::Lambda {
+                    argument_name: argument_name.clone(),
+                    body: body.clone(),
+                },
+                parameter,
+                scope,
+            )?,
+            Term::Index(_) | Term::Primitive(_) | Term::Data { .. } => {
+                return Err(ASTError::Custom(
+                    self.origin,
+                    "Optimal reduction only supports pure lambda terms",
+                ));
+            }
+        })
+    }
+
+    fn apply(
+        &mut self,
+        function: &Term,
+        parameter: &Term,
+        scope: &mut Vec<(Arc<String>, Vec<Port>)>,
+    ) -> ASTResult<Port> {
+        let application = self.add(Agent::Application);
+        let function = self.compile(function, scope)?;
+        self.link((application, 0), function);
+        let parameter = self.compile(parameter, scope)?;
+        self.link((application, 1), parameter);
+        Ok((application, 2))
+    }
+
+    /// Ports for `count` occurrences of the value at `port`, through a chain of duplicators
+    fn share(&mut self, port: Port, count: usize) -> Vec<Port> {
+        match count {
+            0 => {
+                let eraser = self.add(Agent::Eraser);
+                self.link(port, (eraser, 0));
+                vec![]
+            }
+            1 => vec![port],
+            _ => {
+                self.labels += 1;
+                let duplicator = self.add(Agent::Duplicator(self.labels));
+                self.link(port, (duplicator, 0));
+                let mut occurrences = self.share((duplicator, 2), count - 1);
+                occurrences.push((duplicator, 1));
+                occurrences
+            }
+        }
+    }
+
+    /// Agents connected to `root`
+    fn reachable(&self, root: usize) -> Vec<usize> {
+        let mut seen = vec![false; self.agents.len()];
+        let mut stack = vec![root];
+        let mut reachable = vec![];
+        while let Some(agent) = stack.pop() {
+            if seen[agent] {
+                continue;
+            }
+            seen[agent] = true;
+            reachable.push(agent);
+            let arity = match self.agents[agent] {
+                Some(Agent::Root | Agent::Eraser | Agent::Free(_)) => 1,
+                _ => 3,
+            };
+            stack.extend(self.ports[agent][..arity].iter().map(|&(other, _)| other));
+        }
+        reachable
+    }
+
+    /// Rewrite a pair of agents connected by their principal ports
+    fn interact(&mut self, a: usize, b: usize) -> Interaction {
+        let (Some(left), Some(right)) = (self.agents[a].clone(), self.agents[b].clone()) else {
+            return Interaction::Stuck;
+        };
+        let interaction = match (&left, &right) {
+            (Agent::Root, _) | (_, Agent::Root) => return Interaction::Stuck,
+            (Agent::Lambda(_), Agent::Application) | (Agent::Application, Agent::Lambda(_)) => {
+                let (lambda, application) = match left {
+                    Agent::Lambda(_) => (a, b),
+                    _ => (b, a),
+                };
+                self.link(self.target((lambda, 1)), self.target((application, 2)));
+                self.link(self.target((lambda, 2)), self.target((application, 1)));
+                Interaction::Beta
+            }
+            (Agent::Duplicator(x), Agent::Duplicator(y)) if x == y => {
+                self.link(self.target((a, 1)), self.target((b, 1)));
+                self.link(self.target((a, 2)), self.target((b, 2)));
+                Interaction::Other
+            }
+            (Agent::Eraser, Agent::Eraser | Agent::Free(_)) | (Agent::Free(_), Agent::Eraser) => {
+                Interaction::Other
+            }
+            (Agent::Eraser, _) | (_, Agent::Eraser) => {
+                let binary = if let Agent::Eraser = left { b } else { a };
+                for slot in [1, 2] {
+                    let eraser = self.add(Agent::Eraser);
+                    self.link((eraser, 0), self.target((binary, slot)));
+                }
+                Interaction::Other
+            }
+            (Agent::Free(_), Agent::Duplicator(_)) | (Agent::Duplicator(_), Agent::Free(_)) => {
+                let (free, duplicator) = match left {
+                    Agent::Free(_) => (left.clone(), b),
+                    _ => (right.clone(), a),
+                };
+                for slot in [1, 2] {
+                    let copy = self.add(free.clone());
+                    self.link((copy, 0), self.target((duplicator, slot)));
+                }
+                Interaction::Other
+            }
+            (
+                Agent::Duplicator(_),
+                Agent::Lambda(_) | Agent::Application | Agent::Duplicator(_),
+            )
+            | (Agent::Lambda(_) | Agent::Application, Agent::Duplicator(_)) => {
+                self.commute(a, &left, b, &right);
+                Interaction::Other
+            }
+            _ => return Interaction::Stuck,
+        };
+        self.agents[a] = None;
+        self.agents[b] = None;
+        interaction
+    }
+
+    /// Each agent passes through the other one, getting copied
+    fn commute(&mut self, a: usize, left: &Agent, b: usize, right: &Agent) {
+        let copies_of_a = [self.add(left.clone()), self.add(left.clone())];
+        let copies_of_b = [self.add(right.clone()), self.add(right.clone())];
+        for i in 0..2 {
+            self.link((copies_of_a[i], 0), self.target((b, i + 1)));
+            self.link((copies_of_b[i], 0), self.target((a, i + 1)));
+        }
+        for (i, &copy_of_a) in copies_of_a.iter().enumerate() {
+            for (j, &copy_of_b) in copies_of_b.iter().enumerate() {
+                self.link((copy_of_a, j + 1), (copy_of_b, i + 1));
+            }
+        }
+    }
+
+    /// Term at the other end of `port`. Copies of a shared value are told apart by
+    /// remembering which side of each duplicator the traversal came from
+    fn read_back(
+        &self,
+        port: Port,
+        lambdas: &mut Vec<usize>,
+        sides: &mut HashMap<usize, Vec<usize>>,
+        depth: usize,
+    ) -> ASTResult<Term> {
+        if depth > MAX_READ_BACK_DEPTH {
+            return Err(ASTError::Custom(
+                self.origin,
+                "Normal form is too deep, or not supported by optimal reduction",
+            ));
+        }
+        let (agent, slot) = self.target(port);
+        Ok(match (self.agent(agent)?, slot) {
+            (Agent::Lambda(name), 0) => {
+                lambdas.push(agent);
+                let body = self.read_back((agent, 1), lambdas, sides, depth + 1);
+                lambdas.pop();
+                Term::Lambda {
+                    argument_name: Some(name.clone()),
+                    body: Box::new(body?),
+                }
+            }
+            (Agent::Lambda(name), 2) => match lambdas.iter().rposition(|&lambda| lambda == agent) {
+                Some(position) => Term::Index(lambdas.len() - 1 - position),
+                None => Term::Var(name.clone()),
+            },
+            (Agent::Application, 2) => Term::Application {
+                function: Box::new(self.read_back((agent, 0), lambdas, sides, depth + 1)?),
+                parameter: Box::new(self.read_back((agent, 1), lambdas, sides, depth + 1)?),
+            },
+            (&Agent::Duplicator(label), 0) => {
+                let side = sides
+                    .get_mut(&label)
+                    .and_then(Vec::pop)
+                    .ok_or(ASTError::Custom(
+                        self.origin,
+                        "Unmatched duplicator in normal form",
+                    ))?;
+                let term = self.read_back((agent, side), lambdas, sides, depth + 1);
+                sides.entry(label).or_default().push(side);
+                term?
+            }
+            (&Agent::Duplicator(label), side) => {
+                sides.entry(label).or_default().push(side);
+                let term = self.read_back((agent, 0), lambdas, sides, depth + 1);
+                sides.entry(label).or_default().pop();
+                term?
+            }
+            (Agent::Free(name), _) => Term::Var(name.clone()),
+            _ => {
+                return Err(ASTError::Custom(
+                    self.origin,
+                    "Interaction net has no normal form",
+                ));
+            }
+        })
+    }
+}
+
+/// Number of occurrences of variable `name` in `term`, excluding shadowed ones
+fn uses(name: &Arc<String>, term: &Term) -> usize {
+    match term {
+        Term::Var(variable) => usize::from(variable == name),
+        Term::Lambda {
+            argument_name,
+            body,
+        } => match argument_name {
+            Some(argument_name) if argument_name == name => 0,
+            _ => uses(name, body),
+        },
+        Term::Application {
+            function,
+            parameter,
+        } => uses(name, function) + uses(name, parameter),
+        Term::Closure {
+            argument_name,
+            parameter,
+            body,
+        } => {
+            uses(name, parameter)
+                + match argument_name {
+                    Some(argument_name) if argument_name == name => 0,
+                    _ => uses(name, body),
+                }
+        }
+        Term::Data { arguments, .. } => arguments.iter().map(|argument| uses(name, argument)).sum(),
+        Term::Index(_) | Term::Primitive(_) => 0,
+    }
+}
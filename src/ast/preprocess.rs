@@ -1,29 +1,187 @@
 
 
-use crate::ast::{Node, AST};
+use std::collections::HashSet;
+
+use petgraph::{Direction, graph::NodeIndex, visit::EdgeRef};
+
+use crate::ast::{
+    builtins::ConstructorTag, ASTResult, Edge, Node, Number, Primitive, VariableKind, AST,
+};
+
+/// Evaluation collects garbage once the graph has more than `min_nodes` nodes
+/// and grew `growth` times since the last collection, so that collecting stays cheap
+/// for programs with a lot of live data
+#[derive(Debug, Clone)]
+pub struct GcPolicy {
+    pub min_nodes: usize,
+    pub growth: f32,
+}
+
+impl Default for GcPolicy {
+    fn default() -> Self {
+        Self {
+            min_nodes: 10_000,
+            growth: 1.25,
+        }
+    }
+}
+
+impl GcPolicy {
+    /// Node count triggering the next collection, given how many nodes survived the last one
+    pub fn threshold(&self, live_nodes: usize) -> usize {
+        self.min_nodes
+            .max((live_nodes as f32 * self.growth) as usize)
+    }
+}
 
 impl AST {
     #[tracing::instrument(skip(self))]
-    pub fn garbage_collect(&mut self) {
+    pub fn garbage_collect(&mut self) -> ASTResult<()> {
+        self.stats.garbage_collections += 1;
         loop {
             let unsued_closures = self
                 .graph
                 .node_indices()
                 .filter(|&node_id| {
-                    matches!(
-                        self.graph.node_weight(node_id).unwrap(),
-                        Node::Closure { .. }
-                    ) && self.binder_references(node_id).next().is_none()
+                    matches!(self.graph.node_weight(node_id), Some(Node::Closure { .. }))
+                        && self.binder_references(node_id).next().is_none()
                 })
                 .collect::<Vec<_>>();
 
             if unsued_closures.is_empty() {
-                break;
+                return Ok(());
             }
             for closure_id in unsued_closures {
-                let parameter = self.remove_closure(closure_id).unwrap();
+                let parameter = self.remove_closure(closure_id)?;
                 self.remove_subtree(parameter);
             }
         }
     }
+
+    /// Remove every node that is not reachable from the root, e.g values builtins leaked,
+    /// and return how many there were. Trees being evaluated and definitions kept alive
+    /// by `define` or `enable_incremental` are reachable too.
+    #[tracing::instrument(skip(self))]
+    pub fn collect_unreachable(&mut self) -> usize {
+        let mut stack = vec![self.root];
+        // Evaluated nodes are mostly nested, each tree is only climbed once
+        let mut climbed = HashSet::new();
+        for &id in &self.in_flight {
+            let mut current = id;
+            while self.graph.contains_node(current) && climbed.insert(current) {
+                match self.parent(current) {
+                    Some((parent, _)) => current = parent,
+                    None => stack.push(current),
+                }
+            }
+        }
+        // Anchors of `keep_alive` are variables without a parent
+        for id in self.graph.node_indices() {
+            if let Node::Variable(VariableKind::Bound) = self.graph[id]
+                && self.parent(id).is_none()
+            {
+                stack.push(id);
+                if let Some(binder) = self.binders(id).next() {
+                    stack.push(self.top(binder));
+                }
+            }
+        }
+        if let Some(incremental) = &self.incremental {
+            stack.extend(incremental.sources());
+        }
+
+        let mut reachable = HashSet::new();
+        while let Some(id) = stack.pop() {
+            if !reachable.insert(id) {
+                continue;
+            }
+            stack.extend(
+                self.graph
+                    .edges_directed(id, Direction::Outgoing)
+                    .filter(|e| !matches!(e.weight(), Edge::Binder(_)))
+                    .map(|e| e.target()),
+            );
+        }
+
+        let unreachable = self
+            .graph
+            .node_indices()
+            .filter(|id| !reachable.contains(id))
+            .collect::<Vec<_>>();
+        for &id in &unreachable {
+            self.remove_node(id);
+        }
+        self.stats.swept_nodes += unreachable.len();
+        unreachable.len()
+    }
+
+    /// Run both collectors, as evaluation does according to `gc_policy`
+    pub fn collect_all(&mut self) -> ASTResult<()> {
+        self.garbage_collect()?;
+        self.collect_unreachable();
+        self.nodes_after_gc = self.graph.node_count();
+        Ok(())
+    }
+
+    /// Replace arithmetic on number literals, e.g `(* 60 60)`, with its result before evaluation,
+    /// returning how many builtin calls were folded.
+    /// Calls that would fail or overflow are left to fail at runtime
+    pub fn fold_constants(&mut self) -> ASTResult<usize> {
+        let mut folded = 0;
+        loop {
+            let foldable = self
+                .graph
+                .node_indices()
+                .filter_map(|id| Some((id, self.constant_arithmetic(id)?)))
+                .collect::<Vec<_>>();
+            if foldable.is_empty() {
+                self.stats.folded_constants += folded;
+                return Ok(folded);
+            }
+            for (id, result) in foldable {
+                let number = self
+                    .graph
+                    .add_node(Node::Primitive(Primitive::Number(result)));
+                if let Some(span) = self.span(id) {
+                    self.set_span(number, span);
+                }
+                // Of nested calls only the innermost is foldable, so these are distinct trees
+                self.migrate_node(id, number)?;
+                self.remove_subtree(id);
+                folded += 1;
+            }
+        }
+    }
+
+    /// Result of `id` if it is an arithmetic builtin applied to two number literals
+    fn constant_arithmetic(&self, id: NodeIndex) -> Option<Number> {
+        let (head, 2) = self.application_head(id) else {
+            return None;
+        };
+        let Some(&Node::Data {
+            tag: ConstructorTag::Arithmetic(tag),
+        }) = self.graph.node_weight(head)
+        else {
+            return None;
+        };
+        let number = |application| match self
+            .graph
+            .node_weight(self.follow_edge(application, Edge::Parameter).ok()?)?
+        {
+            Node::Primitive(Primitive::Number(number)) => Some(*number),
+            _ => None,
+        };
+        tag.fold(
+            number(self.follow_edge(id, Edge::Function).ok()?)?,
+            number(id)?,
+        )
+    }
+
+    /// Top of the tree containing `id`
+    fn top(&self, mut id: NodeIndex) -> NodeIndex {
+        while let Some((parent, _)) = self.parent(id) {
+            id = parent;
+        }
+        id
+    }
 }
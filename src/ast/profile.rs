@@ -0,0 +1,212 @@
+use std::{collections::HashMap, fmt::Write, sync::Arc};
+
+use petgraph::{Direction, graph::NodeIndex, visit::EdgeRef};
+
+use crate::ast::{AST, Edge, Node, builtins::ConstructorTag};
+
+/// Name used for nodes that are not part of any `let` definition
+const TOPLEVEL: &str = "<toplevel>";
+
+/// Aggregates evaluation work per source definition.
+/// Every node remembers the `let` binding it originated from,
+/// and clones inherit the origin of the node they were cloned from.
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    origins: HashMap<NodeIndex, Arc<String>>,
+    steps: HashMap<Arc<String>, usize>,
+    clones: HashMap<Arc<String>, usize>,
+
+    /// Currently active definitions and builtins, outermost first
+    stack: Vec<Arc<String>>,
+    /// `stack` joined with ';', kept in sync to avoid re-joining on every step
+    folded_stack: String,
+    /// Reduction steps per unique stack, in "folded stacks" format
+    folded: HashMap<String, usize>,
+}
+
+/// Returned by `profile_enter`, tells `profile_exit` whether a frame was pushed
+pub(super) struct ProfileFrame(Option<usize>);
+
+impl Profile {
+    fn origin(&self, id: NodeIndex) -> Option<Arc<String>> {
+        self.origins.get(&id).cloned()
+    }
+
+    /// Recursion within the same definition collapses into a single frame
+    fn push(&mut self, frame: Arc<String>) -> ProfileFrame {
+        if self.stack.last() == Some(&frame) {
+            return ProfileFrame(None);
+        }
+        let folded_len = self.folded_stack.len();
+        if !self.stack.is_empty() {
+            self.folded_stack.push(';');
+        }
+        self.folded_stack.push_str(&frame);
+        self.stack.push(frame);
+        ProfileFrame(Some(folded_len))
+    }
+
+    fn sample(&mut self) {
+        match self.folded.get_mut(self.folded_stack.as_str()) {
+            Some(count) => *count += 1,
+            None => {
+                self.folded.insert(self.folded_stack.clone(), 1);
+            }
+        }
+    }
+}
+
+impl AST {
+    /// Start attributing reduction steps and clones to the definitions they happen in.
+    /// Tags every node reachable from root with the innermost `let` binding it belongs to.
+    pub fn enable_profiling(&mut self) {
+        let mut profile = Profile::default();
+
+        let mut stack = vec![(self.root, Arc::new(TOPLEVEL.to_string()))];
+        while let Some((id, definition)) = stack.pop() {
+            profile.origins.insert(id, definition.clone());
+            for edge in self
+                .graph
+                .edges_directed(id, Direction::Outgoing)
+                .filter(|e| !matches!(e.weight(), Edge::Binder(_) | Edge::Debug))
+            {
+                let definition = match (self.graph.node_weight(id), edge.weight()) {
+                    (Some(Node::Closure { argument_name }), Edge::Parameter) => {
+                        argument_name.clone()
+                    }
+                    _ => definition.clone(),
+                };
+                stack.push((edge.target(), definition));
+            }
+        }
+
+        self.profile = Some(profile);
+    }
+
+    /// Count a reduction step of node `id` and push its definition onto the profile stack
+    pub(super) fn profile_enter(&mut self, id: NodeIndex) -> ProfileFrame {
+        let Some(profile) = &mut self.profile else {
+            return ProfileFrame(None);
+        };
+        let Some(definition) = profile.origin(id) else {
+            return ProfileFrame(None);
+        };
+        *profile.steps.entry(definition.clone()).or_default() += 1;
+
+        let frame = profile.push(definition);
+        profile.sample();
+        frame
+    }
+
+    pub(super) fn profile_enter_builtin(&mut self, tag: ConstructorTag) -> ProfileFrame {
+        match &mut self.profile {
+            Some(profile) => profile.push(Arc::new(String::from(tag))),
+            None => ProfileFrame(None),
+        }
+    }
+
+    pub(super) fn profile_exit(&mut self, frame: ProfileFrame) {
+        if let (Some(profile), ProfileFrame(Some(folded_len))) = (&mut self.profile, frame) {
+            profile.stack.pop();
+            profile.folded_stack.truncate(folded_len);
+        }
+    }
+
+    pub(super) fn profile_clone(&mut self, from: NodeIndex, to: NodeIndex) {
+        if let Some(profile) = &mut self.profile
+            && let Some(definition) = profile.origin(from)
+        {
+            *profile.clones.entry(definition.clone()).or_default() += 1;
+            profile.origins.insert(to, definition);
+        }
+    }
+
+    /// Node `to` takes place of `from`, so it inherits its origin unless it has one already
+    pub(super) fn profile_migrate(&mut self, from: NodeIndex, to: NodeIndex) {
+        if let Some(profile) = &mut self.profile
+            && let Some(definition) = profile.origin(from)
+        {
+            profile.origins.entry(to).or_insert(definition);
+        }
+    }
+
+    pub(super) fn profile_forget(&mut self, id: NodeIndex) {
+        if let Some(profile) = &mut self.profile {
+            profile.origins.remove(&id);
+        }
+    }
+
+    /// Human-readable summary of where evaluation work happened,
+    /// or None if profiling was not enabled
+    pub fn profile_report(&self) -> Option<String> {
+        let profile = self.profile.as_ref()?;
+
+        let total_steps = profile.steps.values().sum::<usize>();
+        let total_clones = profile.clones.values().sum::<usize>();
+
+        let mut definitions = profile
+            .steps
+            .keys()
+            .chain(profile.clones.keys())
+            .collect::<Vec<_>>();
+        definitions.sort();
+        definitions.dedup();
+        definitions.sort_by_key(|&definition| {
+            std::cmp::Reverse(profile.steps.get(definition).copied().unwrap_or_default())
+        });
+
+        let percentage = |part: usize, total: usize| {
+            if total == 0 {
+                0.0
+            } else {
+                part as f32 * 100.0 / total as f32
+            }
+        };
+
+        let mut report = String::new();
+        writeln!(
+            report,
+            "Profile: {total_steps} reduction steps, {total_clones} cloned nodes"
+        )
+        .unwrap();
+        for definition in &definitions {
+            let steps = profile.steps.get(*definition).copied().unwrap_or_default();
+            let clones = profile.clones.get(*definition).copied().unwrap_or_default();
+            writeln!(
+                report,
+                "{:>6.1}% {:>10} steps {:>10} clones  {}",
+                percentage(steps, total_steps),
+                steps,
+                clones,
+                definition
+            )
+            .unwrap();
+        }
+        if let Some(hottest) = definitions.first() {
+            let steps = profile.steps.get(*hottest).copied().unwrap_or_default();
+            writeln!(
+                report,
+                "{:.0}% of work happened inside `{}`",
+                percentage(steps, total_steps),
+                hottest
+            )
+            .unwrap();
+        }
+        Some(report)
+    }
+
+    /// Reduction steps per stack of active definitions, one "a;b;c count" line each.
+    /// Compatible with inferno/flamegraph tooling, see `lambo --profile-folded`
+    pub fn profile_folded(&self) -> Option<String> {
+        let profile = self.profile.as_ref()?;
+
+        let mut stacks = profile.folded.iter().collect::<Vec<_>>();
+        stacks.sort();
+
+        let mut result = String::new();
+        for (stack, count) in stacks {
+            writeln!(result, "{stack} {count}").unwrap();
+        }
+        Some(result)
+    }
+}
@@ -0,0 +1,68 @@
+use petgraph::{Direction, graph::NodeIndex, visit::EdgeRef};
+
+use crate::ast::{AST, Edge, Node};
+
+/// Read-only queries over the expression graph for external tooling.
+/// Structural edges (Body, Parameter, Function) form a tree,
+/// Binder edges point from variables and data back to their binders.
+impl AST {
+    pub fn nodes(&self) -> impl Iterator<Item = (NodeIndex, &Node)> {
+        self.graph.node_indices().map(|id| (id, &self.graph[id]))
+    }
+
+    pub fn get(&self, id: NodeIndex) -> Option<&Node> {
+        self.graph.node_weight(id)
+    }
+
+    /// Subexpressions of `id` along with the edge leading to them
+    pub fn children(&self, id: NodeIndex) -> impl Iterator<Item = (Edge, NodeIndex)> {
+        self.graph
+            .edges_directed(id, Direction::Outgoing)
+            .filter(|e| !matches!(e.weight(), Edge::Binder(_) | Edge::Debug))
+            .map(|e| (*e.weight(), e.target()))
+    }
+
+    /// Expression containing `id` and the edge leading from it, None for the root
+    pub fn parent(&self, id: NodeIndex) -> Option<(NodeIndex, Edge)> {
+        self.graph
+            .edges_directed(id, Direction::Incoming)
+            .find(|e| !matches!(e.weight(), Edge::Binder(_) | Edge::Debug))
+            .map(|e| (e.source(), *e.weight()))
+    }
+
+    /// Lambdas and closures bound by variable or data node `id`, in argument order
+    pub fn binders(&self, id: NodeIndex) -> impl Iterator<Item = NodeIndex> {
+        let mut binders = self
+            .graph
+            .edges_directed(id, Direction::Outgoing)
+            .filter_map(|e| match e.weight() {
+                Edge::Binder(argument_index) => Some((*argument_index, e.target())),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        binders.sort();
+        binders.into_iter().map(|(_, binder)| binder)
+    }
+
+    /// Variables and data nodes bound by `binder`
+    pub fn references(&self, binder: NodeIndex) -> impl Iterator<Item = NodeIndex> {
+        self.binder_references(binder)
+    }
+
+    pub fn find(&self, predicate: impl Fn(&Node) -> bool) -> impl Iterator<Item = NodeIndex> {
+        self.nodes()
+            .filter(move |(_, node)| predicate(node))
+            .map(|(id, _)| id)
+    }
+
+    /// Number of nodes in the expression rooted at `id`, including itself
+    pub fn subtree_size(&self, id: NodeIndex) -> usize {
+        let mut size = 0;
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            size += 1;
+            stack.extend(self.children(id).map(|(_, child)| child));
+        }
+        size
+    }
+}
@@ -0,0 +1,49 @@
+use std::sync::Arc;
+
+use petgraph::graph::NodeIndex;
+
+use crate::ast::{AST, builtins::ConstructorTag};
+
+/// Fields of pairs, `(a, b)` is `{ 0 = a, 1 = b }`
+pub const PAIR: &[&str] = &["0", "1"];
+
+/// Records `{ name = expr, ... }` are values of named constructors,
+/// one for every set of field names, with arguments in field name order
+impl AST {
+    /// Constructor of records with exactly `fields`, which must be sorted
+    pub fn record_constructor(&mut self, fields: &[impl AsRef<str>]) -> ConstructorTag {
+        let names = fields.iter().map(AsRef::as_ref).collect::<Vec<_>>();
+        self.named_constructor(&format!("{{{}}}", names.join(",")), fields.len())
+    }
+
+    /// Field names of records built by `tag`, if it is a record constructor
+    pub fn record_fields(&self, tag: ConstructorTag) -> Option<Vec<String>> {
+        self.constructors
+            .iter()
+            .find(|&(_, &constructor)| constructor == tag)
+            .and_then(|(name, _)| name.strip_prefix('{')?.strip_suffix('}'))
+            .map(|fields| match fields {
+                "" => vec![],
+                fields => fields.split(',').map(str::to_string).collect(),
+            })
+    }
+
+    /// Every record constructor created so far, oldest first
+    pub(crate) fn record_shapes(&self) -> Vec<(ConstructorTag, Vec<String>)> {
+        let mut shapes = self
+            .constructors
+            .values()
+            .filter_map(|&tag| Some((tag, self.record_fields(tag)?)))
+            .collect::<Vec<_>>();
+        shapes.sort_by_key(|(tag, _)| match tag {
+            ConstructorTag::CustomTag { uid, .. } => *uid,
+            _ => 0,
+        });
+        shapes
+    }
+
+    /// Remember that application `id` reads `field` of its argument, for the type checker
+    pub(crate) fn mark_field_access(&mut self, id: NodeIndex, field: &str) {
+        self.field_accesses.insert(id, Arc::new(field.to_string()));
+    }
+}
@@ -0,0 +1,269 @@
+use std::{collections::BTreeMap, fmt::Write};
+
+use crate::ast::{
+    Primitive,
+    builtins::{
+        ConstructorTag,
+        arithmetic::{ArithmeticTag, format_float},
+        string::StringOpTag,
+    },
+    term::Term,
+};
+
+/// Notation to print terms in, so results can be pasted into GHCi or utop, or parsed again
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Syntax {
+    /// `\x y -> x y`
+    Haskell,
+    /// `fun x y -> x y`
+    OCaml,
+    /// `λx y.x y`
+    Lambo,
+}
+
+impl Syntax {
+    fn keywords(self) -> &'static [&'static str] {
+        match self {
+            Syntax::Haskell => &[
+                "case", "class", "data", "default", "deriving", "do", "else", "foreign", "if",
+                "import", "in", "infix", "infixl", "infixr", "instance", "let", "module",
+                "newtype", "of", "then", "type", "where",
+            ],
+            Syntax::OCaml => &[
+                "and", "as", "begin", "do", "done", "else", "end", "fun", "function", "if", "in",
+                "let", "match", "method", "module", "new", "of", "open", "or", "rec", "then", "to",
+                "try", "type", "val", "when", "while", "with",
+            ],
+            Syntax::Lambo => &["data", "in", "let", "match", "with"],
+        }
+    }
+
+    /// Valid identifier for `name`: other characters are hex-escaped and keywords get a `'`
+    fn identifier(self, name: &str) -> String {
+        if self == Syntax::Lambo && is_lambo_symbol(name) {
+            return name.to_string();
+        }
+        let mut result = String::new();
+        let is_start = |c: char| match self {
+            Syntax::Lambo => c.is_ascii_alphabetic() || c == '_',
+            _ => c.is_ascii_lowercase() || c == '_',
+        };
+        if !name.starts_with(is_start) {
+            result.push_str("v_");
+        }
+        for c in name.chars() {
+            match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '\'' => result.push(c),
+                c => write!(result, "x{:x}_", c as u32).unwrap(),
+            }
+        }
+        if self.keywords().contains(&result.as_str()) {
+            result.push('\'');
+        }
+        result
+    }
+
+    fn constructor(self, tag: ConstructorTag) -> String {
+        match tag {
+            ConstructorTag::CustomTag { uid, .. } => match self {
+                Syntax::Haskell => format!("Con{uid}"),
+                // OCaml constructors can't be partially applied, so use a function instead.
+                // Lambo ones are declared by `Term::render`
+                Syntax::OCaml | Syntax::Lambo => format!("con{uid}"),
+            },
+            tag if self == Syntax::Lambo => String::from(tag),
+            ConstructorTag::Arithmetic(ArithmeticTag::Eq) => "eq_num".to_string(),
+            ConstructorTag::Arithmetic(operation) => format!("{operation:?}").to_lowercase(),
+            tag => self.identifier(String::from(tag).trim_start_matches('#')),
+        }
+    }
+
+    fn string(self, bytes: &[u8], out: &mut String) {
+        if self == Syntax::Lambo {
+            // Lambo strings are UTF-8 source text with few escapes
+            let escaped = format!("{:?}", String::from_utf8_lossy(bytes));
+            out.push_str(&escaped);
+            return;
+        }
+        out.push('"');
+        for (index, &byte) in bytes.iter().enumerate() {
+            match byte {
+                b'"' => out.push_str("\\\""),
+                b'\\' => out.push_str("\\\\"),
+                b'\n' => out.push_str("\\n"),
+                b'\t' => out.push_str("\\t"),
+                0x20..0x7f => out.push(byte as char),
+                _ => match self {
+                    Syntax::Haskell => {
+                        write!(out, "\\{byte}").unwrap();
+                        // `\&` separates a numeric escape from a digit that follows it
+                        if bytes.get(index + 1).is_some_and(u8::is_ascii_digit) {
+                            out.push_str("\\&");
+                        }
+                    }
+                    Syntax::OCaml => write!(out, "\\{byte:03}").unwrap(),
+                    Syntax::Lambo => unreachable!("Lambo strings are escaped as a whole"),
+                },
+            }
+        }
+        out.push('"');
+    }
+}
+
+/// Whether `name` reads back as a variable named `name`, not as a builtin, number or keyword
+fn is_lambo_symbol(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit() || c == '#')
+        && !name.contains(|c: char| c.is_whitespace() || "()λ@\\.|:{},\"".contains(c))
+        && !["=", ">>", "->"].contains(&name)
+        && !Syntax::Lambo.keywords().contains(&name)
+        && ConstructorTag::try_from(name).is_err()
+}
+
+impl Term {
+    /// Render in Haskell or OCaml notation. Builtins and constructors become
+    /// free identifiers (`add`, `bytes_get`, `Con3`) for the host language to define.
+    /// Lambo notation declares the constructors itself, so the result can be run as is
+    pub fn render(&self, syntax: Syntax) -> String {
+        let mut result = String::new();
+        if syntax == Syntax::Lambo {
+            let mut constructors = BTreeMap::new();
+            self.custom_tags(&mut constructors);
+            for (uid, arity) in constructors {
+                writeln!(result, "let con{uid} #constructor {arity} in").unwrap();
+            }
+        }
+        self.to_named().write_rendered(syntax, &mut result);
+        result
+    }
+
+    /// Arity of every custom constructor used by the term, by uid
+    fn custom_tags(&self, constructors: &mut BTreeMap<usize, usize>) {
+        match self {
+            Term::Var(_) | Term::Index(_) | Term::Primitive(_) => {}
+            Term::Lambda { body, .. } => body.custom_tags(constructors),
+            Term::Application {
+                function,
+                parameter,
+            } => {
+                function.custom_tags(constructors);
+                parameter.custom_tags(constructors);
+            }
+            Term::Closure {
+                parameter, body, ..
+            } => {
+                parameter.custom_tags(constructors);
+                body.custom_tags(constructors);
+            }
+            Term::Data { tag, arguments } => {
+                if let ConstructorTag::CustomTag { uid, arity } = *tag {
+                    constructors.insert(uid, arity);
+                }
+                for argument in arguments {
+                    argument.custom_tags(constructors);
+                }
+            }
+        }
+    }
+
+    fn write_rendered(&self, syntax: Syntax, out: &mut String) {
+        match self {
+            Term::Var(name) => out.push_str(&syntax.identifier(name)),
+            Term::Index(index) => write!(out, "v_idx{index}").unwrap(),
+            Term::Lambda { .. } => {
+                out.push_str(match syntax {
+                    Syntax::Haskell => "\\",
+                    Syntax::OCaml => "fun ",
+                    Syntax::Lambo => "λ",
+                });
+                let mut names = vec![];
+                let mut body = self;
+                while let Term::Lambda {
+                    argument_name,
+                    body: inner,
+                } = body
+                {
+                    names.push(match argument_name {
+                        Some(name) => syntax.identifier(name),
+                        None => "_".to_string(),
+                    });
+                    body = inner;
+                }
+                match syntax {
+                    Syntax::Lambo => write!(out, "{}.", names.join(" ")).unwrap(),
+                    _ => write!(out, "{} -> ", names.join(" ")).unwrap(),
+                }
+                body.write_rendered(syntax, out);
+            }
+            Term::Closure {
+                argument_name,
+                parameter,
+                body,
+            } => {
+                // Haskell `let` is recursive, but `to_named` never lets a binder shadow
+                // a variable used in its own parameter, so both read the same
+                let name = argument_name
+                    .as_ref()
+                    .map_or("_".to_string(), |name| syntax.identifier(name));
+                match syntax {
+                    Syntax::Lambo => {
+                        write!(out, "let {name} ").unwrap();
+                        parameter.write_operand(syntax, out, false);
+                        out.push_str(" in\n");
+                    }
+                    _ => {
+                        write!(out, "let {name} = ").unwrap();
+                        parameter.write_rendered(syntax, out);
+                        out.push_str(" in ");
+                    }
+                }
+                body.write_rendered(syntax, out);
+            }
+            Term::Application {
+                function,
+                parameter,
+            } => {
+                function.write_operand(syntax, out, false);
+                out.push(' ');
+                parameter.write_operand(syntax, out, true);
+            }
+            Term::Primitive(Primitive::Number(number)) => write!(out, "{number}").unwrap(),
+            Term::Primitive(Primitive::Float(float)) => out.push_str(&format_float(*float)),
+            Term::Primitive(Primitive::Bytes(bytes)) => syntax.string(bytes, out),
+            // Strings are written as the Bytes they are made from
+            Term::Primitive(Primitive::String(string)) => Term::Data {
+                tag: ConstructorTag::StringOp(StringOpTag::FromBytes),
+                arguments: vec![Term::Primitive(Primitive::Bytes(
+                    string.as_bytes().to_vec(),
+                ))],
+            }
+            .write_rendered(syntax, out),
+            Term::Data { tag, arguments } => {
+                out.push_str(&syntax.constructor(*tag));
+                for argument in arguments {
+                    out.push(' ');
+                    argument.write_operand(syntax, out, true);
+                }
+            }
+        }
+    }
+
+    /// Parenthesized unless it binds tighter than application,
+    /// which is left-associative, so only arguments need parens around applications
+    fn write_operand(&self, syntax: Syntax, out: &mut String, is_argument: bool) {
+        let needs_parens = match self {
+            Term::Lambda { .. } | Term::Closure { .. } => true,
+            Term::Application { .. } => is_argument,
+            Term::Data { arguments, .. } => is_argument && !arguments.is_empty(),
+            Term::Primitive(Primitive::String(_)) => is_argument,
+            _ => false,
+        };
+        if needs_parens {
+            out.push('(');
+        }
+        self.write_rendered(syntax, out);
+        if needs_parens {
+            out.push(')');
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use std::{collections::HashMap, sync::Arc};
+
+use petgraph::{
+    graph::NodeIndex,
+    visit::{EdgeRef, IntoEdgeReferences},
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+
+use crate::ast::{AST, Edge, Node, builtins::ConstructorTag, data::DataType, types::Type};
+
+/// Bumped on every incompatible change of the on-disk schema
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Stable on-disk representation of an AST.
+/// Node indices are compacted, so the schema does not depend on petgraph internals.
+/// Besides the graph it keeps what the parser learned about the program: constructor names,
+/// `data` declarations, field accesses and type annotations, so the AST evaluates the same.
+/// Runtime configuration (IO handler, limits, profiling, debug output) and
+/// evaluation state (stats, caches, source spans) are not part of it.
+#[derive(Serialize, Deserialize)]
+struct SerializedAST {
+    version: u32,
+    root: usize,
+    next_uid: usize,
+    nodes: Vec<Node>,
+    /// (from, to, edge) referencing positions in `nodes`
+    edges: Vec<(usize, usize, Edge)>,
+    constructors: HashMap<Arc<String>, ConstructorTag>,
+    data_types: Vec<DataType>,
+    /// (application, field) referencing positions in `nodes`
+    field_accesses: Vec<(usize, Arc<String>)>,
+    /// (lambda, argument type) referencing positions in `nodes`
+    annotations: Vec<(usize, Type)>,
+}
+
+impl Serialize for AST {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let positions = self
+            .graph
+            .node_indices()
+            .enumerate()
+            .map(|(position, id)| (id, position))
+            .collect::<HashMap<_, _>>();
+
+        SerializedAST {
+            version: SCHEMA_VERSION,
+            root: positions.get(&self.root).copied().unwrap_or_default(),
+            next_uid: self.next_uid,
+            nodes: self
+                .graph
+                .node_indices()
+                .map(|id| self.graph[id].clone())
+                .collect(),
+            edges: (&self.graph)
+                .edge_references()
+                .map(|e| (positions[&e.source()], positions[&e.target()], *e.weight()))
+                .collect(),
+            constructors: self.constructors.clone(),
+            data_types: self.data_types.clone(),
+            // Side tables may still mention removed nodes
+            field_accesses: self
+                .field_accesses
+                .iter()
+                .filter_map(|(id, field)| Some((*positions.get(id)?, field.clone())))
+                .collect(),
+            annotations: self
+                .annotations
+                .iter()
+                .filter_map(|(id, annotation)| Some((*positions.get(id)?, annotation.clone())))
+                .collect(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for AST {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let serialized = SerializedAST::deserialize(deserializer)?;
+        if serialized.version != SCHEMA_VERSION {
+            return Err(de::Error::custom(format!(
+                "unsupported AST schema version {}, expected {}",
+                serialized.version, SCHEMA_VERSION
+            )));
+        }
+
+        let mut ast = AST::new();
+        let ids = serialized
+            .nodes
+            .into_iter()
+            .map(|node| ast.graph.add_node(node))
+            .collect::<Vec<NodeIndex>>();
+        let id = |position: usize| {
+            ids.get(position)
+                .copied()
+                .ok_or_else(|| de::Error::custom(format!("node {position} does not exist")))
+        };
+
+        for (from, to, edge) in serialized.edges {
+            ast.graph.add_edge(id(from)?, id(to)?, edge);
+        }
+        ast.root = id(serialized.root)?;
+        ast.next_uid = serialized.next_uid;
+        ast.constructors = serialized.constructors;
+        ast.data_types = serialized.data_types;
+        for (position, field) in serialized.field_accesses {
+            ast.field_accesses.insert(id(position)?, field);
+        }
+        for (position, annotation) in serialized.annotations {
+            ast.annotations.insert(id(position)?, annotation);
+        }
+        Ok(ast)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{EvalConfig, Program, ast::AST, parse};
+
+    /// Deserialized AST must evaluate exactly like the one it was serialized from
+    #[test]
+    fn round_trip_evaluates_the_same() {
+        let programs = [
+            "let double \\x.+ x x in double (* 3 4)",
+            "data List = Cons head tail | Nil in
+            let Y \\f.(\\x.f (x x)) (\\x.f (x x)) in
+            let sum Y \\sum list.match list { Cons h t -> + h (sum t), Nil -> 0 } in
+            sum (Cons 1 (Cons 2 (Cons 3 Nil)))",
+            "let point { x = 1, y = 2 } in + point.x point.y",
+            "let pair (3, 4) in * pair.0 pair.1",
+            "let inc \\n:Number.+ 1 n in inc 41",
+        ];
+        for source in programs {
+            let program = parse(source).unwrap();
+            let json = serde_json::to_string(&program.ast).unwrap();
+            let ast: AST = serde_json::from_str(&json).unwrap();
+
+            let evaluate = |program: Program| {
+                let config = EvalConfig {
+                    typed: true,
+                    ..EvalConfig::default()
+                };
+                program.evaluate(config).unwrap().to_string()
+            };
+            assert_eq!(evaluate(Program { ast }), evaluate(program), "{source}");
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use std::sync::Arc;
+
+use petgraph::{Direction, graph::NodeIndex, visit::EdgeRef};
+
+use crate::{
+    ast::{AST, ASTResult, Edge, Node, VariableKind, builtins::ConstructorTag, types::Type},
+    parser::ParseErrors,
+};
+
+/// Long-lived graph that keeps growing, e.g behind a REPL:
+/// definitions stay evaluated between expressions instead of being parsed again.
+impl AST {
+    /// Bind `name` to `expr` for every expression parsed afterwards, like an unclosed `let`.
+    /// The value is evaluated on first use and then shared between all of them.
+    /// Definitions form a chain `let a .. in let b .. in b`, so the session is itself a program.
+    pub fn define(&mut self, name: &str, expr: &str) -> Result<NodeIndex, ParseErrors> {
+        let parameter = self.add_expr_in_scope(expr, self.session_scope())?;
+        let closure = self.graph.add_node(Node::Closure {
+            argument_name: Arc::new(name.to_string()),
+        });
+        self.graph.add_edge(closure, parameter, Edge::Parameter);
+        self.keep_alive(closure);
+
+        let body = self.graph.add_node(Node::Variable(VariableKind::Bound));
+        self.graph.add_edge(body, closure, Edge::Binder(0));
+        self.graph.add_edge(closure, body, Edge::Body);
+
+        if let Some(&previous) = self.session.last()
+            && let Ok(previous_body) = self.follow_edge(previous, Edge::Body)
+        {
+            self.remove_subtree(previous_body);
+            self.graph.add_edge(previous, closure, Edge::Body);
+        }
+        self.session.push(closure);
+        Ok(closure)
+    }
+
+    /// Evaluate `expr` against the definitions so far, performing its effects if it is an IO action.
+    /// Returns the resulting value formatted, the expression itself is then discarded.
+    pub fn evaluate_in_session(&mut self, expr: &str) -> ASTResult<String> {
+        let expr = self.add_expr_in_scope(expr, self.session_scope())?;
+        let mut result = self.evaluate(expr)?;
+        if let Some(&Node::Data {
+            tag: ConstructorTag::IO(io),
+        }) = self.graph.node_weight(result)
+        {
+            result = io.run(self, result)?;
+            result = self.evaluate(result)?;
+        }
+        // Result may still be under closures of its own `let`s
+        let mut top = result;
+        while let Some(parent) = self
+            .graph
+            .edges_directed(top, Direction::Incoming)
+            .find(|e| !matches!(e.weight(), Edge::Binder(_) | Edge::Debug))
+        {
+            top = parent.source();
+        }
+        let formatted = self.fmt_expr(result);
+        self.remove_subtree(top);
+        formatted
+    }
+
+    /// Type of `expr` against the definitions so far, see `check_types`. Nothing is evaluated.
+    pub fn type_of(&mut self, expr: &str) -> ASTResult<Type> {
+        let scope = self.session_scope();
+        let expr = self.add_expr_in_scope(expr, scope.clone())?;
+        let result = self.check_in_scope(expr, &scope);
+        self.remove_subtree(expr);
+        Ok(result?)
+    }
+
+    /// Binders visible to newly parsed expressions, innermost last
+    pub(crate) fn session_scope(&self) -> Vec<NodeIndex> {
+        self.session.clone()
+    }
+}
@@ -0,0 +1,293 @@
+use std::{fmt::Write, iter::Peekable, str::Chars, sync::Arc};
+
+use crate::ast::{
+    Primitive,
+    builtins::{
+        ConstructorTag,
+        arithmetic::{format_float, parse_float},
+    },
+    term::Term,
+};
+
+/// Placeholder for binder names in nameless terms
+const NAMELESS: &str = "_";
+
+#[derive(Debug, thiserror::Error)]
+pub enum SexprError {
+    #[error("unexpected end of input")]
+    UnexpectedEnd,
+    #[error("unexpected {0}")]
+    Unexpected(String),
+    #[error("unknown form ({0} ...)")]
+    UnknownForm(String),
+    #[error("invalid escape sequence in string")]
+    InvalidEscape,
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Atom(String),
+    Quoted(Vec<u8>),
+}
+
+fn tokens(input: &str) -> Result<Vec<Token>, SexprError> {
+    let mut chars = input.chars().peekable();
+    let mut tokens = vec![];
+    while let Some(c) = chars.next() {
+        match c {
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '"' => tokens.push(Token::Quoted(unescape(&mut chars)?)),
+            c if c.is_whitespace() => {}
+            c => {
+                let mut atom = String::from(c);
+                while let Some(c) =
+                    chars.next_if(|c| !c.is_whitespace() && !matches!(c, '(' | ')' | '"'))
+                {
+                    atom.push(c);
+                }
+                tokens.push(Token::Atom(atom));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Reads a string literal up to the closing quote, reverting `<[u8]>::escape_ascii`
+fn unescape(chars: &mut Peekable<Chars>) -> Result<Vec<u8>, SexprError> {
+    let mut bytes = vec![];
+    loop {
+        match chars.next().ok_or(SexprError::UnexpectedEnd)? {
+            '"' => return Ok(bytes),
+            '\\' => bytes.push(match chars.next().ok_or(SexprError::UnexpectedEnd)? {
+                'n' => b'\n',
+                't' => b'\t',
+                'r' => b'\r',
+                '\\' => b'\\',
+                '"' => b'"',
+                '\'' => b'\'',
+                'x' => {
+                    let hex = chars.by_ref().take(2).collect::<String>();
+                    u8::from_str_radix(&hex, 16).map_err(|_| SexprError::InvalidEscape)?
+                }
+                _ => return Err(SexprError::InvalidEscape),
+            }),
+            c => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+}
+
+struct Parser {
+    tokens: std::vec::IntoIter<Token>,
+}
+
+impl Parser {
+    fn next(&mut self) -> Result<Token, SexprError> {
+        self.tokens.next().ok_or(SexprError::UnexpectedEnd)
+    }
+
+    fn atom(&mut self) -> Result<String, SexprError> {
+        match self.next()? {
+            Token::Atom(atom) => Ok(atom),
+            token => Err(SexprError::Unexpected(format!("{token:?}"))),
+        }
+    }
+
+    fn number(&mut self) -> Result<usize, SexprError> {
+        let atom = self.atom()?;
+        atom.parse().map_err(|_| SexprError::Unexpected(atom))
+    }
+
+    fn close(&mut self) -> Result<(), SexprError> {
+        match self.next()? {
+            Token::Close => Ok(()),
+            token => Err(SexprError::Unexpected(format!("{token:?}"))),
+        }
+    }
+
+    fn binder_name(&mut self) -> Result<Option<Arc<String>>, SexprError> {
+        let name = self.atom()?;
+        Ok((name != NAMELESS).then(|| Arc::new(name)))
+    }
+
+    fn term(&mut self) -> Result<Term, SexprError> {
+        match self.next()? {
+            Token::Atom(atom) => Ok(match (atom.parse(), parse_float(&atom)) {
+                (Ok(number), _) => Term::Primitive(Primitive::Number(number)),
+                (_, Some(float)) => Term::Primitive(Primitive::Float(float)),
+                _ => Term::Var(Arc::new(atom)),
+            }),
+            Token::Quoted(bytes) => Ok(Term::Primitive(Primitive::Bytes(bytes))),
+            Token::Open => {
+                let form = self.atom()?;
+                let term = match form.as_str() {
+                    "idx" => Term::Index(self.number()?),
+                    "lam" => Term::Lambda {
+                        argument_name: self.binder_name()?,
+                        body: Box::new(self.term()?),
+                    },
+                    "app" => Term::Application {
+                        function: Box::new(self.term()?),
+                        parameter: Box::new(self.term()?),
+                    },
+                    "let" => Term::Closure {
+                        argument_name: self.binder_name()?,
+                        parameter: Box::new(self.term()?),
+                        body: Box::new(self.term()?),
+                    },
+                    "str" => match self.next()? {
+                        Token::Quoted(bytes) => Term::Primitive(Primitive::String(
+                            String::from_utf8(bytes)
+                                .map_err(|_| SexprError::Unexpected("invalid utf8".to_string()))?,
+                        )),
+                        token => return Err(SexprError::Unexpected(format!("{token:?}"))),
+                    },
+                    "data" => return self.data(),
+                    _ => return Err(SexprError::UnknownForm(form)),
+                };
+                self.close()?;
+                Ok(term)
+            }
+            Token::Close => Err(SexprError::Unexpected(")".to_string())),
+        }
+    }
+
+    /// Rest of `(data TAG arguments...)`, including the closing paren
+    fn data(&mut self) -> Result<Term, SexprError> {
+        let tag = match self.next()? {
+            Token::Atom(name) => {
+                ConstructorTag::try_from(name.as_str()).map_err(|_| SexprError::Unexpected(name))?
+            }
+            Token::Open => match self.atom()?.as_str() {
+                "custom" => {
+                    let tag = ConstructorTag::CustomTag {
+                        uid: self.number()?,
+                        arity: self.number()?,
+                    };
+                    self.close()?;
+                    tag
+                }
+                "array" => {
+                    let tag = ConstructorTag::Array {
+                        length: self.number()?,
+                    };
+                    self.close()?;
+                    tag
+                }
+                "host" => {
+                    let tag = ConstructorTag::HostFunction {
+                        index: self.number()?,
+                        arity: self.number()?,
+                    };
+                    self.close()?;
+                    tag
+                }
+                form => return Err(SexprError::UnknownForm(form.to_string())),
+            },
+            token => return Err(SexprError::Unexpected(format!("{token:?}"))),
+        };
+
+        let mut arguments = vec![];
+        loop {
+            match self.tokens.as_slice().first() {
+                Some(Token::Close) => {
+                    self.next()?;
+                    return Ok(Term::Data { tag, arguments });
+                }
+                _ => arguments.push(self.term()?),
+            }
+        }
+    }
+}
+
+impl Term {
+    /// Format as an S-expression, e.g `(lam x (app x y))`.
+    /// Nameless binders are written as `_`, De Bruijn indices as `(idx N)`.
+    pub fn to_sexpr(&self) -> String {
+        let mut result = String::new();
+        self.write_sexpr(&mut result);
+        result
+    }
+
+    fn write_sexpr(&self, out: &mut String) {
+        let name = |argument_name: &Option<Arc<String>>| {
+            argument_name
+                .as_ref()
+                .map_or(NAMELESS.to_string(), |name| name.to_string())
+        };
+        match self {
+            Term::Var(name) => out.push_str(name),
+            Term::Index(index) => write!(out, "(idx {index})").unwrap(),
+            Term::Lambda {
+                argument_name,
+                body,
+            } => {
+                write!(out, "(lam {} ", name(argument_name)).unwrap();
+                body.write_sexpr(out);
+                out.push(')');
+            }
+            Term::Application {
+                function,
+                parameter,
+            } => {
+                out.push_str("(app ");
+                function.write_sexpr(out);
+                out.push(' ');
+                parameter.write_sexpr(out);
+                out.push(')');
+            }
+            Term::Closure {
+                argument_name,
+                parameter,
+                body,
+            } => {
+                write!(out, "(let {} ", name(argument_name)).unwrap();
+                parameter.write_sexpr(out);
+                out.push(' ');
+                body.write_sexpr(out);
+                out.push(')');
+            }
+            Term::Primitive(Primitive::Number(number)) => write!(out, "{number}").unwrap(),
+            Term::Primitive(Primitive::Float(float)) => out.push_str(&format_float(*float)),
+            Term::Primitive(Primitive::Bytes(bytes)) => {
+                write!(out, "\"{}\"", bytes.escape_ascii()).unwrap()
+            }
+            Term::Primitive(Primitive::String(string)) => {
+                write!(out, "(str \"{}\")", string.as_bytes().escape_ascii()).unwrap()
+            }
+            Term::Data { tag, arguments } => {
+                match tag {
+                    ConstructorTag::CustomTag { uid, arity } => {
+                        write!(out, "(data (custom {uid} {arity})").unwrap()
+                    }
+                    ConstructorTag::HostFunction { index, arity } => {
+                        write!(out, "(data (host {index} {arity})").unwrap()
+                    }
+                    ConstructorTag::Array { length } => {
+                        write!(out, "(data (array {length})").unwrap()
+                    }
+                    _ => write!(out, "(data {}", String::from(*tag)).unwrap(),
+                }
+                for argument in arguments {
+                    out.push(' ');
+                    argument.write_sexpr(out);
+                }
+                out.push(')');
+            }
+        }
+    }
+
+    /// Parse a term written by `to_sexpr`
+    pub fn from_sexpr(input: &str) -> Result<Term, SexprError> {
+        let mut parser = Parser {
+            tokens: tokens(input)?.into_iter(),
+        };
+        let term = parser.term()?;
+        match parser.tokens.next() {
+            None => Ok(term),
+            Some(token) => Err(SexprError::Unexpected(format!("{token:?}"))),
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use std::{collections::HashMap, fmt::Write};
+
+use petgraph::graph::NodeIndex;
+
+use crate::ast::AST;
+
+/// Place in the source, both 1-based. Columns count characters, not bytes.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Source range from `start` up to (not including) `end`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start,
+            end: other.end,
+        }
+    }
+
+    /// Source line of the span start with the span underlined and labeled, e.g
+    /// ```text
+    ///  --> 2:3
+    ///   |
+    /// 2 | f "a"
+    ///   |   ^^^ expected Number
+    /// ```
+    /// Spans over several lines are only underlined up to the end of the first one.
+    pub fn render(&self, source: &str, label: &str) -> String {
+        let line = source.lines().nth(self.start.line - 1).unwrap_or_default();
+        let line_length = line.chars().count() + 1;
+        let end = match self.end.line == self.start.line {
+            true => self.end.column.min(line_length),
+            false => line_length,
+        };
+        let gutter = " ".repeat(self.start.line.to_string().len());
+
+        let mut result = String::new();
+        writeln!(
+            result,
+            "{gutter}--> {}:{}",
+            self.start.line, self.start.column
+        )
+        .unwrap();
+        writeln!(result, "{gutter} |").unwrap();
+        writeln!(result, "{} | {line}", self.start.line).unwrap();
+        writeln!(
+            result,
+            "{gutter} | {}{} {label}",
+            " ".repeat(self.start.column - 1),
+            "^".repeat(end.saturating_sub(self.start.column).max(1)),
+        )
+        .unwrap();
+        result
+    }
+}
+
+/// Where parsed nodes came from, in the source of the parser call that created them
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SourceMap {
+    /// Whole expression of every node
+    pub expressions: HashMap<NodeIndex, Span>,
+    /// Just the `x:Type` or `let x` part of lambdas and closures
+    pub binders: HashMap<NodeIndex, Span>,
+}
+
+impl SourceMap {
+    pub fn forget(&mut self, id: NodeIndex) {
+        self.expressions.remove(&id);
+        self.binders.remove(&id);
+    }
+}
+
+impl AST {
+    /// Source range the expression at `id` was parsed from
+    pub fn span(&self, id: NodeIndex) -> Option<Span> {
+        self.source_map.expressions.get(&id).copied()
+    }
+
+    /// Source range introducing the argument of lambda or closure `id`
+    pub fn binder_span(&self, id: NodeIndex) -> Option<Span> {
+        self.source_map.binders.get(&id).copied()
+    }
+
+    pub(crate) fn set_span(&mut self, id: NodeIndex, span: Span) {
+        self.source_map.expressions.insert(id, span);
+    }
+
+    pub(crate) fn set_binder_span(&mut self, id: NodeIndex, span: Span) {
+        self.source_map.binders.insert(id, span);
+    }
+}
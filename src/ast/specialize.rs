@@ -0,0 +1,55 @@
+use std::io;
+
+use petgraph::graph::NodeIndex;
+
+use crate::ast::{AST, ASTResult, Edge, Node, builtins::io::EffectHandler};
+
+/// Partial evaluation: reduce everything that doesn't depend on the arguments a program
+/// is still waiting for, leaving a smaller program taking the same arguments
+impl AST {
+    /// Evaluate `id`, then for every lambda it evaluates to, up to `depth` lambdas deep,
+    /// evaluate the expressions in its body not using its arguments, see `float_free_expressions`.
+    /// Arguments of constructors are left unevaluated, so infinite structures stay finite.
+    /// Effects can't be performed meanwhile, evaluating an IO action fails.
+    /// Returns NodeIndex under the closure chain, like `evaluate`
+    pub fn specialize(&mut self, id: NodeIndex, depth: usize) -> ASTResult<NodeIndex> {
+        let io = std::mem::replace(&mut self.io, Box::new(NoEffects));
+        let result = self.specialize_within(id, depth);
+        self.io = io;
+        result
+    }
+
+    fn specialize_within(&mut self, id: NodeIndex, depth: usize) -> ASTResult<NodeIndex> {
+        let value = self.evaluate(id)?;
+        if depth > 0
+            && let Node::Lambda { .. } = self.node(value)?
+        {
+            for closure in self.float_out_of(value)? {
+                self.specialize_within(self.follow_edge(closure, Edge::Parameter)?, depth - 1)?;
+            }
+        }
+        Ok(value)
+    }
+}
+
+/// Refuses every effect, programs are only specialized, not run
+#[derive(Debug, Clone)]
+struct NoEffects;
+
+impl EffectHandler for NoEffects {
+    fn handle_read(&mut self) -> io::Result<String> {
+        Err(io::Error::other("can't read input while specializing"))
+    }
+    fn handle_read_all(&mut self) -> io::Result<Vec<u8>> {
+        Err(io::Error::other("can't read input while specializing"))
+    }
+    fn handle_print(&mut self, _text: &str) -> io::Result<()> {
+        Err(io::Error::other("can't print while specializing"))
+    }
+    fn handle_exec(&mut self, _command: &str, _arguments: &[String]) -> io::Result<(Vec<u8>, i32)> {
+        Err(io::Error::other("can't run commands while specializing"))
+    }
+    fn clone_box(&self) -> Box<dyn EffectHandler> {
+        Box::new(self.clone())
+    }
+}
@@ -0,0 +1,74 @@
+use std::fmt::Display;
+
+use crate::ast::AST;
+
+/// Counters of evaluation events, see `AST::stats`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stats {
+    /// Lambdas applied to an argument
+    pub beta_reductions: usize,
+    /// Expressions moved out of lambdas not using their argument, see `float_free_expressions`
+    pub floated_expressions: usize,
+    /// Total size of the moved expressions
+    pub floated_nodes: usize,
+    /// Arguments evaluated before the body of a strict lambda, see `analyze_strictness`
+    pub strict_arguments: usize,
+    /// Lambdas `λx.f x` replaced by `f`, see `eta_reduce`
+    pub eta_reductions: usize,
+    /// Builtins called with all of their arguments
+    pub builtin_calls: usize,
+    /// Arithmetic on number literals computed before evaluation, see `fold_constants`
+    pub folded_constants: usize,
+    /// Closure chains lifted above the application or closure using their value
+    pub lifts: usize,
+    /// Values of shared closures copied for one of their references
+    pub subtree_clones: usize,
+    /// Values moved out of closures on their last reference, without copying
+    pub last_use_moves: usize,
+    /// Bytes copied by modifying builtins because other references still use the original,
+    /// Bytes on their last use are modified in place
+    pub bytes_copies: usize,
+    /// Arrays copied by `#array_set` because other references still use the original
+    pub array_copies: usize,
+    /// Full garbage collection passes
+    pub garbage_collections: usize,
+    /// Unreachable nodes removed by `collect_unreachable`
+    pub swept_nodes: usize,
+    pub peak_nodes: usize,
+}
+
+impl Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let rows = [
+            ("beta reductions", self.beta_reductions),
+            ("floated expressions", self.floated_expressions),
+            ("floated nodes", self.floated_nodes),
+            ("strict arguments", self.strict_arguments),
+            ("eta reductions", self.eta_reductions),
+            ("builtin calls", self.builtin_calls),
+            ("folded constants", self.folded_constants),
+            ("lifts", self.lifts),
+            ("subtree clones", self.subtree_clones),
+            ("last use moves", self.last_use_moves),
+            ("bytes copies", self.bytes_copies),
+            ("array copies", self.array_copies),
+            ("garbage collections", self.garbage_collections),
+            ("swept nodes", self.swept_nodes),
+            ("peak nodes", self.peak_nodes),
+        ];
+        for (name, count) in rows {
+            writeln!(f, "{name:<20} {count:>12}")?;
+        }
+        Ok(())
+    }
+}
+
+impl AST {
+    /// Evaluation counters accumulated so far
+    pub fn stats(&self) -> Stats {
+        Stats {
+            peak_nodes: self.peak_node_count(),
+            ..self.stats.clone()
+        }
+    }
+}
@@ -0,0 +1,432 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    sync::Arc,
+};
+
+use petgraph::graph::NodeIndex;
+
+use crate::ast::{
+    AST, ASTResult, Edge, Node, Primitive, VariableKind,
+    builtins::{ConstructorTag, helpers::HelperFunctionTag},
+    term::Term,
+};
+
+/// Strictness analysis: a lambda is strict when evaluating its body always evaluates its argument,
+/// e.g `λn.+ n 1`, but not `λb.b 1 0` or `λx.cons x nil`. Arguments of strict lambdas are evaluated
+/// before their body, so they don't pile up as unevaluated expressions, e.g accumulators of loops
+impl AST {
+    /// Mark the strict lambdas of the whole graph, returning how many there are.
+    /// In `λm n.+ m n` both are, but `m` only when `n` is given as well, see `strict_lambdas`
+    pub fn analyze_strictness(&mut self) -> ASTResult<usize> {
+        let strict = Analysis::new(self)?.strict_lambdas()?;
+        let count = strict.len();
+        self.strict_lambdas = strict;
+        Ok(count)
+    }
+
+    /// Functions bound by `let` and which of their parameters are strict, see `analyze_strictness`
+    pub fn strictness(&mut self) -> ASTResult<Vec<Signature>> {
+        self.analyze_strictness()?;
+        let mut signatures = vec![];
+        for closure in self.find(|node| matches!(node, Node::Closure { .. })) {
+            let Node::Closure { argument_name } = self.node(closure)? else {
+                continue;
+            };
+            let mut function = self.follow_edge(closure, Edge::Parameter)?;
+            // Recursive functions take themselves first
+            if let Node::Application = self.node(function)? {
+                let parameter = self.follow_edge(function, Edge::Parameter)?;
+                if self.is_fixpoint(self.follow_edge(function, Edge::Function)?)?
+                    && let Node::Lambda { .. } = self.node(parameter)?
+                {
+                    function = self.follow_edge(parameter, Edge::Body)?;
+                }
+            }
+            let mut parameters = vec![];
+            while let Node::Lambda { argument_name } = self.node(function)? {
+                parameters.push((
+                    argument_name.clone(),
+                    self.strict_lambdas.contains_key(&function),
+                ));
+                function = self.follow_edge(function, Edge::Body)?;
+            }
+            if !parameters.is_empty() {
+                signatures.push(Signature {
+                    name: argument_name.clone(),
+                    parameters,
+                });
+            }
+        }
+        Ok(signatures)
+    }
+
+    /// `id` is the fixpoint combinator, or a variable bound to it by `let`
+    fn is_fixpoint(&self, id: NodeIndex) -> ASTResult<bool> {
+        let id = match self.node(id)? {
+            Node::Variable(VariableKind::Bound) => {
+                let binder = self.follow_edge(id, Edge::Binder(0))?;
+                match self.node(binder)? {
+                    Node::Closure { .. } => self.follow_edge(binder, Edge::Parameter)?,
+                    _ => return Ok(false),
+                }
+            }
+            _ => id,
+        };
+        let Node::Lambda { .. } = self.node(id)? else {
+            return Ok(false);
+        };
+        let body = self.follow_edge(id, Edge::Body)?;
+        Ok(matches!(self.node(body)?, Node::Application)
+            && self.to_de_bruijn_term(id)? == Term::y_combinator())
+    }
+
+    /// Whether the application `id` is applied to at least `count - 1` more arguments,
+    /// so that a lambda applied by it gets the arguments it's strict with, see `analyze_strictness`.
+    /// Closures lifted in between don't matter
+    pub(super) fn is_applied_to(&self, mut id: NodeIndex, count: usize) -> bool {
+        let mut applied = 1;
+        while applied < count {
+            let Some((parent, edge)) = self.parent(id) else {
+                return false;
+            };
+            match (&self.graph[parent], edge) {
+                (Node::Application, Edge::Function) => applied += 1,
+                (Node::Closure { .. }, Edge::Body) => {}
+                _ => return false,
+            }
+            id = parent;
+        }
+        true
+    }
+}
+
+/// Parameters of a function bound by `let`, printed like `ackermann !m !n` with strict ones banged
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    pub name: Arc<String>,
+    pub parameters: Vec<(Arc<String>, bool)>,
+}
+
+impl Display for Signature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)?;
+        for (name, strict) in &self.parameters {
+            write!(f, " {}{name}", if *strict { "!" } else { "" })?;
+        }
+        Ok(())
+    }
+}
+
+/// Binders whose value is evaluated whenever some expression is
+type Forced = HashSet<NodeIndex>;
+
+struct Analysis<'a> {
+    ast: &'a AST,
+    /// Strictness in each argument of recursive functions `Y (λself x y.body)`, by `λself`
+    recursive: HashMap<NodeIndex, Vec<bool>>,
+    /// Forced by evaluating the value of each closure, and its strictness if it's a function
+    closures: HashMap<NodeIndex, (Forced, Option<Vec<bool>>)>,
+}
+
+impl<'a> Analysis<'a> {
+    /// Find recursive functions and how strict they are, assuming they are strict in everything
+    /// until shown otherwise
+    fn new(ast: &'a AST) -> ASTResult<Self> {
+        let mut analysis = Self {
+            ast,
+            recursive: HashMap::new(),
+            closures: HashMap::new(),
+        };
+        for application in ast.find(|node| matches!(node, Node::Application)) {
+            let function = ast.follow_edge(application, Edge::Parameter)?;
+            if let Node::Lambda { .. } = ast.node(function)?
+                && ast.is_fixpoint(ast.follow_edge(application, Edge::Function)?)?
+            {
+                let (parameters, _) = analysis.chain(ast.follow_edge(function, Edge::Body)?)?;
+                analysis
+                    .recursive
+                    .insert(function, vec![true; parameters.len()]);
+            }
+        }
+
+        let functions = analysis.recursive.keys().copied().collect::<Vec<_>>();
+        loop {
+            analysis.closures.clear();
+            let mut changed = false;
+            for &function in &functions {
+                let (parameters, body) = analysis.chain(ast.follow_edge(function, Edge::Body)?)?;
+                let forced = analysis.forced(body, &[])?;
+                let signature = parameters
+                    .iter()
+                    .zip(&analysis.recursive[&function])
+                    .map(|(parameter, &strict)| strict && forced.contains(parameter))
+                    .collect::<Vec<_>>();
+                if signature != analysis.recursive[&function] {
+                    analysis.recursive.insert(function, signature);
+                    changed = true;
+                }
+            }
+            if !changed {
+                return Ok(analysis);
+            }
+        }
+    }
+
+    /// Strict lambdas with the number of arguments they need, counting their own
+    fn strict_lambdas(&mut self) -> ASTResult<HashMap<NodeIndex, usize>> {
+        let mut strict = HashMap::new();
+        for lambda in self.ast.find(|node| matches!(node, Node::Lambda { .. })) {
+            if let Some((parent, Edge::Body)) = self.ast.parent(lambda)
+                && let Node::Lambda { .. } = self.ast.node(parent)?
+            {
+                continue;
+            }
+            let (parameters, body) = self.chain(lambda)?;
+            let forced = self.forced(body, &[])?;
+            for (position, &parameter) in parameters.iter().enumerate() {
+                if forced.contains(&parameter) {
+                    strict.insert(parameter, parameters.len() - position);
+                }
+            }
+        }
+        Ok(strict)
+    }
+
+    /// Lambdas directly nested in each other starting from `id`, and the body of the last one
+    fn chain(&self, mut id: NodeIndex) -> ASTResult<(Vec<NodeIndex>, NodeIndex)> {
+        let mut lambdas = vec![];
+        while let Node::Lambda { .. } = self.ast.node(id)? {
+            lambdas.push(id);
+            id = self.ast.follow_edge(id, Edge::Body)?;
+        }
+        Ok((lambdas, id))
+    }
+
+    /// Binders evaluated when `id` applied to `arguments` is evaluated, None for unknown arguments
+    fn forced(&mut self, id: NodeIndex, arguments: &[Option<NodeIndex>]) -> ASTResult<Forced> {
+        let ast = self.ast;
+        if let Some(Some(function)) = arguments.first()
+            && let Some(signature) = self.recursive.get(function).cloned()
+            && ast.is_fixpoint(id)?
+        {
+            return self.strict_arguments(&signature, &arguments[1..]);
+        }
+        Ok(match *ast.node(id)? {
+            Node::Variable(VariableKind::Bound) => {
+                let binder = ast.follow_edge(id, Edge::Binder(0))?;
+                let mut forced = Forced::from([binder]);
+                let signature = match ast.node(binder)? {
+                    Node::Closure { .. } => {
+                        let (value, signature) = self.closure(binder)?;
+                        forced.extend(value);
+                        signature
+                    }
+                    _ => self.recursive.get(&binder).cloned(),
+                };
+                if let Some(signature) = signature {
+                    forced.extend(self.strict_arguments(&signature, arguments)?);
+                }
+                forced
+            }
+            Node::Lambda { .. } => match arguments.split_first() {
+                None => Forced::new(),
+                Some((argument, rest)) => {
+                    let mut forced = self.forced(ast.follow_edge(id, Edge::Body)?, rest)?;
+                    if forced.contains(&id)
+                        && let Some(argument) = argument
+                    {
+                        forced.extend(self.forced(*argument, &[])?);
+                    }
+                    forced
+                }
+            },
+            Node::Application => {
+                let mut all = vec![Some(ast.follow_edge(id, Edge::Parameter)?)];
+                all.extend_from_slice(arguments);
+                self.forced(ast.follow_edge(id, Edge::Function)?, &all)?
+            }
+            Node::Closure { .. } => self.forced(ast.follow_edge(id, Edge::Body)?, arguments)?,
+            Node::Data { tag } if ast.binders(id).next().is_none() => {
+                self.builtin(tag, arguments)?
+            }
+            _ => Forced::new(),
+        })
+    }
+
+    /// What evaluating the value of `closure` forces, and its strictness if it's a function
+    fn closure(&mut self, closure: NodeIndex) -> ASTResult<(Forced, Option<Vec<bool>>)> {
+        if let Some(known) = self.closures.get(&closure) {
+            return Ok(known.clone());
+        }
+        let value = self.ast.follow_edge(closure, Edge::Parameter)?;
+        let forced = self.forced(value, &[])?;
+        let signature = match self.ast.node(value)? {
+            Node::Lambda { .. } => {
+                let (parameters, body) = self.chain(value)?;
+                let forced = self.forced(body, &[])?;
+                Some(parameters.iter().map(|p| forced.contains(p)).collect())
+            }
+            Node::Application => {
+                let function = self.ast.follow_edge(value, Edge::Parameter)?;
+                match self.recursive.get(&function) {
+                    Some(signature)
+                        if self
+                            .ast
+                            .is_fixpoint(self.ast.follow_edge(value, Edge::Function)?)? =>
+                    {
+                        Some(signature.clone())
+                    }
+                    _ => None,
+                }
+            }
+            Node::Variable(VariableKind::Bound) => {
+                let binder = self.ast.follow_edge(value, Edge::Binder(0))?;
+                match self.ast.node(binder)? {
+                    Node::Closure { .. } => self.closure(binder)?.1,
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        self.closures
+            .insert(closure, (forced.clone(), signature.clone()));
+        Ok((forced, signature))
+    }
+
+    /// Forced by a function with `signature` applied to `arguments`, if they are enough
+    fn strict_arguments(
+        &mut self,
+        signature: &[bool],
+        arguments: &[Option<NodeIndex>],
+    ) -> ASTResult<Forced> {
+        let mut forced = Forced::new();
+        if arguments.len() >= signature.len() {
+            for (&strict, argument) in signature.iter().zip(arguments) {
+                if strict && let Some(argument) = argument {
+                    forced.extend(self.forced(*argument, &[])?);
+                }
+            }
+        }
+        Ok(forced)
+    }
+
+    fn builtin(
+        &mut self,
+        tag: ConstructorTag,
+        arguments: &[Option<NodeIndex>],
+    ) -> ASTResult<Forced> {
+        let mut forced = Forced::new();
+        if arguments.len() < tag.arity() {
+            return Ok(forced);
+        }
+        forced.extend(self.strict_arguments(&tag.strict_arguments(), arguments)?);
+        match tag {
+            // Church boolean choosing between the next two arguments
+            ConstructorTag::Arithmetic(arithmetic) => {
+                if arithmetic.is_comparison()
+                    && let [_, _, Some(then), Some(otherwise), ..] = *arguments
+                {
+                    let then = self.forced(then, &[])?;
+                    let otherwise = self.forced(otherwise, &[])?;
+                    forced.extend(then.intersection(&otherwise));
+                }
+            }
+            ConstructorTag::HelperFunction(HelperFunctionTag::Match) => {
+                if let [
+                    Some(constructor),
+                    Some(transform),
+                    Some(fallback),
+                    value,
+                    ..,
+                ] = *arguments
+                    && let Some(arity) = self.constructor_arity(constructor)?
+                {
+                    let transformed = self.forced(transform, &vec![None; arity])?;
+                    let fallen_back = self.forced(fallback, &[value])?;
+                    forced.extend(transformed.intersection(&fallen_back));
+                }
+            }
+            _ => {}
+        }
+        Ok(forced)
+    }
+
+    /// Arity of the constructor `let name #constructor arity` that `id` refers to
+    fn constructor_arity(&self, id: NodeIndex) -> ASTResult<Option<usize>> {
+        let ast = self.ast;
+        let Node::Variable(VariableKind::Bound) = ast.node(id)? else {
+            return Ok(None);
+        };
+        let binder = ast.follow_edge(id, Edge::Binder(0))?;
+        let Node::Closure { .. } = ast.node(binder)? else {
+            return Ok(None);
+        };
+        let value = ast.follow_edge(binder, Edge::Parameter)?;
+        let Node::Application = ast.node(value)? else {
+            return Ok(None);
+        };
+        let function = ast.follow_edge(value, Edge::Function)?;
+        let arity = ast.follow_edge(value, Edge::Parameter)?;
+        Ok(match (ast.node(function)?, ast.node(arity)?) {
+            (
+                Node::Data {
+                    tag: ConstructorTag::HelperFunction(HelperFunctionTag::CreateConstructor),
+                },
+                Node::Primitive(Primitive::Number(arity)),
+            ) => Some(*arity),
+            _ => None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{EvalConfig, parse};
+
+    fn evaluate(source: &str, strictness: bool) -> Option<usize> {
+        let value = parse(source).unwrap().evaluate(EvalConfig {
+            strictness,
+            // Evaluating an unused argument would never end
+            max_steps: Some(100_000),
+            ..EvalConfig::default()
+        });
+        value.unwrap().to_usize()
+    }
+
+    /// Arguments that aren't always used are left alone, so they may diverge
+    #[test]
+    fn strict_and_lazy_agree() {
+        let programs = [
+            ("let const \\x y.x in const 1 ((\\x.x x) (\\x.x x))", 1),
+            ("let f \\x y.+ x 1 in f 2 ((\\x.x x) (\\x.x x))", 3),
+            (
+                "let pick \\b n.(=num b 0) 1 n in pick 0 ((\\x.x x) (\\x.x x))",
+                1,
+            ),
+            (
+                "let pair \\a b f.f a b in (pair 1 ((\\x.x x) (\\x.x x))) (\\a b.a)",
+                1,
+            ),
+            (
+                "let Y \\f.(\\x.f (x x)) (\\x.f (x x)) in
+                let sum Y \\sum n acc.(=num n 0) acc (sum (- 1 n) (+ acc n)) in
+                sum 10 0",
+                55,
+            ),
+        ];
+        for (source, expected) in programs {
+            assert_eq!(evaluate(source, false), Some(expected), "{source}");
+            assert_eq!(evaluate(source, true), Some(expected), "{source}");
+        }
+    }
+
+    #[test]
+    fn signatures() {
+        let mut program =
+            parse("let add \\m n.+ m n in let const \\x y.x in const (add 1 2) 3").unwrap();
+        let signatures = program.ast.strictness().unwrap();
+        let signatures: Vec<_> = signatures.iter().map(ToString::to_string).collect();
+        assert_eq!(signatures, ["add !m !n", "const !x y"]);
+    }
+}
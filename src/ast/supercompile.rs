@@ -0,0 +1,863 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use petgraph::graph::NodeIndex;
+
+use crate::ast::{
+    AST, ASTError, ASTResult, Number, Primitive,
+    builtins::{ConstructorTag, arithmetic::ArithmeticTag, helpers::HelperFunctionTag},
+    term::Term,
+};
+
+/// Experimental positive supercompilation: the program is driven symbolically, unknown
+/// values stay variables, and whatever can be decided ahead of time is. Pattern matches on
+/// a constructor built nearby are resolved, so intermediate structures disappear,
+/// e.g `nth n (numbers_from 0)` becomes a loop over two numbers without any list.
+/// Recursion must go through the `Y` combinator, which is recognized by its definition.
+/// Unfolding a recursive call similar to one unfolded earlier folds back to it,
+/// or generalizes the differences into `let` bindings so that it eventually can.
+impl AST {
+    /// Replace `id` with its supercompiled version and return the new root.
+    /// Nothing changes when the driver gives up, see `MAX_DRIVING_STEPS`
+    pub fn supercompile(&mut self, id: NodeIndex) -> ASTResult<NodeIndex> {
+        let term = self.to_term(id)?;
+        let residual = {
+            let mut driver = Driver {
+                ast: self,
+                origin: id,
+                names: vec![],
+                history: vec![],
+                steps: 0,
+            };
+            let expr = driver.expr(&term, &mut vec![])?;
+            let residual = driver.drive(expr)?;
+            driver.term(&residual, &mut vec![])
+        };
+        let result = self.add_term(&residual)?;
+        self.migrate_node(id, result)?;
+        self.remove_subtree(id);
+        Ok(result)
+    }
+}
+
+/// Driving gives up past this many steps, the program is then left as is
+const MAX_DRIVING_STEPS: usize = 200_000;
+
+/// Binder of an expression, unique among all expressions being driven
+type Id = usize;
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Var(Id),
+    /// Variable not bound anywhere in the program
+    Free(Arc<String>),
+    Lambda(Id, Box<Expr>),
+    Application(Box<Expr>, Box<Expr>),
+    /// Lazy and shared, like `Node::Closure`
+    Let(Id, Box<Expr>, Box<Expr>),
+    Primitive(Primitive),
+    /// Builtin or constructor without its arguments
+    Data(ConstructorTag),
+    /// The `Y` combinator
+    Fix,
+}
+
+/// What happens to the expression being driven once it's reduced
+#[derive(Debug, Clone)]
+enum Frame {
+    /// It's applied to this
+    Argument(Expr),
+    /// It's the value of `#match constructor transform fallback`
+    Match {
+        constructor: Expr,
+        transform: Expr,
+        fallback: Expr,
+    },
+    /// It's the first number of an arithmetic builtin
+    Left { tag: ArithmeticTag, right: Expr },
+    /// It's the second number of an arithmetic builtin
+    Right { tag: ArithmeticTag, left: Number },
+}
+
+/// Configuration in which a recursive function was unfolded
+struct Unfolding {
+    configuration: Expr,
+    /// Argument of `Y`
+    function: Expr,
+    /// Binder of the residual function, called when a later configuration folds back to this one
+    name: Id,
+    /// Free variables of the configuration, the arguments of the residual function
+    parameters: Vec<Id>,
+    folded: bool,
+}
+
+struct Driver<'a> {
+    ast: &'a mut AST,
+    origin: NodeIndex,
+    /// Name of every binder, only used to print the result
+    names: Vec<Arc<String>>,
+    /// Unfoldings the expression being driven is nested in, innermost last
+    history: Vec<Unfolding>,
+    steps: usize,
+}
+
+impl Driver<'_> {
+    fn fresh(&mut self, name: Arc<String>) -> Id {
+        self.names.push(name);
+        self.names.len() - 1
+    }
+
+    fn fresh_named(&mut self, name: &str) -> Id {
+        self.fresh(Arc::new(name.to_string()))
+    }
+
+    /// `term` in named form, with variables looked up in `scope`
+    fn expr(&mut self, term: &Term, scope: &mut Vec<(Arc<String>, Id)>) -> ASTResult<Expr> {
+        Ok(match term {
+            Term::Var(name) => match scope.iter().rev().find(|(binder, _)| binder == name) {
+                Some(&(_, id)) => Expr::Var(id),
+                None => Expr::Free(name.clone()),
+            },
+            Term::Index(index) => return Err(ASTError::UnboundIndex(*index)),
+            Term::Lambda { .. } if term.to_de_bruijn() == Term::y_combinator() => Expr::Fix,
+            Term::Lambda {
+                argument_name,
+                body,
+            } => {
+                let name = argument_name.clone().unwrap_or_default();
+                let id = self.fresh(name.clone());
+                scope.push((name, id));
+                let body = self.expr(body, scope);
+                scope.pop();
+                Expr::Lambda(id, Box::new(body?))
+            }
+            Term::Application {
+                function,
+                parameter,
+            } => application(self.expr(function, scope)?, self.expr(parameter, scope)?),
+            Term::Closure {
+                argument_name,
+                parameter,
+                body,
+            } => {
+                let parameter = self.expr(parameter, scope)?;
+                let name = argument_name.clone().unwrap_or_default();
+                let id = self.fresh(name.clone());
+                scope.push((name, id));
+                let body = self.expr(body, scope);
+                scope.pop();
+                Expr::Let(id, Box::new(parameter), Box::new(body?))
+            }
+            Term::Primitive(primitive) => Expr::Primitive(primitive.clone()),
+            Term::Data { tag, arguments } => arguments.iter().try_fold(
+                Expr::Data(*tag),
+                |data, argument| -> ASTResult<Expr> {
+                    Ok(application(data, self.expr(argument, scope)?))
+                },
+            )?,
+        })
+    }
+
+    /// `expr` as a term in nameless form, binders keep their names as hints
+    fn term(&self, expr: &Expr, scope: &mut Vec<Id>) -> Term {
+        let within = |id: Id, body: &Expr, scope: &mut Vec<Id>| {
+            scope.push(id);
+            let body = self.term(body, scope);
+            scope.pop();
+            Box::new(body)
+        };
+        match expr {
+            Expr::Var(id) => match scope.iter().rposition(|binder| binder == id) {
+                Some(position) => Term::Index(scope.len() - 1 - position),
+                None => Term::Var(self.names[*id].clone()),
+            },
+            Expr::Free(name) => Term::Var(name.clone()),
+            Expr::Lambda(id, body) => Term::Lambda {
+                argument_name: Some(self.names[*id].clone()),
+                body: within(*id, body, scope),
+            },
+            Expr::Application(function, parameter) => Term::Application {
+                function: Box::new(self.term(function, scope)),
+                parameter: Box::new(self.term(parameter, scope)),
+            },
+            Expr::Let(id, parameter, body) => Term::Closure {
+                argument_name: Some(self.names[*id].clone()),
+                parameter: Box::new(self.term(parameter, scope)),
+                body: within(*id, body, scope),
+            },
+            Expr::Primitive(primitive) => Term::Primitive(primitive.clone()),
+            Expr::Data(tag) => Term::Data {
+                tag: *tag,
+                arguments: vec![],
+            },
+            Expr::Fix => Term::y_combinator(),
+        }
+    }
+
+    fn tick(&mut self) -> ASTResult<()> {
+        self.steps += 1;
+        if self.steps > MAX_DRIVING_STEPS {
+            return Err(ASTError::Custom(self.origin, "Supercompilation gave up"));
+        }
+        Ok(())
+    }
+
+    fn drive(&mut self, expr: Expr) -> ASTResult<Expr> {
+        self.run(expr, vec![])
+    }
+
+    /// Reduce `focus` in the context of `frames`, innermost last, as far as it's known,
+    /// then drive whatever is left separately
+    fn run(&mut self, mut focus: Expr, mut frames: Vec<Frame>) -> ASTResult<Expr> {
+        loop {
+            self.tick()?;
+            focus = descend(focus, &mut frames);
+            focus = match focus {
+                Expr::Let(id, parameter, body) => {
+                    if let Some(tag) = self.constructor_definition(&parameter) {
+                        self.substitute(*body, id, &Expr::Data(tag))
+                    } else if is_cheap(&parameter) || is_linear(&body, id) {
+                        self.substitute(*body, id, &parameter)
+                    } else {
+                        let parameter = self.drive(*parameter)?;
+                        let body = self.run(*body, frames)?;
+                        return Ok(Expr::Let(id, Box::new(parameter), Box::new(body)));
+                    }
+                }
+                Expr::Lambda(id, body) => match frames.pop() {
+                    Some(Frame::Argument(argument)) => Expr::Let(id, Box::new(argument), body),
+                    None => return Ok(Expr::Lambda(id, Box::new(self.drive(*body)?))),
+                    Some(frame) => {
+                        frames.push(frame);
+                        return self.residualize(Expr::Lambda(id, body), frames);
+                    }
+                },
+                Expr::Fix => match frames.pop() {
+                    Some(Frame::Argument(function)) => return self.unfold(function, frames),
+                    frame => {
+                        frames.extend(frame);
+                        return self.residualize(Expr::Fix, frames);
+                    }
+                },
+                Expr::Data(tag @ ConstructorTag::CustomTag { .. }) => {
+                    match self.select(tag, &mut frames) {
+                        Some(next) => next,
+                        None => return self.residualize(Expr::Data(tag), frames),
+                    }
+                }
+                Expr::Primitive(Primitive::Number(number)) => match frames.pop() {
+                    Some(Frame::Left { tag, right }) => {
+                        frames.push(Frame::Right { tag, left: number });
+                        right
+                    }
+                    Some(Frame::Right { tag, left }) => match self.arithmetic(tag, left, number) {
+                        Some(result) => result,
+                        None => {
+                            frames.push(Frame::Right { tag, left });
+                            return self
+                                .residualize(Expr::Primitive(Primitive::Number(number)), frames);
+                        }
+                    },
+                    frame => {
+                        frames.extend(frame);
+                        return self
+                            .residualize(Expr::Primitive(Primitive::Number(number)), frames);
+                    }
+                },
+                focus => return self.residualize(focus, frames),
+            };
+        }
+    }
+
+    /// New tag bound by `let name #constructor arity`, like every evaluation of it creates
+    fn constructor_definition(&mut self, parameter: &Expr) -> Option<ConstructorTag> {
+        let (head, arguments) = spine(parameter);
+        let (
+            Expr::Data(ConstructorTag::HelperFunction(HelperFunctionTag::CreateConstructor)),
+            [Expr::Primitive(Primitive::Number(arity))],
+        ) = (head, arguments.as_slice())
+        else {
+            return None;
+        };
+        Some(ConstructorTag::CustomTag {
+            uid: self.ast.next_uid(),
+            arity: *arity,
+        })
+    }
+
+    /// Resolve `#match` on a constructor applied to all of its fields
+    fn select(&mut self, tag: ConstructorTag, frames: &mut Vec<Frame>) -> Option<Expr> {
+        let position = frames.len().checked_sub(tag.arity() + 1)?;
+        if !frames[position + 1..]
+            .iter()
+            .all(|frame| matches!(frame, Frame::Argument(_)))
+        {
+            return None;
+        }
+        let Frame::Match { constructor, .. } = &frames[position] else {
+            return None;
+        };
+        let matched = known_constructor(constructor)?;
+
+        let fields = frames.split_off(position + 1);
+        let Some(Frame::Match {
+            transform,
+            fallback,
+            ..
+        }) = frames.pop()
+        else {
+            return None;
+        };
+        if matched == tag {
+            frames.extend(fields);
+            Some(transform)
+        } else {
+            frames.push(Frame::Argument(plug(Expr::Data(tag), fields)));
+            Some(fallback)
+        }
+    }
+
+    fn arithmetic(&mut self, tag: ArithmeticTag, what: Number, to: Number) -> Option<Expr> {
+        if let Some(holds) = tag.compare(what, to) {
+            let [x, y] = [self.fresh_named("x"), self.fresh_named("y")];
+            let chosen = if holds { x } else { y };
+            return Some(Expr::Lambda(
+                x,
+                Box::new(Expr::Lambda(y, Box::new(Expr::Var(chosen)))),
+            ));
+        }
+        tag.fold(what, to)
+            .map(|number| Expr::Primitive(Primitive::Number(number)))
+    }
+
+    /// Unfold `Y function` applied within `frames`, unless the same configuration up to
+    /// renaming was unfolded before, or one that is embedded in it
+    fn unfold(&mut self, function: Expr, mut frames: Vec<Frame>) -> ASTResult<Expr> {
+        let configuration = plug(application(Expr::Fix, function.clone()), frames.clone());
+
+        for unfolding in self.history.iter_mut().rev() {
+            if let Some(renaming) = renaming(&unfolding.configuration, &configuration) {
+                unfolding.folded = true;
+                let call = unfolding
+                    .parameters
+                    .iter()
+                    .fold(Expr::Var(unfolding.name), |call, parameter| {
+                        application(call, Expr::Var(renaming[parameter]))
+                    });
+                return Ok(call);
+            }
+        }
+
+        let embedded = self
+            .history
+            .iter()
+            .rev()
+            .filter(|unfolding| is_alpha_equivalent(&unfolding.function, &function))
+            .find(|unfolding| Embedding::default().embeds(&unfolding.configuration, &configuration))
+            .map(|unfolding| unfolding.configuration.clone());
+        if let Some(previous) = embedded {
+            let mut holes = vec![];
+            let generalized =
+                self.generalize(&previous, configuration, &mut HashMap::new(), &mut holes);
+            if holes.is_empty() {
+                // Nothing to generalize, give up on unfolding it
+                frames.push(Frame::Argument(function));
+                return self.residualize(Expr::Fix, frames);
+            }
+            let body = self.drive(generalized)?;
+            return holes.into_iter().rev().try_fold(body, |body, (id, value)| {
+                Ok(Expr::Let(id, Box::new(self.drive(value)?), Box::new(body)))
+            });
+        }
+
+        let name = self.fresh_named("loop");
+        let parameters = free_variables(&configuration);
+        self.history.push(Unfolding {
+            configuration,
+            function: function.clone(),
+            name,
+            parameters: parameters.clone(),
+            folded: false,
+        });
+        let copy = self.freshen(&function, &mut HashMap::new());
+        frames.push(Frame::Argument(application(Expr::Fix, copy)));
+        let body = self.run(function, frames);
+        let folded = self.history.pop().is_some_and(|unfolding| unfolding.folded);
+        let body = body?;
+        if !folded {
+            return Ok(body);
+        }
+
+        let recursive = parameters.iter().rev().fold(body, |body, &parameter| {
+            Expr::Lambda(parameter, Box::new(body))
+        });
+        let call = application(Expr::Fix, Expr::Lambda(name, Box::new(recursive)));
+        Ok(parameters.iter().fold(call, |call, &parameter| {
+            application(call, Expr::Var(parameter))
+        }))
+    }
+
+    /// Most specific expression that both `previous` and `current` are instances of,
+    /// with `current` being it with `holes` substituted. Subexpressions using variables
+    /// bound within `current` can't be moved out, they are kept even if they differ
+    fn generalize(
+        &mut self,
+        previous: &Expr,
+        current: Expr,
+        bound: &mut HashMap<Id, Id>,
+        holes: &mut Vec<(Id, Expr)>,
+    ) -> Expr {
+        match (previous, current) {
+            (Expr::Lambda(x, previous), Expr::Lambda(y, current)) => {
+                bound.insert(*x, y);
+                Expr::Lambda(
+                    y,
+                    Box::new(self.generalize(previous, *current, bound, holes)),
+                )
+            }
+            (Expr::Let(x, previous_parameter, previous), Expr::Let(y, parameter, current)) => {
+                let parameter = self.generalize(previous_parameter, *parameter, bound, holes);
+                bound.insert(*x, y);
+                let body = self.generalize(previous, *current, bound, holes);
+                Expr::Let(y, Box::new(parameter), Box::new(body))
+            }
+            (
+                Expr::Application(previous_function, previous_parameter),
+                Expr::Application(function, parameter),
+            ) => application(
+                self.generalize(previous_function, *function, bound, holes),
+                self.generalize(previous_parameter, *parameter, bound, holes),
+            ),
+            (previous, current) => {
+                let same = match (previous, &current) {
+                    (Expr::Primitive(a), Expr::Primitive(b)) => a == b,
+                    (Expr::Data(a), Expr::Data(b)) => a == b,
+                    (Expr::Free(a), Expr::Free(b)) => a == b,
+                    (Expr::Fix, Expr::Fix) => true,
+                    _ => false,
+                };
+                let uses_bound = free_variables(&current)
+                    .iter()
+                    .any(|id| bound.values().any(|binder| binder == id));
+                if same || uses_bound || matches!(current, Expr::Var(_)) {
+                    current
+                } else {
+                    let hole = self.fresh_named("v");
+                    holes.push((hole, current));
+                    Expr::Var(hole)
+                }
+            }
+        }
+    }
+
+    /// Drive everything around `focus`, which can't be reduced any further
+    fn residualize(&mut self, focus: Expr, mut frames: Vec<Frame>) -> ASTResult<Expr> {
+        // Case of case: what happens to the result of a stuck match
+        // happens to each branch instead, where more is known
+        let position = frames
+            .iter()
+            .rposition(|frame| !matches!(frame, Frame::Argument(_)));
+        if let Some(position) = position
+            && position > 0
+            && let Frame::Match { constructor, .. } = &frames[position]
+            && let Some(tag) = known_constructor(constructor)
+        {
+            let arguments = frames.split_off(position + 1);
+            if let Some(Frame::Match {
+                constructor,
+                transform,
+                fallback,
+            }) = frames.pop()
+            {
+                let value = self.residualize(focus, arguments)?;
+                let fields = (0..tag.arity())
+                    .map(|_| self.fresh_named("field"))
+                    .collect::<Vec<_>>();
+                let other = self.fresh_named("value");
+                let copy = frames
+                    .iter()
+                    .map(|frame| self.freshen_frame(frame))
+                    .collect();
+
+                let transformed = fields.iter().fold(transform, |transform, &field| {
+                    application(transform, Expr::Var(field))
+                });
+                let transform = fields
+                    .iter()
+                    .rev()
+                    .fold(plug(transformed, frames), |body, &field| {
+                        Expr::Lambda(field, Box::new(body))
+                    });
+                let fallback = Expr::Lambda(
+                    other,
+                    Box::new(plug(application(fallback, Expr::Var(other)), copy)),
+                );
+                return Ok(match_expr(
+                    self.drive(constructor)?,
+                    self.drive(transform)?,
+                    self.drive(fallback)?,
+                    value,
+                ));
+            }
+        }
+
+        let mut result = match focus {
+            Expr::Lambda(id, body) => Expr::Lambda(id, Box::new(self.drive(*body)?)),
+            focus => focus,
+        };
+        for frame in frames.into_iter().rev() {
+            result = match frame {
+                Frame::Argument(argument) => application(result, self.drive(argument)?),
+                Frame::Match {
+                    constructor,
+                    transform,
+                    fallback,
+                } => match_expr(
+                    self.drive(constructor)?,
+                    self.drive(transform)?,
+                    self.drive(fallback)?,
+                    result,
+                ),
+                Frame::Left { tag, right } => application(
+                    application(Expr::Data(ConstructorTag::Arithmetic(tag)), result),
+                    self.drive(right)?,
+                ),
+                Frame::Right { tag, left } => application(
+                    application(
+                        Expr::Data(ConstructorTag::Arithmetic(tag)),
+                        Expr::Primitive(Primitive::Number(left)),
+                    ),
+                    result,
+                ),
+            };
+        }
+        Ok(result)
+    }
+
+    /// Replace `id` with copies of `value`, binders stay unique so nothing can be captured
+    fn substitute(&mut self, expr: Expr, id: Id, value: &Expr) -> Expr {
+        match expr {
+            Expr::Var(var) if var == id => self.freshen(value, &mut HashMap::new()),
+            Expr::Lambda(binder, body) => {
+                Expr::Lambda(binder, Box::new(self.substitute(*body, id, value)))
+            }
+            Expr::Application(function, parameter) => application(
+                self.substitute(*function, id, value),
+                self.substitute(*parameter, id, value),
+            ),
+            Expr::Let(binder, parameter, body) => Expr::Let(
+                binder,
+                Box::new(self.substitute(*parameter, id, value)),
+                Box::new(self.substitute(*body, id, value)),
+            ),
+            expr => expr,
+        }
+    }
+
+    /// Copy of `expr` with new binders
+    fn freshen(&mut self, expr: &Expr, renamed: &mut HashMap<Id, Id>) -> Expr {
+        match expr {
+            Expr::Var(id) => Expr::Var(renamed.get(id).copied().unwrap_or(*id)),
+            Expr::Lambda(id, body) => {
+                let fresh = self.fresh(self.names[*id].clone());
+                renamed.insert(*id, fresh);
+                Expr::Lambda(fresh, Box::new(self.freshen(body, renamed)))
+            }
+            Expr::Application(function, parameter) => application(
+                self.freshen(function, renamed),
+                self.freshen(parameter, renamed),
+            ),
+            Expr::Let(id, parameter, body) => {
+                let parameter = self.freshen(parameter, renamed);
+                let fresh = self.fresh(self.names[*id].clone());
+                renamed.insert(*id, fresh);
+                Expr::Let(
+                    fresh,
+                    Box::new(parameter),
+                    Box::new(self.freshen(body, renamed)),
+                )
+            }
+            expr => expr.clone(),
+        }
+    }
+
+    fn freshen_frame(&mut self, frame: &Frame) -> Frame {
+        let mut renamed = HashMap::new();
+        match frame {
+            Frame::Argument(argument) => Frame::Argument(self.freshen(argument, &mut renamed)),
+            Frame::Match {
+                constructor,
+                transform,
+                fallback,
+            } => Frame::Match {
+                constructor: self.freshen(constructor, &mut renamed),
+                transform: self.freshen(transform, &mut renamed),
+                fallback: self.freshen(fallback, &mut renamed),
+            },
+            Frame::Left { tag, right } => Frame::Left {
+                tag: *tag,
+                right: self.freshen(right, &mut renamed),
+            },
+            Frame::Right { tag, left } => Frame::Right {
+                tag: *tag,
+                left: *left,
+            },
+        }
+    }
+}
+
+fn application(function: Expr, parameter: Expr) -> Expr {
+    Expr::Application(Box::new(function), Box::new(parameter))
+}
+
+fn match_expr(constructor: Expr, transform: Expr, fallback: Expr, value: Expr) -> Expr {
+    [constructor, transform, fallback, value].into_iter().fold(
+        Expr::Data(ConstructorTag::HelperFunction(HelperFunctionTag::Match)),
+        application,
+    )
+}
+
+/// Function and arguments of an application chain
+fn spine(expr: &Expr) -> (&Expr, Vec<&Expr>) {
+    let mut arguments = vec![];
+    let mut head = expr;
+    while let Expr::Application(function, parameter) = head {
+        arguments.push(&**parameter);
+        head = function;
+    }
+    arguments.reverse();
+    (head, arguments)
+}
+
+/// Move down to the part of `focus` that has to be reduced first, pushing the rest onto `frames`
+fn descend(mut focus: Expr, frames: &mut Vec<Frame>) -> Expr {
+    loop {
+        focus = match focus {
+            Expr::Application(function, parameter) => {
+                frames.push(Frame::Argument(*parameter));
+                *function
+            }
+            Expr::Data(tag @ ConstructorTag::HelperFunction(HelperFunctionTag::Match)) => {
+                match take_arguments(frames) {
+                    Some([constructor, transform, fallback, value]) => {
+                        frames.push(Frame::Match {
+                            constructor,
+                            transform,
+                            fallback,
+                        });
+                        value
+                    }
+                    None => return Expr::Data(tag),
+                }
+            }
+            Expr::Data(ConstructorTag::Arithmetic(tag)) => match take_arguments(frames) {
+                Some([left, right]) => {
+                    frames.push(Frame::Left { tag, right });
+                    left
+                }
+                None => return Expr::Data(ConstructorTag::Arithmetic(tag)),
+            },
+            focus => return focus,
+        };
+    }
+}
+
+/// First `N` arguments on top of `frames`, if there are that many
+fn take_arguments<const N: usize>(frames: &mut Vec<Frame>) -> Option<[Expr; N]> {
+    let start = frames.len().checked_sub(N)?;
+    if !frames[start..]
+        .iter()
+        .all(|frame| matches!(frame, Frame::Argument(_)))
+    {
+        return None;
+    }
+    let arguments = frames
+        .split_off(start)
+        .into_iter()
+        .rev()
+        .filter_map(|frame| match frame {
+            Frame::Argument(argument) => Some(argument),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    arguments.try_into().ok()
+}
+
+/// Plug `focus` into `frames`, innermost last
+fn plug(focus: Expr, frames: Vec<Frame>) -> Expr {
+    frames
+        .into_iter()
+        .rev()
+        .fold(focus, |expr, frame| match frame {
+            Frame::Argument(argument) => application(expr, argument),
+            Frame::Match {
+                constructor,
+                transform,
+                fallback,
+            } => match_expr(constructor, transform, fallback, expr),
+            Frame::Left { tag, right } => application(
+                application(Expr::Data(ConstructorTag::Arithmetic(tag)), expr),
+                right,
+            ),
+            Frame::Right { tag, left } => application(
+                application(
+                    Expr::Data(ConstructorTag::Arithmetic(tag)),
+                    Expr::Primitive(Primitive::Number(left)),
+                ),
+                expr,
+            ),
+        })
+}
+
+fn known_constructor(expr: &Expr) -> Option<ConstructorTag> {
+    match expr {
+        Expr::Data(tag @ ConstructorTag::CustomTag { .. }) => Some(*tag),
+        _ => None,
+    }
+}
+
+/// Copying it wouldn't duplicate any work
+fn is_cheap(expr: &Expr) -> bool {
+    let (head, arguments) = spine(expr);
+    let partial = |arity: usize| arguments.len() < arity && arguments.iter().all(|a| is_cheap(a));
+    match head {
+        Expr::Var(_) | Expr::Free(_) | Expr::Primitive(_) | Expr::Lambda(..) => {
+            arguments.is_empty()
+        }
+        Expr::Fix => arguments.len() <= 1,
+        Expr::Data(tag @ ConstructorTag::CustomTag { .. }) => partial(tag.arity() + 1),
+        Expr::Data(tag) => partial(tag.arity()),
+        Expr::Application(..) | Expr::Let(..) => false,
+    }
+}
+
+/// `id` is used at most once in `expr`, and not inside a lambda
+fn is_linear(expr: &Expr, id: Id) -> bool {
+    fn uses(expr: &Expr, id: Id, under_lambda: bool) -> Option<usize> {
+        match expr {
+            Expr::Var(var) if *var == id => (!under_lambda).then_some(1),
+            Expr::Lambda(_, body) => uses(body, id, true),
+            Expr::Application(a, b) | Expr::Let(_, a, b) => {
+                Some(uses(a, id, under_lambda)? + uses(b, id, under_lambda)?)
+            }
+            _ => Some(0),
+        }
+    }
+    uses(expr, id, false).is_some_and(|count| count <= 1)
+}
+
+/// Variables used but not bound in `expr`, in order of first use
+fn free_variables(expr: &Expr) -> Vec<Id> {
+    fn collect(expr: &Expr, bound: &mut HashSet<Id>, free: &mut Vec<Id>) {
+        match expr {
+            Expr::Var(id) if !bound.contains(id) && !free.contains(id) => free.push(*id),
+            Expr::Lambda(id, body) => {
+                bound.insert(*id);
+                collect(body, bound, free);
+            }
+            Expr::Application(function, parameter) => {
+                collect(function, bound, free);
+                collect(parameter, bound, free);
+            }
+            Expr::Let(id, parameter, body) => {
+                collect(parameter, bound, free);
+                bound.insert(*id);
+                collect(body, bound, free);
+            }
+            _ => {}
+        }
+    }
+    let mut free = vec![];
+    collect(expr, &mut HashSet::new(), &mut free);
+    free
+}
+
+/// Bijection between free variables of `a` and `b` making them equal up to bound variable names
+fn renaming(a: &Expr, b: &Expr) -> Option<HashMap<Id, Id>> {
+    fn walk(
+        a: &Expr,
+        b: &Expr,
+        bound: &mut HashMap<Id, Id>,
+        free: &mut HashMap<Id, Id>,
+        targets: &mut HashSet<Id>,
+    ) -> bool {
+        match (a, b) {
+            (Expr::Var(x), Expr::Var(y)) => match bound.get(x) {
+                Some(binder) => binder == y,
+                None if bound.values().any(|binder| binder == y) => false,
+                None => match free.get(x) {
+                    Some(target) => target == y,
+                    None => {
+                        free.insert(*x, *y);
+                        targets.insert(*y)
+                    }
+                },
+            },
+            (Expr::Lambda(x, a), Expr::Lambda(y, b)) => {
+                bound.insert(*x, *y);
+                walk(a, b, bound, free, targets)
+            }
+            (Expr::Application(f, a), Expr::Application(g, b)) => {
+                walk(f, g, bound, free, targets) && walk(a, b, bound, free, targets)
+            }
+            (Expr::Let(x, p, a), Expr::Let(y, q, b)) => {
+                walk(p, q, bound, free, targets) && {
+                    bound.insert(*x, *y);
+                    walk(a, b, bound, free, targets)
+                }
+            }
+            (Expr::Free(x), Expr::Free(y)) => x == y,
+            (Expr::Primitive(x), Expr::Primitive(y)) => x == y,
+            (Expr::Data(x), Expr::Data(y)) => x == y,
+            (Expr::Fix, Expr::Fix) => true,
+            _ => false,
+        }
+    }
+    let mut free = HashMap::new();
+    walk(a, b, &mut HashMap::new(), &mut free, &mut HashSet::new()).then_some(free)
+}
+
+fn is_alpha_equivalent(a: &Expr, b: &Expr) -> bool {
+    renaming(a, b).is_some_and(|renaming| renaming.iter().all(|(x, y)| x == y))
+}
+
+/// Homeomorphic embedding, the whistle telling that unfolding might go on forever.
+/// Variables all look the same, so do numbers. Results are remembered by address.
+#[derive(Default)]
+struct Embedding(HashMap<(*const Expr, *const Expr), bool>);
+
+impl Embedding {
+    /// `a` can be obtained from `b` by removing parts of it
+    fn embeds(&mut self, a: &Expr, b: &Expr) -> bool {
+        let key = (a as *const Expr, b as *const Expr);
+        if let Some(&result) = self.0.get(&key) {
+            return result;
+        }
+        let result = self.couples(a, b)
+            || match b {
+                Expr::Lambda(_, body) => self.embeds(a, body),
+                Expr::Application(x, y) | Expr::Let(_, x, y) => {
+                    self.embeds(a, x) || self.embeds(a, y)
+                }
+                _ => false,
+            };
+        self.0.insert(key, result);
+        result
+    }
+
+    fn couples(&mut self, a: &Expr, b: &Expr) -> bool {
+        match (a, b) {
+            (Expr::Var(_) | Expr::Free(_), Expr::Var(_) | Expr::Free(_)) => true,
+            (Expr::Primitive(Primitive::Number(_)), Expr::Primitive(Primitive::Number(_))) => true,
+            (Expr::Primitive(x), Expr::Primitive(y)) => x == y,
+            (Expr::Data(x), Expr::Data(y)) => x == y,
+            (Expr::Fix, Expr::Fix) => true,
+            (Expr::Lambda(_, x), Expr::Lambda(_, y)) => self.embeds(x, y),
+            (Expr::Application(f, x), Expr::Application(g, y))
+            | (Expr::Let(_, f, x), Expr::Let(_, g, y)) => self.embeds(f, g) && self.embeds(x, y),
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,416 @@
+use std::{collections::HashSet, sync::Arc};
+
+use petgraph::graph::NodeIndex;
+
+use crate::ast::{
+    AST, ASTError, ASTResult, Edge, Node, Primitive, VariableKind, builtins::ConstructorTag,
+};
+
+/// Expression tree detached from the graph.
+/// Variables can be referenced either by name (named form) or by De Bruijn index
+/// (nameless form), and both may be mixed while converting between the two.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Term {
+    /// Variable referenced by name, bound by the innermost binder with that name or free
+    Var(Arc<String>),
+    /// Bound variable referenced by the number of binders in between, 0 is the innermost one
+    Index(usize),
+    /// Binder names are None in nameless form
+    Lambda {
+        argument_name: Option<Arc<String>>,
+        body: Box<Term>,
+    },
+    Application {
+        function: Box<Term>,
+        parameter: Box<Term>,
+    },
+    /// Binds `parameter` within `body`, see `Node::Closure`
+    Closure {
+        argument_name: Option<Arc<String>>,
+        parameter: Box<Term>,
+        body: Box<Term>,
+    },
+    Primitive(Primitive),
+    /// Arguments are variables referencing the binders of the data node
+    Data {
+        tag: ConstructorTag,
+        arguments: Vec<Term>,
+    },
+}
+
+/// Binders visible at some point of a term, innermost last
+struct Scope<T>(Vec<(Option<Arc<String>>, T)>);
+
+impl<T: Clone> Scope<T> {
+    fn by_name(&self, name: &Arc<String>) -> Option<(usize, T)> {
+        let position = self
+            .0
+            .iter()
+            .rposition(|(binder, _)| binder.as_ref() == Some(name))?;
+        Some((self.0.len() - 1 - position, self.0[position].1.clone()))
+    }
+    fn by_index(&self, index: usize) -> Option<T> {
+        let position = self.0.len().checked_sub(index + 1)?;
+        Some(self.0[position].1.clone())
+    }
+    fn with<R>(
+        &mut self,
+        name: Option<Arc<String>>,
+        value: T,
+        f: impl FnOnce(&mut Self) -> R,
+    ) -> R {
+        self.0.push((name, value));
+        let result = f(self);
+        self.0.pop();
+        result
+    }
+}
+
+impl Term {
+    /// Fixpoint combinator `λf.(λx.f (x x)) (λx.f (x x))` in nameless form,
+    /// compare with `to_de_bruijn` to recognize recursion
+    pub fn y_combinator() -> Term {
+        let half = Term::Lambda {
+            argument_name: None,
+            body: Box::new(Term::Application {
+                function: Box::new(Term::Index(1)),
+                parameter: Box::new(Term::Application {
+                    function: Box::new(Term::Index(0)),
+                    parameter: Box::new(Term::Index(0)),
+                }),
+            }),
+        };
+        Term::Lambda {
+            argument_name: None,
+            body: Box::new(Term::Application {
+                function: Box::new(half.clone()),
+                parameter: Box::new(half),
+            }),
+        }
+    }
+
+    /// Same term with every bound variable referenced by De Bruijn index and without binder names
+    pub fn to_de_bruijn(&self) -> Term {
+        self.de_bruijn_within(&mut Scope(vec![]))
+    }
+
+    fn de_bruijn_within(&self, scope: &mut Scope<()>) -> Term {
+        match self {
+            Term::Var(name) => match scope.by_name(name) {
+                Some((index, _)) => Term::Index(index),
+                None => Term::Var(name.clone()),
+            },
+            Term::Index(index) => Term::Index(*index),
+            Term::Lambda {
+                argument_name,
+                body,
+            } => Term::Lambda {
+                argument_name: None,
+                body: Box::new(scope.with(argument_name.clone(), (), |s| body.de_bruijn_within(s))),
+            },
+            Term::Application {
+                function,
+                parameter,
+            } => Term::Application {
+                function: Box::new(function.de_bruijn_within(scope)),
+                parameter: Box::new(parameter.de_bruijn_within(scope)),
+            },
+            Term::Closure {
+                argument_name,
+                parameter,
+                body,
+            } => Term::Closure {
+                argument_name: None,
+                parameter: Box::new(parameter.de_bruijn_within(scope)),
+                body: Box::new(scope.with(argument_name.clone(), (), |s| body.de_bruijn_within(s))),
+            },
+            Term::Primitive(primitive) => Term::Primitive(primitive.clone()),
+            Term::Data { tag, arguments } => Term::Data {
+                tag: *tag,
+                arguments: arguments
+                    .iter()
+                    .map(|a| a.de_bruijn_within(scope))
+                    .collect(),
+            },
+        }
+    }
+
+    /// Same term with every bound variable referenced by name.
+    /// Binders keep their names unless that would capture another variable,
+    /// nameless binders get fresh ones. Indices pointing outside the term are kept as is.
+    pub fn to_named(&self) -> Term {
+        let mut taken = self.free_variables();
+        self.named_within(&mut Scope(vec![]), &mut taken)
+    }
+
+    fn named_within(
+        &self,
+        scope: &mut Scope<Arc<String>>,
+        taken: &mut HashSet<Arc<String>>,
+    ) -> Term {
+        let bind = |argument_name: &Option<Arc<String>>,
+                    scope: &mut Scope<Arc<String>>,
+                    taken: &mut HashSet<Arc<String>>,
+                    inner: &Term| {
+            let fresh = fresh_name(argument_name.as_deref().map_or("x", |n| n), taken);
+            taken.insert(fresh.clone());
+            let term = scope.with(argument_name.clone(), fresh.clone(), |s| {
+                inner.named_within(s, taken)
+            });
+            taken.remove(&fresh);
+            (Some(fresh), Box::new(term))
+        };
+
+        match self {
+            Term::Var(name) => Term::Var(scope.by_name(name).map_or(name.clone(), |(_, n)| n)),
+            Term::Index(index) => scope
+                .by_index(*index)
+                .map_or(Term::Index(*index), Term::Var),
+            Term::Lambda {
+                argument_name,
+                body,
+            } => {
+                let (argument_name, body) = bind(argument_name, scope, taken, body);
+                Term::Lambda {
+                    argument_name,
+                    body,
+                }
+            }
+            Term::Application {
+                function,
+                parameter,
+            } => Term::Application {
+                function: Box::new(function.named_within(scope, taken)),
+                parameter: Box::new(parameter.named_within(scope, taken)),
+            },
+            Term::Closure {
+                argument_name,
+                parameter,
+                body,
+            } => {
+                let parameter = Box::new(parameter.named_within(scope, taken));
+                let (argument_name, body) = bind(argument_name, scope, taken, body);
+                Term::Closure {
+                    argument_name,
+                    parameter,
+                    body,
+                }
+            }
+            Term::Primitive(primitive) => Term::Primitive(primitive.clone()),
+            Term::Data { tag, arguments } => Term::Data {
+                tag: *tag,
+                arguments: arguments
+                    .iter()
+                    .map(|a| a.named_within(scope, taken))
+                    .collect(),
+            },
+        }
+    }
+
+    /// Names of variables not bound within the term
+    pub fn free_variables(&self) -> HashSet<Arc<String>> {
+        let mut free = HashSet::new();
+        self.collect_free(&mut Scope(vec![]), &mut free);
+        free
+    }
+
+    fn collect_free(&self, scope: &mut Scope<()>, free: &mut HashSet<Arc<String>>) {
+        match self {
+            Term::Var(name) => {
+                if scope.by_name(name).is_none() {
+                    free.insert(name.clone());
+                }
+            }
+            Term::Index(_) | Term::Primitive(_) => {}
+            Term::Lambda {
+                argument_name,
+                body,
+            } => scope.with(argument_name.clone(), (), |s| body.collect_free(s, free)),
+            Term::Application {
+                function,
+                parameter,
+            } => {
+                function.collect_free(scope, free);
+                parameter.collect_free(scope, free);
+            }
+            Term::Closure {
+                argument_name,
+                parameter,
+                body,
+            } => {
+                parameter.collect_free(scope, free);
+                scope.with(argument_name.clone(), (), |s| body.collect_free(s, free));
+            }
+            Term::Data { arguments, .. } => {
+                for argument in arguments {
+                    argument.collect_free(scope, free);
+                }
+            }
+        }
+    }
+}
+
+/// `base`, or `base` with the smallest numeric suffix that is not taken
+fn fresh_name(base: &str, taken: &HashSet<Arc<String>>) -> Arc<String> {
+    let mut candidate = Arc::new(base.to_string());
+    let mut suffix = 1;
+    while taken.contains(&candidate) {
+        candidate = Arc::new(format!("{base}{suffix}"));
+        suffix += 1;
+    }
+    candidate
+}
+
+impl AST {
+    /// Read the expression at `id` back as a term in named form, see `Term::to_named`
+    pub fn to_term(&self, id: NodeIndex) -> ASTResult<Term> {
+        Ok(self.term_within(id, &mut Scope(vec![]))?.to_named())
+    }
+
+    /// Read the expression at `id` back as a term in nameless De Bruijn form
+    pub fn to_de_bruijn_term(&self, id: NodeIndex) -> ASTResult<Term> {
+        Ok(self.term_within(id, &mut Scope(vec![]))?.to_de_bruijn())
+    }
+
+    /// Variables are referenced by index when their binder is within the converted
+    /// subtree, and by name otherwise. Binders keep their names as hints.
+    fn term_within(&self, id: NodeIndex, scope: &mut Scope<NodeIndex>) -> ASTResult<Term> {
+        let reference = |binder: NodeIndex, scope: &Scope<NodeIndex>| -> ASTResult<Term> {
+            match scope.0.iter().rposition(|(_, b)| *b == binder) {
+                Some(position) => Ok(Term::Index(scope.0.len() - 1 - position)),
+                None => match self.node(binder)? {
+                    Node::Lambda { argument_name } | Node::Closure { argument_name } => {
+                        Ok(Term::Var(argument_name.clone()))
+                    }
+                    _ => Err(ASTError::Custom(binder, "Incorrect binder")),
+                },
+            }
+        };
+
+        Ok(match self.node(id)? {
+            Node::Variable(VariableKind::Free(name)) => Term::Var(name.clone()),
+            Node::Variable(VariableKind::Bound) => {
+                reference(self.follow_edge(id, Edge::Binder(0))?, scope)?
+            }
+            Node::Lambda { argument_name } => {
+                let body = self.follow_edge(id, Edge::Body)?;
+                Term::Lambda {
+                    argument_name: Some(argument_name.clone()),
+                    body: Box::new(scope.with(Some(argument_name.clone()), id, |s| {
+                        self.term_within(body, s)
+                    })?),
+                }
+            }
+            Node::Application => Term::Application {
+                function: Box::new(self.term_within(self.follow_edge(id, Edge::Function)?, scope)?),
+                parameter: Box::new(
+                    self.term_within(self.follow_edge(id, Edge::Parameter)?, scope)?,
+                ),
+            },
+            Node::Closure { argument_name } => {
+                let body = self.follow_edge(id, Edge::Body)?;
+                Term::Closure {
+                    argument_name: Some(argument_name.clone()),
+                    parameter: Box::new(
+                        self.term_within(self.follow_edge(id, Edge::Parameter)?, scope)?,
+                    ),
+                    body: Box::new(scope.with(Some(argument_name.clone()), id, |s| {
+                        self.term_within(body, s)
+                    })?),
+                }
+            }
+            Node::Primitive(primitive) => Term::Primitive(primitive.clone()),
+            Node::Data { tag } => Term::Data {
+                tag: *tag,
+                arguments: ConstructorTag::get_binders(self, id)?
+                    .into_iter()
+                    .map(|binder| reference(binder, scope))
+                    .collect::<ASTResult<_>>()?,
+            },
+            Node::Debug(_) => return Err(ASTError::Custom(id, "Debug node is not a term")),
+        })
+    }
+
+    /// Build the term in the graph and return its root node.
+    /// Accepts both forms, names that are not bound within the term become free variables.
+    pub fn add_term(&mut self, term: &Term) -> ASTResult<NodeIndex> {
+        self.add_named_term(&term.to_named(), &mut Scope(vec![]))
+    }
+
+    fn add_named_term(
+        &mut self,
+        term: &Term,
+        scope: &mut Scope<NodeIndex>,
+    ) -> ASTResult<NodeIndex> {
+        Ok(match term {
+            Term::Var(name) => match scope.by_name(name) {
+                Some((_, binder)) => {
+                    let node = self.graph.add_node(Node::Variable(VariableKind::Bound));
+                    self.graph.add_edge(node, binder, Edge::Binder(0));
+                    node
+                }
+                None => self
+                    .graph
+                    .add_node(Node::Variable(VariableKind::Free(name.clone()))),
+            },
+            Term::Index(index) => return Err(ASTError::UnboundIndex(*index)),
+            Term::Lambda {
+                argument_name,
+                body,
+            } => {
+                let argument_name = argument_name.clone().unwrap_or_default();
+                let node = self.graph.add_node(Node::Lambda {
+                    argument_name: argument_name.clone(),
+                });
+                let body =
+                    scope.with(Some(argument_name), node, |s| self.add_named_term(body, s))?;
+                self.graph.add_edge(node, body, Edge::Body);
+                node
+            }
+            Term::Application {
+                function,
+                parameter,
+            } => {
+                let function = self.add_named_term(function, scope)?;
+                let parameter = self.add_named_term(parameter, scope)?;
+                let node = self.graph.add_node(Node::Application);
+                self.graph.add_edge(node, function, Edge::Function);
+                self.graph.add_edge(node, parameter, Edge::Parameter);
+                node
+            }
+            Term::Closure {
+                argument_name,
+                parameter,
+                body,
+            } => {
+                let argument_name = argument_name.clone().unwrap_or_default();
+                let parameter = self.add_named_term(parameter, scope)?;
+                let node = self.graph.add_node(Node::Closure {
+                    argument_name: argument_name.clone(),
+                });
+                let body =
+                    scope.with(Some(argument_name), node, |s| self.add_named_term(body, s))?;
+                self.graph.add_edge(node, body, Edge::Body);
+                self.graph.add_edge(node, parameter, Edge::Parameter);
+                node
+            }
+            Term::Primitive(primitive) => self.graph.add_node(Node::Primitive(primitive.clone())),
+            Term::Data { tag, arguments } => {
+                let node = self.graph.add_node(Node::Data { tag: *tag });
+                for (argument_index, argument) in arguments.iter().enumerate() {
+                    let binder = match argument {
+                        Term::Var(name) => scope.by_name(name).map(|(_, binder)| binder),
+                        _ => None,
+                    }
+                    .ok_or(ASTError::Custom(
+                        node,
+                        "Data argument must be a bound variable",
+                    ))?;
+                    self.graph
+                        .add_edge(node, binder, Edge::Binder(argument_index));
+                }
+                node
+            }
+        })
+    }
+}
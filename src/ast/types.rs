@@ -0,0 +1,607 @@
+use std::{collections::HashMap, fmt::Display};
+
+use petgraph::{Direction, graph::NodeIndex, visit::EdgeRef};
+
+use crate::ast::{
+    AST, Edge, Node, Primitive, VariableKind,
+    builtins::{ConstructorTag, helpers::HelperFunctionTag},
+    span::Span,
+};
+
+/// Simple type of an expression, as written in `λx:Number.` annotations
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Type {
+    Number,
+    Float,
+    Bytes,
+    String,
+    IO,
+    /// Value built by a constructor from `#constructor`
+    Data,
+    /// `{ age : Number, name : Bytes }`, fields sorted by name
+    Record(Vec<(String, Type)>),
+    /// `Number -> Bytes`
+    Arrow(Box<Type>, Box<Type>),
+    /// Unannotated binders, constructor arguments and anything else the checker can't see through.
+    /// Consistent with every type, so untyped code still passes, see `AST::insert_casts`.
+    Dyn,
+}
+
+impl Type {
+    /// Base type called `name`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "Number" => Some(Type::Number),
+            "Float" => Some(Type::Float),
+            "Bytes" => Some(Type::Bytes),
+            "String" => Some(Type::String),
+            "IO" => Some(Type::IO),
+            "Data" => Some(Type::Data),
+            "Dyn" => Some(Type::Dyn),
+            _ => None,
+        }
+    }
+
+    /// Curried function taking `arguments` in order, e.g `Number -> Bytes -> Number`
+    pub fn function(arguments: impl IntoIterator<Item = Type>, result: Type) -> Self {
+        let arguments = arguments.into_iter().collect::<Vec<_>>();
+        arguments
+            .into_iter()
+            .rev()
+            .fold(result, |result, argument| {
+                Type::Arrow(Box::new(argument), Box::new(result))
+            })
+    }
+
+    /// What `#typeof` returns for values of this type
+    pub fn runtime_name(&self) -> &'static str {
+        match self {
+            Type::Number => "Number",
+            Type::Float => "Float",
+            Type::Bytes => "Bytes",
+            Type::String => "String",
+            Type::IO => "IO",
+            Type::Data | Type::Record(_) => "Data",
+            Type::Arrow(..) => "Function",
+            Type::Dyn => "Dyn",
+        }
+    }
+
+    /// What remains of a function type once `count` arguments are applied
+    pub(crate) fn skip_arguments(self, count: usize) -> Self {
+        match self {
+            Type::Arrow(_, codomain) if count > 0 => codomain.skip_arguments(count - 1),
+            other => other,
+        }
+    }
+
+    /// Whether a value of type `other` can be used where `self` is expected
+    fn is_consistent_with(&self, other: &Type) -> bool {
+        match (self, other) {
+            (Type::Dyn, _) | (_, Type::Dyn) => true,
+            (Type::Arrow(a, b), Type::Arrow(c, d)) => {
+                a.is_consistent_with(c) && b.is_consistent_with(d)
+            }
+            (Type::Data, Type::Record(_)) | (Type::Record(_), Type::Data) => true,
+            (Type::Record(a), Type::Record(b)) => {
+                a.len() == b.len()
+                    && a.iter()
+                        .zip(b)
+                        .all(|((a, x), (b, y))| a == b && x.is_consistent_with(y))
+            }
+            (a, b) => a == b,
+        }
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Number => write!(f, "Number"),
+            Type::Float => write!(f, "Float"),
+            Type::Bytes => write!(f, "Bytes"),
+            Type::String => write!(f, "String"),
+            Type::IO => write!(f, "IO"),
+            Type::Data => write!(f, "Data"),
+            Type::Record(fields) if fields.is_empty() => write!(f, "{{}}"),
+            // Tuples are records with fields named by position
+            Type::Record(fields)
+                if fields.len() > 1
+                    && (0..fields.len())
+                        .all(|index| fields.iter().any(|(name, _)| *name == index.to_string())) =>
+            {
+                let mut elements = fields.iter().collect::<Vec<_>>();
+                elements.sort_by_key(|(name, _)| name.parse::<usize>().unwrap());
+                let elements = elements
+                    .iter()
+                    .map(|(_, element)| element.to_string())
+                    .collect::<Vec<_>>();
+                write!(f, "({})", elements.join(", "))
+            }
+            Type::Record(fields) => {
+                let fields = fields
+                    .iter()
+                    .map(|(name, field)| format!("{name} : {field}"))
+                    .collect::<Vec<_>>();
+                write!(f, "{{ {} }}", fields.join(", "))
+            }
+            Type::Arrow(domain, codomain) if matches!(**domain, Type::Arrow(..)) => {
+                write!(f, "({domain}) -> {codomain}")
+            }
+            Type::Arrow(domain, codomain) => write!(f, "{domain} -> {codomain}"),
+            Type::Dyn => write!(f, "Dyn"),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("type error in `{expr}`: {message}")]
+pub struct TypeCheckError {
+    /// Offending expression
+    pub node: NodeIndex,
+    expr: String,
+    message: String,
+    /// Where the offending expression is, with its actual type as label,
+    /// followed by the binding or builtin the expected type came from
+    labels: Vec<(Span, String)>,
+}
+
+impl TypeCheckError {
+    /// Message followed by source snippets pointing at both
+    /// the offending expression and the origin of the expected type
+    pub fn render(&self, source: &str) -> String {
+        let mut result = format!("error: {}\n", self.message);
+        for (span, label) in &self.labels {
+            result.push_str(&span.render(source, label));
+        }
+        result
+    }
+}
+
+/// State of one walk over the program
+#[derive(Default)]
+struct Context {
+    /// Types of binders in scope
+    binders: HashMap<NodeIndex, Type>,
+    /// `Dyn` arguments passed where a more precise type is expected
+    casts: Vec<(NodeIndex, Type)>,
+    /// Types of top level definitions applied to arguments of given types, see `instantiate`
+    instances: HashMap<(NodeIndex, Vec<Type>), Option<Type>>,
+}
+
+impl AST {
+    /// Remember the annotated type of `lambda`'s argument
+    pub(crate) fn annotate(&mut self, lambda: NodeIndex, argument_type: Type) {
+        self.annotations.insert(lambda, argument_type);
+    }
+
+    /// Check root against the `λx:Type.` annotations written in the source.
+    /// Must be called before evaluation, which rewrites the graph and drops annotations.
+    pub fn check_types(&self) -> Result<Type, TypeCheckError> {
+        self.check().map(|(program_type, _)| program_type)
+    }
+
+    /// Check like `check_types`, then guard every boundary where a `Dyn` value
+    /// is passed for a more precise type with `#expect_type`, which fails at runtime
+    /// if the value turns out to be of a different type. Values are checked as deep as `#typeof` goes,
+    /// so a function is only known to be a function.
+    pub fn insert_casts(&mut self) -> Result<Type, TypeCheckError> {
+        let (program_type, casts) = self.check()?;
+        for (value, expected) in casts {
+            let cast = self.graph.add_node(Node::Data {
+                tag: ConstructorTag::HelperFunction(HelperFunctionTag::ExpectType),
+            });
+            let name = self.graph.add_node(Node::Primitive(Primitive::Bytes(
+                expected.runtime_name().into(),
+            )));
+            let partial = self.graph.add_node(Node::Application);
+            self.graph.add_edge(partial, cast, Edge::Function);
+            self.graph.add_edge(partial, name, Edge::Parameter);
+            let application = self.graph.add_node(Node::Application);
+            self.graph.add_edge(application, partial, Edge::Function);
+
+            self.migrate_node(value, application)
+                .expect("value is in the graph");
+            self.graph.add_edge(application, value, Edge::Parameter);
+            if let Some(span) = self.span(value) {
+                self.set_span(application, span);
+            }
+        }
+        Ok(program_type)
+    }
+
+    /// Type of expression `id` parsed in scope of closures `scope`, outermost first, e.g of a session.
+    /// Definitions that don't check are `Dyn`, so only `id` itself can fail.
+    pub(crate) fn check_in_scope(
+        &self,
+        id: NodeIndex,
+        scope: &[NodeIndex],
+    ) -> Result<Type, TypeCheckError> {
+        let mut context = Context::default();
+        for &closure in scope {
+            let parameter = match self.follow_edge(closure, Edge::Parameter) {
+                Ok(parameter) => self.infer(parameter, &mut context).unwrap_or(Type::Dyn),
+                Err(_) => Type::Dyn,
+            };
+            context.binders.insert(closure, parameter);
+        }
+        self.infer(id, &mut context)
+    }
+
+    fn check(&self) -> Result<(Type, Vec<(NodeIndex, Type)>), TypeCheckError> {
+        let mut context = Context::default();
+        let program_type = self.infer(self.root, &mut context)?;
+
+        // Effects only happen when the program evaluates to an IO action
+        if let Some(builtin) = self.io_builtin()
+            && !Type::IO.is_consistent_with(&program_type)
+        {
+            let mut body = self.root;
+            while let Some(Node::Closure { .. }) = self.graph.node_weight(body)
+                && let Some(edge) = self
+                    .graph
+                    .edges_directed(body, Direction::Outgoing)
+                    .find(|e| *e.weight() == Edge::Body)
+            {
+                body = edge.target();
+            }
+            return Err(self.mismatch(
+                body,
+                &program_type,
+                format!("program uses IO, but evaluates to {program_type} instead of an IO action"),
+                builtin,
+                &HashMap::new(),
+            ));
+        }
+        Ok((program_type, context.casts))
+    }
+
+    /// Any IO builtin in the graph, so the program is meant to perform IO
+    pub fn io_builtin(&self) -> Option<NodeIndex> {
+        self.graph.node_indices().find(|&id| {
+            matches!(
+                self.graph[id],
+                Node::Data {
+                    tag: ConstructorTag::IO(_)
+                }
+            )
+        })
+    }
+
+    /// Type of expression `id`, given types of the binders around it
+    fn infer(&self, id: NodeIndex, context: &mut Context) -> Result<Type, TypeCheckError> {
+        let child = |edge| {
+            self.graph
+                .edges_directed(id, Direction::Outgoing)
+                .find(|e| *e.weight() == edge)
+                .map(|e| e.target())
+                .ok_or_else(|| self.type_error(id, format!("missing {edge:?} edge")))
+        };
+
+        match self.graph.node_weight(id) {
+            Some(Node::Primitive(Primitive::Number(_))) => Ok(Type::Number),
+            Some(Node::Primitive(Primitive::Float(_))) => Ok(Type::Float),
+            Some(Node::Primitive(Primitive::Bytes(_))) => Ok(Type::Bytes),
+            Some(Node::Primitive(Primitive::String(_))) => Ok(Type::String),
+            Some(Node::Variable(VariableKind::Bound)) => {
+                let binder = child(Edge::Binder(0))?;
+                Ok(context.binders.get(&binder).cloned().unwrap_or(Type::Dyn))
+            }
+            Some(Node::Lambda { .. }) => {
+                let argument = self.annotations.get(&id).cloned().unwrap_or(Type::Dyn);
+                context.binders.insert(id, argument.clone());
+                let body = self.infer(child(Edge::Body)?, context)?;
+                Ok(Type::Arrow(Box::new(argument), Box::new(body)))
+            }
+            Some(Node::Closure { .. }) => {
+                let parameter = self.infer(child(Edge::Parameter)?, context)?;
+                context.binders.insert(id, parameter);
+                self.infer(child(Edge::Body)?, context)
+            }
+            Some(Node::Application) if let Some(field) = self.field_accesses.get(&id) => {
+                let record_id = child(Edge::Parameter)?;
+                let record = self.infer(record_id, context)?;
+                let message = match &record {
+                    Type::Record(fields) => {
+                        match fields.iter().find(|(name, _)| name == &**field) {
+                            Some((_, field)) => return Ok(field.clone()),
+                            None => format!("record has no field `{field}`"),
+                        }
+                    }
+                    Type::Dyn | Type::Data => return Ok(Type::Dyn),
+                    record => format!("value of type {record} is not a record"),
+                };
+                let mut error = self.type_error(record_id, message);
+                if let Some((_, label)) = error.labels.first_mut() {
+                    *label = format!("this is {record}");
+                }
+                Err(error)
+            }
+            Some(Node::Application) if let Some(fields) = self.record_literal(id) => {
+                let mut current = id;
+                let mut types = vec![];
+                for _ in 0..fields.len() {
+                    let parameter = self.follow_edge(current, Edge::Parameter).map_err(|_| {
+                        self.type_error(current, "missing Parameter edge".to_string())
+                    })?;
+                    types.push(self.infer(parameter, context)?);
+                    current = self.follow_edge(current, Edge::Function).map_err(|_| {
+                        self.type_error(current, "missing Function edge".to_string())
+                    })?;
+                }
+                types.reverse();
+                Ok(Type::Record(fields.into_iter().zip(types).collect()))
+            }
+            Some(Node::Application) => {
+                let function_id = child(Edge::Function)?;
+                let function = self.infer(function_id, context)?;
+                let parameter_id = child(Edge::Parameter)?;
+                let parameter = self.infer(parameter_id, context)?;
+
+                // Arity is known statically when it's a literal
+                if let Some(Node::Data {
+                    tag: ConstructorTag::HelperFunction(HelperFunctionTag::CreateConstructor),
+                }) = self.graph.node_weight(function_id)
+                    && let Some(Node::Primitive(Primitive::Number(arity))) =
+                        self.graph.node_weight(parameter_id)
+                {
+                    return Ok(Type::function(vec![Type::Dyn; *arity], Type::Data));
+                }
+
+                match function {
+                    Type::Arrow(domain, codomain) => {
+                        if !domain.is_consistent_with(&parameter) {
+                            return Err(self.mismatch(
+                                parameter_id,
+                                &parameter,
+                                format!("expected argument of type {domain}, got {parameter}"),
+                                function_id,
+                                &context.binders,
+                            ));
+                        }
+                        if parameter == Type::Dyn && *domain != Type::Dyn {
+                            context.casts.push((parameter_id, *domain));
+                        }
+                        Ok(self.instantiate(id, context).unwrap_or(*codomain))
+                    }
+                    Type::Dyn => Ok(Type::Dyn),
+                    function => Err(self.mismatch(
+                        function_id,
+                        &function,
+                        format!("value of type {function} is not a function"),
+                        function_id,
+                        &context.binders,
+                    )),
+                }
+            }
+            Some(Node::Data { tag }) => {
+                let applied = ConstructorTag::get_binders(self, id).map_or(0, |b| b.len());
+                Ok(tag.signature().skip_arguments(applied))
+            }
+            Some(Node::Variable(VariableKind::Free(_)) | Node::Debug(_)) => Ok(Type::Dyn),
+            None => Err(self.type_error(id, "node does not exist".to_string())),
+        }
+    }
+
+    /// Type of application chain `id` of a `let`-bound lambda, checked again with the types
+    /// of the actual arguments for its unannotated binders. This makes definitions polymorphic:
+    /// with `let id \x.x`, `id 1` is a Number and `id "a"` is Bytes rather than both being Dyn.
+    /// `None` if the head is not such a lambda, or if the more precise check fails,
+    /// leaving the type of the definition itself.
+    fn instantiate(&self, id: NodeIndex, context: &mut Context) -> Option<Type> {
+        // Only once per chain, at its outermost application
+        if self
+            .graph
+            .edges_directed(id, Direction::Incoming)
+            .any(|e| *e.weight() == Edge::Function)
+        {
+            return None;
+        }
+        let (head, _) = self.application_head(id);
+        let mut arguments = vec![];
+        let mut current = id;
+        while current != head {
+            arguments.push(self.follow_edge(current, Edge::Parameter).ok()?);
+            current = self.follow_edge(current, Edge::Function).ok()?;
+        }
+        arguments.reverse();
+
+        let Some(Node::Variable(VariableKind::Bound)) = self.graph.node_weight(head) else {
+            return None;
+        };
+        let binder = self.follow_edge(head, Edge::Binder(0)).ok()?;
+        let Some(Node::Closure { .. }) = self.graph.node_weight(binder) else {
+            return None;
+        };
+        let value = self.follow_edge(binder, Edge::Parameter).ok()?;
+        if !matches!(self.graph.node_weight(value), Some(Node::Lambda { .. })) {
+            return None;
+        }
+
+        // Casts were already collected for the definition itself
+        let mut scratch = Context {
+            binders: context.binders.clone(),
+            casts: vec![],
+            instances: std::mem::take(&mut context.instances),
+        };
+        let result = self.instance(binder, value, &arguments, &mut scratch);
+        context.instances = scratch.instances;
+        result
+    }
+
+    /// Type of lambda `value` bound by closure `binder` applied to `arguments`
+    fn instance(
+        &self,
+        binder: NodeIndex,
+        value: NodeIndex,
+        arguments: &[NodeIndex],
+        scratch: &mut Context,
+    ) -> Option<Type> {
+        let mut types = vec![];
+        for &argument in arguments {
+            types.push(self.infer(argument, scratch).ok()?);
+        }
+        // Definitions inside lambdas may see different types around them on every instance
+        let cached = self.is_top_level(binder);
+        let key = (binder, types);
+        if cached && let Some(result) = scratch.instances.get(&key) {
+            return result.clone();
+        }
+
+        let result = self.instance_body(value, &key.1, scratch);
+        if cached {
+            scratch.instances.insert(key, result.clone());
+        }
+        result
+    }
+
+    /// Type of the body of lambda `value` with unannotated binders of given types
+    fn instance_body(
+        &self,
+        mut value: NodeIndex,
+        types: &[Type],
+        scratch: &mut Context,
+    ) -> Option<Type> {
+        for argument in types {
+            let Some(Node::Lambda { .. }) = self.graph.node_weight(value) else {
+                return None;
+            };
+            let binder_type = self.annotations.get(&value).unwrap_or(argument);
+            scratch.binders.insert(value, binder_type.clone());
+            value = self.follow_edge(value, Edge::Body).ok()?;
+        }
+        self.infer(value, scratch).ok()
+    }
+
+    /// Whether closure `id` is only inside other closures, so binders around it always have the same types
+    fn is_top_level(&self, id: NodeIndex) -> bool {
+        let mut current = id;
+        loop {
+            let Some(parent) = self
+                .graph
+                .edges_directed(current, Direction::Incoming)
+                .find(|e| !matches!(e.weight(), Edge::Binder(_) | Edge::Debug))
+            else {
+                return true;
+            };
+            match (&self.graph[parent.source()], parent.weight()) {
+                (Node::Closure { .. }, Edge::Body) => current = parent.source(),
+                _ => return false,
+            }
+        }
+    }
+
+    /// Field names of record literal `id`, i.e a record constructor applied to all its fields
+    fn record_literal(&self, id: NodeIndex) -> Option<Vec<String>> {
+        let (head, arguments) = self.application_head(id);
+        let Some(&Node::Data { tag }) = self.graph.node_weight(head) else {
+            return None;
+        };
+        self.record_fields(tag)
+            .filter(|fields| fields.len() == arguments)
+    }
+
+    /// Expression applied in application chain `id`, and how many arguments it gets
+    pub(super) fn application_head(&self, id: NodeIndex) -> (NodeIndex, usize) {
+        let mut current = id;
+        let mut arguments = 0;
+        while let Some(Node::Application) = self.graph.node_weight(current)
+            && let Some(function) = self
+                .graph
+                .edges_directed(current, Direction::Outgoing)
+                .find(|e| *e.weight() == Edge::Function)
+        {
+            arguments += 1;
+            current = function.target();
+        }
+        (current, arguments)
+    }
+
+    /// Binder of the variable or the builtin at the head of application `id`, labeled with its type
+    fn type_origin(
+        &self,
+        id: NodeIndex,
+        binders: &HashMap<NodeIndex, Type>,
+    ) -> Option<(Span, String)> {
+        let mut current = id;
+        loop {
+            let edge = match self.graph.node_weight(current)? {
+                Node::Application => Edge::Function,
+                Node::Variable(VariableKind::Bound) => Edge::Binder(0),
+                Node::Lambda { argument_name } => {
+                    let label = match self.annotations.get(&current) {
+                        Some(argument) => format!("`{argument_name}` is annotated as {argument}"),
+                        None => format!("`{argument_name}` is bound here"),
+                    };
+                    return Some((self.binder_span(current)?, label));
+                }
+                Node::Closure { argument_name } => {
+                    let label = match binders.get(&current) {
+                        Some(value) => format!("`{argument_name}` is defined as {value}"),
+                        None => format!("`{argument_name}` is defined here"),
+                    };
+                    return Some((self.binder_span(current)?, label));
+                }
+                Node::Data { tag } => {
+                    let label = format!("`{}` : {}", String::from(*tag), tag.signature());
+                    return Some((self.span(current)?, label));
+                }
+                _ => return None,
+            };
+            current = self
+                .graph
+                .edges_directed(current, Direction::Outgoing)
+                .find(|e| *e.weight() == edge)?
+                .target();
+        }
+    }
+
+    fn type_error(&self, node: NodeIndex, message: String) -> TypeCheckError {
+        TypeCheckError {
+            node,
+            expr: self.fmt_expr(node).unwrap_or_default(),
+            message,
+            labels: self
+                .span(node)
+                .map(|span| (span, String::new()))
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    /// Error at `node` labeled with `label`, followed by the binding or builtin `origin` refers to
+    pub(super) fn labeled_error(
+        &self,
+        node: NodeIndex,
+        message: String,
+        label: String,
+        origin: NodeIndex,
+    ) -> TypeCheckError {
+        let mut error = self.type_error(node, message);
+        if let Some((_, first)) = error.labels.first_mut() {
+            *first = label;
+        }
+        error
+            .labels
+            .extend(self.type_origin(origin, &HashMap::new()));
+        error
+    }
+
+    /// `node` has `actual` type, but the binding at the head of `function` expects otherwise
+    fn mismatch(
+        &self,
+        node: NodeIndex,
+        actual: &Type,
+        message: String,
+        function: NodeIndex,
+        binders: &HashMap<NodeIndex, Type>,
+    ) -> TypeCheckError {
+        let mut error = self.type_error(node, message);
+        if let Some((_, label)) = error.labels.first_mut() {
+            *label = format!("this is {actual}");
+        }
+        error.labels.extend(self.type_origin(function, binders));
+        error
+    }
+}
@@ -0,0 +1,67 @@
+use petgraph::{Direction, graph::NodeIndex, visit::EdgeRef};
+
+use crate::ast::{AST, Edge, span::Span};
+
+/// Suspicious but valid code, reported before evaluation without stopping it
+#[derive(Debug)]
+pub struct Warning {
+    pub node: NodeIndex,
+    pub message: String,
+    span: Option<Span>,
+}
+
+impl Warning {
+    /// Message followed by a source snippet pointing at the offending code
+    pub fn render(&self, source: &str) -> String {
+        let mut result = format!("warning: {}\n", self.message);
+        if let Some(span) = self.span {
+            result.push_str(&span.render(source, "here"));
+        }
+        result
+    }
+}
+
+impl AST {
+    /// Every warning about the program, in source order, see `check_matches` and `check_names`
+    pub fn warnings(&self) -> Vec<Warning> {
+        let mut warnings = self.check_matches();
+        warnings.extend(self.check_names());
+        warnings.sort_by(|a, b| {
+            let position = |warning: &Warning| warning.span.map(|span| span.start);
+            position(a).partial_cmp(&position(b)).unwrap()
+        });
+        warnings
+    }
+
+    /// Warning pointing at `id`, or the closest expression around it with a known span
+    pub(crate) fn warning(&self, id: NodeIndex, message: String) -> Warning {
+        let mut current = id;
+        let span = loop {
+            if let Some(span) = self.span(current) {
+                break Some(span);
+            }
+            match self
+                .graph
+                .edges_directed(current, Direction::Incoming)
+                .find(|e| !matches!(e.weight(), Edge::Binder(_) | Edge::Debug))
+            {
+                Some(parent) => current = parent.source(),
+                None => break None,
+            }
+        };
+        Warning {
+            node: id,
+            message,
+            span,
+        }
+    }
+
+    /// Warning pointing at the `x:Type` or `let x` part of lambda or closure `id`
+    pub(crate) fn binder_warning(&self, id: NodeIndex, message: String) -> Warning {
+        Warning {
+            node: id,
+            message,
+            span: self.binder_span(id),
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use std::{future::Future, io, thread};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, Stdin, Stdout},
+    sync::{mpsc, oneshot},
+};
+
+use crate::{
+    EVALUATION_STACK_SIZE, EvalConfig, LamboError, LamboResult, ast::builtins::io::EffectHandler,
+};
+
+/// Async counterpart of `EffectHandler`, awaited on the caller's runtime
+pub trait AsyncIOHandler {
+    fn read_line(&mut self) -> impl Future<Output = io::Result<String>> + Send;
+    /// Everything left to read as raw bytes. By default line by line until `read_line`
+    /// gives nothing, for handlers that only deal in text
+    fn read_all(&mut self) -> impl Future<Output = io::Result<Vec<u8>>> + Send
+    where
+        Self: Send,
+    {
+        async move {
+            let mut bytes = vec![];
+            loop {
+                let line = self.read_line().await?;
+                if line.is_empty() {
+                    return Ok(bytes);
+                }
+                bytes.extend(line.into_bytes());
+            }
+        }
+    }
+    fn print(&mut self, text: String) -> impl Future<Output = io::Result<()>> + Send;
+}
+
+/// Talks to the real STDIN/STDOUT through tokio
+#[derive(Debug)]
+pub struct TokioStdIO {
+    stdin: BufReader<Stdin>,
+    stdout: Stdout,
+}
+
+impl Default for TokioStdIO {
+    fn default() -> Self {
+        Self {
+            stdin: BufReader::new(tokio::io::stdin()),
+            stdout: tokio::io::stdout(),
+        }
+    }
+}
+
+impl AsyncIOHandler for TokioStdIO {
+    async fn read_line(&mut self) -> io::Result<String> {
+        let mut line = String::new();
+        self.stdin.read_line(&mut line).await?;
+        Ok(line)
+    }
+    async fn read_all(&mut self) -> io::Result<Vec<u8>> {
+        let mut bytes = vec![];
+        self.stdin.read_to_end(&mut bytes).await?;
+        Ok(bytes)
+    }
+    async fn print(&mut self, text: String) -> io::Result<()> {
+        self.stdout.write_all(text.as_bytes()).await?;
+        self.stdout.flush().await
+    }
+}
+
+enum IORequest {
+    ReadLine(oneshot::Sender<io::Result<String>>),
+    ReadAll(oneshot::Sender<io::Result<Vec<u8>>>),
+    Print(String, oneshot::Sender<io::Result<()>>),
+}
+
+/// Forwards IO from the evaluation thread to the async side and waits for the answer
+#[derive(Debug, Clone)]
+struct ForwardIO {
+    requests: mpsc::UnboundedSender<IORequest>,
+}
+
+impl ForwardIO {
+    fn request<T>(
+        &self,
+        request: impl FnOnce(oneshot::Sender<io::Result<T>>) -> IORequest,
+    ) -> io::Result<T> {
+        let closed = || io::Error::new(io::ErrorKind::BrokenPipe, "Async IO handler is gone");
+        let (sender, receiver) = oneshot::channel();
+        self.requests.send(request(sender)).map_err(|_| closed())?;
+        receiver.blocking_recv().map_err(|_| closed())?
+    }
+}
+
+impl EffectHandler for ForwardIO {
+    fn handle_read(&mut self) -> io::Result<String> {
+        self.request(IORequest::ReadLine)
+    }
+    fn handle_read_all(&mut self) -> io::Result<Vec<u8>> {
+        self.request(IORequest::ReadAll)
+    }
+    fn handle_print(&mut self, text: &str) -> io::Result<()> {
+        self.request(|sender| IORequest::Print(text.to_string(), sender))
+    }
+    fn clone_box(&self) -> Box<dyn EffectHandler> {
+        Box::new(self.clone())
+    }
+}
+
+/// Parse and evaluate `source`, awaiting every IO action on `handler`.
+/// Reduction happens on a dedicated thread, so executor threads are never blocked.
+/// Returns the formatted result, like `Value`'s Display.
+pub async fn evaluate_async(
+    source: String,
+    config: EvalConfig,
+    mut handler: impl AsyncIOHandler + Send,
+) -> LamboResult<String> {
+    let (requests, mut pending) = mpsc::unbounded_channel();
+    let (result_sender, mut result) = oneshot::channel();
+
+    thread::Builder::new()
+        .stack_size(EVALUATION_STACK_SIZE)
+        .spawn(move || {
+            let value = crate::parse(&source).and_then(|mut program| {
+                program.ast.io = Box::new(ForwardIO { requests });
+                program.evaluate(config)
+            });
+            result_sender
+                .send(value.map(|value| value.to_string()))
+                .ok();
+        })?;
+
+    loop {
+        tokio::select! {
+            Some(request) = pending.recv() => match request {
+                IORequest::ReadLine(reply) => {
+                    reply.send(handler.read_line().await).ok();
+                }
+                IORequest::ReadAll(reply) => {
+                    reply.send(handler.read_all().await).ok();
+                }
+                IORequest::Print(text, reply) => {
+                    reply.send(handler.print(text).await).ok();
+                }
+            },
+            value = &mut result => {
+                return value.map_err(|_| {
+                    LamboError::IO(io::Error::other("Evaluation thread panicked"))
+                })?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Only has raw bytes to give, like a binary file piped in
+    struct Binary(Vec<u8>);
+
+    impl AsyncIOHandler for Binary {
+        async fn read_line(&mut self) -> io::Result<String> {
+            Err(io::ErrorKind::InvalidData.into())
+        }
+        async fn read_all(&mut self) -> io::Result<Vec<u8>> {
+            Ok(std::mem::take(&mut self.0))
+        }
+        async fn print(&mut self, _text: String) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn read_all_is_forwarded() {
+        let source = "#io_flatmap (\\input.#io_pure (#bytes_len input)) #io_readall";
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let result = runtime.block_on(evaluate_async(
+            source.to_string(),
+            EvalConfig::default(),
+            Binary(b"a\r\n\xff\0".to_vec()),
+        ));
+        assert_eq!(result.unwrap(), "5");
+    }
+}
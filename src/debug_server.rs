@@ -0,0 +1,119 @@
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Condvar, Mutex},
+    thread,
+};
+
+/// Bundled browser UI, see debug.html
+const DEBUG_HTML: &str = include_str!("../debug.html");
+
+#[derive(Debug, Clone)]
+pub struct DebugFrame {
+    pub dot: String,
+    pub nodes: usize,
+    pub edges: usize,
+}
+
+/// Frames produced by a running evaluation, shared with the server threads
+#[derive(Debug, Default)]
+pub struct FrameLog {
+    frames: Mutex<Vec<DebugFrame>>,
+    updated: Condvar,
+}
+
+impl FrameLog {
+    pub fn push(&self, frame: DebugFrame) {
+        self.frames.lock().unwrap().push(frame);
+        self.updated.notify_all();
+    }
+
+    /// Snapshot of all frames pushed so far
+    pub fn frames(&self) -> Vec<DebugFrame> {
+        self.frames.lock().unwrap().clone()
+    }
+
+    /// Blocks until there are more than `seen` frames, returns the new ones
+    fn wait_for_new(&self, seen: usize) -> Vec<DebugFrame> {
+        let frames = self
+            .updated
+            .wait_while(self.frames.lock().unwrap(), |frames| frames.len() <= seen)
+            .unwrap();
+        frames[seen..].to_vec()
+    }
+}
+
+/// Start serving the debug UI on `addr` in the background.
+/// Frames pushed into the returned log are streamed to every connected browser
+/// as Server-Sent Events, so long evaluations can be watched while they run.
+/// Address may omit the host (`:8080`), in which case only localhost is served.
+pub fn serve(addr: &str) -> io::Result<Arc<FrameLog>> {
+    let addr = match addr.strip_prefix(':') {
+        Some(port) => format!("127.0.0.1:{port}"),
+        None => addr.to_string(),
+    };
+    let listener = TcpListener::bind(addr)?;
+    let log = Arc::new(FrameLog::default());
+
+    let shared_log = log.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let log = shared_log.clone();
+            // Errors here only mean that the browser went away
+            thread::spawn(move || handle_connection(stream, &log).ok());
+        }
+    });
+
+    Ok(log)
+}
+
+fn handle_connection(mut stream: TcpStream, log: &FrameLog) -> io::Result<()> {
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    // Drain headers, we don't need any of them
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    match request_line.split_whitespace().nth(1) {
+        Some("/") => write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\n\r\n{}",
+            DEBUG_HTML.len(),
+            DEBUG_HTML
+        ),
+        Some("/events") => stream_events(stream, log),
+        _ => write!(
+            stream,
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n"
+        ),
+    }
+}
+
+fn stream_events(mut stream: TcpStream, log: &FrameLog) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\n\r\n"
+    )?;
+
+    let mut seen = 0;
+    loop {
+        for frame in log.wait_for_new(seen) {
+            seen += 1;
+            writeln!(stream, "event: frame")?;
+            for line in frame.dot.lines() {
+                writeln!(stream, "data: {line}")?;
+            }
+            writeln!(stream)?;
+            writeln!(
+                stream,
+                "event: stats\ndata: {{\"frame\": {seen}, \"nodes\": {}, \"edges\": {}}}\n",
+                frame.nodes, frame.edges
+            )?;
+        }
+        stream.flush()?;
+    }
+}
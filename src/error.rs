@@ -0,0 +1,71 @@
+use crate::{
+    ast::{ASTError, types::TypeCheckError},
+    parser::ParseErrors,
+};
+
+/// Crate-wide error returned by the public API
+#[derive(Debug, thiserror::Error)]
+pub enum LamboError {
+    /// Evaluation graph is malformed, e.g a node lost one of its edges
+    #[error("evaluation error: {0}")]
+    Eval(ASTError),
+    /// Builtin received a value of the wrong shape, or a host function failed
+    #[error("builtin error: {0}")]
+    Builtin(ASTError),
+    /// Source is not valid syntax
+    #[error(transparent)]
+    Parse(#[from] ParseErrors),
+    /// Program does not match its type annotations
+    #[error(transparent)]
+    Type(#[from] TypeCheckError),
+    /// Program needed more nodes than `EvalConfig::max_nodes` allows
+    #[error("heap limit of {0} nodes exceeded")]
+    HeapLimitExceeded(usize),
+    /// Program took more reductions than `EvalConfig::max_steps` allows
+    #[error("fuel of {0} reduction steps exhausted")]
+    FuelExhausted(usize),
+    /// Program uses IO builtins, but evaluated to something other than an IO action
+    #[error("program uses IO, but evaluated to pure value `{0}` instead of an IO action")]
+    NotIO(String),
+    /// Program aborted itself with `#io_throw`
+    #[error("uncaught exception: {0}")]
+    Thrown(String),
+    #[error("IO error: {0}")]
+    IO(#[from] std::io::Error),
+}
+
+impl From<ASTError> for LamboError {
+    fn from(error: ASTError) -> Self {
+        match error {
+            ASTError::IO(error) => Self::IO(error),
+            ASTError::Parse(error) => Self::Parse(error),
+            ASTError::TypeCheck(error) => Self::Type(error),
+            ASTError::HeapLimitExceeded(max_nodes) => Self::HeapLimitExceeded(max_nodes),
+            ASTError::FuelExhausted(max_steps) => Self::FuelExhausted(max_steps),
+            ASTError::Thrown(message) => Self::Thrown(message),
+            ASTError::TypeError(..)
+            | ASTError::BuiltinTypeError(..)
+            | ASTError::CastFailed(..)
+            | ASTError::Arithmetic(..)
+            | ASTError::Host(..) => Self::Builtin(error),
+            _ => Self::Eval(error),
+        }
+    }
+}
+
+impl LamboError {
+    /// Exit status of a process that failed with this error, so scripts can tell failures apart:
+    /// 1 for `#io_throw`, 2 for syntax and type errors, 3 for exceeded limits, 4 for IO errors
+    /// and 70 for anything else going wrong during evaluation
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Thrown(_) => 1,
+            Self::Parse(_) | Self::Type(_) => 2,
+            Self::HeapLimitExceeded(_) | Self::FuelExhausted(_) => 3,
+            Self::IO(_) => 4,
+            Self::Eval(_) | Self::Builtin(_) | Self::NotIO(_) => 70,
+        }
+    }
+}
+
+pub type LamboResult<T> = Result<T, LamboError>;
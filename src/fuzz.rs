@@ -0,0 +1,123 @@
+//! Structured input generation for fuzzing, see fuzz/ for the targets
+
+use std::sync::Arc;
+
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+use crate::ast::{Primitive, builtins::ConstructorTag, term::Term};
+
+const NAMES: &[&str] = &["x", "y", "f", "cons", "nil"];
+
+/// Builtins that are safe to run without a real terminal, IO is left out
+const BUILTINS: &[&str] = &[
+    "#constructor",
+    "#match",
+    "#gc",
+    "=num",
+    "+",
+    "-",
+    "*",
+    "/",
+    "^",
+    "#bytes_new",
+    "#bytes_get",
+    "#bytes_set",
+    "#bytes_push",
+    "#bytes_pop",
+    "#bytes_len",
+];
+
+#[derive(Debug, Arbitrary)]
+enum FuzzToken {
+    Lambda,
+    Dot,
+    OpenParen,
+    CloseParen,
+    Pipe,
+    Colon,
+    Let,
+    In,
+    Name(u8),
+    Builtin(u8),
+    Number(u8),
+    Quoted(String),
+}
+
+/// Lambo source assembled from syntactically plausible tokens, for the parser
+#[derive(Debug)]
+pub struct ArbitrarySource(pub String);
+
+impl<'a> Arbitrary<'a> for ArbitrarySource {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let tokens = u.arbitrary::<Vec<FuzzToken>>()?;
+        let source = tokens
+            .iter()
+            .map(|token| match token {
+                FuzzToken::Lambda => "λ".to_string(),
+                FuzzToken::Dot => ".".to_string(),
+                FuzzToken::OpenParen => "(".to_string(),
+                FuzzToken::CloseParen => ")".to_string(),
+                FuzzToken::Pipe => "|".to_string(),
+                FuzzToken::Colon => ":".to_string(),
+                FuzzToken::Let => "let".to_string(),
+                FuzzToken::In => "in".to_string(),
+                FuzzToken::Name(i) => NAMES[*i as usize % NAMES.len()].to_string(),
+                FuzzToken::Builtin(i) => BUILTINS[*i as usize % BUILTINS.len()].to_string(),
+                FuzzToken::Number(number) => number.to_string(),
+                FuzzToken::Quoted(text) => format!("{text:?}"),
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        Ok(Self(source))
+    }
+}
+
+/// Term where every De Bruijn index refers to an enclosing binder, for the evaluator
+#[derive(Debug)]
+pub struct ArbitraryTerm(pub Term);
+
+impl<'a> Arbitrary<'a> for ArbitraryTerm {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let mut budget = u.arbitrary_len::<u8>()?.min(200);
+        Ok(Self(arbitrary_term(u, 0, &mut budget)?))
+    }
+}
+
+/// `depth` is the number of binders in scope
+fn arbitrary_term(u: &mut Unstructured, depth: usize, budget: &mut usize) -> Result<Term> {
+    let boxed = |u: &mut Unstructured, depth, budget: &mut usize| {
+        arbitrary_term(u, depth, budget).map(Box::new)
+    };
+    let name = |u: &mut Unstructured| -> Result<Option<Arc<String>>> {
+        Ok(Some(Arc::new(u.choose(NAMES)?.to_string())))
+    };
+
+    let choice = if *budget == 0 {
+        u.int_in_range(0..=3)?
+    } else {
+        u.int_in_range(0..=6)?
+    };
+    *budget = budget.saturating_sub(1);
+    Ok(match choice {
+        0 if depth > 0 => Term::Index(u.choose_index(depth)?),
+        0 | 1 => Term::Primitive(Primitive::Number(u.int_in_range(0..=16)?)),
+        2 => Term::Primitive(Primitive::Bytes(u.arbitrary()?)),
+        3 => Term::Data {
+            tag: ConstructorTag::try_from(*u.choose(BUILTINS)?).unwrap(),
+            arguments: vec![],
+        },
+        4 => Term::Lambda {
+            argument_name: name(u)?,
+            body: boxed(u, depth + 1, budget)?,
+        },
+        5 => Term::Application {
+            function: boxed(u, depth, budget)?,
+            parameter: boxed(u, depth, budget)?,
+        },
+        _ => Term::Closure {
+            argument_name: name(u)?,
+            parameter: boxed(u, depth, budget)?,
+            body: boxed(u, depth + 1, budget)?,
+        },
+    })
+}
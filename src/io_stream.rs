@@ -0,0 +1,162 @@
+use std::{
+    io,
+    sync::mpsc::{self, Receiver, SyncSender},
+    thread::{self, JoinHandle},
+};
+
+use crate::{
+    EVALUATION_STACK_SIZE, EvalConfig, LamboError, LamboResult,
+    ast::{builtins::io::EffectHandler, io_log::IOEvent},
+};
+
+/// Performs IO through the inner handler and reports every interaction to the host.
+/// The channel has no buffer, so evaluation waits until the host takes the event.
+#[derive(Debug, Clone)]
+struct StreamingIO<H> {
+    inner: H,
+    events: SyncSender<IOEvent>,
+}
+
+impl<H> StreamingIO<H> {
+    fn report(&self, event: IOEvent) -> io::Result<()> {
+        self.events
+            .send(event)
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "IO events receiver is gone"))
+    }
+}
+
+impl<H: EffectHandler + Clone + 'static> EffectHandler for StreamingIO<H> {
+    fn handle_read(&mut self) -> io::Result<String> {
+        let line = self.inner.handle_read()?;
+        self.report(IOEvent::Read(line.clone()))?;
+        Ok(line)
+    }
+    fn handle_read_all(&mut self) -> io::Result<Vec<u8>> {
+        let bytes = self.inner.handle_read_all()?;
+        self.report(IOEvent::ReadAll(bytes.clone()))?;
+        Ok(bytes)
+    }
+    fn handle_print(&mut self, text: &str) -> io::Result<()> {
+        self.inner.handle_print(text)?;
+        self.report(IOEvent::Print(text.to_string()))
+    }
+    fn handle_exec(&mut self, command: &str, arguments: &[String]) -> io::Result<(Vec<u8>, i32)> {
+        let (stdout, code) = self.inner.handle_exec(command, arguments)?;
+        self.report(IOEvent::Exec {
+            command: command.to_string(),
+            arguments: arguments.to_vec(),
+            stdout: stdout.clone(),
+            code,
+        })?;
+        Ok((stdout, code))
+    }
+    fn handle_throw(&mut self, message: &str) {
+        self.inner.handle_throw(message)
+    }
+    fn clone_box(&self) -> Box<dyn EffectHandler> {
+        Box::new(self.clone())
+    }
+}
+
+/// IO actions performed by a running program, in order.
+/// Dropping it stops the program at its next IO action.
+pub struct IOEvents {
+    events: Receiver<IOEvent>,
+    evaluation: JoinHandle<LamboResult<String>>,
+}
+
+impl Iterator for IOEvents {
+    type Item = IOEvent;
+
+    fn next(&mut self) -> Option<IOEvent> {
+        self.events.recv().ok()
+    }
+}
+
+impl IOEvents {
+    /// Wait for the program to finish and return its formatted result.
+    /// Remaining events are still performed, but not reported.
+    pub fn result(self) -> LamboResult<String> {
+        let Self { events, evaluation } = self;
+        for _ in events {}
+        evaluation
+            .join()
+            .map_err(|_| LamboError::IO(io::Error::other("Evaluation thread panicked")))?
+    }
+}
+
+/// Parse and evaluate `source` in the background, performing IO through `handler`
+/// and yielding each action as it happens
+pub fn stream_io<H>(source: String, config: EvalConfig, handler: H) -> io::Result<IOEvents>
+where
+    H: EffectHandler + Clone + Send + 'static,
+{
+    let (sender, events) = mpsc::sync_channel(0);
+
+    let evaluation = thread::Builder::new()
+        .stack_size(EVALUATION_STACK_SIZE)
+        .spawn(move || {
+            let mut program = crate::parse(&source)?;
+            program.ast.io = Box::new(StreamingIO {
+                inner: handler,
+                events: sender,
+            });
+            Ok(program.evaluate(config)?.to_string())
+        })?;
+
+    Ok(IOEvents { events, evaluation })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use crate::{
+        EvalConfig,
+        ast::{builtins::io::EffectHandler, io_log::IOEvent},
+    };
+
+    use super::stream_io;
+
+    /// STDIN holding a command line argument and a command that only succeeds
+    #[derive(Debug, Clone)]
+    struct Scripted;
+
+    impl EffectHandler for Scripted {
+        fn handle_read(&mut self) -> io::Result<String> {
+            Err(io::ErrorKind::InvalidData.into())
+        }
+        fn handle_read_all(&mut self) -> io::Result<Vec<u8>> {
+            Ok(b"-a".to_vec())
+        }
+        fn handle_print(&mut self, _text: &str) -> io::Result<()> {
+            Ok(())
+        }
+        fn handle_exec(
+            &mut self,
+            _command: &str,
+            _arguments: &[String],
+        ) -> io::Result<(Vec<u8>, i32)> {
+            Ok((vec![], 0))
+        }
+        fn clone_box(&self) -> Box<dyn EffectHandler> {
+            Box::new(self.clone())
+        }
+    }
+
+    #[test]
+    fn every_effect_is_streamed() {
+        let source = "#io_flatmap (\\input.#io_exec \"true\" (#cons input #nil)) #io_readall";
+        let events = stream_io(source.to_string(), EvalConfig::default(), Scripted).unwrap();
+        let expected = [
+            IOEvent::ReadAll(b"-a".to_vec()),
+            IOEvent::Exec {
+                command: "true".to_string(),
+                arguments: vec!["-a".to_string()],
+                stdout: vec![],
+                code: 0,
+            },
+        ];
+        assert_eq!(events.collect::<Vec<_>>(), expected);
+    }
+}
@@ -1,2 +1,222 @@
+use std::fmt::Display;
+
+use petgraph::graph::NodeIndex;
+
+use crate::ast::{AST, Node, Number, Primitive, builtins::ConstructorTag, preprocess::GcPolicy};
+
 pub mod ast;
+#[cfg(feature = "tokio")]
+pub mod async_io;
+pub mod debug_server;
+mod error;
+#[cfg(feature = "arbitrary")]
+pub mod fuzz;
+pub mod io_stream;
 pub mod parser;
+mod prelude;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use ast::backend::Backend;
+pub use error::{LamboError, LamboResult};
+pub use prelude::Prelude;
+
+/// Builtins and graph traversals still recurse on deep structures,
+/// so evaluation needs a lot more than the default stack
+pub const EVALUATION_STACK_SIZE: usize = 1024 * 1024 * 100;
+
+/// Run `f` on a new thread with `stack_size` bytes of stack and wait for its result,
+/// e.g `run_with_stack(EVALUATION_STACK_SIZE, move || program.evaluate(config))`.
+/// A panic in `f` is resumed on the calling thread.
+pub fn run_with_stack<T, F>(stack_size: usize, f: F) -> std::io::Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let child = std::thread::Builder::new()
+        .stack_size(stack_size)
+        .spawn(f)?;
+    Ok(child
+        .join()
+        .unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+}
+
+/// Parse lambo source into a program ready for evaluation
+pub fn parse(source: &str) -> LamboResult<Program> {
+    parse_with(AST::new(), source)
+}
+
+/// Parse lambo source into `ast`, keeping whatever was set up on it,
+/// e.g functions from `AST::register_function`
+pub fn parse_with(mut ast: AST, source: &str) -> LamboResult<Program> {
+    ast.root = ast.add_expr_from_str(source)?;
+    ast.garbage_collect()?;
+    ast.fold_constants()?;
+    Ok(Program { ast })
+}
+
+#[derive(Debug, Clone)]
+pub struct EvalConfig {
+    /// If the program evaluates to an IO action, perform it and return its result
+    pub run_io: bool,
+    /// Fail with `LamboError::HeapLimitExceeded` instead of growing the graph past this many nodes,
+    /// e.g when evaluating untrusted programs
+    pub max_nodes: Option<usize>,
+    /// Fail with `LamboError::FuelExhausted` after this many reductions,
+    /// e.g to stop divergent programs like `(λx.x x) (λx.x x)`
+    pub max_steps: Option<usize>,
+    /// When to collect garbage during evaluation
+    pub gc_policy: GcPolicy,
+    /// Check `λx:Type.` annotations first, failing with `LamboError::Type`,
+    /// and check values of unannotated code at runtime where they meet annotated code
+    pub typed: bool,
+    /// How to reduce the program, see `Backend`
+    pub backend: Backend,
+    /// Fail with `LamboError::Builtin` on overflow instead of wrapping around,
+    /// off by default like in the CLI. Division by zero always fails, see `AST::checked_arithmetic`
+    pub checked_arithmetic: bool,
+    /// Reduce under lambdas too, returning the full normal form, see `AST::normalize`
+    pub normalize: bool,
+    /// Share computations inside lambdas that don't depend on their argument
+    /// between calls, off by default, see `AST::float_free_expressions`
+    pub full_laziness: bool,
+    /// Rewrite `λx.f x` to `f` in the program and in its result, see `AST::eta_reduce`
+    pub eta: bool,
+    /// Evaluate arguments of lambdas that always use them before their body,
+    /// off by default, see `AST::analyze_strictness`
+    pub strictness: bool,
+}
+
+impl Default for EvalConfig {
+    fn default() -> Self {
+        Self {
+            run_io: true,
+            max_nodes: None,
+            max_steps: None,
+            gc_policy: GcPolicy::default(),
+            typed: false,
+            backend: Backend::default(),
+            checked_arithmetic: false,
+            normalize: false,
+            full_laziness: false,
+            eta: false,
+            strictness: false,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Program {
+    ast: AST,
+}
+
+// Programs can be parsed on one thread and evaluated on a worker
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Program>();
+    assert_send_sync::<Value>();
+};
+
+impl Program {
+    /// Underlying graph, e.g for rendering it with `AST::to_dot`
+    pub fn ast(&self) -> &AST {
+        &self.ast
+    }
+
+    pub fn evaluate(mut self, config: EvalConfig) -> LamboResult<Value> {
+        self.ast.max_nodes = config.max_nodes;
+        self.ast.max_steps = config.max_steps;
+        self.ast.gc_policy = config.gc_policy;
+        self.ast.checked_arithmetic = config.checked_arithmetic;
+        self.ast.check_arity()?;
+        if config.typed {
+            self.ast.insert_casts()?;
+        }
+        if config.eta {
+            self.ast.eta_reduce(self.ast.root)?;
+        }
+        if config.strictness {
+            self.ast.analyze_strictness()?;
+        }
+        if config.full_laziness {
+            self.ast.float_free_expressions()?;
+        }
+        let uses_io = self.ast.io_builtin().is_some();
+        let mut node = self
+            .ast
+            .reduce(self.ast.root, config.backend, config.normalize)?;
+
+        if config.run_io {
+            match self.ast.graph.node_weight(node) {
+                Some(&Node::Data {
+                    tag: ConstructorTag::IO(io),
+                }) => {
+                    node = io.run(&mut self.ast, node)?;
+                    node = self.ast.evaluate(node)?;
+                }
+                // Effects of a program returning a pure value would silently never happen
+                _ if uses_io => return Err(LamboError::NotIO(self.ast.fmt_expr(node)?)),
+                _ => {}
+            }
+        }
+        if config.eta {
+            node = self.ast.eta_reduce(node)?;
+        }
+
+        Ok(Value {
+            ast: self.ast,
+            node,
+        })
+    }
+}
+
+/// Result of evaluating a program
+pub struct Value {
+    ast: AST,
+    node: NodeIndex,
+}
+
+impl Value {
+    /// Graph the value lives in, e.g for `AST::peak_node_count`
+    pub fn ast(&self) -> &AST {
+        &self.ast
+    }
+
+    pub fn to_usize(&self) -> Option<Number> {
+        match self.ast.graph.node_weight(self.node)? {
+            Node::Primitive(Primitive::Number(number)) => Some(*number),
+            _ => None,
+        }
+    }
+
+    pub fn to_f64(&self) -> Option<f64> {
+        match self.ast.graph.node_weight(self.node)? {
+            Node::Primitive(Primitive::Float(float)) => Some(*float),
+            _ => None,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Option<&[u8]> {
+        match self.ast.graph.node_weight(self.node)? {
+            Node::Primitive(Primitive::Bytes(bytes)) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    pub fn to_str(&self) -> Option<&str> {
+        match self.ast.graph.node_weight(self.node)? {
+            Node::Primitive(Primitive::String(string)) => Some(string),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            self.ast.fmt_expr(self.node).map_err(|_| std::fmt::Error)?
+        )
+    }
+}
@@ -1,6 +1,18 @@
-use lambo::ast::{AST, Node, builtins::ConstructorTag};
+use clap::{Parser, Subcommand};
+use lambo::{
+    Backend, EVALUATION_STACK_SIZE, LamboError,
+    ast::{
+        AST, ASTError, ASTResult, Node, NodeIndex,
+        builtins::ConstructorTag,
+        io_log::{RecordingIO, ReplayIO},
+        preprocess::GcPolicy,
+        render::Syntax,
+    },
+    debug_server, run_with_stack,
+};
 use std::{
     io::{Read, stdin},
+    path::PathBuf,
     thread,
 };
 use tracing_flame::FlameLayer;
@@ -20,43 +32,454 @@ fn setup_global_subscriber() -> impl Drop {
     _guard
 }
 
-const ENABLE_TRACING: bool = false;
+#[derive(Parser)]
+#[command(args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Stack size of the evaluation thread in bytes, deeply nested data may need more
+    #[arg(long, global = true, env = "LAMBO_STACK_SIZE", value_name = "BYTES", default_value_t = EVALUATION_STACK_SIZE)]
+    stack_size: usize,
+    /// Also look for `#import`ed files in this directory, after the one of the importing file
+    #[arg(long = "import-path", global = true, value_name = "DIR")]
+    import_paths: Vec<PathBuf>,
+    #[command(flatten)]
+    run: RunArgs,
+}
 
-fn main() {
-    let child = thread::Builder::new()
-        // Increase stack size
-        .stack_size(1024 * 1024 * 100)
-        .spawn(|| {
+#[derive(Subcommand)]
+enum Command {
+    /// Evaluate the program, same as giving no subcommand
+    Run {
+        #[command(flatten)]
+        run: RunArgs,
+    },
+    /// Print the parsed program without evaluating it
+    Parse {
+        /// Source file, read from STDIN if omitted
+        file: Option<PathBuf>,
+    },
+    /// Print the parsed program as a DOT graph without evaluating it
+    Dot {
+        /// Source file, read from STDIN if omitted
+        file: Option<PathBuf>,
+    },
+    /// Evaluate expressions interactively, `let name value` defines `name` for the following ones
+    /// and `:type expr` prints the type of `expr` instead of evaluating it
+    Repl,
+    /// Evaluate the program while streaming debug frames to a browser UI
+    Debug {
+        /// Address to serve the debug UI on, e.g `:8080`
+        #[arg(long, value_name = "ADDR")]
+        serve: String,
+        #[command(flatten)]
+        run: RunArgs,
+    },
+    /// Parse the program and check constructor arities, `match` cases and names without evaluating it
+    Check {
+        /// Source file, read from STDIN if omitted
+        file: Option<PathBuf>,
+        /// Also check `λx:Type.` annotations, e.g `λf:Number->Number.`
+        #[arg(long)]
+        typed: bool,
+        /// Print every function bound by `let` with `!` before the parameters it always evaluates
+        #[arg(long)]
+        strictness: bool,
+    },
+    /// Evaluate everything that doesn't depend on the arguments the program still takes,
+    /// printing the smaller program left as Lambo source
+    Specialize {
+        /// Source file, read from STDIN if omitted
+        file: Option<PathBuf>,
+        /// Apply the program to this expression first, can be repeated
+        #[arg(long = "arg", value_name = "EXPR")]
+        arguments: Vec<String>,
+        /// How many nested lambdas to evaluate expressions not using their arguments in
+        #[arg(long, value_name = "N", default_value_t = 4)]
+        depth: usize,
+        /// Abort after this many reductions, e.g when a definition doesn't terminate
+        #[arg(long, value_name = "STEPS", default_value_t = 10_000_000)]
+        max_steps: usize,
+        /// Write the program into this file instead of STDOUT
+        #[arg(long, short, value_name = "PATH")]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(clap::Args)]
+struct RunArgs {
+    /// Source file, read from STDIN if omitted
+    file: Option<PathBuf>,
+    /// Record all IO interactions into a log file
+    #[arg(long, conflicts_with = "replay")]
+    record: Option<PathBuf>,
+    /// Feed IO interactions from a recorded log instead of using STDIN/STDOUT
+    #[arg(long)]
+    replay: Option<PathBuf>,
+    /// Check type annotations before evaluating, see `lambo check --typed`,
+    /// and check unannotated values at runtime where they are passed to annotated code
+    #[arg(long)]
+    typed: bool,
+    /// Write DOT snapshots of the graph taken during evaluation into this directory
+    #[arg(long, value_name = "PATH")]
+    debug_dir: Option<PathBuf>,
+    /// Only keep every Nth snapshot, see `--debug-dir`
+    #[arg(long, value_name = "N", default_value_t = 1, requires = "debug_dir")]
+    debug_dump_every: usize,
+    /// Abort evaluation once the graph grows past this many nodes
+    #[arg(long, value_name = "NODES")]
+    max_nodes: Option<usize>,
+    /// Print evaluation counters to STDERR once finished
+    #[arg(long)]
+    stats: bool,
+    /// Print reduction steps and clones per definition to STDERR once finished
+    #[arg(long)]
+    profile: bool,
+    /// Write reduction steps per stack of active definitions into this file once finished,
+    /// in the folded format of flamegraph tools
+    #[arg(long, value_name = "PATH")]
+    profile_folded: Option<PathBuf>,
+    /// Abort evaluation after this many reductions
+    #[arg(long, value_name = "STEPS")]
+    max_steps: Option<usize>,
+    /// Collect garbage automatically only once the graph has more than this many nodes
+    #[arg(long, value_name = "NODES", default_value_t = GcPolicy::default().min_nodes)]
+    gc_min_nodes: usize,
+    /// Collect garbage automatically once the graph grew this many times since the last collection
+    #[arg(long, value_name = "FACTOR", default_value_t = GcPolicy::default().growth)]
+    gc_growth: f32,
+    /// How to reduce the program, e.g to check a suspicious result with another backend
+    #[arg(long, value_enum, default_value_t)]
+    backend: Backend,
+    /// Fail on overflow instead of wrapping around, which is the default
+    #[arg(long)]
+    checked_arithmetic: bool,
+    /// Also reduce inside lambdas and constructors, printing the full normal form
+    #[arg(long)]
+    normalize: bool,
+    /// Move computations not depending on the argument of a lambda out of it,
+    /// sharing them between its calls
+    #[arg(long)]
+    full_laziness: bool,
+    /// Evaluate arguments a function always uses before its body instead of when first used
+    #[arg(long)]
+    strictness: bool,
+    /// Rewrite `λx.f x` to `f` before evaluating and in the result
+    #[arg(long)]
+    eta: bool,
+    /// Supercompile the program before evaluating it, fusing away intermediate data structures
+    #[cfg(feature = "supercompile")]
+    #[arg(long)]
+    supercompile: bool,
+}
+
+/// Evaluate the program, or reduce it to normal form if asked to
+fn reduce(ast: &mut AST, args: &RunArgs) -> ASTResult<NodeIndex> {
+    let result = ast.reduce(ast.root, args.backend, args.normalize)?;
+    if args.eta {
+        ast.eta_reduce(result)
+    } else {
+        Ok(result)
+    }
+}
+
+fn read_source(file: &Option<PathBuf>) -> String {
+    match file {
+        Some(path) => std::fs::read_to_string(path).unwrap(),
+        None => {
             let mut input = String::new();
             stdin().read_to_string(&mut input).unwrap();
+            input
+        }
+    }
+}
+
+/// Parse `source` of `file` or exit, pointing at the syntax error.
+/// Imports are also looked up next to `file`.
+fn parse(source: &str, file: &Option<PathBuf>, import_paths: &[PathBuf]) -> AST {
+    let mut ast = AST::new();
+    ast.import_paths = file
+        .iter()
+        .filter_map(|file| file.parent())
+        .map(PathBuf::from)
+        .chain(import_paths.iter().cloned())
+        .collect();
+    match ast.add_expr_from_str(source) {
+        Ok(root) => {
+            ast.root = root;
+            ast
+        }
+        Err(error) => {
+            eprint!("{}", error.render(source));
+            std::process::exit(LamboError::from(error).exit_code());
+        }
+    }
+}
+
+fn check(file: Option<PathBuf>, typed: bool, strictness: bool, import_paths: &[PathBuf]) {
+    let source = read_source(&file);
+    let mut ast = parse(&source, &file, import_paths);
+    if let Err(error) = ast.check_arity() {
+        eprint!("{}", error.render(&source));
+        std::process::exit(LamboError::from(error).exit_code());
+    }
+    for warning in ast.warnings() {
+        eprint!("{}", warning.render(&source));
+    }
+    if typed {
+        match ast.check_types() {
+            Ok(program_type) => println!("{program_type}"),
+            Err(error) => {
+                eprint!("{}", error.render(&source));
+                std::process::exit(LamboError::from(error).exit_code());
+            }
+        }
+    }
+    if strictness {
+        for signature in ast.strictness().unwrap() {
+            println!("{signature}");
+        }
+    }
+}
 
-            let mut ast = AST::from_str(&input);
-            ast.garbage_collect();
-            println!(" $\n{}", ast);
-            ast.add_debug_frame();
+fn specialize(
+    file: Option<PathBuf>,
+    arguments: Vec<String>,
+    depth: usize,
+    max_steps: usize,
+    import_paths: &[PathBuf],
+) -> Result<String, LamboError> {
+    let source = read_source(&file);
+    let mut ast = parse(&source, &file, import_paths);
+    if let Err(error) = ast.check_arity() {
+        eprint!("{}", error.render(&source));
+        return Err(error.into());
+    }
+    for argument in arguments {
+        let parameter = ast.add_expr_from_str(&argument).inspect_err(|error| {
+            eprint!("{}", error.render(&argument));
+        })?;
+        ast.root = ast.apply(ast.root, parameter);
+    }
+    ast.max_steps = Some(max_steps);
+    ast.garbage_collect()?;
+    ast.fold_constants()?;
+    ast.specialize(ast.root, depth)?;
+    ast.garbage_collect()?;
+    Ok(ast.to_term(ast.root)?.render(Syntax::Lambo))
+}
+
+fn repl(import_paths: Vec<PathBuf>) {
+    let mut ast = AST::new();
+    ast.import_paths = import_paths;
+    let mut line = String::new();
+    loop {
+        eprint!("λ> ");
+        line.clear();
+        if stdin().read_line(&mut line).unwrap() == 0 {
+            return;
+        }
+        let input = line.trim();
+        if input.is_empty() {
+            continue;
+        }
+
+        if let Some(expr) = input.strip_prefix(":type ") {
+            match ast.type_of(expr) {
+                Ok(expr_type) => println!("{expr_type}"),
+                Err(ASTError::Parse(error)) => eprint!("{}", error.render(expr)),
+                Err(ASTError::TypeCheck(error)) => eprint!("{}", error.render(expr)),
+                Err(error) => eprintln!("{}", LamboError::from(error)),
+            }
+            continue;
+        }
+
+        let definition = input
+            .strip_prefix("let ")
+            .and_then(|rest| rest.trim_start().split_once(char::is_whitespace))
+            .filter(|_| !input.split_whitespace().any(|token| token == "in"));
+        match definition {
+            Some((name, value)) => {
+                if let Err(error) = ast.define(name, value) {
+                    eprint!("{}", error.render(value));
+                }
+            }
+            None => match ast.evaluate_in_session(input) {
+                Ok(result) => println!("{result}"),
+                Err(ASTError::Parse(error)) => eprint!("{}", error.render(input)),
+                Err(error) => eprintln!("{}", LamboError::from(error)),
+            },
+        }
+    }
+}
+
+const ENABLE_TRACING: bool = false;
 
-            if ENABLE_TRACING {
-                setup_global_subscriber();
+fn main() {
+    let cli = Cli::parse();
+    let import_paths = cli.import_paths;
+    let (args, serve) = match cli.command {
+        Some(Command::Run { run }) => (run, None),
+        Some(Command::Debug { serve, run }) => (run, Some(serve)),
+        Some(Command::Check {
+            file,
+            typed,
+            strictness,
+        }) => return check(file, typed, strictness, &import_paths),
+        Some(Command::Specialize {
+            file,
+            arguments,
+            depth,
+            max_steps,
+            output,
+        }) => {
+            let result = run_with_stack(cli.stack_size, move || {
+                specialize(file, arguments, depth, max_steps, &import_paths)
+            })
+            .unwrap();
+            match (result, output) {
+                (Ok(program), Some(path)) => std::fs::write(path, program + "\n").unwrap(),
+                (Ok(program), None) => println!("{program}"),
+                (Err(error), _) => {
+                    eprintln!("{error}");
+                    std::process::exit(error.exit_code());
+                }
             }
+            return;
+        }
+        Some(Command::Parse { file }) => {
+            return println!("{}", parse(&read_source(&file), &file, &import_paths));
+        }
+        Some(Command::Dot { file }) => {
+            return print!(
+                "{}",
+                parse(&read_source(&file), &file, &import_paths).to_dot()
+            );
+        }
+        Some(Command::Repl) => {
+            return run_with_stack(cli.stack_size, move || repl(import_paths)).unwrap();
+        }
+        None => (cli.run, None),
+    };
+
+    let code = run_with_stack(cli.stack_size, move || {
+        let input = read_source(&args.file);
+        let mut ast = parse(&input, &args.file, &import_paths);
+        if let Err(error) = ast.check_arity() {
+            eprint!("{}", error.render(&input));
+            return LamboError::from(error).exit_code();
+        }
+        for warning in ast.warnings() {
+            eprint!("{}", warning.render(&input));
+        }
+        if args.typed
+            && let Err(error) = ast.insert_casts()
+        {
+            eprint!("{}", error.render(&input));
+            return LamboError::from(error).exit_code();
+        }
+        if let Some(path) = &args.record {
+            ast.io = Box::new(RecordingIO::new(path).unwrap());
+        }
+        if let Some(path) = &args.replay {
+            ast.io = Box::new(ReplayIO::new(path).unwrap());
+        }
+        ast.max_nodes = args.max_nodes;
+        ast.max_steps = args.max_steps;
+        ast.checked_arithmetic = args.checked_arithmetic;
+        ast.gc_policy = GcPolicy {
+            min_nodes: args.gc_min_nodes,
+            growth: args.gc_growth,
+        };
+        if let Some(dir) = &args.debug_dir {
+            ast.enable_debug(dir, args.debug_dump_every);
+        }
+        if let Some(addr) = &serve {
+            ast.debug_stream = Some(debug_server::serve(addr).unwrap());
+            eprintln!("Serving debug UI on {addr}");
+        }
+        ast.garbage_collect().unwrap();
+        ast.fold_constants().unwrap();
+        if args.eta {
+            ast.eta_reduce(ast.root).unwrap();
+        }
+        #[cfg(feature = "supercompile")]
+        if args.supercompile
+            && let Err(error) = ast.supercompile(ast.root)
+        {
+            eprintln!("Running the program as is: {error:?}");
+        }
+        if args.strictness {
+            ast.analyze_strictness().unwrap();
+        }
+        if args.full_laziness {
+            ast.float_free_expressions().unwrap();
+        }
+        println!(" $\n{}", ast);
+        ast.add_debug_frame();
+
+        if ENABLE_TRACING {
+            setup_global_subscriber();
+        }
+        if args.profile || args.profile_folded.is_some() {
+            ast.enable_profiling();
+        }
 
-            if let Err(err) = ast.evaluate(ast.root) {
-                ast.debug_ast_error(err)
-            };
-            ast.garbage_collect();
-
-            if let &Node::Data {
-                tag: ConstructorTag::IO(io),
-            } = ast.graph.node_weight(ast.root).unwrap()
-            {
-                let root = ast.root;
-                io.run(&mut ast, root).unwrap();
+        let uses_io = ast.io_builtin().is_some();
+        let outcome = match reduce(&mut ast, &args) {
+            Ok(result) => {
+                if let Err(err) = ast.garbage_collect() {
+                    ast.debug_ast_error(&err)
+                }
+
+                match ast.graph.node_weight(result) {
+                    Some(&Node::Data {
+                        tag: ConstructorTag::IO(io),
+                    }) => io.run(&mut ast, result).map(|_| ()).map_err(|err| {
+                        ast.debug_ast_error(&err);
+                        LamboError::from(err)
+                    }),
+                    _ if uses_io => {
+                        let value = ast.fmt_expr(result).unwrap_or_default();
+                        let error = LamboError::NotIO(value);
+                        eprintln!("{error}");
+                        Err(error)
+                    }
+                    _ => Ok(()),
+                }
+            }
+            Err(err) => {
+                ast.debug_ast_error(&err);
+                Err(LamboError::from(err))
             }
+        };
 
-            ast.add_debug_frame();
-            ast.dump_debug();
-            println!(" >\n{}", ast);
-        })
-        .unwrap();
+        ast.add_debug_frame();
+        ast.dump_debug();
+        println!(" >\n{}", ast);
 
-    child.join().unwrap();
+        if args.stats {
+            eprint!("{}", ast.stats());
+        }
+        if args.profile
+            && let Some(report) = ast.profile_report()
+        {
+            eprint!("{report}");
+        }
+        if let Some(path) = &args.profile_folded
+            && let Some(folded) = ast.profile_folded()
+        {
+            std::fs::write(path, folded).unwrap();
+        }
+
+        if serve.is_some() {
+            eprintln!("Evaluation finished, press Ctrl-C to stop the debug server");
+            loop {
+                thread::park();
+            }
+        }
+        outcome.map_or_else(|error| error.exit_code(), |_| 0)
+    })
+    .unwrap();
+    std::process::exit(code);
 }
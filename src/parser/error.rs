@@ -0,0 +1,65 @@
+use crate::ast::span::Span;
+
+/// Malformed source, with the range where parsing stopped
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum ParseError {
+    /// `found` token (or end of input) where `expected` should have been
+    #[error("expected {expected}, found {found}")]
+    Unexpected {
+        expected: &'static str,
+        found: String,
+        span: Span,
+    },
+    #[error("unknown type `{name}`")]
+    UnknownType { name: String, span: Span },
+    #[error("duplicate field `{name}`")]
+    DuplicateField { name: String, span: Span },
+    /// Field access of a field no record in the source has
+    #[error("no record has field `{name}`")]
+    UnknownField { name: String, span: Span },
+    /// Number literal with more digits than a Number can hold
+    #[error("number literal `{literal}` is too large")]
+    NumberOverflow { literal: String, span: Span },
+    /// `0x` or `0b` literal without valid digits after the prefix
+    #[error("malformed number literal `{literal}`")]
+    MalformedNumber { literal: String, span: Span },
+    /// `#import "name"` could not be spliced in
+    #[error("can't import `{name}`: {message}")]
+    Import {
+        name: String,
+        message: String,
+        span: Span,
+    },
+}
+
+impl ParseError {
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::Unexpected { span, .. }
+            | ParseError::UnknownType { span, .. }
+            | ParseError::DuplicateField { span, .. }
+            | ParseError::UnknownField { span, .. }
+            | ParseError::NumberOverflow { span, .. }
+            | ParseError::MalformedNumber { span, .. }
+            | ParseError::Import { span, .. } => *span,
+        }
+    }
+
+    /// Message followed by a source snippet pointing at the error
+    pub fn render(&self, source: &str) -> String {
+        format!("error: {self}\n{}", self.span().render(source, "here"))
+    }
+}
+
+/// Every syntax error found in one pass, ordered by position.
+/// The parser skips a malformed `(...)` or `let` value and keeps going.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error("{}{}", .0[0], match .0.len() { 1 => String::new(), n => format!(" (and {} more)", n - 1) })]
+pub struct ParseErrors(pub Vec<ParseError>);
+
+impl ParseErrors {
+    /// Every error rendered, see `ParseError::render`
+    pub fn render(&self, source: &str) -> String {
+        self.0.iter().map(|error| error.render(source)).collect()
+    }
+}
@@ -0,0 +1,73 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    ast::span::Span,
+    parser::{error::ParseError, lexer::Token, strip_comments},
+};
+
+/// Replace every `#import "file.lambo"` with the tokens of that file, which must only consist of
+/// `let` definitions, so they end up in scope of everything after the import.
+/// Files are looked up next to the importing file first, then in `search_path`.
+/// Imported tokens take the span of the import itself, since they come from another source.
+pub fn expand_imports(
+    tokens: Vec<(Token, Span)>,
+    directory: Option<&Path>,
+    search_path: &[PathBuf],
+    importing: &mut Vec<PathBuf>,
+) -> Result<Vec<(Token, Span)>, ParseError> {
+    let mut result = Vec::with_capacity(tokens.len());
+    let mut tokens = tokens.into_iter().peekable();
+    while let Some((token, span)) = tokens.next() {
+        if !matches!(&token, Token::Symbol(symbol) if symbol == "#import") {
+            result.push((token, span));
+            continue;
+        }
+        let (name, span) = match tokens.next() {
+            Some((Token::Quoted(name), end)) => (name, span.to(end)),
+            found => {
+                return Err(ParseError::Unexpected {
+                    expected: "quoted file name after `#import`",
+                    found: found
+                        .as_ref()
+                        .map_or("end of input".to_string(), |(token, _)| token.to_string()),
+                    span: found.map_or(span, |(_, span)| span),
+                });
+            }
+        };
+        let error = |message: String| ParseError::Import {
+            name: name.clone(),
+            message,
+            span,
+        };
+
+        let path = directory
+            .into_iter()
+            .chain(search_path.iter().map(PathBuf::as_path))
+            .map(|directory| directory.join(&name))
+            .find(|path| path.is_file())
+            .ok_or_else(|| error("file not found".to_string()))?;
+        let path = path.canonicalize().map_err(|e| error(e.to_string()))?;
+        if importing.contains(&path) {
+            return Err(error("import cycle".to_string()));
+        }
+        let source = fs::read_to_string(&path).map_err(|e| error(e.to_string()))?;
+
+        let mut imported = crate::parser::lexer::lexer(&strip_comments(&source))
+            .map(|(token, _)| (token, span))
+            .collect::<Vec<_>>();
+        imported.pop(); // Eof
+        importing.push(path.clone());
+        let imported = expand_imports(imported, path.parent(), search_path, importing)?;
+        importing.pop();
+        if !matches!(imported.last(), None | Some((Token::In, _))) {
+            return Err(error(
+                "imported file must only consist of let definitions".to_string(),
+            ));
+        }
+        result.extend(imported);
+    }
+    Ok(result)
+}
@@ -1,4 +1,9 @@
-use std::iter::{from_fn, once};
+use std::{
+    fmt::Display,
+    iter::{Peekable, from_fn, once},
+};
+
+use crate::ast::span::{Position, Span};
 
 #[derive(Debug, Clone)]
 pub enum Token {
@@ -12,9 +17,33 @@ pub enum Token {
     With,
     In,
     Colon,
+    OpenBrace,
+    CloseBrace,
+    Comma,
     Eof,
 }
 
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Symbol(name) => write!(f, "`{name}`"),
+            Token::Quoted(_) => write!(f, "string literal"),
+            Token::OpenParen => write!(f, "`(`"),
+            Token::CloseParen => write!(f, "`)`"),
+            Token::Lambda => write!(f, "`λ`"),
+            Token::Dot => write!(f, "`.`"),
+            Token::Pipe => write!(f, "`|`"),
+            Token::With => write!(f, "`let`"),
+            Token::In => write!(f, "`in`"),
+            Token::Colon => write!(f, "`:`"),
+            Token::OpenBrace => write!(f, "`{{`"),
+            Token::CloseBrace => write!(f, "`}}`"),
+            Token::Comma => write!(f, "`,`"),
+            Token::Eof => write!(f, "end of input"),
+        }
+    }
+}
+
 fn match_single_char_token(c: char) -> Option<Token> {
     match c {
         '(' => Some(Token::OpenParen),
@@ -23,77 +52,123 @@ fn match_single_char_token(c: char) -> Option<Token> {
         '.' => Some(Token::Dot),
         '|' => Some(Token::Pipe),
         ':' => Some(Token::Colon),
+        '{' => Some(Token::OpenBrace),
+        '}' => Some(Token::CloseBrace),
+        ',' => Some(Token::Comma),
         _ => None,
     }
 }
 
-/// Create a Token iterator from &str
-pub fn lexer(input: &str) -> impl Iterator<Item = Token> {
-    let mut chars = input.chars().peekable();
+/// Position of every char in `input`, followed by the position right after it
+fn positions(input: &str) -> impl Iterator<Item = (Position, Option<char>)> + Clone {
+    let mut position = Position { line: 1, column: 1 };
+    input.chars().map(Some).chain(once(None)).map(move |c| {
+        let current = position;
+        if c == Some('\n') {
+            position.line += 1;
+            position.column = 1;
+        } else {
+            position.column += 1;
+        }
+        (current, c)
+    })
+}
+
+fn next_char_if(
+    chars: &mut Peekable<impl Iterator<Item = (Position, Option<char>)>>,
+    predicate: impl Fn(char) -> bool,
+) -> Option<char> {
+    chars
+        .next_if(|&(_, c)| c.is_some_and(&predicate))
+        .and_then(|(_, c)| c)
+}
 
+/// Create a Token iterator from &str, with the span of each token
+pub fn lexer(input: &str) -> impl Iterator<Item = (Token, Span)> {
+    let mut chars = positions(input).peekable();
+    let mut after_dot = false;
     from_fn(move || {
         // Skip whitespace
-        while chars.next_if(|c| c.is_ascii_whitespace()).is_some() {}
-
-        let c = chars.peek()?;
+        while next_char_if(&mut chars, |c| c.is_ascii_whitespace()).is_some() {}
 
-        // Check for single-char tokens
-        if let Some(token) = match_single_char_token(*c) {
-            chars.next(); // Consume
-            return Some(token);
-        }
-
-        // Handle quoted strings
-        if *c == '"' {
-            chars.next(); // Consume opening quote
-            let mut string_content = String::new();
+        let &(start, c) = chars.peek()?;
+        let token = match c {
+            None => {
+                chars.next(); // Consume end of input
+                Token::Eof
+            }
+            // Check for single-char tokens
+            Some(c) if let Some(token) = match_single_char_token(c) => {
+                chars.next(); // Consume
+                token
+            }
+            // Handle quoted strings
+            Some('"') => {
+                chars.next(); // Consume opening quote
+                let mut string_content = String::new();
 
-            while let Some(ch) = chars.next() {
-                if ch == '"' {
-                    // Found closing quote
-                    return Some(Token::Quoted(string_content));
-                }
-                if ch == '\\' {
-                    // Handle escape sequences
-                    if let Some(escaped) = chars.next() {
-                        match escaped {
-                            'n' => string_content.push('\n'),
-                            't' => string_content.push('\t'),
-                            'r' => string_content.push('\r'),
-                            '\\' => string_content.push('\\'),
-                            '"' => string_content.push('"'),
-                            _ => {
-                                string_content.push('\\');
-                                string_content.push(escaped);
+                // Unclosed string - take what we have
+                while let Some(ch) = next_char_if(&mut chars, |_| true) {
+                    if ch == '"' {
+                        // Found closing quote
+                        break;
+                    }
+                    if ch == '\\' {
+                        // Handle escape sequences
+                        if let Some(escaped) = next_char_if(&mut chars, |_| true) {
+                            match escaped {
+                                'n' => string_content.push('\n'),
+                                't' => string_content.push('\t'),
+                                'r' => string_content.push('\r'),
+                                '\\' => string_content.push('\\'),
+                                '"' => string_content.push('"'),
+                                _ => {
+                                    string_content.push('\\');
+                                    string_content.push(escaped);
+                                }
                             }
                         }
+                    } else {
+                        string_content.push(ch);
                     }
-                } else {
-                    string_content.push(ch);
                 }
+                Token::Quoted(string_content)
             }
-            // Unclosed string - return what we have
-            return Some(Token::Quoted(string_content));
-        }
-
-        // Parse variable name
-        let mut variable_name = String::new();
-        while let Some(c) = chars.next_if(|&c| {
-            match_single_char_token(c).is_none() && !c.is_ascii_whitespace() && c != '"'
-        }) {
-            variable_name.push(c);
-        }
-
-        if variable_name.is_empty() {
-            None
-        } else {
-            Some(Token::Symbol(variable_name))
-        }
-    })
-    .map(|token| match token {
-        Token::Symbol(name) if name == "with" || name == "let" => Token::With,
-        Token::Symbol(name) if name == "in" => Token::In,
-        _ => token,
+            // Parse variable name
+            Some(_) => {
+                let mut variable_name = String::new();
+                let is_symbol_char = |c| {
+                    match_single_char_token(c).is_none() && !c.is_ascii_whitespace() && c != '"'
+                };
+                while let Some(c) = next_char_if(&mut chars, is_symbol_char) {
+                    variable_name.push(c);
+                }
+                // Float literals `3.14` and float operators `+.` keep their dot,
+                // unless it's a field access like `pair.0.1`
+                let is_float = !after_dot
+                    && variable_name.bytes().all(|c| c.is_ascii_digit())
+                    && chars
+                        .clone()
+                        .nth(1)
+                        .is_some_and(|(_, c)| c.is_some_and(|c| c.is_ascii_digit()));
+                let is_float_operator = ["+", "-", "*", "/"].contains(&variable_name.as_str());
+                if (is_float || is_float_operator)
+                    && let Some(dot) = next_char_if(&mut chars, |c| c == '.')
+                {
+                    variable_name.push(dot);
+                    while is_float && let Some(c) = next_char_if(&mut chars, is_symbol_char) {
+                        variable_name.push(c);
+                    }
+                }
+                match variable_name.as_str() {
+                    "with" | "let" => Token::With,
+                    "in" => Token::In,
+                    _ => Token::Symbol(variable_name),
+                }
+            }
+        };
+        let end = chars.peek().map_or(start, |&(end, _)| end);
+        after_dot = matches!(token, Token::Dot);
+        Some((token, Span { start, end }))
     })
-    .chain(once(Token::Eof))
 }
@@ -1,29 +1,95 @@
+use std::{path::Path, sync::Arc};
+
 use petgraph::graph::NodeIndex;
 
 use crate::{
-    ast::AST,
-    parser::{lexer::lexer, parser::parse_expr},
+    ast::{AST, Edge, Node, builtins::ConstructorTag},
+    parser::{
+        imports::expand_imports,
+        lexer::lexer,
+        parser::{Tokens, parse_program},
+        records::record_shapes,
+    },
 };
 
+mod error;
+mod imports;
 mod lexer;
+#[allow(clippy::module_inception)]
 mod parser;
+mod records;
+
+pub use error::{ParseError, ParseErrors};
 
 impl AST {
-    pub fn from_str(s: &str) -> Self {
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Result<Self, ParseErrors> {
         let mut ast = Self::new();
+        ast.root = ast.add_expr_from_str(s)?;
+        Ok(ast)
+    }
+    /// Parse `s`, resolving names against definitions from `define`.
+    /// On error, nodes parsed so far are left detached in the graph.
+    pub fn add_expr_from_str(&mut self, s: &str) -> Result<NodeIndex, ParseErrors> {
+        self.add_expr_with_env(s, &[])
+    }
+    /// Parse `s` inside closures binding each name to an existing node,
+    /// e.g `add_expr_with_env("+ x 1", &[("x", ast.encode_usize(41))])`.
+    /// Later bindings shadow earlier ones, just like nested `let`.
+    pub fn add_expr_with_env(
+        &mut self,
+        s: &str,
+        env: &[(&str, NodeIndex)],
+    ) -> Result<NodeIndex, ParseErrors> {
+        let closures = env
+            .iter()
+            .map(|&(name, value)| {
+                let closure = self.graph.add_node(Node::Closure {
+                    argument_name: Arc::new(name.to_string()),
+                });
+                self.graph.add_edge(closure, value, Edge::Parameter);
+                closure
+            })
+            .collect::<Vec<_>>();
 
-        // Strip comments
-        let input = s
-            .lines()
-            .map(|line| line.split("//").next().unwrap())
-            .collect::<Vec<_>>()
-            .join("\n");
+        let mut scope = self.session_scope();
+        scope.extend(&closures);
+        let body = self.add_expr_in_scope(s, scope)?;
 
-        ast.root = parse_expr(&mut ast, &mut lexer(&input).peekable(), 0, vec![]);
-        ast
+        let mut chain = closures;
+        chain.push(body);
+        for window in chain.windows(2) {
+            self.graph.add_edge(window[0], window[1], Edge::Body);
+        }
+        Ok(chain[0])
     }
-    pub fn add_expr_from_str(&mut self, s: &str) -> NodeIndex {
-        parse_expr(self, &mut lexer(s).peekable(), 0, vec![])
-        // unimplemented!("Please provide reference to parent environment");
+    /// Parse `s` so that names resolve against `scope` binders, innermost last
+    pub(crate) fn add_expr_in_scope(
+        &mut self,
+        s: &str,
+        scope: Vec<NodeIndex>,
+    ) -> Result<NodeIndex, ParseErrors> {
+        let tokens = lexer(&strip_comments(s)).collect::<Vec<_>>();
+        let tokens = expand_imports(
+            tokens,
+            Some(Path::new(".")),
+            &self.import_paths,
+            &mut vec![],
+        )
+        .map_err(|error| ParseErrors(vec![error]))?;
+        for fields in record_shapes(&tokens) {
+            self.record_constructor(&fields);
+        }
+        for fields in ConstructorTag::builtin_record_shapes() {
+            self.record_constructor(fields);
+        }
+        parse_program(self, &mut Tokens::new(tokens.into_iter()), scope)
     }
 }
+
+fn strip_comments(s: &str) -> String {
+    s.lines()
+        .map(|line| line.split("//").next().unwrap())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
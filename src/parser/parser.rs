@@ -1,89 +1,619 @@
-use std::{iter::Peekable, panic, rc::Rc};
+use std::{fmt::Display, iter::Peekable, num::IntErrorKind, sync::Arc};
 
 use petgraph::graph::NodeIndex;
 
 use crate::{
-    ast::{builtins::ConstructorTag, Edge, Node, Primitive, VariableKind, AST},
-    parser::lexer::Token,
+    ast::{
+        builtins::{ConstructorTag, arithmetic::parse_float, helpers::HelperFunctionTag},
+        span::{Position, Span},
+        types::Type,
+        Edge, Node, Primitive, VariableKind, AST,
+    },
+    parser::{
+        error::{ParseError, ParseErrors},
+        lexer::Token,
+        records::{field_access, record},
+    },
 };
 
+/// Lexer output, keeping track of where the consumed tokens are
+pub struct Tokens<I: Iterator<Item = (Token, Span)>> {
+    tokens: Peekable<I>,
+    /// Last consumed token
+    last: Span,
+    /// Errors recovered from so far, see `recover`
+    errors: Vec<ParseError>,
+}
+
+impl<I: Iterator<Item = (Token, Span)>> Tokens<I> {
+    pub fn new(tokens: I) -> Self {
+        Self {
+            tokens: tokens.peekable(),
+            last: Span {
+                start: Position { line: 1, column: 1 },
+                end: Position { line: 1, column: 1 },
+            },
+            errors: vec![],
+        }
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let (token, span) = self.tokens.next()?;
+        self.last = span;
+        Some(token)
+    }
+
+    fn peek(&mut self) -> Option<&Token> {
+        self.tokens.peek().map(|(token, _)| token)
+    }
+
+    /// Start of the next token
+    fn start(&mut self) -> Position {
+        self.tokens
+            .peek()
+            .map_or(self.last.end, |(_, span)| span.start)
+    }
+
+    /// From `start` up to the end of the last consumed token
+    fn span_from(&self, start: Position) -> Span {
+        Span {
+            start,
+            end: self.last.end,
+        }
+    }
+
+    /// Error about the next token, which is left unconsumed
+    fn expected(&mut self, expected: &'static str) -> ParseError {
+        let (found, span) = match self.tokens.peek() {
+            Some((token, span)) => (token.to_string(), *span),
+            None => ("end of input".to_string(), self.span_from(self.last.end)),
+        };
+        ParseError::Unexpected {
+            expected,
+            found,
+            span,
+        }
+    }
+
+    /// Consume the next token if it `matches`, otherwise fail without consuming it
+    fn expect(
+        &mut self,
+        expected: &'static str,
+        matches: impl Fn(&Token) -> bool,
+    ) -> Result<Token, ParseError> {
+        match self.peek() {
+            Some(token) if matches(token) => Ok(self.next().unwrap()),
+            _ => Err(self.expected(expected)),
+        }
+    }
+
+    /// Remember `error` and skip past the token closing the group it happened in,
+    /// e.g the `)` of a malformed `(...)`, so that parsing can go on after it.
+    /// A group never closed ends before the `in` of the enclosing `let`, left for it to consume.
+    /// Fails with `error` itself if there is none either.
+    fn recover(
+        &mut self,
+        error: ParseError,
+        opens: impl Fn(&Token) -> bool,
+        closes: impl Fn(&Token) -> bool,
+    ) -> Result<(), ParseError> {
+        let mut depth = 0;
+        // `let`s started while skipping, their `in` is not the one of the enclosing `let`
+        let mut lets = 0;
+        loop {
+            match self.peek() {
+                None | Some(Token::Eof) => return Err(error),
+                Some(token) if closes(token) => {
+                    self.next();
+                    if depth == 0 {
+                        self.errors.push(error);
+                        return Ok(());
+                    }
+                    depth -= 1;
+                }
+                Some(Token::In) if lets == 0 => {
+                    self.errors.push(error);
+                    return Ok(());
+                }
+                Some(token) => {
+                    match token {
+                        Token::With => lets += 1,
+                        Token::In => lets -= 1,
+                        _ => {}
+                    }
+                    if opens(token) {
+                        depth += 1;
+                    }
+                    self.next();
+                }
+            }
+        }
+    }
+}
+
 type BindingPower = usize;
 
 fn binding_power(token: &Token) -> (BindingPower, BindingPower) {
     match token {
         Token::Pipe => (10, 11), // Very small binding power for pipe | operator
+        Token::Symbol(symbol) if symbol == ">>" => (20, 21), // Composes whole applications
         _ => (100, 101),         // Everything else is left-associative
     }
 }
 
+#[derive(Debug)]
+enum TypeToken {
+    Name(String),
+    Arrow,
+    OpenParen,
+    CloseParen,
+}
+
+impl Display for TypeToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TypeToken::Name(name) => write!(f, "`{name}`"),
+            TypeToken::Arrow => write!(f, "`->`"),
+            TypeToken::OpenParen => write!(f, "`(`"),
+            TypeToken::CloseParen => write!(f, "`)`"),
+        }
+    }
+}
+
+/// Take tokens making up a type annotation, e.g `(Number->Number)->Bytes`.
+/// The lexer keeps `->` inside symbols, so a type continues while parens are open
+/// or an arrow joins it to the next part.
+fn type_tokens<I: Iterator<Item = (Token, Span)>>(
+    tokens: &mut Tokens<I>,
+) -> Result<Vec<TypeToken>, ParseError> {
+    let mut result = vec![];
+    let mut depth = 0;
+    loop {
+        let continues = depth > 0
+            || matches!(result.last(), None | Some(TypeToken::Arrow))
+            || matches!(tokens.peek(), Some(Token::Symbol(symbol)) if symbol.starts_with("->"));
+        if !continues {
+            return Ok(result);
+        }
+        match tokens.peek() {
+            Some(Token::OpenParen) => {
+                tokens.next();
+                depth += 1;
+                result.push(TypeToken::OpenParen);
+            }
+            Some(Token::CloseParen) if depth > 0 => {
+                tokens.next();
+                depth -= 1;
+                result.push(TypeToken::CloseParen);
+            }
+            Some(Token::Symbol(_)) => {
+                let Some(Token::Symbol(symbol)) = tokens.next() else {
+                    unreachable!()
+                };
+                for (index, name) in symbol.split("->").enumerate() {
+                    if index > 0 {
+                        result.push(TypeToken::Arrow);
+                    }
+                    if !name.is_empty() {
+                        result.push(TypeToken::Name(name.to_string()));
+                    }
+                }
+            }
+            _ => return Err(tokens.expected("type")),
+        }
+    }
+}
+
+/// Arrows are right-associative: `Number->Number->Number` is `Number->(Number->Number)`.
+/// Errors point at the whole annotation `span`.
+fn parse_type<I: Iterator<Item = TypeToken>>(
+    tokens: &mut Peekable<I>,
+    span: Span,
+) -> Result<Type, ParseError> {
+    let unexpected = |expected, found: Option<TypeToken>| ParseError::Unexpected {
+        expected,
+        found: found.map_or("end of type".to_string(), |token| token.to_string()),
+        span,
+    };
+    let domain = match tokens.next() {
+        Some(TypeToken::Name(name)) => match Type::from_name(&name) {
+            Some(domain) => domain,
+            None => return Err(ParseError::UnknownType { name, span }),
+        },
+        Some(TypeToken::OpenParen) => {
+            let inner = parse_type(tokens, span)?;
+            match tokens.next() {
+                Some(TypeToken::CloseParen) => inner,
+                token => return Err(unexpected("`)` in type", token)),
+            }
+        }
+        token => return Err(unexpected("type", token)),
+    };
+    Ok(
+        match tokens.next_if(|token| matches!(token, TypeToken::Arrow)) {
+            Some(_) => Type::Arrow(Box::new(domain), Box::new(parse_type(tokens, span)?)),
+            None => domain,
+        },
+    )
+}
+
+/// Parse a whole expression, reporting every syntax error in it
+pub fn parse_program<I: Iterator<Item = (Token, Span)>>(
+    ast: &mut AST,
+    tokens: &mut Tokens<I>,
+    binder_ctx: Vec<NodeIndex>,
+) -> Result<NodeIndex, ParseErrors> {
+    let result = parse_expr(ast, tokens, 0, binder_ctx).and_then(|expr| {
+        tokens.expect("end of input", |token| matches!(token, Token::Eof))?;
+        Ok(expr)
+    });
+    let mut errors = std::mem::take(&mut tokens.errors);
+    match result {
+        Ok(expr) if errors.is_empty() => return Ok(expr),
+        Ok(_) => {}
+        Err(error) => errors.push(error),
+    }
+    errors.sort_by(|a, b| a.span().start.partial_cmp(&b.span().start).unwrap());
+    // Nested groups left unclosed all stop at the same `in`
+    errors.dedup();
+    Err(ParseErrors(errors))
+}
+
+/// Decimal, `0x` hexadecimal or `0b` binary number literal
+fn parse_number(symbol: &str) -> Option<usize> {
+    let Some((digits, radix)) = prefixed_digits(symbol) else {
+        return symbol.parse().ok();
+    };
+    usize::from_str_radix(digits, radix).ok()
+}
+
+/// Why `symbol` looks like a number literal but isn't one: it has too many digits for a Number,
+/// or is a `0x` or `0b` literal without valid digits, like a bare `0x`
+fn number_error(symbol: &str, span: Span) -> Option<ParseError> {
+    let (digits, radix) = prefixed_digits(symbol).unwrap_or((symbol, 10));
+    if radix == 10 && !symbol.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    let error = usize::from_str_radix(digits, radix).err()?;
+    let literal = symbol.to_string();
+    Some(match error.kind() {
+        IntErrorKind::PosOverflow => ParseError::NumberOverflow { literal, span },
+        _ => ParseError::MalformedNumber { literal, span },
+    })
+}
+
+/// Digits and radix of a `0x` hexadecimal or `0b` binary literal
+fn prefixed_digits(symbol: &str) -> Option<(&str, u32)> {
+    let (digits, radix) = match symbol.get(..2) {
+        Some("0x") => (&symbol[2..], 16),
+        Some("0b") => (&symbol[2..], 2),
+        _ => return None,
+    };
+    // `from_str_radix` would also accept a sign after the prefix
+    (!digits.starts_with('+')).then_some((digits, radix))
+}
+
+/// Variable bound by the innermost binder called `name`, a builtin, a number or a free variable
+fn symbol(ast: &mut AST, name: String, binder_ctx: &[NodeIndex]) -> NodeIndex {
+    let name = Arc::new(name);
+    match binder_ctx.iter().rfind(|index| {
+        if let Some(Node::Lambda { argument_name } | Node::Closure { argument_name }) =
+            ast.graph.node_weight(**index)
+        {
+            return *argument_name == name;
+        }
+        panic!("lambda_ctx elements can only point to lambda/closure nodes")
+    }) {
+        Some(binder_id) => {
+            let node = ast.graph.add_node(Node::Variable(VariableKind::Bound));
+            ast.graph.add_edge(node, *binder_id, Edge::Binder(0));
+            node
+        }
+        None => {
+            if let Some(tag) = ast.host_function_tag(&name) {
+                ast.graph.add_node(Node::Data { tag })
+            } else if let Some(tag) = ast.list_constructor(&name) {
+                ast.graph.add_node(Node::Data { tag })
+            } else if let Ok(tag) = ConstructorTag::try_from(name.as_str()) {
+                ast.graph.add_node(Node::Data { tag })
+            } else if let Some(number) = parse_number(&name) {
+                ast.graph
+                    .add_node(Node::Primitive(Primitive::Number(number)))
+            } else if let Some(float) = parse_float(&name) {
+                ast.graph.add_node(Node::Primitive(Primitive::Float(float)))
+            } else {
+                ast.graph.add_node(Node::Variable(VariableKind::Free(name)))
+            }
+        }
+    }
+}
+
+/// `match value { cons head tail -> a, nil -> b, _ -> c }` becomes a `#match` chain:
+/// `#match cons (λhead tail.a) (#match nil b (λ_.c)) value`.
+/// Without a `_` arm, values matching no constructor evaluate to free variable `NO_MATCH`.
+/// The value can be an application, `match f x { ... }`, but operators like `|` need parens.
+fn parse_match<I: Iterator<Item = (Token, Span)>>(
+    ast: &mut AST,
+    tokens: &mut Tokens<I>,
+    binder_ctx: &[NodeIndex],
+) -> Result<NodeIndex, ParseError> {
+    let start = tokens.start();
+    let mut value = parse_expr(ast, tokens, 101, binder_ctx.to_vec())?;
+    // Arguments one by one, the `{` would otherwise start a record literal
+    while let Some(token) = tokens.peek()
+        && !matches!(
+            token,
+            Token::OpenBrace
+                | Token::Eof
+                | Token::CloseParen
+                | Token::CloseBrace
+                | Token::In
+                | Token::Comma
+        )
+    {
+        let argument = parse_expr(ast, tokens, 101, binder_ctx.to_vec())?;
+        let application = ast.graph.add_node(Node::Application);
+        ast.graph.add_edge(application, value, Edge::Function);
+        ast.graph.add_edge(application, argument, Edge::Parameter);
+        ast.set_span(application, tokens.span_from(start));
+        value = application;
+    }
+    tokens.expect("`{`", |token| matches!(token, Token::OpenBrace))?;
+
+    let mut arms = vec![];
+    let mut fallback = None;
+    loop {
+        let start = tokens.start();
+        let Token::Symbol(name) = tokens.expect("constructor name or `_`", |token| {
+            matches!(token, Token::Symbol(_))
+        })?
+        else {
+            unreachable!()
+        };
+        let pattern_span = tokens.span_from(start);
+        let mut binders = binder_ctx.to_vec();
+        let mut lambdas = vec![];
+        if name != "_" {
+            while let Some(Token::Symbol(argument)) = tokens.peek()
+                && argument != "->"
+            {
+                let binder_start = tokens.start();
+                let Some(Token::Symbol(argument)) = tokens.next() else {
+                    unreachable!()
+                };
+                let lambda = ast.graph.add_node(Node::Lambda {
+                    argument_name: Arc::new(argument),
+                });
+                ast.set_binder_span(lambda, tokens.span_from(binder_start));
+                binders.push(lambda);
+                lambdas.push(lambda);
+            }
+        }
+        tokens.expect(
+            "`->`",
+            |token| matches!(token, Token::Symbol(arrow) if arrow == "->"),
+        )?;
+        let body = parse_expr(ast, tokens, 0, binders)?;
+        lambdas.push(body);
+        for window in lambdas.windows(2) {
+            ast.graph.add_edge(window[0], window[1], Edge::Body);
+        }
+
+        if name == "_" {
+            // Fallback gets the value again
+            let lambda = ast.graph.add_node(Node::Lambda {
+                argument_name: Arc::new("_".to_string()),
+            });
+            ast.graph.add_edge(lambda, body, Edge::Body);
+            ast.set_span(lambda, pattern_span);
+            fallback = Some(lambda);
+            tokens.expect("`}` after `_` arm", |token| {
+                matches!(token, Token::CloseBrace)
+            })?;
+            break;
+        }
+        let constructor = symbol(ast, name, binder_ctx);
+        ast.set_span(constructor, pattern_span);
+        arms.push((constructor, lambdas[0]));
+        match tokens.expect("`,` or `}`", |token| {
+            matches!(token, Token::Comma | Token::CloseBrace)
+        })? {
+            Token::Comma => {}
+            _ => break,
+        }
+    }
+
+    let mut result = match fallback {
+        Some(fallback) => fallback,
+        None => {
+            let lambda = ast.graph.add_node(Node::Lambda {
+                argument_name: Arc::new("value".to_string()),
+            });
+            let missing = ast
+                .graph
+                .add_node(Node::Variable(VariableKind::Free(Arc::new(
+                    "NO_MATCH".to_string(),
+                ))));
+            ast.graph.add_edge(lambda, missing, Edge::Body);
+            lambda
+        }
+    };
+    for (constructor, transform) in arms.into_iter().rev() {
+        let mut matcher = ast.graph.add_node(Node::Data {
+            tag: ConstructorTag::HelperFunction(HelperFunctionTag::Match),
+        });
+        for argument in [constructor, transform, result] {
+            let application = ast.graph.add_node(Node::Application);
+            ast.graph.add_edge(application, matcher, Edge::Function);
+            ast.graph.add_edge(application, argument, Edge::Parameter);
+            matcher = application;
+        }
+        result = matcher;
+    }
+    let application = ast.graph.add_node(Node::Application);
+    ast.graph.add_edge(application, result, Edge::Function);
+    ast.graph.add_edge(application, value, Edge::Parameter);
+    Ok(application)
+}
+
+/// `data List = Cons head tail | Nil in body` binds every constructor like `let` would,
+/// to a named constructor of the AST with as many arguments as there are field names.
+/// Like `let Cons #constructor 2 in let Nil #constructor 0 in body`, but allocated while parsing
+/// and shared by name with builtins and the host, see `AST::named_constructor`
+fn parse_data<I: Iterator<Item = (Token, Span)>>(
+    ast: &mut AST,
+    tokens: &mut Tokens<I>,
+    mut binder_ctx: Vec<NodeIndex>,
+) -> Result<NodeIndex, ParseError> {
+    let Token::Symbol(type_name) =
+        tokens.expect("type name", |token| matches!(token, Token::Symbol(_)))?
+    else {
+        unreachable!()
+    };
+    tokens.expect(
+        "`=` after type name",
+        |token| matches!(token, Token::Symbol(equals) if equals == "="),
+    )?;
+
+    let mut closures = vec![];
+    let mut constructors = vec![];
+    loop {
+        let start = tokens.start();
+        let Token::Symbol(name) = tokens.expect("constructor name", |token| {
+            matches!(token, Token::Symbol(_))
+        })?
+        else {
+            unreachable!()
+        };
+        let mut arity = 0;
+        while let Some(Token::Symbol(_)) = tokens.peek() {
+            tokens.next(); // Field names only count arguments
+            arity += 1;
+        }
+        let tag = ast.named_constructor(&name, arity);
+        constructors.push((name.clone(), tag));
+        let constructor = ast.graph.add_node(Node::Data { tag });
+        let closure = ast.graph.add_node(Node::Closure {
+            argument_name: Arc::new(name),
+        });
+        ast.graph.add_edge(closure, constructor, Edge::Parameter);
+        ast.set_binder_span(closure, tokens.span_from(start));
+        binder_ctx.push(closure);
+        closures.push(closure);
+
+        match tokens.expect("`|` or `in`", |token| {
+            matches!(token, Token::Pipe | Token::In)
+        })? {
+            Token::Pipe => {}
+            _ => break,
+        }
+    }
+    ast.declare_data(type_name, constructors);
+
+    let body = parse_expr(ast, tokens, 0, binder_ctx)?;
+    closures.push(body);
+    for window in closures.windows(2) {
+        ast.graph.add_edge(window[0], window[1], Edge::Body);
+    }
+    Ok(closures[0])
+}
+
+/// `f >> g` is `λx.f (g x)`, applying `g` first like `x | g | f`
+fn compose(ast: &mut AST, f: NodeIndex, g: NodeIndex) -> NodeIndex {
+    let lambda = ast.graph.add_node(Node::Lambda {
+        argument_name: Arc::new("x".to_string()),
+    });
+    let x = ast.graph.add_node(Node::Variable(VariableKind::Bound));
+    ast.graph.add_edge(x, lambda, Edge::Binder(0));
+
+    let inner = ast.graph.add_node(Node::Application);
+    ast.graph.add_edge(inner, g, Edge::Function);
+    ast.graph.add_edge(inner, x, Edge::Parameter);
+    let outer = ast.graph.add_node(Node::Application);
+    ast.graph.add_edge(outer, f, Edge::Function);
+    ast.graph.add_edge(outer, inner, Edge::Parameter);
+
+    ast.graph.add_edge(lambda, outer, Edge::Body);
+    lambda
+}
+
+/// Stand-in for an expression that failed to parse, so the rest can still be checked
+fn malformed(ast: &mut AST) -> NodeIndex {
+    ast.graph
+        .add_node(Node::Variable(VariableKind::Free(Arc::new(
+            "?".to_string(),
+        ))))
+}
+
 /// Parse Token iterator into an Expression
-pub fn parse_expr<I: Iterator<Item = Token>>(
+pub fn parse_expr<I: Iterator<Item = (Token, Span)>>(
     ast: &mut AST,
-    tokens: &mut Peekable<I>,
+    tokens: &mut Tokens<I>,
     min_binding_power: BindingPower,
     mut binder_ctx: Vec<NodeIndex>,
-) -> NodeIndex {
-    let mut lhs = match tokens.next().unwrap() {
-        Token::Symbol(name) => {
-            let name = Rc::new(name);
-            match binder_ctx.iter().rfind(|index| {
-                if let Some(Node::Lambda { argument_name } | Node::Closure { argument_name }) =
-                    ast.graph.node_weight(**index)
-                {
-                    return *argument_name == name;
-                }
-                panic!("lambda_ctx elements can only point to lambda/closure nodes")
-            }) {
-                Some(binder_id) => {
-                    let node = ast.graph.add_node(Node::Variable(VariableKind::Bound));
-                    ast.graph.add_edge(node, *binder_id, Edge::Binder(0));
-                    node
-                }
-                None => {
-                    if let Ok(tag) = ConstructorTag::try_from(name.as_str()) {
-                        ast.graph.add_node(Node::Data { tag })
-                    } else if let Ok(number) = name.parse::<usize>() {
-                        ast.graph
-                            .add_node(Node::Primitive(Primitive::Number(number)))
-                    } else {
-                        ast.graph.add_node(Node::Variable(VariableKind::Free(name)))
-                    }
-                }
+) -> Result<NodeIndex, ParseError> {
+    let start = tokens.start();
+    // Leave tokens closing an enclosing group to it
+    if let None
+    | Some(Token::Eof | Token::CloseParen | Token::In | Token::Comma | Token::CloseBrace) =
+        tokens.peek()
+    {
+        return Err(tokens.expected("expression"));
+    }
+    let mut lhs = match tokens.next() {
+        Some(Token::Symbol(name)) if name == "match" => parse_match(ast, tokens, &binder_ctx)?,
+        Some(Token::Symbol(name)) if name == "data" => parse_data(ast, tokens, binder_ctx.clone())?,
+        // Would otherwise silently become a free variable
+        Some(Token::Symbol(name)) => match number_error(&name, tokens.span_from(start)) {
+            Some(error) => {
+                tokens.errors.push(error);
+                malformed(ast)
             }
-        }
-        Token::Lambda => {
+            None => symbol(ast, name, &binder_ctx),
+        },
+        Some(Token::Lambda) => {
             // Support nested syntax: \x y z.x y z
             let mut lambdas_chain = vec![];
             while let Some(Token::Symbol(_)) = tokens.peek() {
+                let binder_start = tokens.start();
                 let Some(Token::Symbol(variable_name)) = tokens.next() else {
                     unreachable!()
                 };
 
-                match tokens.peek() {
-                    Some(Token::Colon) => {
-                        tokens.next(); // Consume :
-                        match tokens.next() {
-                            Some(Token::Symbol(_type_name)) => {} // TODO: do something with type
-                            token => panic!("Expected type, got: {:?}", token),
-                        };
-                    }
-                    _ => {} // TODO: Default to any type
-                };
                 let lambda_node = ast.graph.add_node(Node::Lambda {
-                    argument_name: Rc::new(variable_name),
+                    argument_name: Arc::new(variable_name),
                 });
+                // Unannotated arguments are left for the type checker to treat as any type
+                if let Some(Token::Colon) = tokens.peek() {
+                    tokens.next(); // Consume :
+                    let type_start = tokens.start();
+                    let mut type_tokens = type_tokens(tokens)?.into_iter().peekable();
+                    let type_span = tokens.span_from(type_start);
+                    let argument_type = parse_type(&mut type_tokens, type_span)?;
+                    if let Some(token) = type_tokens.next() {
+                        return Err(ParseError::Unexpected {
+                            expected: "end of type",
+                            found: token.to_string(),
+                            span: type_span,
+                        });
+                    }
+                    ast.annotate(lambda_node, argument_type);
+                }
+                ast.set_binder_span(lambda_node, tokens.span_from(binder_start));
                 binder_ctx.push(lambda_node);
                 lambdas_chain.push(lambda_node);
             }
-            match tokens.next() {
-                Some(Token::Dot) => {}
-                token => panic!("Expected DOT, got: {:?}", token),
-            }
-            let head = *lambdas_chain
-                .first()
-                .expect("At least one lambda node must have been created!");
+            let head = match lambdas_chain.first() {
+                Some(&head) => head,
+                None => return Err(tokens.expected("argument name")),
+            };
+            tokens.expect("`.`", |token| matches!(token, Token::Dot))?;
 
-            let body = parse_expr(ast, tokens, 0, binder_ctx.clone());
+            let body = parse_expr(ast, tokens, 0, binder_ctx.clone())?;
+            for &lambda_node in &lambdas_chain[1..] {
+                let binder_start = ast.binder_span(lambda_node).unwrap().start;
+                ast.set_span(lambda_node, tokens.span_from(binder_start));
+            }
             lambdas_chain.push(body);
 
             for window in lambdas_chain.windows(2) {
@@ -92,45 +622,131 @@ pub fn parse_expr<I: Iterator<Item = Token>>(
 
             head
         }
-        Token::OpenParen => {
-            let result = parse_expr(ast, tokens, 0, binder_ctx.clone());
-            match tokens.next() {
-                Some(Token::CloseParen) => {}
-                token => panic!("Expected CloseParen, got: {:?}", token),
+        Some(Token::OpenParen) => {
+            let result = parse_expr(ast, tokens, 0, binder_ctx.clone()).and_then(|first| {
+                let mut elements = vec![first];
+                while let Token::Comma = tokens.expect("`,` or `)`", |token| {
+                    matches!(token, Token::Comma | Token::CloseParen)
+                })? {
+                    elements.push(parse_expr(ast, tokens, 0, binder_ctx.clone())?);
+                }
+                match elements.len() {
+                    1 => Ok(first),
+                    _ => {
+                        let fields = elements
+                            .into_iter()
+                            .enumerate()
+                            .map(|(index, element)| (index.to_string(), element))
+                            .collect();
+                        record(ast, fields, tokens.span_from(start))
+                    }
+                }
+            });
+            match result {
+                Ok(result) => result,
+                Err(error) => {
+                    tokens.recover(
+                        error,
+                        |token| matches!(token, Token::OpenParen),
+                        |token| matches!(token, Token::CloseParen),
+                    )?;
+                    malformed(ast)
+                }
             }
-            result
         }
-        Token::With => {
-            let variable_name = match tokens.next() {
-                Some(Token::Symbol(name)) => name,
-                token => panic!("Expected variable name, got: {:?}", token),
+        Some(Token::With) => {
+            let Token::Symbol(variable_name) =
+                tokens.expect("variable name", |token| matches!(token, Token::Symbol(_)))?
+            else {
+                unreachable!()
             };
-            let value = parse_expr(ast, tokens, 0, binder_ctx.clone());
-            match tokens.next() {
-                Some(Token::In) => {}
-                token => panic!("Expected In, got: {:?}", token),
+            let binder_span = tokens.span_from(start);
+            let value = parse_expr(ast, tokens, 0, binder_ctx.clone()).and_then(|value| {
+                tokens.expect("`in`", |token| matches!(token, Token::In))?;
+                Ok(value)
+            });
+            // A malformed value does not stop the body from being checked
+            let value = match value {
+                Ok(value) => value,
+                Err(error) => {
+                    tokens.recover(
+                        error,
+                        |token| matches!(token, Token::With),
+                        |token| matches!(token, Token::In),
+                    )?;
+                    malformed(ast)
+                }
             };
             let closure_node = ast.graph.add_node(Node::Closure {
-                argument_name: Rc::new(variable_name),
+                argument_name: Arc::new(variable_name),
             });
+            ast.set_binder_span(closure_node, binder_span);
 
             binder_ctx.push(closure_node);
-            let body = parse_expr(ast, tokens, 0, binder_ctx.clone());
+            let body = parse_expr(ast, tokens, 0, binder_ctx.clone())?;
 
             ast.graph.add_edge(closure_node, body, Edge::Body);
             ast.graph.add_edge(closure_node, value, Edge::Parameter);
 
             closure_node
         }
-        Token::Quoted(quoted) => ast
+        Some(Token::Quoted(quoted)) => ast
             .graph
             .add_node(Node::Primitive(Primitive::Bytes(quoted.into()))),
-        token => panic!("Invalid syntax: unexpected token {:?}", token),
+        Some(Token::OpenBrace) => {
+            let mut fields = vec![];
+            if let Some(Token::CloseBrace) = tokens.peek() {
+                tokens.next();
+            } else {
+                loop {
+                    let Token::Symbol(name) =
+                        tokens.expect("field name", |token| matches!(token, Token::Symbol(_)))?
+                    else {
+                        unreachable!()
+                    };
+                    tokens.expect(
+                        "`=` after field name",
+                        |token| matches!(token, Token::Symbol(equals) if equals == "="),
+                    )?;
+                    let value = parse_expr(ast, tokens, 0, binder_ctx.clone())?;
+                    fields.push((name, value));
+                    match tokens.expect("`,` or `}`", |token| {
+                        matches!(token, Token::Comma | Token::CloseBrace)
+                    })? {
+                        Token::Comma => {}
+                        _ => break,
+                    }
+                }
+            }
+            record(ast, fields, tokens.span_from(start))?
+        }
+        Some(token) => {
+            return Err(ParseError::Unexpected {
+                expected: "expression",
+                found: token.to_string(),
+                span: tokens.span_from(start),
+            });
+        }
+        None => unreachable!(),
     };
+    ast.set_span(lhs, tokens.span_from(start));
+
+    // Field access binds tighter than application
+    while let Some(Token::Dot) = tokens.peek() {
+        tokens.next(); // Consume .
+        let Token::Symbol(field) =
+            tokens.expect("field name", |token| matches!(token, Token::Symbol(_)))?
+        else {
+            unreachable!()
+        };
+        lhs = field_access(ast, lhs, &field, tokens.last)?;
+        ast.set_span(lhs, tokens.span_from(start));
+    }
     loop {
-        let next_token = match tokens.peek().unwrap() {
-            Token::Eof | Token::CloseParen | Token::In => break,
-            token => token,
+        let next_token = match tokens.peek() {
+            None | Some(Token::Eof | Token::CloseParen | Token::In | Token::Comma) => break,
+            Some(Token::CloseBrace) => break,
+            Some(token) => token,
         };
         let (l_bp, r_bp) = binding_power(next_token);
         if l_bp < min_binding_power {
@@ -141,14 +757,24 @@ pub fn parse_expr<I: Iterator<Item = Token>>(
         let next_token = next_token.clone();
 
         // Some tokens we have to consume
-        match next_token {
+        match &next_token {
             Token::Pipe | Token::Colon => {
                 tokens.next().unwrap();
             }
+            Token::Symbol(symbol) if symbol == ">>" => {
+                tokens.next().unwrap();
+            }
             _ => {}
         };
 
-        let rhs = parse_expr(ast, tokens, r_bp, binder_ctx.clone());
+        let rhs = parse_expr(ast, tokens, r_bp, binder_ctx.clone())?;
+        if let Token::Symbol(symbol) = &next_token
+            && symbol == ">>"
+        {
+            lhs = compose(ast, lhs, rhs);
+            ast.set_span(lhs, tokens.span_from(start));
+            continue;
+        }
         let app_node = ast.graph.add_node(Node::Application);
 
         match next_token {
@@ -163,7 +789,105 @@ pub fn parse_expr<I: Iterator<Item = Token>>(
             }
         };
 
+        ast.set_span(app_node, tokens.span_from(start));
         lhs = app_node
     }
-    lhs
+    Ok(lhs)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        EvalConfig,
+        ast::{AST, span::Position},
+        parse,
+        parser::ParseError,
+    };
+
+    fn evaluate(source: &str) -> Option<usize> {
+        let program = parse(source).unwrap();
+        program.evaluate(EvalConfig::default()).unwrap().to_usize()
+    }
+
+    #[test]
+    fn composition_applies_right_operand_first() {
+        // Decrement and double
+        let functions = "let f \\x.- 1 x in let g \\x.* 2 x in ";
+        assert_eq!(evaluate(&format!("{functions}(f >> g) 5")), Some(9));
+        assert_eq!(evaluate(&format!("{functions}(g >> f) 5")), Some(8));
+        assert_eq!(evaluate(&format!("{functions}5 | g | f")), Some(9));
+    }
+
+    #[test]
+    fn overflowing_literal_is_an_error() {
+        assert_eq!(evaluate("+ 0xFF 0b101"), Some(260));
+
+        let source = "+ 1 0xFFFFFFFFFFFFFFFFFF";
+        let errors = AST::new().add_expr_from_str(source).unwrap_err().0;
+        let [ParseError::NumberOverflow { literal, span }] = &errors[..] else {
+            panic!("{errors:?}");
+        };
+        assert_eq!(literal, "0xFFFFFFFFFFFFFFFFFF");
+        assert_eq!(span.start, Position { line: 1, column: 5 });
+
+        let source = "+ 1 18446744073709551616";
+        let errors = AST::new().add_expr_from_str(source).unwrap_err().0;
+        let [ParseError::NumberOverflow { literal, span }] = &errors[..] else {
+            panic!("{errors:?}");
+        };
+        assert_eq!(literal, "18446744073709551616");
+        assert_eq!(span.start, Position { line: 1, column: 5 });
+    }
+
+    #[test]
+    fn prefix_without_digits_is_an_error() {
+        for prefix in ["0x", "0b"] {
+            let source = format!("+ 1 {prefix}");
+            let errors = AST::new().add_expr_from_str(&source).unwrap_err().0;
+            let [ParseError::MalformedNumber { literal, span }] = &errors[..] else {
+                panic!("{errors:?}");
+            };
+            assert_eq!(literal, prefix);
+            assert_eq!(span.start, Position { line: 1, column: 5 });
+        }
+    }
+
+    #[test]
+    fn match_arms() {
+        let list = "data List = Cons head tail | Nil in
+            let Y \\f.(\\x.f (x x)) (\\x.f (x x)) in
+            let sum Y \\sum list.match list { Cons h t -> + h (sum t), Nil -> 0 } in ";
+        assert_eq!(
+            evaluate(&format!("{list}sum (Cons 1 (Cons 2 Nil))")),
+            Some(3)
+        );
+        assert_eq!(
+            evaluate(&format!("{list}match Nil {{ Cons h t -> h, _ -> 7 }}")),
+            Some(7)
+        );
+    }
+
+    #[test]
+    fn match_value_is_an_application() {
+        let source = "data Option = Some value | None in
+            let find \\x.(=num x 0) None (Some x) in
+            match find 5 { Some x -> x, None -> 0 }";
+        assert_eq!(evaluate(source), Some(5));
+        // Field access binds tighter than application there too
+        let source = "data Option = Some value | None in
+            let p { x = 4 } in
+            match (\\n.Some n) p.x { Some x -> x, None -> 0 }";
+        assert_eq!(evaluate(source), Some(4));
+    }
+
+    #[test]
+    fn unclosed_parens_are_reported_separately() {
+        let source = "let a (+ 1 in\nlet b (* 2 in\n+ a b";
+        let errors = AST::new().add_expr_from_str(source).unwrap_err().0;
+        let lines = errors.iter().map(|error| error.span().start.line);
+        assert_eq!(lines.collect::<Vec<_>>(), [1, 2], "{errors:?}");
+        for error in errors {
+            assert_eq!(error.to_string(), "expected `,` or `)`, found `in`");
+        }
+    }
 }
@@ -0,0 +1,224 @@
+use std::sync::Arc;
+
+use petgraph::graph::NodeIndex;
+
+use crate::{
+    ast::{
+        AST, Edge, Node, VariableKind,
+        builtins::{ConstructorTag, helpers::HelperFunctionTag},
+        span::Span,
+    },
+    parser::{error::ParseError, lexer::Token},
+};
+
+enum Group {
+    /// `{ ... }` with its field names so far
+    Record(Vec<String>),
+    /// `( ... )` with the number of commas in it, a tuple if there are any
+    Parens(usize),
+}
+
+/// Field names of every record and tuple literal in `tokens`, so that field accesses
+/// can be lowered even when they come before the records they read
+pub fn record_shapes(tokens: &[(Token, Span)]) -> Vec<Vec<String>> {
+    let mut stack = vec![];
+    let mut expects_field = false;
+    let mut shapes = vec![];
+    for (index, (token, _)) in tokens.iter().enumerate() {
+        match token {
+            Token::OpenBrace => {
+                stack.push(Group::Record(vec![]));
+                expects_field = true;
+                continue;
+            }
+            Token::OpenParen => stack.push(Group::Parens(0)),
+            Token::CloseBrace | Token::CloseParen => match stack.pop() {
+                Some(Group::Record(mut fields)) => {
+                    fields.sort();
+                    shapes.push(fields);
+                }
+                Some(Group::Parens(commas)) if commas > 0 => {
+                    shapes.push(tuple_fields(commas + 1));
+                }
+                _ => {}
+            },
+            Token::Comma => {
+                match stack.last_mut() {
+                    Some(Group::Record(_)) => expects_field = true,
+                    Some(Group::Parens(commas)) => *commas += 1,
+                    None => {}
+                }
+                continue;
+            }
+            // Braces of `match` arms have no `=` after the name
+            Token::Symbol(name)
+                if expects_field
+                    && matches!(tokens.get(index + 1), Some((Token::Symbol(equals), _)) if equals == "=") =>
+            {
+                if let Some(Group::Record(fields)) = stack.last_mut() {
+                    fields.push(name.clone());
+                }
+            }
+            _ => {}
+        }
+        expects_field = false;
+    }
+    shapes
+}
+
+/// Tuples are records with fields named by position, `(a, b)` is `{ 0 = a, 1 = b }`
+fn tuple_fields(size: usize) -> Vec<String> {
+    let mut fields = (0..size).map(|index| index.to_string()).collect::<Vec<_>>();
+    fields.sort();
+    fields
+}
+
+/// `{ name = value, ... }` becomes its record constructor applied to values in field name order
+pub fn record(
+    ast: &mut AST,
+    mut fields: Vec<(String, NodeIndex)>,
+    span: Span,
+) -> Result<NodeIndex, ParseError> {
+    fields.sort_by(|(a, _), (b, _)| a.cmp(b));
+    if let Some(window) = fields.windows(2).find(|window| window[0].0 == window[1].0) {
+        return Err(ParseError::DuplicateField {
+            name: window[0].0.clone(),
+            span,
+        });
+    }
+
+    let names = fields
+        .iter()
+        .map(|(name, _)| name.clone())
+        .collect::<Vec<_>>();
+    let tag = ast.record_constructor(&names);
+    let mut result = ast.graph.add_node(Node::Data { tag });
+    for (_, value) in fields {
+        let application = ast.graph.add_node(Node::Application);
+        ast.graph.add_edge(application, result, Edge::Function);
+        ast.graph.add_edge(application, value, Edge::Parameter);
+        result = application;
+    }
+    Ok(result)
+}
+
+/// `record.field` becomes a chain of `#match` over every record constructor having `field`:
+/// `#match {a,field} (λa field.field) (#match ... (λrecord.NO_FIELD_field)) record`.
+/// Only a record of another shape turns up at `NO_FIELD_field`, a field no record has is an error
+pub fn field_access(
+    ast: &mut AST,
+    record: NodeIndex,
+    field: &str,
+    span: Span,
+) -> Result<NodeIndex, ParseError> {
+    let shapes = ast.record_shapes();
+    if !shapes
+        .iter()
+        .any(|(_, fields)| fields.iter().any(|name| name == field))
+    {
+        return Err(ParseError::UnknownField {
+            name: field.to_string(),
+            span,
+        });
+    }
+
+    let missing = ast
+        .graph
+        .add_node(Node::Variable(VariableKind::Free(Arc::new(format!(
+            "NO_FIELD_{field}"
+        )))));
+    let mut fallback = lambdas(ast, &["record".to_string()], missing);
+
+    for (tag, fields) in shapes {
+        let Some(position) = fields.iter().position(|name| name == field) else {
+            continue;
+        };
+        let value = ast.graph.add_node(Node::Variable(VariableKind::Bound));
+        let transform = lambdas(ast, &fields, value);
+        let binder = (0..position).fold(transform, |lambda, _| {
+            ast.graph
+                .neighbors(lambda)
+                .find(|&child| matches!(ast.graph[child], Node::Lambda { .. }))
+                .unwrap()
+        });
+        ast.graph.add_edge(value, binder, Edge::Binder(0));
+
+        let mut matcher = ast.graph.add_node(Node::Data {
+            tag: ConstructorTag::HelperFunction(HelperFunctionTag::Match),
+        });
+        let constructor = ast.graph.add_node(Node::Data { tag });
+        for argument in [constructor, transform, fallback] {
+            let application = ast.graph.add_node(Node::Application);
+            ast.graph.add_edge(application, matcher, Edge::Function);
+            ast.graph.add_edge(application, argument, Edge::Parameter);
+            matcher = application;
+        }
+        fallback = matcher;
+    }
+
+    let access = ast.graph.add_node(Node::Application);
+    ast.graph.add_edge(access, fallback, Edge::Function);
+    ast.graph.add_edge(access, record, Edge::Parameter);
+    ast.mark_field_access(access, field);
+    Ok(access)
+}
+
+/// `λname1 name2 ... .body`
+fn lambdas(ast: &mut AST, names: &[String], body: NodeIndex) -> NodeIndex {
+    names.iter().rev().fold(body, |body, name| {
+        let lambda = ast.graph.add_node(Node::Lambda {
+            argument_name: Arc::new(name.clone()),
+        });
+        ast.graph.add_edge(lambda, body, Edge::Body);
+        lambda
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        EvalConfig,
+        ast::{AST, span::Position},
+        parse,
+        parser::ParseError,
+    };
+
+    fn evaluate(source: &str) -> String {
+        let program = parse(source).unwrap();
+        program.evaluate(EvalConfig::default()).unwrap().to_string()
+    }
+
+    #[test]
+    fn fields_are_read() {
+        assert_eq!(evaluate("let p { x = 1, y = 2 } in + p.x p.y"), "3");
+        assert_eq!(evaluate("(1, 2, 3).2"), "3");
+        // Shapes are known before the records that have them
+        assert_eq!(evaluate("let get \\r.r.y in get { y = 5 }"), "5");
+        // Builtins declare the records they return
+        assert_eq!(evaluate("(#divmod 10 1234).1"), "4");
+    }
+
+    #[test]
+    fn unknown_field_is_an_error() {
+        let source = "let p { x = 1 } in p.z";
+        let errors = AST::new().add_expr_from_str(source).unwrap_err().0;
+        let [ParseError::UnknownField { name, span }] = &errors[..] else {
+            panic!("{errors:?}");
+        };
+        assert_eq!(name, "z");
+        assert_eq!(
+            span.start,
+            Position {
+                line: 1,
+                column: 22
+            }
+        );
+    }
+
+    /// The field is known, but not to this record
+    #[test]
+    fn record_of_another_shape() {
+        let source = "let a { x = 1 } in let b { y = 2 } in b.x";
+        assert_eq!(evaluate(source), "NO_FIELD_x");
+    }
+}
@@ -0,0 +1,145 @@
+use petgraph::{Direction, graph::NodeIndex, visit::EdgeRef};
+
+use crate::{
+    LamboResult, Program,
+    ast::{AST, ASTError, Edge, Node, Primitive},
+};
+
+/// Library of `let` definitions parsed once and shared by many programs.
+/// Each program gets its own copy, so it evaluates exactly as if
+/// the library source had been pasted in front of it.
+#[derive(Clone)]
+pub struct Prelude {
+    /// Chain of closures ending in a placeholder body
+    ast: AST,
+}
+
+impl Prelude {
+    /// Parse `source`, a sequence of `let name value in` without a final expression
+    pub fn parse(source: &str) -> LamboResult<Self> {
+        let mut ast = AST::new();
+        ast.root = ast.add_expr_from_str(&format!("{source}\n0"))?;
+        let prelude = Self { ast };
+
+        let (_, hole) = prelude.definitions();
+        match prelude.ast.graph.node_weight(hole) {
+            Some(Node::Primitive(Primitive::Number(0))) => Ok(prelude),
+            _ => Err(ASTError::Custom(hole, "Prelude must only consist of let definitions").into()),
+        }
+    }
+
+    /// Start a program from a copy of the prelude, with `source` in scope of its definitions.
+    /// Definitions the program does not use are garbage collected.
+    pub fn program(&self, source: &str) -> LamboResult<Program> {
+        let mut ast = self.ast.clone();
+        let (closures, hole) = self.definitions();
+        let body = ast.add_expr_in_scope(source, closures.clone())?;
+
+        ast.remove_node(hole);
+        match closures.last() {
+            Some(&last) => {
+                ast.graph.add_edge(last, body, Edge::Body);
+            }
+            None => ast.root = body,
+        }
+        ast.garbage_collect()?;
+        ast.fold_constants()?;
+        Ok(Program { ast })
+    }
+
+    /// Closures along the spine, outermost first, and the placeholder under them
+    fn definitions(&self) -> (Vec<NodeIndex>, NodeIndex) {
+        let graph = &self.ast.graph;
+        let mut closures = vec![];
+        let mut current = self.ast.root;
+        while let Some(Node::Closure { .. }) = graph.node_weight(current)
+            && let Some(body) = graph
+                .edges_directed(current, Direction::Outgoing)
+                .find(|edge| *edge.weight() == Edge::Body)
+        {
+            closures.push(current);
+            current = body.target();
+        }
+        (closures, current)
+    }
+}
+
+#[cfg(feature = "serde")]
+mod cache {
+    use std::{fs, io, path::Path};
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::{
+        LamboResult,
+        ast::{AST, builtins::helpers::fnv1a, serialize::SCHEMA_VERSION},
+    };
+
+    use super::Prelude;
+
+    #[derive(Serialize, Deserialize)]
+    struct CachedPrelude {
+        /// Hash of the schema version and of the source the graph was parsed from,
+        /// stable across builds unlike `DefaultHasher`
+        source_hash: u64,
+        ast: AST,
+    }
+
+    impl Prelude {
+        /// Like `parse`, but reuse the graph stored in file `cache` if it was parsed from the same source.
+        /// A missing, stale or unreadable cache (e.g from an older schema version) is rewritten.
+        pub fn parse_cached(source: &str, cache: impl AsRef<Path>) -> LamboResult<Self> {
+            let key = [&SCHEMA_VERSION.to_le_bytes(), source.as_bytes()].concat();
+            let source_hash = fnv1a(&key);
+
+            if let Ok(contents) = fs::read(&cache)
+                && let Ok(cached) = serde_json::from_slice::<CachedPrelude>(&contents)
+                && cached.source_hash == source_hash
+            {
+                return Ok(Self { ast: cached.ast });
+            }
+
+            let prelude = Self::parse(source)?;
+            let cached = CachedPrelude {
+                source_hash,
+                ast: prelude.ast,
+            };
+            fs::write(
+                &cache,
+                serde_json::to_vec(&cached).map_err(io::Error::from)?,
+            )?;
+            Ok(Self { ast: cached.ast })
+        }
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use std::fs;
+
+    use crate::EvalConfig;
+
+    use super::Prelude;
+
+    /// Records are lowered using constructor names and field accesses kept beside the graph,
+    /// so a cached prelude must bring them along
+    #[test]
+    fn cached_prelude_keeps_records() {
+        let source = "let mk \\x.{ a = x } in let get \\r.r.a in";
+        let cache = std::env::temp_dir().join(format!("lambo-prelude-{}.json", std::process::id()));
+        let _ = fs::remove_file(&cache);
+
+        let evaluate = |prelude: &Prelude, program: &str| {
+            let program = prelude.program(program).unwrap();
+            program.evaluate(EvalConfig::default()).unwrap().to_usize()
+        };
+        let fresh = Prelude::parse_cached(source, &cache).unwrap();
+        let cached = Prelude::parse_cached(source, &cache).unwrap();
+        fs::remove_file(&cache).unwrap();
+
+        for prelude in [&fresh, &cached] {
+            assert_eq!(evaluate(prelude, "get { a = 5 }"), Some(5));
+            assert_eq!(evaluate(prelude, "(mk 7).a"), Some(7));
+        }
+    }
+}
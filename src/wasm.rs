@@ -0,0 +1,108 @@
+use std::{io, sync::Arc};
+
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    EvalConfig, LamboError, Program,
+    ast::builtins::io::EffectHandler,
+    debug_server::{DebugFrame, FrameLog},
+};
+
+#[wasm_bindgen]
+extern "C" {
+    /// Provided by the embedding page, returns a line of input including the newline
+    #[wasm_bindgen(catch, js_name = lamboReadLine)]
+    fn js_read_line() -> Result<String, JsValue>;
+
+    #[wasm_bindgen(catch, js_name = lamboPrint)]
+    fn js_print(text: &str) -> Result<(), JsValue>;
+}
+
+/// Routes IO builtins to the `lamboReadLine` and `lamboPrint` JS functions
+/// JS only passes text, so `#io_readall` calls `lamboReadLine` until it gives an empty string
+#[derive(Debug, Clone, Default)]
+pub struct JsIO;
+
+impl EffectHandler for JsIO {
+    fn handle_read(&mut self) -> io::Result<String> {
+        js_read_line().map_err(|err| io::Error::other(format!("{err:?}")))
+    }
+    fn handle_print(&mut self, text: &str) -> io::Result<()> {
+        js_print(text).map_err(|err| io::Error::other(format!("{err:?}")))
+    }
+    fn clone_box(&self) -> Box<dyn EffectHandler> {
+        Box::new(self.clone())
+    }
+}
+
+fn to_js(err: LamboError) -> JsError {
+    JsError::new(&err.to_string())
+}
+
+fn parse_with_js_io(source: &str) -> Result<Program, JsError> {
+    let mut program = crate::parse(source).map_err(to_js)?;
+    program.ast.io = Box::new(JsIO);
+    Ok(program)
+}
+
+/// Evaluate the program (running its IO, if any) and format the result
+#[wasm_bindgen]
+pub fn parse_and_eval(source: &str) -> Result<String, JsError> {
+    let value = parse_with_js_io(source)?
+        .evaluate(EvalConfig::default())
+        .map_err(to_js)?;
+    Ok(value.to_string())
+}
+
+/// Evaluation that can be walked through one reduction step at a time.
+/// The program is evaluated upfront, recording a DOT graph after every step.
+#[wasm_bindgen]
+pub struct Stepper {
+    frames: Vec<DebugFrame>,
+    position: usize,
+    result: String,
+}
+
+#[wasm_bindgen]
+impl Stepper {
+    #[wasm_bindgen(constructor)]
+    pub fn new(source: &str) -> Result<Stepper, JsError> {
+        let mut program = parse_with_js_io(source)?;
+        let log = Arc::new(FrameLog::default());
+        program.ast.debug_stream = Some(log.clone());
+        program.ast.add_debug_frame();
+
+        let result = program
+            .evaluate(EvalConfig::default())
+            .map_err(to_js)?
+            .to_string();
+
+        Ok(Self {
+            frames: log.frames(),
+            position: 0,
+            result,
+        })
+    }
+
+    /// DOT graph of the next step, or `undefined` once evaluation is over
+    pub fn step(&mut self) -> Option<String> {
+        let frame = self.frames.get(self.position)?;
+        self.position += 1;
+        Some(frame.dot.clone())
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn steps(&self) -> usize {
+        self.frames.len()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn result(&self) -> String {
+        self.result.clone()
+    }
+}
@@ -0,0 +1,67 @@
+use lambo::{Backend, EvalConfig, Prelude, parse};
+
+fn evaluate(program: lambo::Program, backend: Backend) -> String {
+    program
+        .evaluate(EvalConfig {
+            backend,
+            ..EvalConfig::default()
+        })
+        .unwrap()
+        .to_string()
+}
+
+/// Sharing is an optimization, evaluating every argument at each use must give the same results
+#[test]
+fn call_by_name_agrees_with_lazy() {
+    let programs = [
+        "let double \\x.+ x x in double (* 3 4)",
+        "let Y \\f.(\\x.f (x x)) (\\x.f (x x)) in
+        let fact Y \\fact n.(=num n 0) 1 (* n (fact (- 1 n))) in
+        fact 10",
+        "data List = Cons head tail | Nil in
+        let Y \\f.(\\x.f (x x)) (\\x.f (x x)) in
+        let sum Y \\sum list.match list { Cons h t -> + h (sum t), Nil -> 0 } in
+        let xs Cons 1 (Cons 2 (Cons 3 Nil)) in
+        + (sum xs) (sum xs)",
+        "let greeting \"hello\" in #bytes_len (#bytes_push 33 greeting)",
+        "let point { x = 1, y = 2 } in + point.x point.y",
+        // Every use of `some` is the same constructor, not a copy of `#constructor 1`
+        "let some #constructor 1 in #match some (\\x.x) EXHAUSTED (some 5)",
+    ];
+    for source in programs {
+        let evaluate = |backend| evaluate(parse(source).unwrap(), backend);
+        assert_eq!(
+            evaluate(Backend::CallByName),
+            evaluate(Backend::Lazy),
+            "{source}"
+        );
+    }
+}
+
+#[test]
+fn call_by_name_runs_benchmarks() {
+    let prelude = Prelude::parse(include_str!("../benches/benchmarks.lambo")).unwrap();
+    for source in ["bench_numbers 10", "bench_primes 3", "bench_sort 5"] {
+        let evaluate = |backend| evaluate(prelude.program(source).unwrap(), backend);
+        assert_eq!(
+            evaluate(Backend::CallByName),
+            evaluate(Backend::Lazy),
+            "{source}"
+        );
+    }
+}
+
+/// Closures are dropped on the last use of their argument, or the normal form would keep them
+#[test]
+fn call_by_name_normal_form_has_no_closures() {
+    let source = "let two \\f x.f (f x) in let mul \\m n f.m (n f) in mul two (mul two two)";
+    let value = parse(source)
+        .unwrap()
+        .evaluate(EvalConfig {
+            backend: Backend::CallByName,
+            normalize: true,
+            ..EvalConfig::default()
+        })
+        .unwrap();
+    assert_eq!(value.to_string(), "λf.λx.(f (f (f (f (f (f (f (f x))))))))");
+}
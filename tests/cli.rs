@@ -0,0 +1,39 @@
+use std::{
+    io::Write,
+    process::{Command, Output, Stdio},
+};
+
+/// Run `lambo` with `arguments` on `source` given through STDIN
+fn lambo(arguments: &[&str], source: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_lambo"))
+        .args(arguments)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(source.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+/// Same default as `EvalConfig::default()`
+#[test]
+fn overflow_wraps_by_default() {
+    let output = lambo(&[], "^ 64 2");
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.lines().last(), Some("0"));
+}
+
+#[test]
+fn checked_arithmetic_fails_on_overflow() {
+    let output = lambo(&["--checked-arithmetic"], "^ 64 2");
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Arithmetic overflow"), "{stdout}");
+}
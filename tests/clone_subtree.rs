@@ -0,0 +1,60 @@
+use std::sync::Arc;
+
+use lambo::ast::{AST, Edge, Node, Primitive, VariableKind};
+
+/// Values of shared closures are cloned for every reference but the last one.
+/// Cloning used to recurse once per node and overflow the stack on long lists.
+#[test]
+fn clone_long_list() {
+    // Every element takes a number and two applications
+    const LENGTH: usize = 1_000_000 / 3;
+
+    let mut ast = AST::new();
+    let cons = ast.named_constructor("cons", 2);
+    let nil = ast.named_constructor("nil", 0);
+    let mut list = ast.graph.add_node(Node::Data { tag: nil });
+    for element in 0..LENGTH {
+        let constructor = ast.graph.add_node(Node::Data { tag: cons });
+        let head = ast
+            .graph
+            .add_node(Node::Primitive(Primitive::Number(element)));
+        let partial = ast.graph.add_node(Node::Application);
+        ast.graph.add_edge(partial, constructor, Edge::Function);
+        ast.graph.add_edge(partial, head, Edge::Parameter);
+        let cell = ast.graph.add_node(Node::Application);
+        ast.graph.add_edge(cell, partial, Edge::Function);
+        ast.graph.add_edge(cell, list, Edge::Parameter);
+        list = cell;
+    }
+    // A lambda is already a value, so its whole body is cloned
+    let function = ast.graph.add_node(Node::Lambda {
+        argument_name: Arc::new("_".to_string()),
+    });
+    ast.graph.add_edge(function, list, Edge::Body);
+    let size = ast.subtree_size(function);
+
+    // let list \_.[...] in pair list list
+    let closure = ast.graph.add_node(Node::Closure {
+        argument_name: Arc::new("list".to_string()),
+    });
+    ast.graph.add_edge(closure, function, Edge::Parameter);
+    let pair = ast.named_constructor("pair", 2);
+    let pair = ast.graph.add_node(Node::Data { tag: pair });
+    let mut body = pair;
+    let mut references = vec![];
+    for _ in 0..2 {
+        let reference = ast.graph.add_node(Node::Variable(VariableKind::Bound));
+        ast.graph.add_edge(reference, closure, Edge::Binder(0));
+        let application = ast.graph.add_node(Node::Application);
+        ast.graph.add_edge(application, body, Edge::Function);
+        ast.graph.add_edge(application, reference, Edge::Parameter);
+        references.push(reference);
+        body = application;
+    }
+    ast.graph.add_edge(closure, body, Edge::Body);
+    ast.root = closure;
+
+    let copy = ast.evaluate(references[0]).unwrap();
+    assert_eq!(ast.stats().subtree_clones, 1);
+    assert_eq!(ast.subtree_size(copy), size);
+}